@@ -0,0 +1,126 @@
+use criterion::{BenchmarkId, Criterion};
+use orderbook_rs::OrderBook;
+use pricelevel::{OrderId, Side, TimeInForce};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Register stress benchmarks that check the best bid/ask stay exact while price levels
+/// are created and emptied concurrently
+pub fn register_best_price_benchmarks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("OrderBook - Best Price Consistency");
+
+    for thread_count in [2, 4, 8].iter() {
+        group.bench_with_input(
+            BenchmarkId::new("level_churn", thread_count),
+            thread_count,
+            |b, &thread_count| {
+                b.iter_custom(|iters| measure_level_churn(thread_count, iters));
+            },
+        );
+    }
+
+    group.finish();
+}
+
+/// Adds, cancels and matches orders over a few price levels from several threads, then
+/// checks the best prices against a full scan of the resting orders
+fn measure_level_churn(thread_count: usize, iterations: u64) -> Duration {
+    let order_book = Arc::new(OrderBook::new("TEST-SYMBOL"));
+    let barrier = Arc::new(Barrier::new(thread_count + 1)); // +1 for main thread
+
+    let mut handles = Vec::with_capacity(thread_count);
+
+    for thread_id in 0..thread_count {
+        let thread_order_book = Arc::clone(&order_book);
+        let thread_barrier = Arc::clone(&barrier);
+
+        handles.push(thread::spawn(move || {
+            let mut resting = Vec::new();
+
+            // Wait for all threads to be ready
+            thread_barrier.wait();
+
+            for i in 0..iterations {
+                // Spread the threads over ten levels per side, bids below 1000 and asks
+                // above it, so limit orders never cross
+                let offset = (thread_id as u64 * 7 + i) % 10;
+
+                match i % 5 {
+                    0 | 1 => {
+                        let id = OrderId(Uuid::new_v4());
+                        let (side, price) = if i % 5 == 0 {
+                            (Side::Buy, 999 - offset)
+                        } else {
+                            (Side::Sell, 1001 + offset)
+                        };
+                        thread_order_book
+                            .add_limit_order(id, price, 1 + offset, side, TimeInForce::Gtc)
+                            .unwrap();
+                        resting.push(id);
+                    }
+                    2 | 3 => {
+                        // Cancel our oldest order, which may already have been filled
+                        if !resting.is_empty() {
+                            let id = resting.swap_remove(0);
+                            thread_order_book.cancel_order(id).ok();
+                        }
+                    }
+                    _ => {
+                        // Sweep the top of one side
+                        let id = OrderId(Uuid::new_v4());
+                        let side = if thread_id % 2 == 0 {
+                            Side::Buy
+                        } else {
+                            Side::Sell
+                        };
+                        thread_order_book.submit_market_order(id, 5, side).ok();
+                    }
+                }
+            }
+
+            // Signal completion
+            thread_barrier.wait();
+        }));
+    }
+
+    // Start timing
+    barrier.wait();
+    let start = Instant::now();
+
+    // Wait for all threads to complete
+    barrier.wait();
+    let duration = start.elapsed();
+
+    // Join all threads
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    assert_best_prices_consistent(&order_book);
+
+    duration
+}
+
+/// Panics if the best prices of the book differ from those of its resting orders
+fn assert_best_prices_consistent(order_book: &OrderBook) {
+    let orders = order_book.get_all_orders();
+    let best_bid = orders
+        .iter()
+        .filter(|order| order.side() == Side::Buy)
+        .map(|order| order.price())
+        .max();
+    let best_ask = orders
+        .iter()
+        .filter(|order| order.side() == Side::Sell)
+        .map(|order| order.price())
+        .min();
+
+    assert_eq!(order_book.best_bid(), best_bid, "best bid out of sync");
+    assert_eq!(order_book.best_ask(), best_ask, "best ask out of sync");
+
+    let snapshot = order_book.create_snapshot(usize::MAX);
+    assert_eq!(snapshot.best_bid().map(|(price, _)| price), best_bid);
+    assert_eq!(snapshot.best_ask().map(|(price, _)| price), best_ask);
+}
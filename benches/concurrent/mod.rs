@@ -1,8 +1,10 @@
 use criterion::criterion_group;
 
+mod best_prices;
 mod contention;
 mod register;
 
+pub use best_prices::register_best_price_benchmarks;
 pub use contention::register_contention_benchmarks;
 pub use register::register_benchmarks;
 
@@ -10,5 +12,6 @@ pub use register::register_benchmarks;
 criterion_group!(
     concurrent_benches,
     register_benchmarks,
-    register_contention_benchmarks
+    register_contention_benchmarks,
+    register_best_price_benchmarks
 );
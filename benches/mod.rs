@@ -2,10 +2,13 @@ use criterion::{criterion_group, criterion_main};
 
 mod concurrent;
 mod order_book;
+mod order_entry;
 mod simple;
 
 use concurrent::register_benchmarks as register_concurrent_benchmarks;
+use concurrent::register_best_price_benchmarks;
 use order_book::register_benchmarks as register_order_book_benchmarks;
+use order_entry::register_benchmarks as register_order_entry_benchmarks;
 use simple::basic::benchmark_data;
 
 // Define the benchmark groups
@@ -14,6 +17,8 @@ criterion_group!(
     benchmark_data,
     register_order_book_benchmarks,
     register_concurrent_benchmarks,
+    register_best_price_benchmarks,
+    register_order_entry_benchmarks,
 );
 
 criterion_main!(benches);
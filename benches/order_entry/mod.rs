@@ -0,0 +1,133 @@
+use actix_codec::{Decoder, Encoder};
+use bytes::BytesMut;
+use criterion::Criterion;
+use dashmap::DashMap;
+use orderbook_rs::api::binary::{
+    BinaryClient, BinaryCodec, BinaryGateway, BinaryGatewayConfig, EnterOrder, ExecutionReport,
+    OrderKind, ReasonCode, ServerMessage, run_binary_acceptor,
+};
+use orderbook_rs::api::persistence::Outbox;
+use orderbook_rs::api::stream::{
+    OrderEvent, UserStreamConfig, UserStreamHub, UserStreamSink, run_user_streams,
+};
+use orderbook_rs::{OrderBook, Sequencer};
+use pricelevel::{OrderId, Side, TimeInForce};
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const SYMBOL: &str = "BENCH";
+
+/// Register the benchmarks of the binary order entry protocol
+pub fn register_benchmarks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Order Entry - Binary Protocol");
+
+    // Encoding and decoding the largest message
+    let report = ServerMessage::ExecutionReport(ExecutionReport {
+        sequence: 42,
+        client_order_id: 7,
+        order_id: OrderId(Uuid::new_v4()),
+        symbol: SYMBOL.to_string(),
+        event: OrderEvent::PartiallyFilled,
+        side: Side::Buy,
+        role: None,
+        reason: ReasonCode::None,
+        price: 1000,
+        quantity: 10,
+        last_price: 1000,
+        last_quantity: 4,
+        cumulative_quantity: 4,
+        remaining_quantity: 6,
+        trade_id: Uuid::new_v4(),
+        timestamp: 1_700_000_000_000,
+    });
+    group.bench_function("encode_decode_execution_report", |b| {
+        let mut codec = BinaryCodec::<ServerMessage>::new();
+        let mut buffer = BytesMut::with_capacity(256);
+        b.iter(|| {
+            codec
+                .encode(black_box(report.clone()), &mut buffer)
+                .unwrap();
+            black_box(codec.decode(&mut buffer).unwrap())
+        })
+    });
+
+    // Round trips over loopback: enter an order and wait for its acknowledgement, then cancel
+    // it and wait for the cancellation, through the sequencer and the user stream hub
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .unwrap();
+    let mut client = runtime.block_on(start_gateway());
+    let mut client_order_id = 0;
+    group.bench_function("loopback_enter_and_cancel_round_trips", |b| {
+        b.iter_custom(|iterations| {
+            runtime.block_on(async {
+                let start = Instant::now();
+                for _ in 0..iterations {
+                    client_order_id += 1;
+                    client
+                        .enter_order(resting_order(client_order_id))
+                        .await
+                        .unwrap();
+                    wait_for(&mut client, OrderEvent::Accepted).await;
+                    client.cancel_order(client_order_id, SYMBOL).await.unwrap();
+                    wait_for(&mut client, OrderEvent::Cancelled).await;
+                }
+                start.elapsed()
+            })
+        })
+    });
+    group.finish();
+}
+
+/// Start a gateway on loopback and log a client in
+async fn start_gateway() -> BinaryClient {
+    let hub = Arc::new(UserStreamHub::new(UserStreamConfig::default(), None, 1));
+    let (sink, receiver) = UserStreamSink::channel();
+    let book = OrderBook::new(SYMBOL);
+    book.subscribe(Arc::new(sink));
+    let sequencers = Arc::new(DashMap::new());
+    sequencers.insert(SYMBOL.to_string(), Arc::new(Sequencer::start(book)));
+    tokio::spawn(run_user_streams(hub.clone(), receiver));
+
+    let dir = std::env::temp_dir().join(format!("order-entry-bench-{}", Uuid::new_v4()));
+    let outbox = Arc::new(Outbox::open(dir).unwrap());
+    let config = BinaryGatewayConfig {
+        idle_timeout: Duration::from_secs(3600),
+        ..BinaryGatewayConfig::default()
+    }
+    .with_session("bench", Uuid::new_v4());
+    let gateway = Arc::new(BinaryGateway::new(config, sequencers, hub, outbox));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(run_binary_acceptor(listener, gateway));
+
+    let mut client = BinaryClient::connect(addr).await.unwrap();
+    client.login("bench", 0).await.unwrap();
+    client
+}
+
+fn resting_order(client_order_id: u64) -> EnterOrder {
+    EnterOrder {
+        client_order_id,
+        symbol: SYMBOL.to_string(),
+        side: Side::Buy,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        price: 1000,
+        quantity: 1,
+    }
+}
+
+async fn wait_for(client: &mut BinaryClient, event: OrderEvent) {
+    loop {
+        match client.next_message().await.unwrap() {
+            Some(ServerMessage::ExecutionReport(report)) if report.event == event => return,
+            Some(ServerMessage::Heartbeat) => {}
+            other => panic!("expected a {event:?} report, got {other:?}"),
+        }
+    }
+}
@@ -61,11 +61,12 @@ fn demo_adding_orders(book: &crate::OrderBook) {
         let result = book.add_limit_order(id, price, quantity, Side::Buy, TimeInForce::Gtc);
 
         match result {
-            Ok(order) => info!(
-                "Added BUY limit order: id={}, price={}, qty={}",
-                order.id(),
-                order.price(),
-                order.visible_quantity()
+            Ok(report) => info!(
+                "Added BUY limit order: id={}, status={:?}, filled={}, resting={}",
+                report.order_id,
+                report.status,
+                report.filled_quantity(),
+                report.resting_quantity()
             ),
             Err(e) => info!("Failed to add BUY limit order: {}", e),
         }
@@ -80,11 +81,12 @@ fn demo_adding_orders(book: &crate::OrderBook) {
         let result = book.add_limit_order(id, price, quantity, Side::Sell, TimeInForce::Gtc);
 
         match result {
-            Ok(order) => info!(
-                "Added SELL limit order: id={}, price={}, qty={}",
-                order.id(),
-                order.price(),
-                order.visible_quantity()
+            Ok(report) => info!(
+                "Added SELL limit order: id={}, status={:?}, filled={}, resting={}",
+                report.order_id,
+                report.status,
+                report.filled_quantity(),
+                report.resting_quantity()
             ),
             Err(e) => info!("Failed to add SELL limit order: {}", e),
         }
@@ -95,12 +97,12 @@ fn demo_adding_orders(book: &crate::OrderBook) {
     let result = book.add_iceberg_order(id, 9990, 5, 45, Side::Buy, TimeInForce::Gtc);
 
     match result {
-        Ok(order) => info!(
-            "Added iceberg order: id={}, price={}, visible={}, hidden={}",
-            order.id(),
-            order.price(),
-            order.visible_quantity(),
-            order.hidden_quantity()
+        Ok(report) => info!(
+            "Added iceberg order: id={}, status={:?}, filled={}, resting={}",
+            report.order_id,
+            report.status,
+            report.filled_quantity(),
+            report.resting_quantity()
         ),
         Err(e) => info!("Failed to add iceberg order: {}", e),
     }
@@ -110,11 +112,12 @@ fn demo_adding_orders(book: &crate::OrderBook) {
     let result = book.add_post_only_order(id, 10100, 20, Side::Sell, TimeInForce::Gtc);
 
     match result {
-        Ok(order) => info!(
-            "Added post-only order: id={}, price={}, qty={}",
-            order.id(),
-            order.price(),
-            order.visible_quantity()
+        Ok(report) => info!(
+            "Added post-only order: id={}, status={:?}, filled={}, resting={}",
+            report.order_id,
+            report.status,
+            report.filled_quantity(),
+            report.resting_quantity()
         ),
         Err(e) => info!("Failed to add post-only order: {}", e),
     }
@@ -133,11 +136,12 @@ fn demo_adding_orders(book: &crate::OrderBook) {
     let result = book.add_limit_order(id, 9970, 5, Side::Sell, TimeInForce::Fok);
 
     match result {
-        Ok(order) => info!(
-            "Added FOK order: id={}, price={}, qty={}",
-            order.id(),
-            order.price(),
-            order.visible_quantity()
+        Ok(report) => info!(
+            "Added FOK order: id={}, status={:?}, filled={}, resting={}",
+            report.order_id,
+            report.status,
+            report.filled_quantity(),
+            report.resting_quantity()
         ),
         Err(e) => info!("Failed to add FOK order: {}", e),
     }
@@ -147,11 +151,12 @@ fn demo_adding_orders(book: &crate::OrderBook) {
     let result = book.add_limit_order(id, 9975, 8, Side::Sell, TimeInForce::Ioc);
 
     match result {
-        Ok(order) => info!(
-            "Added IOC order: id={}, price={}, qty={}",
-            order.id(),
-            order.price(),
-            order.visible_quantity()
+        Ok(report) => info!(
+            "Added IOC order: id={}, status={:?}, filled={}, resting={}",
+            report.order_id,
+            report.status,
+            report.filled_quantity(),
+            report.resting_quantity()
         ),
         Err(e) => info!("Failed to add IOC order: {}", e),
     }
@@ -228,20 +233,20 @@ fn demo_market_orders(book: &crate::OrderBook) {
     let result = book.submit_market_order(id, 25, Side::Buy);
 
     match result {
-        Ok(match_result) => {
+        Ok(report) => {
             info!(
-                "Market BUY result: executed={}, remaining={}, complete={}, transactions={}",
-                match_result.executed_quantity(),
-                match_result.remaining_quantity,
-                match_result.is_complete,
-                match_result.transactions.len()
+                "Market BUY result: executed={}, cancelled={}, status={:?}, fills={}",
+                report.filled_quantity(),
+                report.cancelled.map(|c| c.quantity).unwrap_or(0),
+                report.status,
+                report.fills.len()
             );
 
-            // Display transaction details
-            for (i, tx) in match_result.transactions.as_vec().iter().enumerate() {
+            // Display fill details
+            for (i, fill) in report.fills.iter().enumerate() {
                 info!(
-                    "  Transaction {}: price={}, qty={}, taker={}, maker={}",
-                    i, tx.price, tx.quantity, tx.taker_order_id, tx.maker_order_id
+                    "  Fill {}: price={}, qty={}, maker={}, fee={}",
+                    i, fill.price, fill.quantity, fill.maker_order_id, fill.fee
                 );
             }
         }
@@ -253,13 +258,13 @@ fn demo_market_orders(book: &crate::OrderBook) {
     let result = book.submit_market_order(id, 40, Side::Sell);
 
     match result {
-        Ok(match_result) => {
+        Ok(report) => {
             info!(
-                "Market SELL result: executed={}, remaining={}, complete={}, transactions={}",
-                match_result.executed_quantity(),
-                match_result.remaining_quantity,
-                match_result.is_complete,
-                match_result.transactions.len()
+                "Market SELL result: executed={}, cancelled={}, status={:?}, fills={}",
+                report.filled_quantity(),
+                report.cancelled.map(|c| c.quantity).unwrap_or(0),
+                report.status,
+                report.fills.len()
             );
         }
         Err(e) => info!("Market SELL failed: {}", e),
@@ -270,12 +275,12 @@ fn demo_market_orders(book: &crate::OrderBook) {
     let result = book.submit_market_order(id, 1000, Side::Buy);
 
     match result {
-        Ok(match_result) => {
+        Ok(report) => {
             info!(
-                "Large market BUY result: executed={}, remaining={}, complete={}",
-                match_result.executed_quantity(),
-                match_result.remaining_quantity,
-                match_result.is_complete
+                "Large market BUY result: executed={}, cancelled={}, status={:?}",
+                report.filled_quantity(),
+                report.cancelled.map(|c| c.quantity).unwrap_or(0),
+                report.status
             );
         }
         Err(e) => info!("Large market BUY failed as expected: {}", e),
@@ -290,11 +295,12 @@ fn demo_limit_order_matching(book: &crate::OrderBook) {
     let result = book.add_limit_order(id, 10040, 15, Side::Buy, TimeInForce::Gtc);
 
     match result {
-        Ok(order) => info!(
-            "Added crossing limit order: id={}, price={}, qty={}",
-            order.id(),
-            order.price(),
-            order.visible_quantity()
+        Ok(report) => info!(
+            "Added crossing limit order: id={}, status={:?}, filled={}, resting={}",
+            report.order_id,
+            report.status,
+            report.filled_quantity(),
+            report.resting_quantity()
         ),
         Err(e) => info!("Failed to add crossing limit order: {}", e),
     }
@@ -304,11 +310,12 @@ fn demo_limit_order_matching(book: &crate::OrderBook) {
     let result = book.add_limit_order(id, 10060, 50, Side::Buy, TimeInForce::Ioc);
 
     match result {
-        Ok(order) => info!(
-            "Added IOC order: id={}, price={}, qty={}",
-            order.id(),
-            order.price(),
-            order.visible_quantity()
+        Ok(report) => info!(
+            "Added IOC order: id={}, status={:?}, filled={}, resting={}",
+            report.order_id,
+            report.status,
+            report.filled_quantity(),
+            report.resting_quantity()
         ),
         Err(e) => info!("Failed to add IOC order: {}", e),
     }
@@ -318,11 +325,12 @@ fn demo_limit_order_matching(book: &crate::OrderBook) {
     let result = book.add_limit_order(id, 10080, 10, Side::Buy, TimeInForce::Fok);
 
     match result {
-        Ok(order) => info!(
-            "Added FOK order: id={}, price={}, qty={}",
-            order.id(),
-            order.price(),
-            order.visible_quantity()
+        Ok(report) => info!(
+            "Added FOK order: id={}, status={:?}, filled={}, resting={}",
+            report.order_id,
+            report.status,
+            report.filled_quantity(),
+            report.resting_quantity()
         ),
         Err(e) => info!("Failed to add FOK order: {}", e),
     }
@@ -345,14 +353,14 @@ fn demo_cancel_orders(book: &crate::OrderBook) {
     let result = book.add_limit_order(id, 9850, 30, Side::Buy, TimeInForce::Gtc);
 
     let order_id = match result {
-        Ok(order) => {
+        Ok(report) => {
             info!(
-                "Added order to cancel: id={}, price={}, qty={}",
-                order.id(),
-                order.price(),
-                order.visible_quantity()
+                "Added order to cancel: id={}, status={:?}, resting={}",
+                report.order_id,
+                report.status,
+                report.resting_quantity()
             );
-            order.id()
+            report.order_id
         }
         Err(e) => {
             info!("Failed to add order: {}", e);
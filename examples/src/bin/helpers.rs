@@ -10,7 +10,7 @@ pub fn setup_orders_for_read_write_test(order_book: &OrderBook) {
     // Buy orders
     for i in 0..250 {
         let price = 9900 + (i % 20) * 5; // 20 price levels: 9900-9995
-        let id = OrderId::from_u64(i as u64);
+        let id = OrderId::from_u64(i);
         let quantity = 10 + (i % 10);
 
         let _ = order_book.add_limit_order(id, price, quantity, Side::Buy, TimeInForce::Gtc);
@@ -19,7 +19,7 @@ pub fn setup_orders_for_read_write_test(order_book: &OrderBook) {
     // Sell orders
     for i in 0..250 {
         let price = 10000 + (i % 20) * 5; // 20 price levels: 10000-10095
-        let id = OrderId::from_u64((i + 250) as u64);
+        let id = OrderId::from_u64(i + 250);
         let quantity = 10 + (i % 10);
 
         let _ = order_book.add_limit_order(id, price, quantity, Side::Sell, TimeInForce::Gtc);
@@ -48,13 +48,13 @@ pub fn setup_orders_for_hot_spot_test(order_book: &crate::OrderBook) {
         let is_buy = i % 2 == 0;
         let side = if is_buy { Side::Buy } else { Side::Sell };
         let price_base = if is_buy { 9900 } else { 10000 };
-        let price_offset = (i % 100) * 1;
+        let price_offset = i % 100;
         let price = if is_buy {
             price_base - price_offset
         } else {
             price_base + price_offset
         };
-        let id = OrderId::from_u64(i as u64);
+        let id = OrderId::from_u64(i);
 
         let _ = order_book.add_limit_order(id, price, 10, side, TimeInForce::Gtc);
     }
@@ -321,7 +321,7 @@ fn spawn_maker_thread(
             } else {
                 BASE_ASK_PRICE
             };
-            let price_offset = (local_count % PRICE_LEVELS as u64) * 10;
+            let price_offset = (local_count % PRICE_LEVELS) * 10;
             let price = if is_buy {
                 price_base - price_offset
             } else {
@@ -338,30 +338,35 @@ fn spawn_maker_thread(
             match local_count % 5 {
                 0 => {
                     // Standard limit order
-                    if let Ok(_) =
-                        order_book.add_limit_order(id, price, quantity, side, TimeInForce::Gtc)
+                    if order_book
+                        .add_limit_order(id, price, quantity, side, TimeInForce::Gtc)
+                        .is_ok()
                     {
                         order_added = true;
                     }
                 }
                 1 => {
                     // Post-only order
-                    if let Ok(_) =
-                        order_book.add_post_only_order(id, price, quantity, side, TimeInForce::Gtc)
+                    if order_book
+                        .add_post_only_order(id, price, quantity, side, TimeInForce::Gtc)
+                        .is_ok()
                     {
                         order_added = true;
                     }
                 }
                 2 => {
                     // Iceberg order
-                    if let Ok(_) = order_book.add_iceberg_order(
-                        id,
-                        price,
-                        quantity / 4,
-                        quantity * 3 / 4,
-                        side,
-                        TimeInForce::Gtc,
-                    ) {
+                    if order_book
+                        .add_iceberg_order(
+                            id,
+                            price,
+                            quantity / 4,
+                            quantity * 3 / 4,
+                            side,
+                            TimeInForce::Gtc,
+                        )
+                        .is_ok()
+                    {
                         order_added = true;
                     }
                 }
@@ -372,13 +377,10 @@ fn spawn_maker_thread(
                     } else {
                         BASE_BID_PRICE - 10
                     };
-                    if let Ok(_) = order_book.add_limit_order(
-                        id,
-                        cross_price,
-                        quantity,
-                        side,
-                        TimeInForce::Ioc,
-                    ) {
+                    if order_book
+                        .add_limit_order(id, cross_price, quantity, side, TimeInForce::Ioc)
+                        .is_ok()
+                    {
                         // IOC orders that don't fully execute may still leave resting quantity
                         order_added = true;
                     }
@@ -390,26 +392,21 @@ fn spawn_maker_thread(
                     } else {
                         BASE_BID_PRICE - 5
                     };
-                    if let Ok(_) = order_book.add_limit_order(
-                        id,
-                        cross_price,
-                        quantity,
-                        side,
-                        TimeInForce::Fok,
-                    ) {
+                    if order_book
+                        .add_limit_order(id, cross_price, quantity, side, TimeInForce::Fok)
+                        .is_ok()
+                    {
                         order_added = true;
                     }
                 }
             }
 
             // Add order ID to queue for potential cancellation if it was successfully added
-            if order_added {
-                if let Ok(mut queue) = order_id_queue.try_lock() {
-                    queue.push_back(id);
-                    // Keep queue size reasonable
-                    if queue.len() > 1000 {
-                        queue.pop_front();
-                    }
+            if order_added && let Ok(mut queue) = order_id_queue.try_lock() {
+                queue.push_back(id);
+                // Keep queue size reasonable
+                if queue.len() > 1000 {
+                    queue.pop_front();
                 }
             }
 
@@ -465,10 +462,10 @@ fn spawn_taker_thread(
             let result = order_book.submit_market_order(id, quantity, side);
 
             // Only count successful matches
-            if let Ok(match_result) = result {
-                if match_result.executed_quantity() > 0 {
-                    local_count += 1;
-                }
+            if let Ok(report) = result
+                && report.filled_quantity() > 0
+            {
+                local_count += 1;
             }
 
             // Update global counter periodically
@@ -0,0 +1,65 @@
+//! Bearer tokens identifying the account of a streaming client.
+//!
+//! A token is `{account}.{expires_at}.{signature}`, where `expires_at` is in milliseconds since
+//! epoch and `signature` is the unpadded URL-safe base64 HMAC-SHA256 of `{account}.{expires_at}`
+//! under a secret shared with the service issuing the tokens (`USER_STREAM_SECRET`).
+
+use base64::prelude::*;
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+use std::fmt;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Why a token was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenError {
+    Malformed,
+    BadSignature,
+    Expired,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Malformed => f.write_str("malformed token"),
+            TokenError::BadSignature => f.write_str("invalid token signature"),
+            TokenError::Expired => f.write_str("token expired"),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+fn mac(secret: &[u8], payload: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// Issue a token for `account`, valid until `expires_at` (milliseconds since epoch)
+pub fn issue_token(secret: &[u8], account: Uuid, expires_at: u64) -> String {
+    let payload = format!("{}.{}", account, expires_at);
+    let signature = BASE64_URL_SAFE_NO_PAD.encode(mac(secret, &payload).finalize().into_bytes());
+    format!("{}.{}", payload, signature)
+}
+
+/// Check a token at `now` (milliseconds since epoch) and return its account
+pub fn verify_token(secret: &[u8], token: &str, now: u64) -> Result<Uuid, TokenError> {
+    let (payload, signature) = token.rsplit_once('.').ok_or(TokenError::Malformed)?;
+    let (account, expires_at) = payload.split_once('.').ok_or(TokenError::Malformed)?;
+    let account = Uuid::parse_str(account).map_err(|_| TokenError::Malformed)?;
+    let expires_at: u64 = expires_at.parse().map_err(|_| TokenError::Malformed)?;
+    let signature = BASE64_URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| TokenError::Malformed)?;
+
+    mac(secret, payload)
+        .verify_slice(&signature)
+        .map_err(|_| TokenError::BadSignature)?;
+    if expires_at <= now {
+        return Err(TokenError::Expired);
+    }
+    Ok(account)
+}
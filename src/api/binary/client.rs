@@ -0,0 +1,103 @@
+use actix_codec::Framed;
+use futures_util::{SinkExt, StreamExt};
+use std::io;
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use super::protocol::{BinaryCodec, ClientMessage, EnterOrder, ReplaceOrder, ServerMessage};
+
+/// Client of the binary order entry gateway. Clients send a message, a heartbeat if nothing
+/// else, at least once per idle timeout of the gateway.
+#[derive(Debug)]
+pub struct BinaryClient {
+    framed: Framed<TcpStream, BinaryCodec<ServerMessage>>,
+}
+
+impl BinaryClient {
+    pub async fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            framed: Framed::new(stream, BinaryCodec::new()),
+        })
+    }
+
+    /// Log in as `username`, asking for the execution reports from notification
+    /// `from_sequence` on (0 for new ones only). Returns the epoch of the notifications and
+    /// the sequence of the first one the session will report.
+    pub async fn login(&mut self, username: &str, from_sequence: u64) -> io::Result<(u64, u64)> {
+        self.send(ClientMessage::Login {
+            username: username.to_string(),
+            from_sequence,
+        })
+        .await?;
+        match self.next_message().await? {
+            Some(ServerMessage::LoginAccepted {
+                epoch,
+                next_sequence,
+            }) => Ok((epoch, next_sequence)),
+            Some(ServerMessage::LoginRejected { reason }) => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("login rejected: {:?}", reason),
+            )),
+            Some(other) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected answer to a login: {:?}", other),
+            )),
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed during login",
+            )),
+        }
+    }
+
+    pub async fn send(&mut self, message: ClientMessage) -> io::Result<()> {
+        self.framed.send(message).await
+    }
+
+    pub async fn enter_order(&mut self, order: EnterOrder) -> io::Result<()> {
+        self.send(ClientMessage::EnterOrder(order)).await
+    }
+
+    pub async fn cancel_order(&mut self, client_order_id: u64, symbol: &str) -> io::Result<()> {
+        self.send(ClientMessage::CancelOrder {
+            client_order_id,
+            symbol: symbol.to_string(),
+        })
+        .await
+    }
+
+    pub async fn replace_order(&mut self, replace: ReplaceOrder) -> io::Result<()> {
+        self.send(ClientMessage::ReplaceOrder(replace)).await
+    }
+
+    /// Cancel the live orders of the account on `symbol`, or everywhere
+    pub async fn mass_cancel(&mut self, request_id: u64, symbol: Option<&str>) -> io::Result<()> {
+        self.send(ClientMessage::MassCancel {
+            request_id,
+            symbol: symbol.map(str::to_string),
+        })
+        .await
+    }
+
+    pub async fn heartbeat(&mut self) -> io::Result<()> {
+        self.send(ClientMessage::Heartbeat).await
+    }
+
+    /// Next message of the gateway, `None` once the connection is closed
+    pub async fn next_message(&mut self) -> io::Result<Option<ServerMessage>> {
+        self.framed.next().await.transpose()
+    }
+
+    /// Log out and wait for the gateway to confirm, returning the messages received meanwhile
+    pub async fn logout(&mut self) -> io::Result<Vec<ServerMessage>> {
+        self.send(ClientMessage::Logout).await?;
+        let mut received = Vec::new();
+        while let Some(message) = self.next_message().await? {
+            if matches!(message, ServerMessage::Logout { .. }) {
+                break;
+            }
+            received.push(message);
+        }
+        Ok(received)
+    }
+}
@@ -0,0 +1,126 @@
+//! Binary order entry protocol.
+//!
+//! A compact alternative to the REST and FIX order entry for latency sensitive clients, in
+//! the spirit of OUCH and SBE: every message is a little-endian `u16` length, a one byte
+//! message type and a fixed layout body, see [`protocol`] for the layouts. Symbols and
+//! usernames are ASCII fields of 16 bytes padded with zeros, identifiers of orders entered by
+//! the client are `u64`, and prices and quantities are `u64` in the units of the books.
+//!
+//! A session starts with a Login naming a username, which maps to the account owning the
+//! orders. Orders go through the [`crate::Sequencer`] of the books like the REST orders, and
+//! the execution reports are built from the notifications of the account on the
+//! [`UserStreamHub`], so they can be replayed after a reconnect from the sequence given in
+//! the Login. Requests refused before reaching a book are answered right away.
+
+mod client;
+pub mod protocol;
+mod session;
+
+pub use client::BinaryClient;
+pub use protocol::{
+    BinaryCodec, ClientMessage, EnterOrder, ExecutionReport, OrderKind, ReasonCode, ReplaceOrder,
+    ServerMessage, WireMessage,
+};
+
+use crate::Sequencer;
+use crate::api::persistence::Outbox;
+use crate::api::stream::UserStreamHub;
+use dashmap::DashMap;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Settings of the binary order entry gateway
+#[derive(Debug, Clone)]
+pub struct BinaryGatewayConfig {
+    /// Account of each username
+    pub sessions: HashMap<String, Uuid>,
+    /// Time a new connection has to send its Login
+    pub login_timeout: Duration,
+    /// The server sends a heartbeat after sending nothing for this long
+    pub heartbeat_interval: Duration,
+    /// Sessions of clients silent for this long are logged out
+    pub idle_timeout: Duration,
+}
+
+impl Default for BinaryGatewayConfig {
+    fn default() -> Self {
+        Self {
+            sessions: HashMap::new(),
+            login_timeout: Duration::from_secs(5),
+            heartbeat_interval: Duration::from_secs(1),
+            idle_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl BinaryGatewayConfig {
+    /// Accept logins of `username`, entering orders for `account`
+    pub fn with_session(mut self, username: impl Into<String>, account: Uuid) -> Self {
+        self.sessions.insert(username.into(), account);
+        self
+    }
+}
+
+/// State shared by the sessions of the gateway
+pub struct BinaryGateway {
+    config: BinaryGatewayConfig,
+    sequencers: Arc<DashMap<String, Arc<Sequencer>>>,
+    user_streams: Arc<UserStreamHub>,
+    outbox: Arc<Outbox>,
+    /// Usernames currently logged on
+    logged_on: Mutex<HashSet<String>>,
+}
+
+impl std::fmt::Debug for BinaryGateway {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BinaryGateway")
+            .field("config", &self.config)
+            .field("symbols", &self.sequencers.len())
+            .finish()
+    }
+}
+
+impl BinaryGateway {
+    pub fn new(
+        config: BinaryGatewayConfig,
+        sequencers: Arc<DashMap<String, Arc<Sequencer>>>,
+        user_streams: Arc<UserStreamHub>,
+        outbox: Arc<Outbox>,
+    ) -> Self {
+        Self {
+            config,
+            sequencers,
+            user_streams,
+            outbox,
+            logged_on: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn config(&self) -> &BinaryGatewayConfig {
+        &self.config
+    }
+}
+
+/// Accept binary order entry connections on `listener` until it fails, serving each one on
+/// its own task
+pub async fn run_binary_acceptor(
+    listener: TcpListener,
+    gateway: Arc<BinaryGateway>,
+) -> io::Result<()> {
+    info!("Binary order entry listening on {}", listener.local_addr()?);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        if let Err(e) = stream.set_nodelay(true) {
+            warn!(
+                "Failed to disable Nagle's algorithm for order entry connection {}: {}",
+                peer, e
+            );
+        }
+        tokio::spawn(session::serve_connection(gateway.clone(), stream, peer));
+    }
+}
@@ -0,0 +1,653 @@
+use actix_codec::{Decoder, Encoder};
+use bytes::{Buf, BufMut, BytesMut};
+use pricelevel::{OrderId, Side, TimeInForce};
+use std::io;
+use std::marker::PhantomData;
+use uuid::Uuid;
+
+use crate::TerminalReason;
+use crate::api::stream::{LiquidityRole, OrderEvent};
+
+/// Bytes of the length prefix of a frame
+pub const LENGTH_BYTES: usize = 2;
+/// Bytes of the symbol and username fields, ASCII padded with zeros
+pub const TEXT_BYTES: usize = 16;
+
+/// Message type codes, the first byte after the length prefix
+pub mod message_type {
+    pub const LOGIN: u8 = 0x01;
+    pub const HEARTBEAT: u8 = 0x02;
+    pub const LOGOUT: u8 = 0x03;
+    pub const ENTER_ORDER: u8 = 0x10;
+    pub const CANCEL_ORDER: u8 = 0x11;
+    pub const REPLACE_ORDER: u8 = 0x12;
+    pub const MASS_CANCEL: u8 = 0x13;
+    pub const LOGIN_ACCEPTED: u8 = 0x81;
+    pub const LOGIN_REJECTED: u8 = 0x82;
+    pub const EXECUTION_REPORT: u8 = 0x90;
+    pub const CANCEL_REJECTED: u8 = 0x91;
+    pub const MASS_CANCEL_ACK: u8 = 0x92;
+}
+
+/// Why an order ended, or why a request or session was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ReasonCode {
+    None = 0,
+    Filled = 1,
+    Cancelled = 2,
+    NoLiquidity = 3,
+    WouldCross = 4,
+    Expired = 5,
+    Rejected = 6,
+    UnknownSymbol = 7,
+    InvalidOrder = 8,
+    UnknownOrder = 9,
+    NotLive = 10,
+    UnknownUser = 11,
+    AlreadyLoggedOn = 12,
+    ReplayUnavailable = 13,
+    HeartbeatTimeout = 14,
+}
+
+impl ReasonCode {
+    fn from_u8(code: u8) -> io::Result<Self> {
+        const CODES: [ReasonCode; 15] = [
+            ReasonCode::None,
+            ReasonCode::Filled,
+            ReasonCode::Cancelled,
+            ReasonCode::NoLiquidity,
+            ReasonCode::WouldCross,
+            ReasonCode::Expired,
+            ReasonCode::Rejected,
+            ReasonCode::UnknownSymbol,
+            ReasonCode::InvalidOrder,
+            ReasonCode::UnknownOrder,
+            ReasonCode::NotLive,
+            ReasonCode::UnknownUser,
+            ReasonCode::AlreadyLoggedOn,
+            ReasonCode::ReplayUnavailable,
+            ReasonCode::HeartbeatTimeout,
+        ];
+        CODES
+            .get(code as usize)
+            .copied()
+            .ok_or_else(|| invalid(format!("unknown reason code {}", code)))
+    }
+}
+
+impl From<TerminalReason> for ReasonCode {
+    fn from(reason: TerminalReason) -> Self {
+        match reason {
+            TerminalReason::Filled => ReasonCode::Filled,
+            TerminalReason::Cancelled => ReasonCode::Cancelled,
+            TerminalReason::NoLiquidity => ReasonCode::NoLiquidity,
+            TerminalReason::WouldCross => ReasonCode::WouldCross,
+            TerminalReason::Expired => ReasonCode::Expired,
+            TerminalReason::Rejected => ReasonCode::Rejected,
+        }
+    }
+}
+
+/// How an order executes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderKind {
+    Limit,
+    Market,
+    PostOnly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnterOrder {
+    /// Identifier chosen by the client, unique among the live orders of its account
+    pub client_order_id: u64,
+    pub symbol: String,
+    pub side: Side,
+    pub kind: OrderKind,
+    pub time_in_force: TimeInForce,
+    /// Limit price, ignored for market orders
+    pub price: u64,
+    pub quantity: u64,
+}
+
+/// Amend the price and quantity of a live order, which keeps its client order id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplaceOrder {
+    pub client_order_id: u64,
+    pub symbol: String,
+    /// New price, 0 to keep the current one
+    pub price: u64,
+    /// New total quantity, including what was already executed
+    pub quantity: u64,
+}
+
+/// Messages of the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientMessage {
+    /// First message of a session. `from_sequence` asks for the execution reports from that
+    /// notification on, 0 for new ones only.
+    Login {
+        username: String,
+        from_sequence: u64,
+    },
+    Heartbeat,
+    Logout,
+    EnterOrder(EnterOrder),
+    CancelOrder {
+        client_order_id: u64,
+        symbol: String,
+    },
+    ReplaceOrder(ReplaceOrder),
+    /// Cancel the live orders of the account on `symbol`, or on every book
+    MassCancel {
+        request_id: u64,
+        symbol: Option<String>,
+    },
+}
+
+/// Change of an order, or one of its fills
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionReport {
+    /// Account notification the report comes from, 0 for orders refused before reaching a book.
+    /// The reports of the fills of one notification share its sequence.
+    pub sequence: u64,
+    /// Client order id, 0 for orders whose id is not a number
+    pub client_order_id: u64,
+    pub order_id: OrderId,
+    pub symbol: String,
+    pub event: OrderEvent,
+    pub side: Side,
+    /// Side of the fill, `None` for reports without one
+    pub role: Option<LiquidityRole>,
+    pub reason: ReasonCode,
+    /// Limit price, 0 for market orders
+    pub price: u64,
+    /// Total quantity of the order
+    pub quantity: u64,
+    pub last_price: u64,
+    pub last_quantity: u64,
+    pub cumulative_quantity: u64,
+    pub remaining_quantity: u64,
+    /// Trade of the fill, nil for reports without one
+    pub trade_id: Uuid,
+    /// Milliseconds since the epoch
+    pub timestamp: u64,
+}
+
+/// Messages of the server
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerMessage {
+    /// `next_sequence` is the notification the next report of the account will carry; it is
+    /// only meaningful within `epoch`
+    LoginAccepted {
+        epoch: u64,
+        next_sequence: u64,
+    },
+    LoginRejected {
+        reason: ReasonCode,
+    },
+    Heartbeat,
+    Logout {
+        reason: ReasonCode,
+    },
+    ExecutionReport(ExecutionReport),
+    CancelRejected {
+        client_order_id: u64,
+        reason: ReasonCode,
+    },
+    MassCancelAck {
+        request_id: u64,
+        cancelled: u32,
+    },
+}
+
+/// A message with a fixed binary layout
+pub trait WireMessage: Sized {
+    /// Message type code
+    fn message_type(&self) -> u8;
+    /// Append the body of the message
+    fn encode_body(&self, buffer: &mut BytesMut) -> io::Result<()>;
+    /// Decode a body of message type `message_type`
+    fn decode_body(message_type: u8, body: &[u8]) -> io::Result<Self>;
+}
+
+pub(crate) fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+pub(crate) fn put_text(buffer: &mut BytesMut, text: &str) -> io::Result<()> {
+    if text.len() > TEXT_BYTES || !text.is_ascii() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} is not ASCII of at most {} bytes", text, TEXT_BYTES),
+        ));
+    }
+    buffer.put_slice(text.as_bytes());
+    buffer.put_bytes(0, TEXT_BYTES - text.len());
+    Ok(())
+}
+
+pub(crate) fn get_text(body: &mut &[u8]) -> io::Result<String> {
+    let field = &body[..TEXT_BYTES];
+    body.advance(TEXT_BYTES);
+    let end = field
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(TEXT_BYTES);
+    std::str::from_utf8(&field[..end])
+        .ok()
+        .filter(|text| text.is_ascii())
+        .map(str::to_string)
+        .ok_or_else(|| invalid("text field is not ASCII"))
+}
+
+pub(crate) fn get_uuid(body: &mut &[u8]) -> Uuid {
+    let mut bytes = [0; 16];
+    body.copy_to_slice(&mut bytes);
+    Uuid::from_bytes(bytes)
+}
+
+pub(crate) fn side_code(side: Side) -> u8 {
+    match side {
+        Side::Buy => 1,
+        Side::Sell => 2,
+    }
+}
+
+pub(crate) fn get_side(body: &mut &[u8]) -> io::Result<Side> {
+    match body.get_u8() {
+        1 => Ok(Side::Buy),
+        2 => Ok(Side::Sell),
+        code => Err(invalid(format!("unknown side {}", code))),
+    }
+}
+
+fn time_in_force_code(time_in_force: TimeInForce) -> io::Result<u8> {
+    match time_in_force {
+        TimeInForce::Gtc => Ok(0),
+        TimeInForce::Ioc => Ok(1),
+        TimeInForce::Fok => Ok(2),
+        TimeInForce::Day => Ok(3),
+        other => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("time in force {:?} is not supported", other),
+        )),
+    }
+}
+
+fn get_time_in_force(body: &mut &[u8]) -> io::Result<TimeInForce> {
+    match body.get_u8() {
+        0 => Ok(TimeInForce::Gtc),
+        1 => Ok(TimeInForce::Ioc),
+        2 => Ok(TimeInForce::Fok),
+        3 => Ok(TimeInForce::Day),
+        code => Err(invalid(format!("unknown time in force {}", code))),
+    }
+}
+
+fn event_code(event: OrderEvent) -> u8 {
+    match event {
+        OrderEvent::Accepted => 0,
+        OrderEvent::Replaced => 1,
+        OrderEvent::PartiallyFilled => 2,
+        OrderEvent::Filled => 3,
+        OrderEvent::Cancelled => 4,
+        OrderEvent::Expired => 5,
+        OrderEvent::Rejected => 6,
+    }
+}
+
+fn get_event(body: &mut &[u8]) -> io::Result<OrderEvent> {
+    match body.get_u8() {
+        0 => Ok(OrderEvent::Accepted),
+        1 => Ok(OrderEvent::Replaced),
+        2 => Ok(OrderEvent::PartiallyFilled),
+        3 => Ok(OrderEvent::Filled),
+        4 => Ok(OrderEvent::Cancelled),
+        5 => Ok(OrderEvent::Expired),
+        6 => Ok(OrderEvent::Rejected),
+        code => Err(invalid(format!("unknown order event {}", code))),
+    }
+}
+
+/// Bytes of the body of each message type, `None` for unknown types
+pub fn body_length(message_type: u8) -> Option<usize> {
+    Some(match message_type {
+        message_type::LOGIN => TEXT_BYTES + 8,
+        message_type::HEARTBEAT => 0,
+        message_type::LOGOUT => 1,
+        message_type::ENTER_ORDER => 8 + TEXT_BYTES + 3 + 8 + 8,
+        message_type::CANCEL_ORDER => 8 + TEXT_BYTES,
+        message_type::REPLACE_ORDER => 8 + TEXT_BYTES + 8 + 8,
+        message_type::MASS_CANCEL => 8 + TEXT_BYTES,
+        message_type::LOGIN_ACCEPTED => 8 + 8,
+        message_type::LOGIN_REJECTED => 1,
+        message_type::EXECUTION_REPORT => 8 + 8 + 16 + TEXT_BYTES + 4 + 6 * 8 + 16 + 8,
+        message_type::CANCEL_REJECTED => 8 + 1,
+        message_type::MASS_CANCEL_ACK => 8 + 4,
+        _ => return None,
+    })
+}
+
+impl WireMessage for ClientMessage {
+    fn message_type(&self) -> u8 {
+        match self {
+            ClientMessage::Login { .. } => message_type::LOGIN,
+            ClientMessage::Heartbeat => message_type::HEARTBEAT,
+            ClientMessage::Logout => message_type::LOGOUT,
+            ClientMessage::EnterOrder(_) => message_type::ENTER_ORDER,
+            ClientMessage::CancelOrder { .. } => message_type::CANCEL_ORDER,
+            ClientMessage::ReplaceOrder(_) => message_type::REPLACE_ORDER,
+            ClientMessage::MassCancel { .. } => message_type::MASS_CANCEL,
+        }
+    }
+
+    fn encode_body(&self, buffer: &mut BytesMut) -> io::Result<()> {
+        match self {
+            ClientMessage::Login {
+                username,
+                from_sequence,
+            } => {
+                put_text(buffer, username)?;
+                buffer.put_u64_le(*from_sequence);
+            }
+            ClientMessage::Heartbeat => {}
+            ClientMessage::Logout => buffer.put_u8(ReasonCode::None as u8),
+            ClientMessage::EnterOrder(order) => {
+                buffer.put_u64_le(order.client_order_id);
+                put_text(buffer, &order.symbol)?;
+                buffer.put_u8(side_code(order.side));
+                buffer.put_u8(match order.kind {
+                    OrderKind::Limit => 0,
+                    OrderKind::Market => 1,
+                    OrderKind::PostOnly => 2,
+                });
+                buffer.put_u8(time_in_force_code(order.time_in_force)?);
+                buffer.put_u64_le(order.price);
+                buffer.put_u64_le(order.quantity);
+            }
+            ClientMessage::CancelOrder {
+                client_order_id,
+                symbol,
+            } => {
+                buffer.put_u64_le(*client_order_id);
+                put_text(buffer, symbol)?;
+            }
+            ClientMessage::ReplaceOrder(replace) => {
+                buffer.put_u64_le(replace.client_order_id);
+                put_text(buffer, &replace.symbol)?;
+                buffer.put_u64_le(replace.price);
+                buffer.put_u64_le(replace.quantity);
+            }
+            ClientMessage::MassCancel { request_id, symbol } => {
+                buffer.put_u64_le(*request_id);
+                put_text(buffer, symbol.as_deref().unwrap_or_default())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_body(message_type: u8, mut body: &[u8]) -> io::Result<Self> {
+        let body = &mut body;
+        Ok(match message_type {
+            message_type::LOGIN => ClientMessage::Login {
+                username: get_text(body)?,
+                from_sequence: body.get_u64_le(),
+            },
+            message_type::HEARTBEAT => ClientMessage::Heartbeat,
+            message_type::LOGOUT => ClientMessage::Logout,
+            message_type::ENTER_ORDER => {
+                let client_order_id = body.get_u64_le();
+                let symbol = get_text(body)?;
+                let side = get_side(body)?;
+                let kind = match body.get_u8() {
+                    0 => OrderKind::Limit,
+                    1 => OrderKind::Market,
+                    2 => OrderKind::PostOnly,
+                    code => return Err(invalid(format!("unknown order kind {}", code))),
+                };
+                let time_in_force = get_time_in_force(body)?;
+                ClientMessage::EnterOrder(EnterOrder {
+                    client_order_id,
+                    symbol,
+                    side,
+                    kind,
+                    time_in_force,
+                    price: body.get_u64_le(),
+                    quantity: body.get_u64_le(),
+                })
+            }
+            message_type::CANCEL_ORDER => ClientMessage::CancelOrder {
+                client_order_id: body.get_u64_le(),
+                symbol: get_text(body)?,
+            },
+            message_type::REPLACE_ORDER => ClientMessage::ReplaceOrder(ReplaceOrder {
+                client_order_id: body.get_u64_le(),
+                symbol: get_text(body)?,
+                price: body.get_u64_le(),
+                quantity: body.get_u64_le(),
+            }),
+            message_type::MASS_CANCEL => {
+                let request_id = body.get_u64_le();
+                let symbol = get_text(body)?;
+                ClientMessage::MassCancel {
+                    request_id,
+                    symbol: (!symbol.is_empty()).then_some(symbol),
+                }
+            }
+            other => {
+                return Err(invalid(format!(
+                    "unexpected message type {:#04x} from a client",
+                    other
+                )));
+            }
+        })
+    }
+}
+
+impl WireMessage for ServerMessage {
+    fn message_type(&self) -> u8 {
+        match self {
+            ServerMessage::LoginAccepted { .. } => message_type::LOGIN_ACCEPTED,
+            ServerMessage::LoginRejected { .. } => message_type::LOGIN_REJECTED,
+            ServerMessage::Heartbeat => message_type::HEARTBEAT,
+            ServerMessage::Logout { .. } => message_type::LOGOUT,
+            ServerMessage::ExecutionReport(_) => message_type::EXECUTION_REPORT,
+            ServerMessage::CancelRejected { .. } => message_type::CANCEL_REJECTED,
+            ServerMessage::MassCancelAck { .. } => message_type::MASS_CANCEL_ACK,
+        }
+    }
+
+    fn encode_body(&self, buffer: &mut BytesMut) -> io::Result<()> {
+        match self {
+            ServerMessage::LoginAccepted {
+                epoch,
+                next_sequence,
+            } => {
+                buffer.put_u64_le(*epoch);
+                buffer.put_u64_le(*next_sequence);
+            }
+            ServerMessage::LoginRejected { reason } | ServerMessage::Logout { reason } => {
+                buffer.put_u8(*reason as u8)
+            }
+            ServerMessage::Heartbeat => {}
+            ServerMessage::ExecutionReport(report) => {
+                buffer.put_u64_le(report.sequence);
+                buffer.put_u64_le(report.client_order_id);
+                buffer.put_slice(report.order_id.0.as_bytes());
+                put_text(buffer, &report.symbol)?;
+                buffer.put_u8(event_code(report.event));
+                buffer.put_u8(side_code(report.side));
+                buffer.put_u8(match report.role {
+                    None => 0,
+                    Some(LiquidityRole::Maker) => 1,
+                    Some(LiquidityRole::Taker) => 2,
+                });
+                buffer.put_u8(report.reason as u8);
+                for value in [
+                    report.price,
+                    report.quantity,
+                    report.last_price,
+                    report.last_quantity,
+                    report.cumulative_quantity,
+                    report.remaining_quantity,
+                ] {
+                    buffer.put_u64_le(value);
+                }
+                buffer.put_slice(report.trade_id.as_bytes());
+                buffer.put_u64_le(report.timestamp);
+            }
+            ServerMessage::CancelRejected {
+                client_order_id,
+                reason,
+            } => {
+                buffer.put_u64_le(*client_order_id);
+                buffer.put_u8(*reason as u8);
+            }
+            ServerMessage::MassCancelAck {
+                request_id,
+                cancelled,
+            } => {
+                buffer.put_u64_le(*request_id);
+                buffer.put_u32_le(*cancelled);
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_body(message_type: u8, mut body: &[u8]) -> io::Result<Self> {
+        let body = &mut body;
+        Ok(match message_type {
+            message_type::LOGIN_ACCEPTED => ServerMessage::LoginAccepted {
+                epoch: body.get_u64_le(),
+                next_sequence: body.get_u64_le(),
+            },
+            message_type::LOGIN_REJECTED => ServerMessage::LoginRejected {
+                reason: ReasonCode::from_u8(body.get_u8())?,
+            },
+            message_type::HEARTBEAT => ServerMessage::Heartbeat,
+            message_type::LOGOUT => ServerMessage::Logout {
+                reason: ReasonCode::from_u8(body.get_u8())?,
+            },
+            message_type::EXECUTION_REPORT => {
+                let sequence = body.get_u64_le();
+                let client_order_id = body.get_u64_le();
+                let order_id = OrderId(get_uuid(body));
+                let symbol = get_text(body)?;
+                let event = get_event(body)?;
+                let side = get_side(body)?;
+                let role = match body.get_u8() {
+                    0 => None,
+                    1 => Some(LiquidityRole::Maker),
+                    2 => Some(LiquidityRole::Taker),
+                    code => return Err(invalid(format!("unknown liquidity role {}", code))),
+                };
+                let reason = ReasonCode::from_u8(body.get_u8())?;
+                ServerMessage::ExecutionReport(ExecutionReport {
+                    sequence,
+                    client_order_id,
+                    order_id,
+                    symbol,
+                    event,
+                    side,
+                    role,
+                    reason,
+                    price: body.get_u64_le(),
+                    quantity: body.get_u64_le(),
+                    last_price: body.get_u64_le(),
+                    last_quantity: body.get_u64_le(),
+                    cumulative_quantity: body.get_u64_le(),
+                    remaining_quantity: body.get_u64_le(),
+                    trade_id: get_uuid(body),
+                    timestamp: body.get_u64_le(),
+                })
+            }
+            message_type::CANCEL_REJECTED => ServerMessage::CancelRejected {
+                client_order_id: body.get_u64_le(),
+                reason: ReasonCode::from_u8(body.get_u8())?,
+            },
+            message_type::MASS_CANCEL_ACK => ServerMessage::MassCancelAck {
+                request_id: body.get_u64_le(),
+                cancelled: body.get_u32_le(),
+            },
+            other => {
+                return Err(invalid(format!(
+                    "unexpected message type {:#04x} from the server",
+                    other
+                )));
+            }
+        })
+    }
+}
+
+/// Frames binary messages: a little-endian `u16` length of the rest of the frame, the message
+/// type, then the fixed body of that type. Decodes `D` and encodes any [`WireMessage`].
+/// A frame of unknown type or of the wrong length is an error that ends the connection.
+#[derive(Debug)]
+pub struct BinaryCodec<D> {
+    decoded: PhantomData<fn() -> D>,
+}
+
+impl<D> BinaryCodec<D> {
+    pub fn new() -> Self {
+        Self {
+            decoded: PhantomData,
+        }
+    }
+}
+
+impl<D> Default for BinaryCodec<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: WireMessage> Decoder for BinaryCodec<D> {
+    type Item = D;
+    type Error = io::Error;
+
+    fn decode(&mut self, buffer: &mut BytesMut) -> Result<Option<D>, io::Error> {
+        if buffer.len() < LENGTH_BYTES + 1 {
+            return Ok(None);
+        }
+        let length = u16::from_le_bytes([buffer[0], buffer[1]]) as usize;
+        let message_type = buffer[LENGTH_BYTES];
+        if body_length(message_type).is_none_or(|body| body + 1 != length) {
+            return Err(invalid(format!(
+                "invalid frame of type {:#04x} and length {}",
+                message_type, length
+            )));
+        }
+        if buffer.len() < LENGTH_BYTES + length {
+            buffer.reserve(LENGTH_BYTES + length - buffer.len());
+            return Ok(None);
+        }
+        let frame = buffer.split_to(LENGTH_BYTES + length);
+        D::decode_body(message_type, &frame[LENGTH_BYTES + 1..]).map(Some)
+    }
+}
+
+impl<D, E: WireMessage> Encoder<E> for BinaryCodec<D> {
+    type Error = io::Error;
+
+    fn encode(&mut self, message: E, buffer: &mut BytesMut) -> Result<(), io::Error> {
+        let message_type = message.message_type();
+        let length = body_length(message_type).expect("every message type has a body length") + 1;
+        let frame_start = buffer.len();
+        buffer.reserve(LENGTH_BYTES + length);
+        buffer.put_u16_le(length as u16);
+        buffer.put_u8(message_type);
+        if let Err(e) = message.encode_body(buffer) {
+            // Leave no partial frame behind
+            buffer.truncate(frame_start);
+            return Err(e);
+        }
+        debug_assert_eq!(
+            buffer.len() - frame_start,
+            LENGTH_BYTES + length,
+            "body of message type {:#04x}",
+            message_type
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,525 @@
+use actix_codec::Framed;
+use futures_util::{SinkExt, StreamExt};
+use pricelevel::{OrderId, OrderType, OrderUpdate};
+use std::collections::HashSet;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use super::BinaryGateway;
+use super::protocol::{
+    BinaryCodec, ClientMessage, EnterOrder, ExecutionReport, OrderKind, ReasonCode, ReplaceOrder,
+    ServerMessage,
+};
+use crate::api::orders::{self, NewOrder, SubmitError};
+use crate::api::persistence::NewOrderRow;
+use crate::api::stream::{OrderEvent, OrderNotification, UserStreamSession};
+use crate::{OrderOwner, Sequencer};
+
+/// Removes the username from the logged on ones when the connection ends
+struct LoginGuard {
+    gateway: Arc<BinaryGateway>,
+    username: String,
+}
+
+impl Drop for LoginGuard {
+    fn drop(&mut self) {
+        self.gateway
+            .logged_on
+            .lock()
+            .unwrap()
+            .remove(&self.username);
+    }
+}
+
+/// A logged on session
+struct Session {
+    gateway: Arc<BinaryGateway>,
+    framed: Framed<TcpStream, BinaryCodec<ClientMessage>>,
+    account: Uuid,
+    notifications: UserStreamSession,
+    /// Orders entered by this session and not reported yet
+    unacknowledged: HashSet<OrderId>,
+    last_sent: Instant,
+    last_received: Instant,
+    _login: LoginGuard,
+}
+
+/// Serve a connection: wait for its Login, then run the session until it logs out or the
+/// connection drops
+pub(super) async fn serve_connection(
+    gateway: Arc<BinaryGateway>,
+    stream: TcpStream,
+    peer: SocketAddr,
+) {
+    let mut framed = Framed::new(stream, BinaryCodec::<ClientMessage>::new());
+    let (username, from_sequence) =
+        match tokio::time::timeout(gateway.config.login_timeout, framed.next()).await {
+            Ok(Some(Ok(ClientMessage::Login {
+                username,
+                from_sequence,
+            }))) => (username, from_sequence),
+            _ => {
+                warn!("Order entry connection {} closed without logging in", peer);
+                return;
+            }
+        };
+    let Some(account) = gateway.config.sessions.get(&username).copied() else {
+        warn!(
+            "Order entry connection {} refused: unknown user {:?}",
+            peer, username
+        );
+        let _ = framed
+            .send(ServerMessage::LoginRejected {
+                reason: ReasonCode::UnknownUser,
+            })
+            .await;
+        return;
+    };
+    if !gateway.logged_on.lock().unwrap().insert(username.clone()) {
+        warn!(
+            "Order entry connection {} refused: {} is already logged in",
+            peer, username
+        );
+        let _ = framed
+            .send(ServerMessage::LoginRejected {
+                reason: ReasonCode::AlreadyLoggedOn,
+            })
+            .await;
+        return;
+    }
+
+    let now = Instant::now();
+    let mut session = Session {
+        notifications: gateway
+            .user_streams
+            .open_session(account, (from_sequence > 0).then_some(from_sequence)),
+        _login: LoginGuard {
+            gateway: gateway.clone(),
+            username: username.clone(),
+        },
+        gateway,
+        framed,
+        account,
+        unacknowledged: HashSet::new(),
+        last_sent: now,
+        last_received: now,
+    };
+    info!(
+        "Order entry session {} logged in from {} for account {}",
+        username, peer, account
+    );
+    if let Err(e) = session.run().await {
+        warn!("Order entry session {} failed: {}", username, e);
+    }
+    info!("Order entry session {} from {} ended", username, peer);
+}
+
+impl Session {
+    async fn run(&mut self) -> io::Result<()> {
+        let accepted = ServerMessage::LoginAccepted {
+            epoch: self.notifications.epoch(),
+            next_sequence: self.notifications.next_sequence(),
+        };
+        self.send(accepted).await?;
+        let mut ticker = tokio::time::interval(self.gateway.config.heartbeat_interval / 4);
+        loop {
+            tokio::select! {
+                frame = self.framed.next() => {
+                    let message = match frame {
+                        Some(Ok(message)) => message,
+                        Some(Err(e)) => return Err(e),
+                        None => return Ok(()),
+                    };
+                    self.last_received = Instant::now();
+                    if !self.on_message(message).await? {
+                        return Ok(());
+                    }
+                }
+                batch = self.notifications.next_batch() => match batch {
+                    Ok(batch) => {
+                        for notification in &batch {
+                            for report in self.execution_reports(notification) {
+                                self.framed.feed(ServerMessage::ExecutionReport(report)).await?;
+                            }
+                        }
+                        self.flush().await?;
+                    }
+                    Err(gap) => {
+                        warn!(
+                            "Order entry session of account {} asked for notifications from {}, the oldest kept is {}",
+                            self.account, gap.from_sequence, gap.first_available
+                        );
+                        self.send(ServerMessage::Logout {
+                            reason: ReasonCode::ReplayUnavailable,
+                        })
+                        .await?;
+                        return Ok(());
+                    }
+                },
+                _ = ticker.tick() => {
+                    let now = Instant::now();
+                    if now - self.last_received >= self.gateway.config.idle_timeout {
+                        self.send(ServerMessage::Logout {
+                            reason: ReasonCode::HeartbeatTimeout,
+                        })
+                        .await?;
+                        return Ok(());
+                    }
+                    if now - self.last_sent >= self.gateway.config.heartbeat_interval {
+                        self.send(ServerMessage::Heartbeat).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Handle a message of the client. Returns false once the session is over.
+    async fn on_message(&mut self, message: ClientMessage) -> io::Result<bool> {
+        let response = match message {
+            ClientMessage::Heartbeat => None,
+            ClientMessage::Logout => {
+                self.send(ServerMessage::Logout {
+                    reason: ReasonCode::None,
+                })
+                .await?;
+                return Ok(false);
+            }
+            ClientMessage::Login { .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "already logged in",
+                ));
+            }
+            ClientMessage::EnterOrder(order) => self.enter_order(order).await,
+            ClientMessage::CancelOrder {
+                client_order_id,
+                symbol,
+            } => self.cancel_order(client_order_id, &symbol).await,
+            ClientMessage::ReplaceOrder(replace) => self.replace_order(replace).await,
+            ClientMessage::MassCancel { request_id, symbol } => {
+                Some(self.mass_cancel(request_id, symbol.as_deref()).await)
+            }
+        };
+        if let Some(response) = response {
+            self.send(response).await?;
+        }
+        Ok(true)
+    }
+
+    fn sequencer(&self, symbol: &str) -> Option<Arc<Sequencer>> {
+        self.gateway
+            .sequencers
+            .get(symbol)
+            .map(|item| item.value().clone())
+    }
+
+    async fn enter_order(&mut self, order: EnterOrder) -> Option<ServerMessage> {
+        let id = OrderId(Uuid::new_v4());
+        let market = order.kind == OrderKind::Market;
+        let price = (!market).then_some(order.price);
+        let reject = |reason: ReasonCode| {
+            let report = ExecutionReport {
+                sequence: 0,
+                client_order_id: order.client_order_id,
+                order_id: id,
+                symbol: order.symbol.clone(),
+                event: OrderEvent::Rejected,
+                side: order.side,
+                role: None,
+                reason,
+                price: order.price,
+                quantity: order.quantity,
+                last_price: 0,
+                last_quantity: 0,
+                cumulative_quantity: 0,
+                remaining_quantity: 0,
+                trade_id: Uuid::nil(),
+                timestamp: crate::current_time_millis(),
+            };
+            Some(ServerMessage::ExecutionReport(report))
+        };
+        if order.quantity == 0 || price == Some(0) {
+            return reject(ReasonCode::InvalidOrder);
+        }
+        let Some(sequencer) = self.sequencer(&order.symbol) else {
+            return reject(ReasonCode::UnknownSymbol);
+        };
+
+        let EnterOrder {
+            client_order_id,
+            side,
+            quantity,
+            time_in_force,
+            ..
+        } = order;
+        let timestamp = sequencer.book().clock().now_millis();
+        let row = NewOrderRow {
+            id: id.0,
+            symbol: order.symbol.clone(),
+            side: format!("{:?}", side),
+            order_type: format!("{:?}", order.kind),
+            quantity,
+            price,
+            time_in_force: format!("{:?}", time_in_force),
+            user_id: self.account,
+            client_order_id: Some(client_order_id.to_string()),
+            visible_quantity: None,
+            hidden_quantity: None,
+        };
+        let order = match (order.kind, price) {
+            (OrderKind::PostOnly, Some(price)) => Some(OrderType::PostOnly {
+                id,
+                price,
+                quantity,
+                side,
+                timestamp,
+                time_in_force,
+            }),
+            (_, Some(price)) => Some(OrderType::Standard {
+                id,
+                price,
+                quantity,
+                side,
+                timestamp,
+                time_in_force,
+            }),
+            (_, None) => None,
+        };
+        self.unacknowledged.insert(id);
+        let owner = OrderOwner::new(self.account, Some(client_order_id.to_string()));
+        let gateway = &self.gateway;
+        match orders::submit(
+            &sequencer,
+            &gateway.outbox,
+            &gateway.user_streams,
+            NewOrder {
+                row,
+                order,
+                side,
+                owner,
+                timestamp,
+            },
+        )
+        .await
+        {
+            Err(SubmitError::NotRecorded(e)) => {
+                warn!("Failed to record order {}: {}", id, e);
+                self.unacknowledged.remove(&id);
+                return reject(ReasonCode::Rejected);
+            }
+            Err(SubmitError::Rejected(_)) => {
+                self.unacknowledged.remove(&id);
+            }
+            Ok(_) | Err(SubmitError::Failed(_)) => {}
+        }
+        None
+    }
+
+    /// The live order of the account with `client_order_id` on `symbol`
+    fn find_order(
+        &self,
+        client_order_id: u64,
+        symbol: &str,
+    ) -> Result<(Arc<Sequencer>, OrderId), ReasonCode> {
+        let sequencer = self.sequencer(symbol).ok_or(ReasonCode::UnknownSymbol)?;
+        let order_id = sequencer
+            .book()
+            .find_order_id(self.account, &client_order_id.to_string())
+            .ok_or(ReasonCode::UnknownOrder)?;
+        Ok((sequencer, order_id))
+    }
+
+    async fn cancel_order(&mut self, client_order_id: u64, symbol: &str) -> Option<ServerMessage> {
+        let reason = match self.find_order(client_order_id, symbol) {
+            Ok((sequencer, order_id)) => match sequencer.cancel_order_async(order_id).await {
+                Ok(Some(_)) => return None,
+                Ok(None) => ReasonCode::NotLive,
+                Err(_) => ReasonCode::Rejected,
+            },
+            Err(reason) => reason,
+        };
+        Some(ServerMessage::CancelRejected {
+            client_order_id,
+            reason,
+        })
+    }
+
+    async fn replace_order(&mut self, replace: ReplaceOrder) -> Option<ServerMessage> {
+        let client_order_id = replace.client_order_id;
+        let reason = match self.find_order(client_order_id, &replace.symbol) {
+            Ok((sequencer, order_id)) => {
+                // The quantity is the new total, the book amends the quantity left to execute
+                let filled = sequencer
+                    .book()
+                    .order_state(order_id)
+                    .map_or(0, |record| record.filled_quantity);
+                match replace
+                    .quantity
+                    .checked_sub(filled)
+                    .filter(|remaining| *remaining > 0)
+                {
+                    Some(new_quantity) => {
+                        let update = match replace.price {
+                            0 => OrderUpdate::UpdateQuantity {
+                                order_id,
+                                new_quantity,
+                            },
+                            new_price => OrderUpdate::UpdatePriceAndQuantity {
+                                order_id,
+                                new_price,
+                                new_quantity,
+                            },
+                        };
+                        match sequencer.update_order_async(update).await {
+                            Ok(Some(_)) => return None,
+                            Ok(None) => ReasonCode::NotLive,
+                            Err(_) => ReasonCode::Rejected,
+                        }
+                    }
+                    None => ReasonCode::InvalidOrder,
+                }
+            }
+            Err(reason) => reason,
+        };
+        Some(ServerMessage::CancelRejected {
+            client_order_id,
+            reason,
+        })
+    }
+
+    async fn mass_cancel(&mut self, request_id: u64, symbol: Option<&str>) -> ServerMessage {
+        let sequencers: Vec<Arc<Sequencer>> = match symbol {
+            Some(symbol) => match self.sequencer(symbol) {
+                Some(sequencer) => vec![sequencer],
+                None => {
+                    return ServerMessage::CancelRejected {
+                        client_order_id: request_id,
+                        reason: ReasonCode::UnknownSymbol,
+                    };
+                }
+            },
+            None => self
+                .gateway
+                .sequencers
+                .iter()
+                .map(|item| item.value().clone())
+                .collect(),
+        };
+        let mut cancelled = 0;
+        for sequencer in sequencers {
+            match sequencer.cancel_account_orders_async(self.account).await {
+                Ok(orders) => cancelled += orders.len() as u32,
+                Err(e) => {
+                    warn!(
+                        "Mass cancel {} of account {} failed on {}: {}",
+                        request_id,
+                        self.account,
+                        sequencer.book().symbol(),
+                        e
+                    );
+                    return ServerMessage::CancelRejected {
+                        client_order_id: request_id,
+                        reason: ReasonCode::Rejected,
+                    };
+                }
+            }
+        }
+        ServerMessage::MassCancelAck {
+            request_id,
+            cancelled,
+        }
+    }
+
+    /// Execution reports for a notification of the account: one per fill, then one for the
+    /// change of status if it is not a fill. Orders of this session whose first notification
+    /// is a fill or an end are acknowledged first.
+    fn execution_reports(&mut self, n: &OrderNotification) -> Vec<ExecutionReport> {
+        let base = ExecutionReport {
+            sequence: n.sequence,
+            client_order_id: n
+                .client_order_id
+                .as_deref()
+                .and_then(|id| id.parse().ok())
+                .unwrap_or(0),
+            order_id: n.order_id,
+            symbol: n.symbol.clone(),
+            event: n.event,
+            side: n.side,
+            role: None,
+            reason: n.reason.map_or(ReasonCode::None, ReasonCode::from),
+            price: n.price.unwrap_or(0),
+            quantity: n.quantity,
+            last_price: 0,
+            last_quantity: 0,
+            cumulative_quantity: n.cumulative_quantity,
+            remaining_quantity: n.remaining_quantity,
+            trade_id: Uuid::nil(),
+            timestamp: n.timestamp,
+        };
+
+        let mut reports = Vec::new();
+        let mut cumulative = n.cumulative_quantity
+            - n.fills
+                .iter()
+                .map(|fill| fill.quantity)
+                .sum::<u64>()
+                .min(n.cumulative_quantity);
+        if self.unacknowledged.remove(&n.order_id)
+            && !matches!(n.event, OrderEvent::Accepted | OrderEvent::Rejected)
+        {
+            let remaining_quantity = n.quantity.saturating_sub(cumulative);
+            reports.push(ExecutionReport {
+                event: OrderEvent::Accepted,
+                reason: ReasonCode::None,
+                cumulative_quantity: cumulative,
+                remaining_quantity,
+                ..base.clone()
+            });
+        }
+
+        let fill_ends_report = matches!(n.event, OrderEvent::PartiallyFilled | OrderEvent::Filled);
+        for (index, fill) in n.fills.iter().enumerate() {
+            cumulative += fill.quantity;
+            let last = fill_ends_report && index + 1 == n.fills.len();
+            reports.push(ExecutionReport {
+                event: if last {
+                    n.event
+                } else {
+                    OrderEvent::PartiallyFilled
+                },
+                role: Some(fill.role),
+                reason: if last { base.reason } else { ReasonCode::None },
+                last_price: fill.price,
+                last_quantity: fill.quantity,
+                cumulative_quantity: cumulative,
+                remaining_quantity: if last {
+                    n.remaining_quantity
+                } else {
+                    n.quantity.saturating_sub(cumulative)
+                },
+                trade_id: fill.trade_id,
+                timestamp: fill.timestamp,
+                ..base.clone()
+            });
+        }
+        if !fill_ends_report || n.fills.is_empty() {
+            reports.push(base);
+        }
+        reports
+    }
+
+    async fn send(&mut self, message: ServerMessage) -> io::Result<()> {
+        self.last_sent = Instant::now();
+        self.framed.send(message).await
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        self.last_sent = Instant::now();
+        SinkExt::<ServerMessage>::flush(&mut self.framed).await
+    }
+}
@@ -2,6 +2,11 @@ use deadpool_postgres::{Config, Pool, Runtime};
 use tokio_postgres::NoTls;
 use std::env;
 use url;
+use crate::{
+    BookEvent, Candle, CandleInterval, OrderOwner, OrderRecord, OrderStatus as EngineOrderStatus,
+};
+use crate::api::{models::order::OrderStatus, persistence::OutboxRecord};
+use pricelevel::{OrderId, OrderType, Side, TimeInForce};
 
 #[derive(Clone)]
 pub struct Database {
@@ -101,6 +106,14 @@ impl Database {
             &[],
         ).await?;
 
+        // Client-assigned order ids (added after the initial schema)
+        client
+            .execute(
+                "ALTER TABLE orders ADD COLUMN IF NOT EXISTS client_order_id VARCHAR(64)",
+                &[],
+            )
+            .await?;
+
         // Create trades table
         client.execute(
             r#"
@@ -117,6 +130,26 @@ impl Database {
                 created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
             )
             "#,
+                &[],
+            )
+            .await?;
+
+        // Trades of orders submitted without an account have no user
+        client.execute(
+            "ALTER TABLE trades ALTER COLUMN taker_user_id DROP NOT NULL, ALTER COLUMN maker_user_id DROP NOT NULL",
+            &[],
+        ).await?;
+
+        // Orders are taken for any authenticated account, which need not be a row of users
+        client
+            .execute(
+                "ALTER TABLE orders DROP CONSTRAINT IF EXISTS orders_user_id_fkey",
+                &[],
+            )
+            .await?;
+
+        client.execute(
+            "ALTER TABLE trades DROP CONSTRAINT IF EXISTS trades_taker_user_id_fkey, DROP CONSTRAINT IF EXISTS trades_maker_user_id_fkey",
             &[],
         ).await?;
 
@@ -137,15 +170,369 @@ impl Database {
         ).await?;
 
         client.execute(
-            "CREATE INDEX IF NOT EXISTS idx_trades_symbol ON trades(symbol)",
+            "CREATE INDEX IF NOT EXISTS idx_orders_client_order_id ON orders(user_id, client_order_id)",
             &[],
         ).await?;
 
+        client
+            .execute(
+                "CREATE INDEX IF NOT EXISTS idx_trades_symbol ON trades(symbol)",
+                &[],
+            )
+            .await?;
+
         client.execute(
             "CREATE INDEX IF NOT EXISTS idx_trades_created_at ON trades(created_at)",
             &[],
         ).await?;
 
+        // The fills of an order are looked up from both of its sides when it is reloaded
+        client.execute(
+            "CREATE INDEX IF NOT EXISTS idx_trades_taker_order_id ON trades(taker_order_id)",
+            &[],
+        ).await?;
+
+        client.execute(
+            "CREATE INDEX IF NOT EXISTS idx_trades_maker_order_id ON trades(maker_order_id)",
+            &[],
+        ).await?;
+
+        // Create candles table (times are milliseconds since epoch)
+        client
+            .execute(
+                r#"
+            CREATE TABLE IF NOT EXISTS candles (
+                symbol VARCHAR(20) NOT NULL,
+                interval VARCHAR(3) NOT NULL,
+                open_time BIGINT NOT NULL,
+                close_time BIGINT NOT NULL,
+                open BIGINT NOT NULL,
+                high BIGINT NOT NULL,
+                low BIGINT NOT NULL,
+                close BIGINT NOT NULL,
+                volume BIGINT NOT NULL,
+                quote_volume NUMERIC(39, 0) NOT NULL,
+                trade_count BIGINT NOT NULL,
+                PRIMARY KEY (symbol, interval, open_time)
+            )
+            "#,
+                &[],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Persist finished candles in a single transaction. Each interval is finished once, so
+    /// a candle that is already stored (a batch written again after a failure) is replaced.
+    pub async fn insert_candles(
+        &self,
+        symbol: &str,
+        candles: &[Candle],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let statement = tx.prepare(
+            r#"
+            INSERT INTO candles (symbol, interval, open_time, close_time, open, high, low, close, volume, quote_volume, trade_count)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10::TEXT::NUMERIC, $11)
+            ON CONFLICT (symbol, interval, open_time) DO UPDATE SET
+                close_time = EXCLUDED.close_time,
+                open = EXCLUDED.open,
+                high = EXCLUDED.high,
+                low = EXCLUDED.low,
+                close = EXCLUDED.close,
+                volume = EXCLUDED.volume,
+                quote_volume = EXCLUDED.quote_volume,
+                trade_count = EXCLUDED.trade_count
+            "#,
+        ).await?;
+        for candle in candles {
+            tx.execute(
+                &statement,
+                &[
+                    &symbol,
+                    &candle.interval.as_str(),
+                    &(candle.open_time as i64),
+                    &(candle.close_time as i64),
+                    &(candle.open as i64),
+                    &(candle.high as i64),
+                    &(candle.low as i64),
+                    &(candle.close as i64),
+                    &(candle.volume as i64),
+                    &candle.quote_volume.to_string(),
+                    &(candle.trade_count as i64),
+                ],
+            )
+            .await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
+
+    /// Stored candles of `symbol` opened in `[from, to)`, oldest first, at most the `limit` most recent
+    pub async fn get_candles(
+        &self,
+        symbol: &str,
+        interval: CandleInterval,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<Candle>, Box<dyn std::error::Error>> {
+        let client = self.pool.get().await?;
+        let rows = client.query(
+            r#"
+            SELECT * FROM (
+                SELECT open_time, close_time, open, high, low, close, volume, quote_volume::TEXT AS quote_volume, trade_count
+                FROM candles
+                WHERE symbol = $1 AND interval = $2 AND open_time >= $3 AND open_time < $4
+                ORDER BY open_time DESC
+                LIMIT $5
+            ) recent ORDER BY open_time ASC
+            "#,
+            &[&symbol, &interval.as_str(), &(from as i64), &(to.min(i64::MAX as u64) as i64), &(limit as i64)],
+        ).await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(Candle {
+                    interval,
+                    open_time: row.try_get::<_, i64>("open_time")? as u64,
+                    close_time: row.try_get::<_, i64>("close_time")? as u64,
+                    open: row.try_get::<_, i64>("open")? as u64,
+                    high: row.try_get::<_, i64>("high")? as u64,
+                    low: row.try_get::<_, i64>("low")? as u64,
+                    close: row.try_get::<_, i64>("close")? as u64,
+                    volume: row.try_get::<_, i64>("volume")? as u64,
+                    quote_volume: row.try_get::<_, String>("quote_volume")?.parse()?,
+                    trade_count: row.try_get::<_, i64>("trade_count")? as u64,
+                })
+            })
+            .collect()
+    }
+
+    /// Write a batch of outbox records in a single transaction. Every write is idempotent,
+    /// so a batch may safely be written again.
+    pub async fn persist_batch(
+        &self,
+        records: &[OutboxRecord],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let insert_order = tx.prepare(
+            r#"
+            INSERT INTO orders (id, symbol, side, order_type, quantity, price, time_in_force, status, user_id, remaining_quantity, visible_quantity, hidden_quantity, client_order_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $5, $10, $11, $12)
+            ON CONFLICT (id) DO NOTHING
+            "#,
+        ).await?;
+        let update_order = tx
+            .prepare(
+                r#"
+            UPDATE orders SET
+                quantity = $2,
+                price = COALESCE($3, price),
+                filled_quantity = $4,
+                remaining_quantity = $5,
+                status = $6,
+                updated_at = to_timestamp($7::BIGINT / 1000.0)
+            WHERE id = $1
+            "#,
+            )
+            .await?;
+        let insert_trade = tx.prepare(
+            r#"
+            INSERT INTO trades (id, symbol, price, quantity, side, taker_order_id, maker_order_id, taker_user_id, maker_user_id, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7,
+                    (SELECT user_id FROM orders WHERE id = $6),
+                    (SELECT user_id FROM orders WHERE id = $7),
+                    to_timestamp($8::BIGINT / 1000.0))
+            ON CONFLICT (id) DO NOTHING
+            "#,
+        ).await?;
+
+        for record in records {
+            match record {
+                OutboxRecord::NewOrder(order) => {
+                    tx.execute(
+                        &insert_order,
+                        &[
+                            &order.id,
+                            &order.symbol,
+                            &order.side,
+                            &order.order_type,
+                            &(order.quantity as i64),
+                            &order.price.map(|p| p as i64),
+                            &order.time_in_force,
+                            &OrderStatus::Pending.as_str(),
+                            &order.user_id,
+                            &order.visible_quantity.map(|v| v as i64),
+                            &order.hidden_quantity.map(|v| v as i64),
+                            &order.client_order_id,
+                        ],
+                    )
+                    .await?;
+                }
+                OutboxRecord::OrderRejected { order_id } => {
+                    tx.execute(
+                        "UPDATE orders SET status = $2, remaining_quantity = 0, updated_at = NOW() WHERE id = $1",
+                        &[order_id, &OrderStatus::Rejected.as_str()],
+                    ).await?;
+                }
+                OutboxRecord::Book(BookEvent::OrderUpdated { record, .. }) => {
+                    tx.execute(
+                        &update_order,
+                        &[
+                            &record.order_id.0,
+                            &(record.original_quantity as i64),
+                            &record.price.map(|p| p as i64),
+                            &(record.filled_quantity as i64),
+                            &(if record.status.is_terminal() {
+                                0
+                            } else {
+                                record.remaining_quantity() as i64
+                            }),
+                            &OrderStatus::from(record.status).as_str(),
+                            &(record.updated_at as i64),
+                        ],
+                    )
+                    .await?;
+                }
+                OutboxRecord::Book(BookEvent::Trade { symbol, trade, .. }) => {
+                    tx.execute(
+                        &insert_trade,
+                        &[
+                            &trade.transaction_id,
+                            symbol,
+                            &(trade.price as i64),
+                            &(trade.quantity as i64),
+                            &format!("{:?}", trade.taker_side),
+                            &trade.taker_order_id.0,
+                            &trade.maker_order_id.0,
+                            &(trade.timestamp as i64),
+                        ],
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Open orders of `symbol` in their original entry order, rebuilt with their remaining
+    /// quantity and their recorded state, ready to be reloaded into a book. Rows that cannot
+    /// be rebuilt are skipped and returned as errors, so that one bad row does not keep the
+    /// other orders out of the book.
+    pub async fn open_orders(
+        &self,
+        symbol: &str,
+    ) -> Result<(Vec<(OrderType, OrderRecord)>, Vec<String>), Box<dyn std::error::Error>> {
+        let client = self.pool.get().await?;
+        let rows = client.query(
+            r#"
+            SELECT id, side, order_type, quantity, price, time_in_force, status, user_id, client_order_id,
+                   filled_quantity, remaining_quantity, visible_quantity,
+                   (SELECT COALESCE(SUM(price::NUMERIC * quantity), 0) FROM trades
+                    WHERE taker_order_id = orders.id OR maker_order_id = orders.id)::TEXT AS filled_value,
+                   (EXTRACT(EPOCH FROM created_at) * 1000)::BIGINT AS created_ms,
+                   (EXTRACT(EPOCH FROM updated_at) * 1000)::BIGINT AS updated_ms
+            FROM orders
+            WHERE symbol = $1 AND status IN ($2, $3) AND remaining_quantity > 0
+            ORDER BY created_at ASC, id ASC
+            "#,
+            &[&symbol, &OrderStatus::Pending.as_str(), &OrderStatus::PartiallyFilled.as_str()],
+        ).await?;
+
+        let mut orders = Vec::with_capacity(rows.len());
+        let mut skipped = Vec::new();
+        for row in &rows {
+            match open_order(row) {
+                Ok(order) => orders.push(order),
+                Err(e) => skipped.push(format!(
+                    "order {}: {}",
+                    row.try_get::<_, uuid::Uuid>("id")
+                        .map(|id| id.to_string())
+                        .unwrap_or_else(|_| "?".to_string()),
+                    e
+                )),
+            }
+        }
+        Ok((orders, skipped))
+    }
+}
+
+/// Rebuild an open order and its record from a row of `Database::open_orders`
+fn open_order(
+    row: &tokio_postgres::Row,
+) -> Result<(OrderType, OrderRecord), Box<dyn std::error::Error>> {
+    let id = OrderId(row.try_get("id")?);
+    let side = match row.try_get::<_, &str>("side")? {
+        "Buy" => Side::Buy,
+        "Sell" => Side::Sell,
+        other => return Err(format!("unknown side {other}").into()),
+    };
+    let time_in_force = match row.try_get::<_, &str>("time_in_force")? {
+        "Gtc" => TimeInForce::Gtc,
+        "Ioc" => TimeInForce::Ioc,
+        "Fok" => TimeInForce::Fok,
+        "Day" => TimeInForce::Day,
+        other => return Err(format!("unknown time in force {other}").into()),
+    };
+    let price = row.try_get::<_, Option<i64>>("price")?.ok_or("no price")? as u64;
+    let remaining = row.try_get::<_, i64>("remaining_quantity")? as u64;
+    let filled = row.try_get::<_, i64>("filled_quantity")? as u64;
+    let timestamp = row.try_get::<_, i64>("created_ms")? as u64;
+
+    let order = match row.try_get::<_, &str>("order_type")? {
+        "Limit" => OrderType::Standard {
+            id,
+            price,
+            quantity: remaining,
+            side,
+            timestamp,
+            time_in_force,
+        },
+        "PostOnly" => OrderType::PostOnly {
+            id,
+            price,
+            quantity: remaining,
+            side,
+            timestamp,
+            time_in_force,
+        },
+        "Iceberg" => {
+            // The displayed part is refreshed up to the original visible quantity
+            let visible = (row
+                .try_get::<_, Option<i64>>("visible_quantity")?
+                .unwrap_or(0) as u64)
+                .clamp(1, remaining);
+            OrderType::IcebergOrder {
+                id,
+                price,
+                visible_quantity: visible,
+                hidden_quantity: remaining - visible,
+                side,
+                timestamp,
+                time_in_force,
+            }
+        }
+        other => return Err(format!("{other} orders do not rest in the book").into()),
+    };
+
+    let mut record = OrderRecord::new(id, side, Some(price), filled + remaining, timestamp);
+    record.filled_quantity = filled;
+    record.filled_value = row.try_get::<_, String>("filled_value")?.parse()?;
+    record.status = if filled > 0 {
+        EngineOrderStatus::PartiallyFilled
+    } else {
+        EngineOrderStatus::New
+    };
+    record.updated_at = row.try_get::<_, i64>("updated_ms")? as u64;
+    record.owner = Some(OrderOwner::new(
+        row.try_get("user_id")?,
+        row.try_get("client_order_id")?,
+    ));
+    Ok((order, record))
 }
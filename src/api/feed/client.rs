@@ -0,0 +1,62 @@
+use bytes::BytesMut;
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use super::protocol::{Packet, RetransmissionRequest};
+
+/// Client of the retransmission server of the market data feed
+#[derive(Debug)]
+pub struct RetransmissionClient {
+    stream: TcpStream,
+    session: String,
+}
+
+impl RetransmissionClient {
+    pub async fn connect(addr: impl ToSocketAddrs, session: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            session: session.to_string(),
+        })
+    }
+
+    /// Ask for up to `count` messages from `sequence` on. The answer holds fewer when they do
+    /// not fit a datagram, and none when they are no longer retained or not sent yet.
+    pub async fn request(&mut self, sequence: u64, count: u16) -> io::Result<Packet> {
+        let mut buffer = BytesMut::new();
+        RetransmissionRequest {
+            session: self.session.clone(),
+            sequence,
+            count,
+        }
+        .encode(&mut buffer)?;
+        self.stream.write_all(&buffer).await?;
+        let length = self.stream.read_u16_le().await? as usize;
+        let mut packet = vec![0; length];
+        self.stream.read_exact(&mut packet).await?;
+        Packet::decode(&packet)
+    }
+
+    /// Request the messages from `sequence` until `until`, excluded, as answered. Stops early
+    /// when the server no longer has the next message.
+    pub async fn request_range(
+        &mut self,
+        mut sequence: u64,
+        until: u64,
+    ) -> io::Result<Vec<Packet>> {
+        let mut packets = Vec::new();
+        while sequence < until {
+            let packet = self
+                .request(sequence, (until - sequence).min(u16::MAX as u64) as u16)
+                .await?;
+            if packet.messages.is_empty() {
+                break;
+            }
+            sequence = packet.next_sequence();
+            packets.push(packet);
+        }
+        Ok(packets)
+    }
+}
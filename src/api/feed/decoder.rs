@@ -0,0 +1,233 @@
+use crate::OrderBookSnapshot;
+use pricelevel::{OrderId, OrderType, PriceLevelSnapshot, Side, TimeInForce};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use super::protocol::{FeedMessage, FeedPayload, Packet};
+
+/// A packet started after the next expected message: the messages in between must be
+/// retransmitted before the packet can be applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceGap {
+    /// Next message the decoder expects
+    pub expected: u64,
+    /// First message of the packet
+    pub received: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DecodedOrder {
+    side: Side,
+    price: u64,
+    quantity: u64,
+    timestamp: u64,
+    /// Position in the queue of its level, in order of arrival on the feed
+    arrival: u64,
+}
+
+#[derive(Debug, Default)]
+struct DecodedBook {
+    sequence: u64,
+    timestamp: u64,
+    /// A snapshot was received and completed
+    synced: bool,
+    /// Orders of a snapshot still expected
+    pending: Option<u32>,
+    orders: HashMap<OrderId, DecodedOrder>,
+}
+
+/// Rebuilds the books from the packets of the feed.
+///
+/// Messages must be applied in sequence: a packet after a lost one is refused with a
+/// [`SequenceGap`] until the missing messages were applied, from a retransmission. A book is
+/// known once a snapshot of it was received, the messages about it before are ignored.
+#[derive(Debug, Default)]
+pub struct FeedDecoder {
+    session: Option<String>,
+    next_sequence: Option<u64>,
+    symbols: HashMap<u16, String>,
+    books: HashMap<u16, DecodedBook>,
+    next_arrival: u64,
+}
+
+impl FeedDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sequence of the next message to apply, `None` before the first packet
+    pub fn next_sequence(&self) -> Option<u64> {
+        self.next_sequence
+    }
+
+    /// Apply the messages of `packet` not applied yet. The first packet sets the session and
+    /// where the decoder starts, packets of other sessions are ignored.
+    pub fn apply(&mut self, packet: &Packet) -> Result<(), SequenceGap> {
+        match &self.session {
+            Some(session) if *session != packet.session => return Ok(()),
+            Some(_) => {}
+            None => self.session = Some(packet.session.clone()),
+        }
+        let expected = *self.next_sequence.get_or_insert(packet.sequence);
+        if packet.sequence > expected {
+            return Err(SequenceGap {
+                expected,
+                received: packet.sequence,
+            });
+        }
+        let skipped = (expected - packet.sequence) as usize;
+        for message in packet.messages.iter().skip(skipped) {
+            self.apply_message(message);
+        }
+        self.next_sequence = Some(expected.max(packet.next_sequence()));
+        Ok(())
+    }
+
+    fn apply_message(&mut self, message: &FeedMessage) {
+        if let FeedPayload::SymbolDirectory { symbol } = &message.payload {
+            self.symbols.insert(message.locate, symbol.clone());
+            return;
+        }
+        let book = self.books.entry(message.locate).or_default();
+        match &message.payload {
+            FeedPayload::SymbolDirectory { .. } => unreachable!("handled above"),
+            FeedPayload::SnapshotStart { orders } => {
+                book.orders.clear();
+                book.synced = false;
+                book.pending = Some(*orders);
+            }
+            FeedPayload::SnapshotEnd => {
+                book.synced = book.pending.take() == Some(0);
+            }
+            FeedPayload::AddOrder {
+                order_id,
+                side,
+                price,
+                quantity,
+            } => {
+                if let Some(pending) = book.pending.as_mut() {
+                    *pending = pending.saturating_sub(1);
+                } else if !book.synced {
+                    return;
+                }
+                book.orders.insert(
+                    *order_id,
+                    DecodedOrder {
+                        side: *side,
+                        price: *price,
+                        quantity: *quantity,
+                        timestamp: message.timestamp,
+                        arrival: self.next_arrival,
+                    },
+                );
+                self.next_arrival += 1;
+            }
+            _ if !book.synced => return,
+            FeedPayload::OrderExecuted {
+                order_id, quantity, ..
+            } => {
+                if let Some(order) = book.orders.get_mut(order_id) {
+                    order.quantity = order.quantity.saturating_sub(*quantity);
+                    if order.quantity == 0 {
+                        book.orders.remove(order_id);
+                    }
+                }
+            }
+            FeedPayload::OrderCancel {
+                order_id,
+                cancelled,
+            } => {
+                if let Some(order) = book.orders.get_mut(order_id) {
+                    order.quantity = order.quantity.saturating_sub(*cancelled);
+                    if order.quantity == 0 {
+                        book.orders.remove(order_id);
+                    }
+                }
+            }
+            FeedPayload::OrderDelete { order_id } => {
+                book.orders.remove(order_id);
+            }
+            FeedPayload::OrderReplace {
+                order_id,
+                price,
+                quantity,
+            } => {
+                if let Some(order) = book.orders.get_mut(order_id) {
+                    *order = DecodedOrder {
+                        price: *price,
+                        quantity: *quantity,
+                        timestamp: message.timestamp,
+                        arrival: self.next_arrival,
+                        ..*order
+                    };
+                    self.next_arrival += 1;
+                }
+            }
+            FeedPayload::Trade { .. } => {}
+        }
+        book.sequence = message.book_sequence;
+        book.timestamp = message.timestamp;
+    }
+
+    /// The book of `symbol` as rebuilt from the feed, `None` until a snapshot of it was
+    /// received. Only displayed quantities are published, so the levels have no hidden
+    /// quantity, and the orders are standard orders in the order they joined their level.
+    pub fn snapshot(&self, symbol: &str) -> Option<OrderBookSnapshot> {
+        let locate = self
+            .symbols
+            .iter()
+            .find(|(_, known)| *known == symbol)
+            .map(|(locate, _)| *locate)?;
+        let book = self.books.get(&locate).filter(|book| book.synced)?;
+
+        let mut sides: [BTreeMap<u64, Vec<(OrderId, DecodedOrder)>>; 2] = Default::default();
+        for (order_id, order) in &book.orders {
+            let side = match order.side {
+                Side::Buy => 0,
+                Side::Sell => 1,
+            };
+            sides[side]
+                .entry(order.price)
+                .or_default()
+                .push((*order_id, *order));
+        }
+        let levels =
+            |levels: BTreeMap<u64, Vec<(OrderId, DecodedOrder)>>| -> Vec<PriceLevelSnapshot> {
+                levels
+                    .into_iter()
+                    .map(|(price, mut orders)| {
+                        orders.sort_by_key(|(_, order)| order.arrival);
+                        PriceLevelSnapshot {
+                            price,
+                            visible_quantity: orders.iter().map(|(_, order)| order.quantity).sum(),
+                            hidden_quantity: 0,
+                            order_count: orders.len(),
+                            orders: orders
+                                .into_iter()
+                                .map(|(id, order)| {
+                                    Arc::new(OrderType::Standard {
+                                        id,
+                                        price,
+                                        quantity: order.quantity,
+                                        side: order.side,
+                                        timestamp: order.timestamp,
+                                        time_in_force: TimeInForce::Gtc,
+                                    })
+                                })
+                                .collect(),
+                        }
+                    })
+                    .collect()
+            };
+        let [bids, asks] = sides;
+        let mut bids = levels(bids);
+        bids.reverse();
+        Some(OrderBookSnapshot {
+            symbol: symbol.to_string(),
+            sequence: book.sequence,
+            timestamp: book.timestamp,
+            bids,
+            asks: levels(asks),
+        })
+    }
+}
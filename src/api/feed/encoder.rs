@@ -0,0 +1,237 @@
+use crate::{BookEvent, OrderBookSnapshot, OrderRecord, TradeRecord};
+use pricelevel::OrderId;
+use std::collections::HashMap;
+
+use super::protocol::{FeedMessage, FeedPayload};
+
+/// An order as displayed by the feed
+#[derive(Debug, Clone, Copy)]
+struct DisplayedOrder {
+    price: u64,
+    quantity: u64,
+}
+
+#[derive(Debug)]
+struct BookFeed {
+    locate: u16,
+    /// Sequence of the last snapshot sent; the events up to it are part of it
+    snapshot_sequence: u64,
+    orders: HashMap<OrderId, DisplayedOrder>,
+}
+
+/// Turns the events of the books into feed messages.
+///
+/// The encoder keeps the orders it displayed for each book and sends what changed in them.
+/// A book is only published once a snapshot of it was encoded, which resynchronises the
+/// displayed orders with the book.
+#[derive(Debug, Default)]
+pub struct FeedEncoder {
+    books: HashMap<String, BookFeed>,
+}
+
+impl FeedEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a snapshot of the book of `symbol` was encoded
+    pub fn publishes(&self, symbol: &str) -> bool {
+        self.books.contains_key(symbol)
+    }
+
+    /// Messages giving the orders of the full depth `snapshot` of a book, preceded by the
+    /// symbol directory entry of the book. Events of the book up to the sequence of the
+    /// snapshot are ignored afterwards.
+    pub fn encode_snapshot(&mut self, snapshot: &OrderBookSnapshot) -> Vec<FeedMessage> {
+        let locate = u16::try_from(self.books.len() + 1).expect("fewer than 65536 books");
+        let book = self
+            .books
+            .entry(snapshot.symbol.clone())
+            .or_insert_with(|| BookFeed {
+                locate,
+                snapshot_sequence: 0,
+                orders: HashMap::new(),
+            });
+        book.snapshot_sequence = snapshot.sequence;
+        book.orders.clear();
+
+        let locate = book.locate;
+        let message = |timestamp, payload| FeedMessage {
+            locate,
+            book_sequence: snapshot.sequence,
+            timestamp,
+            payload,
+        };
+        let mut adds = Vec::new();
+        for level in snapshot.bids.iter().chain(&snapshot.asks) {
+            for order in level
+                .orders
+                .iter()
+                .filter(|order| order.visible_quantity() > 0)
+            {
+                book.orders.insert(
+                    order.id(),
+                    DisplayedOrder {
+                        price: level.price,
+                        quantity: order.visible_quantity(),
+                    },
+                );
+                adds.push(message(
+                    order.timestamp(),
+                    FeedPayload::AddOrder {
+                        order_id: order.id(),
+                        side: order.side(),
+                        price: level.price,
+                        quantity: order.visible_quantity(),
+                    },
+                ));
+            }
+        }
+
+        let mut messages = Vec::with_capacity(adds.len() + 3);
+        messages.push(message(
+            snapshot.timestamp,
+            FeedPayload::SymbolDirectory {
+                symbol: snapshot.symbol.clone(),
+            },
+        ));
+        messages.push(message(
+            snapshot.timestamp,
+            FeedPayload::SnapshotStart {
+                orders: adds.len() as u32,
+            },
+        ));
+        messages.append(&mut adds);
+        messages.push(message(snapshot.timestamp, FeedPayload::SnapshotEnd));
+        messages
+    }
+
+    /// Messages for `event`, none for books without a snapshot yet or for events already
+    /// included in the last snapshot
+    pub fn encode_event(&mut self, event: &BookEvent) -> Vec<FeedMessage> {
+        let Some(book) = self.books.get_mut(event.symbol()) else {
+            return Vec::new();
+        };
+        if event.sequence() <= book.snapshot_sequence {
+            return Vec::new();
+        }
+        match event {
+            BookEvent::Trade {
+                sequence, trade, ..
+            } => book.trade(*sequence, trade),
+            BookEvent::OrderUpdated {
+                sequence,
+                record,
+                visible_quantity,
+                ..
+            } => book
+                .order_updated(*sequence, record, visible_quantity.unwrap_or(0))
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+impl BookFeed {
+    fn message(&self, book_sequence: u64, timestamp: u64, payload: FeedPayload) -> FeedMessage {
+        FeedMessage {
+            locate: self.locate,
+            book_sequence,
+            timestamp,
+            payload,
+        }
+    }
+
+    /// Execute the displayed quantity of the maker, the rest of the trade was not displayed
+    fn trade(&mut self, sequence: u64, trade: &TradeRecord) -> Vec<FeedMessage> {
+        let mut messages = Vec::new();
+        let mut undisplayed = trade.quantity;
+        if let Some(maker) = self.orders.get_mut(&trade.maker_order_id) {
+            let executed = trade.quantity.min(maker.quantity);
+            maker.quantity -= executed;
+            undisplayed -= executed;
+            if maker.quantity == 0 {
+                self.orders.remove(&trade.maker_order_id);
+            }
+            messages.push(self.message(
+                sequence,
+                trade.timestamp,
+                FeedPayload::OrderExecuted {
+                    order_id: trade.maker_order_id,
+                    quantity: executed,
+                    match_id: trade.transaction_id,
+                },
+            ));
+        }
+        if undisplayed > 0 {
+            messages.push(self.message(
+                sequence,
+                trade.timestamp,
+                FeedPayload::Trade {
+                    side: trade.taker_side,
+                    price: trade.price,
+                    quantity: undisplayed,
+                    match_id: trade.transaction_id,
+                },
+            ));
+        }
+        messages
+    }
+
+    /// Bring the displayed order in line with the book, where it now shows `visible_quantity`
+    fn order_updated(
+        &mut self,
+        sequence: u64,
+        record: &OrderRecord,
+        visible_quantity: u64,
+    ) -> Option<FeedMessage> {
+        let order_id = record.order_id;
+        let resting = record
+            .price
+            .filter(|_| visible_quantity > 0 && !record.status.is_terminal());
+        let payload = match (self.orders.get_mut(&order_id), resting) {
+            (None, None) => return None,
+            (Some(_), None) => {
+                self.orders.remove(&order_id);
+                FeedPayload::OrderDelete { order_id }
+            }
+            (None, Some(price)) => {
+                self.orders.insert(
+                    order_id,
+                    DisplayedOrder {
+                        price,
+                        quantity: visible_quantity,
+                    },
+                );
+                FeedPayload::AddOrder {
+                    order_id,
+                    side: record.side,
+                    price,
+                    quantity: visible_quantity,
+                }
+            }
+            (Some(displayed), Some(price)) => {
+                let previous = *displayed;
+                *displayed = DisplayedOrder {
+                    price,
+                    quantity: visible_quantity,
+                };
+                if price == previous.price && visible_quantity == previous.quantity {
+                    return None;
+                } else if price == previous.price && visible_quantity < previous.quantity {
+                    FeedPayload::OrderCancel {
+                        order_id,
+                        cancelled: previous.quantity - visible_quantity,
+                    }
+                } else {
+                    FeedPayload::OrderReplace {
+                        order_id,
+                        price,
+                        quantity: visible_quantity,
+                    }
+                }
+            }
+        };
+        Some(self.message(sequence, record.updated_at, payload))
+    }
+}
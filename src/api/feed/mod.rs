@@ -0,0 +1,368 @@
+//! Binary market data feed.
+//!
+//! An order by order feed in the spirit of ITCH over MoldUDP64: the books are published as
+//! messages adding, executing, reducing, replacing and deleting the displayed orders, plus
+//! trades against quantity that was not displayed, see [`protocol`] for the layouts. Every
+//! message is numbered in the sequence of the feed session and the messages are packed into
+//! UDP datagrams sent to each configured destination, unicast or multicast. A datagram
+//! without messages is a heartbeat giving the next sequence.
+//!
+//! A receiver joining late or losing a datagram recovers with the snapshots, sent for every
+//! book when the feed starts and then periodically as the orders of the book between a
+//! SnapshotStart and a SnapshotEnd, or asks the retransmission server over TCP for the
+//! messages it missed. Fields are little-endian like the binary order entry protocol.
+
+mod client;
+mod decoder;
+mod encoder;
+pub mod protocol;
+
+pub use client::RetransmissionClient;
+pub use decoder::{FeedDecoder, SequenceGap};
+pub use encoder::FeedEncoder;
+pub use protocol::{FeedMessage, FeedPayload, Packet, RetransmissionRequest};
+
+use crate::api::sink::{ChannelReceiver, ChannelSink};
+use crate::{BookEvent, OrderBook};
+use bytes::{BufMut, Bytes, BytesMut};
+use dashmap::DashMap;
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tracing::{debug, info, warn};
+
+use protocol::{PACKET_HEADER_BYTES, REQUEST_BYTES, put_header};
+
+/// Settings of the market data feed
+#[derive(Debug, Clone)]
+pub struct MarketFeedConfig {
+    /// Name of the feed session, ASCII of at most 10 bytes
+    pub session: String,
+    /// Local address the datagrams are sent from
+    pub bind: SocketAddr,
+    /// Addresses every datagram is sent to, unicast or multicast
+    pub destinations: Vec<SocketAddr>,
+    /// Hops of the multicast datagrams
+    pub multicast_ttl: u32,
+    /// Largest datagram sent, header included
+    pub max_packet_bytes: usize,
+    /// A heartbeat is sent after sending nothing for this long
+    pub heartbeat_interval: Duration,
+    /// Time between two snapshots of every book
+    pub snapshot_interval: Duration,
+    /// Messages kept for retransmission
+    pub retained_messages: usize,
+}
+
+impl Default for MarketFeedConfig {
+    fn default() -> Self {
+        Self {
+            session: "FEED".to_string(),
+            bind: SocketAddr::from(([0, 0, 0, 0], 0)),
+            destinations: Vec::new(),
+            multicast_ttl: 1,
+            max_packet_bytes: 1400,
+            heartbeat_interval: Duration::from_secs(1),
+            snapshot_interval: Duration::from_secs(30),
+            retained_messages: 1_000_000,
+        }
+    }
+}
+
+/// Event sink handing the book events over to [`run_market_feed`]
+pub type MarketFeedSink = ChannelSink<BookEvent>;
+
+/// Receiving end of a [`MarketFeedSink`]
+pub type MarketFeedReceiver = ChannelReceiver<BookEvent>;
+
+/// Messages sent so far, the last ones kept for retransmission
+#[derive(Debug)]
+struct History {
+    /// Sequence of the next message
+    next_sequence: u64,
+    /// Encoded messages, the first one numbered `next_sequence - retained.len()`
+    retained: VecDeque<Bytes>,
+}
+
+impl History {
+    fn first_retained(&self) -> u64 {
+        self.next_sequence - self.retained.len() as u64
+    }
+}
+
+/// State shared by the publisher and the retransmission server of the feed
+pub struct MarketFeed {
+    config: MarketFeedConfig,
+    orderbooks: Arc<DashMap<String, Arc<OrderBook>>>,
+    socket: UdpSocket,
+    history: Mutex<History>,
+}
+
+impl std::fmt::Debug for MarketFeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MarketFeed")
+            .field("config", &self.config)
+            .field("symbols", &self.orderbooks.len())
+            .finish()
+    }
+}
+
+impl MarketFeed {
+    /// Bind the socket the datagrams of the feed are sent from
+    pub async fn bind(
+        config: MarketFeedConfig,
+        orderbooks: Arc<DashMap<String, Arc<OrderBook>>>,
+    ) -> io::Result<Self> {
+        // Refuse a session that does not fit the packet header before anything is sent
+        put_header(&mut BytesMut::new(), &config.session, 0, 0)?;
+        let socket = UdpSocket::bind(config.bind).await?;
+        if config
+            .destinations
+            .iter()
+            .any(|destination| destination.ip().is_multicast())
+        {
+            socket.set_multicast_ttl_v4(config.multicast_ttl)?;
+        }
+        Ok(Self {
+            config,
+            orderbooks,
+            socket,
+            history: Mutex::new(History {
+                next_sequence: 1,
+                retained: VecDeque::new(),
+            }),
+        })
+    }
+
+    pub fn config(&self) -> &MarketFeedConfig {
+        &self.config
+    }
+
+    /// Local address of the socket the datagrams are sent from
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Sequence of the next message of the feed
+    pub fn next_sequence(&self) -> u64 {
+        self.history.lock().unwrap().next_sequence
+    }
+
+    /// Number the messages, keep them for retransmission and send them in as few datagrams
+    /// as fit
+    async fn publish(&self, messages: &[FeedMessage]) {
+        let packets = {
+            let mut history = self.history.lock().unwrap();
+            let mut packets = Vec::new();
+            let mut packet = BytesMut::new();
+            let mut count = 0u16;
+            for message in messages {
+                let mut encoded = BytesMut::with_capacity(message.encoded_len());
+                if let Err(e) = message.encode(&mut encoded) {
+                    warn!("Dropping feed message {:?}: {}", message, e);
+                    continue;
+                }
+                if count > 0
+                    && (PACKET_HEADER_BYTES + packet.len() + encoded.len()
+                        > self.config.max_packet_bytes
+                        || count == u16::MAX)
+                {
+                    packets.push(self.packet(history.next_sequence - count as u64, count, &packet));
+                    packet.clear();
+                    count = 0;
+                }
+                packet.extend_from_slice(&encoded);
+                count += 1;
+                history.next_sequence += 1;
+                history.retained.push_back(encoded.freeze());
+                if history.retained.len() > self.config.retained_messages {
+                    history.retained.pop_front();
+                }
+            }
+            if count > 0 {
+                packets.push(self.packet(history.next_sequence - count as u64, count, &packet));
+            }
+            packets
+        };
+        for packet in packets {
+            self.send(&packet).await;
+        }
+    }
+
+    async fn heartbeat(&self) {
+        let next_sequence = self.next_sequence();
+        self.send(&self.packet(next_sequence, 0, &[])).await;
+    }
+
+    fn packet(&self, sequence: u64, count: u16, messages: &[u8]) -> Bytes {
+        let mut packet = BytesMut::with_capacity(PACKET_HEADER_BYTES + messages.len());
+        put_header(&mut packet, &self.config.session, sequence, count)
+            .expect("the session was checked by MarketFeed::bind");
+        packet.put_slice(messages);
+        packet.freeze()
+    }
+
+    async fn send(&self, packet: &[u8]) {
+        for destination in &self.config.destinations {
+            if let Err(e) = self.socket.send_to(packet, destination).await {
+                warn!("Failed to send a feed datagram to {}: {}", destination, e);
+            }
+        }
+    }
+
+    /// Datagram answering `request`: the retained messages from its sequence on, as many as
+    /// asked and fit. Without messages, the datagram gives the first retained message when
+    /// the request is older, the next message otherwise.
+    fn retransmission(&self, request: &RetransmissionRequest) -> Bytes {
+        let history = self.history.lock().unwrap();
+        let first = history.first_retained();
+        if request.sequence < first || request.sequence >= history.next_sequence {
+            let sequence = if request.sequence < first {
+                first
+            } else {
+                history.next_sequence
+            };
+            return self.packet(sequence, 0, &[]);
+        }
+        let mut messages = BytesMut::new();
+        let mut count = 0u16;
+        for message in history
+            .retained
+            .iter()
+            .skip((request.sequence - first) as usize)
+            .take(request.count as usize)
+        {
+            if count > 0
+                && PACKET_HEADER_BYTES + messages.len() + message.len()
+                    > self.config.max_packet_bytes
+            {
+                break;
+            }
+            messages.extend_from_slice(message);
+            count += 1;
+        }
+        self.packet(request.sequence, count, &messages)
+    }
+
+    /// Snapshot messages of every book, taken now
+    fn snapshots(&self, encoder: &mut FeedEncoder) -> Vec<FeedMessage> {
+        let mut books: Vec<(String, Arc<OrderBook>)> = self
+            .orderbooks
+            .iter()
+            .map(|item| (item.key().clone(), item.value().clone()))
+            .collect();
+        books.sort_by(|a, b| a.0.cmp(&b.0));
+        books
+            .iter()
+            .flat_map(|(_, book)| encoder.encode_snapshot(&book.create_snapshot(usize::MAX)))
+            .collect()
+    }
+}
+
+/// Publish the events of the books on the feed until every sink is dropped, with a snapshot
+/// of every book first and then every snapshot interval
+pub async fn run_market_feed(feed: Arc<MarketFeed>, receiver: MarketFeedReceiver) {
+    let mut receiver = receiver.0;
+    let mut encoder = FeedEncoder::new();
+    let mut heartbeats = tokio::time::interval(feed.config.heartbeat_interval);
+    heartbeats.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut snapshots = tokio::time::interval(feed.config.snapshot_interval);
+    snapshots.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut last_sent = Instant::now();
+    info!(
+        "Market data feed {} publishing to {:?}",
+        feed.config.session, feed.config.destinations
+    );
+
+    loop {
+        let messages = tokio::select! {
+            event = receiver.recv() => {
+                let Some(event) = event else { break };
+                let mut messages = Vec::new();
+                let mut next = Some(event);
+                // Pack the events already queued into the same datagrams
+                while let Some(event) = next {
+                    // A book added since the last snapshots is published from its own
+                    if !encoder.publishes(event.symbol())
+                        && let Some(book) = feed
+                            .orderbooks
+                            .get(event.symbol())
+                            .map(|book| book.value().clone())
+                    {
+                        messages.extend(encoder.encode_snapshot(&book.create_snapshot(usize::MAX)));
+                    }
+                    messages.extend(encoder.encode_event(&event));
+                    next = receiver.try_recv().ok();
+                }
+                messages
+            }
+            _ = snapshots.tick() => feed.snapshots(&mut encoder),
+            _ = heartbeats.tick() => {
+                if last_sent.elapsed() >= feed.config.heartbeat_interval {
+                    feed.heartbeat().await;
+                    last_sent = Instant::now();
+                }
+                continue;
+            }
+        };
+        if !messages.is_empty() {
+            feed.publish(&messages).await;
+            last_sent = Instant::now();
+        }
+    }
+}
+
+/// Answer retransmission requests on `listener` until it fails, serving each connection on
+/// its own task.
+///
+/// A request is laid out like a packet header: the session, the first message wanted and how
+/// many. Each one is answered with a `u16` length followed by a datagram of the feed.
+pub async fn run_retransmission_server(
+    listener: TcpListener,
+    feed: Arc<MarketFeed>,
+) -> io::Result<()> {
+    info!(
+        "Market data retransmission listening on {}",
+        listener.local_addr()?
+    );
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        if let Err(e) = stream.set_nodelay(true) {
+            warn!(
+                "Failed to disable Nagle's algorithm for retransmission connection {}: {}",
+                peer, e
+            );
+        }
+        let feed = feed.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_retransmissions(&feed, stream).await {
+                debug!("Retransmission connection {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn serve_retransmissions(feed: &MarketFeed, mut stream: TcpStream) -> io::Result<()> {
+    let mut request = [0; REQUEST_BYTES];
+    loop {
+        match stream.read_exact(&mut request).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        let request = RetransmissionRequest::decode(&request)?;
+        if request.session != feed.config.session {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown session {:?}", request.session),
+            ));
+        }
+        let packet = feed.retransmission(&request);
+        stream.write_u16_le(packet.len() as u16).await?;
+        stream.write_all(&packet).await?;
+    }
+}
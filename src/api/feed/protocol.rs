@@ -0,0 +1,392 @@
+use bytes::{Buf, BufMut, BytesMut};
+use pricelevel::{OrderId, Side};
+use std::io;
+use uuid::Uuid;
+
+use crate::api::binary::protocol::{
+    TEXT_BYTES, get_side, get_text, get_uuid, invalid, put_text, side_code,
+};
+
+/// Bytes of the session name at the start of every packet, ASCII padded with zeros
+pub const SESSION_BYTES: usize = 10;
+/// Bytes of a packet header: session, sequence of the first message and message count
+pub const PACKET_HEADER_BYTES: usize = SESSION_BYTES + 8 + 2;
+/// Bytes of a retransmission request, laid out like a packet header
+pub const REQUEST_BYTES: usize = PACKET_HEADER_BYTES;
+/// Bytes of the fields every message starts with: locate, book sequence and timestamp
+pub const MESSAGE_HEADER_BYTES: usize = 2 + 8 + 8;
+
+/// Message type codes, the first byte after the length of a message
+pub mod message_type {
+    pub const SYMBOL_DIRECTORY: u8 = b'R';
+    pub const SNAPSHOT_START: u8 = b'G';
+    pub const SNAPSHOT_END: u8 = b'g';
+    pub const ADD_ORDER: u8 = b'A';
+    pub const ORDER_EXECUTED: u8 = b'E';
+    pub const ORDER_CANCEL: u8 = b'X';
+    pub const ORDER_DELETE: u8 = b'D';
+    pub const ORDER_REPLACE: u8 = b'U';
+    pub const TRADE: u8 = b'P';
+}
+
+/// A message of the feed about one book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedMessage {
+    /// Number standing for the symbol of the book, given by the symbol directory
+    pub locate: u16,
+    /// Sequence of the book mutation the message comes from
+    pub book_sequence: u64,
+    /// Milliseconds since epoch. Time of the event, or when the order was placed for the
+    /// orders of a snapshot.
+    pub timestamp: u64,
+    pub payload: FeedPayload,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedPayload {
+    /// The book of `symbol` is published under the locate of the message
+    SymbolDirectory { symbol: String },
+    /// The orders resting in the book follow as `orders` AddOrder messages, replacing the
+    /// book known so far
+    SnapshotStart { orders: u32 },
+    /// Every order of the snapshot was sent
+    SnapshotEnd,
+    /// An order is displayed in the book, behind the orders already at its price
+    AddOrder {
+        order_id: OrderId,
+        side: Side,
+        price: u64,
+        quantity: u64,
+    },
+    /// `quantity` of a displayed order was executed at its price, the order leaves the book
+    /// once nothing of it is displayed
+    OrderExecuted {
+        order_id: OrderId,
+        quantity: u64,
+        match_id: Uuid,
+    },
+    /// The displayed quantity of an order was reduced by `cancelled`, keeping its priority
+    OrderCancel { order_id: OrderId, cancelled: u64 },
+    /// An order left the book
+    OrderDelete { order_id: OrderId },
+    /// An order now displays `quantity` at `price`, behind the orders already at that price
+    OrderReplace {
+        order_id: OrderId,
+        price: u64,
+        quantity: u64,
+    },
+    /// An execution against quantity that was not displayed. `side` is the side of the taker.
+    Trade {
+        side: Side,
+        price: u64,
+        quantity: u64,
+        match_id: Uuid,
+    },
+}
+
+/// Length of the body of a message of `message_type`, after the common header, or `None` for
+/// an unknown type
+pub fn body_length(message_type: u8) -> Option<usize> {
+    Some(match message_type {
+        message_type::SYMBOL_DIRECTORY => TEXT_BYTES,
+        message_type::SNAPSHOT_START => 4,
+        message_type::SNAPSHOT_END => 0,
+        message_type::ADD_ORDER => 16 + 1 + 8 + 8,
+        message_type::ORDER_EXECUTED => 16 + 8 + 16,
+        message_type::ORDER_CANCEL => 16 + 8,
+        message_type::ORDER_DELETE => 16,
+        message_type::ORDER_REPLACE => 16 + 8 + 8,
+        message_type::TRADE => 1 + 8 + 8 + 16,
+        _ => return None,
+    })
+}
+
+impl FeedMessage {
+    pub fn message_type(&self) -> u8 {
+        match self.payload {
+            FeedPayload::SymbolDirectory { .. } => message_type::SYMBOL_DIRECTORY,
+            FeedPayload::SnapshotStart { .. } => message_type::SNAPSHOT_START,
+            FeedPayload::SnapshotEnd => message_type::SNAPSHOT_END,
+            FeedPayload::AddOrder { .. } => message_type::ADD_ORDER,
+            FeedPayload::OrderExecuted { .. } => message_type::ORDER_EXECUTED,
+            FeedPayload::OrderCancel { .. } => message_type::ORDER_CANCEL,
+            FeedPayload::OrderDelete { .. } => message_type::ORDER_DELETE,
+            FeedPayload::OrderReplace { .. } => message_type::ORDER_REPLACE,
+            FeedPayload::Trade { .. } => message_type::TRADE,
+        }
+    }
+
+    /// Bytes of the message in a packet, length prefix included
+    pub fn encoded_len(&self) -> usize {
+        2 + 1
+            + MESSAGE_HEADER_BYTES
+            + body_length(self.message_type()).expect("every message type has a body length")
+    }
+
+    /// Append the message to `buffer`: a little-endian `u16` length of the rest, the type,
+    /// the common header then the body
+    pub fn encode(&self, buffer: &mut BytesMut) -> io::Result<()> {
+        let start = buffer.len();
+        buffer.reserve(self.encoded_len());
+        buffer.put_u16_le((self.encoded_len() - 2) as u16);
+        buffer.put_u8(self.message_type());
+        buffer.put_u16_le(self.locate);
+        buffer.put_u64_le(self.book_sequence);
+        buffer.put_u64_le(self.timestamp);
+        let body = match &self.payload {
+            FeedPayload::SymbolDirectory { symbol } => put_text(buffer, symbol),
+            FeedPayload::SnapshotStart { orders } => {
+                buffer.put_u32_le(*orders);
+                Ok(())
+            }
+            FeedPayload::SnapshotEnd => Ok(()),
+            FeedPayload::AddOrder {
+                order_id,
+                side,
+                price,
+                quantity,
+            } => {
+                buffer.put_slice(order_id.0.as_bytes());
+                buffer.put_u8(side_code(*side));
+                buffer.put_u64_le(*price);
+                buffer.put_u64_le(*quantity);
+                Ok(())
+            }
+            FeedPayload::OrderExecuted {
+                order_id,
+                quantity,
+                match_id,
+            } => {
+                buffer.put_slice(order_id.0.as_bytes());
+                buffer.put_u64_le(*quantity);
+                buffer.put_slice(match_id.as_bytes());
+                Ok(())
+            }
+            FeedPayload::OrderCancel {
+                order_id,
+                cancelled,
+            } => {
+                buffer.put_slice(order_id.0.as_bytes());
+                buffer.put_u64_le(*cancelled);
+                Ok(())
+            }
+            FeedPayload::OrderDelete { order_id } => {
+                buffer.put_slice(order_id.0.as_bytes());
+                Ok(())
+            }
+            FeedPayload::OrderReplace {
+                order_id,
+                price,
+                quantity,
+            } => {
+                buffer.put_slice(order_id.0.as_bytes());
+                buffer.put_u64_le(*price);
+                buffer.put_u64_le(*quantity);
+                Ok(())
+            }
+            FeedPayload::Trade {
+                side,
+                price,
+                quantity,
+                match_id,
+            } => {
+                buffer.put_u8(side_code(*side));
+                buffer.put_u64_le(*price);
+                buffer.put_u64_le(*quantity);
+                buffer.put_slice(match_id.as_bytes());
+                Ok(())
+            }
+        };
+        if let Err(e) = body {
+            // Leave no partial message behind
+            buffer.truncate(start);
+            return Err(e);
+        }
+        debug_assert_eq!(
+            buffer.len() - start,
+            self.encoded_len(),
+            "message type {}",
+            self.message_type() as char
+        );
+        Ok(())
+    }
+
+    /// Take the next message off `buffer`
+    pub fn decode(buffer: &mut &[u8]) -> io::Result<Self> {
+        if buffer.len() < 3 {
+            return Err(invalid("truncated message"));
+        }
+        let length = buffer.get_u16_le() as usize;
+        let message_type = buffer[0];
+        if body_length(message_type).is_none_or(|body| 1 + MESSAGE_HEADER_BYTES + body != length)
+            || buffer.len() < length
+        {
+            return Err(invalid(format!(
+                "invalid message of type {:#04x} and length {}",
+                message_type, length
+            )));
+        }
+        buffer.advance(1);
+        let body = buffer;
+        let locate = body.get_u16_le();
+        let book_sequence = body.get_u64_le();
+        let timestamp = body.get_u64_le();
+        let payload = match message_type {
+            message_type::SYMBOL_DIRECTORY => FeedPayload::SymbolDirectory {
+                symbol: get_text(body)?,
+            },
+            message_type::SNAPSHOT_START => FeedPayload::SnapshotStart {
+                orders: body.get_u32_le(),
+            },
+            message_type::SNAPSHOT_END => FeedPayload::SnapshotEnd,
+            message_type::ADD_ORDER => FeedPayload::AddOrder {
+                order_id: OrderId(get_uuid(body)),
+                side: get_side(body)?,
+                price: body.get_u64_le(),
+                quantity: body.get_u64_le(),
+            },
+            message_type::ORDER_EXECUTED => FeedPayload::OrderExecuted {
+                order_id: OrderId(get_uuid(body)),
+                quantity: body.get_u64_le(),
+                match_id: get_uuid(body),
+            },
+            message_type::ORDER_CANCEL => FeedPayload::OrderCancel {
+                order_id: OrderId(get_uuid(body)),
+                cancelled: body.get_u64_le(),
+            },
+            message_type::ORDER_DELETE => FeedPayload::OrderDelete {
+                order_id: OrderId(get_uuid(body)),
+            },
+            message_type::ORDER_REPLACE => FeedPayload::OrderReplace {
+                order_id: OrderId(get_uuid(body)),
+                price: body.get_u64_le(),
+                quantity: body.get_u64_le(),
+            },
+            message_type::TRADE => FeedPayload::Trade {
+                side: get_side(body)?,
+                price: body.get_u64_le(),
+                quantity: body.get_u64_le(),
+                match_id: get_uuid(body),
+            },
+            _ => unreachable!("the length of unknown types was refused"),
+        };
+        Ok(Self {
+            locate,
+            book_sequence,
+            timestamp,
+            payload,
+        })
+    }
+}
+
+/// A datagram of the feed: the messages from `sequence` on, none for a heartbeat
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub session: String,
+    /// Sequence of the first message, or of the next one for a packet without messages
+    pub sequence: u64,
+    pub messages: Vec<FeedMessage>,
+}
+
+impl Packet {
+    /// Sequence following the last message of the packet
+    pub fn next_sequence(&self) -> u64 {
+        self.sequence + self.messages.len() as u64
+    }
+
+    pub fn encode(&self, buffer: &mut BytesMut) -> io::Result<()> {
+        put_header(
+            buffer,
+            &self.session,
+            self.sequence,
+            self.messages.len() as u16,
+        )?;
+        for message in &self.messages {
+            message.encode(buffer)?;
+        }
+        Ok(())
+    }
+
+    pub fn decode(mut buffer: &[u8]) -> io::Result<Self> {
+        let buffer = &mut buffer;
+        let (session, sequence, count) = get_header(buffer)?;
+        let messages = (0..count)
+            .map(|_| FeedMessage::decode(buffer))
+            .collect::<io::Result<Vec<_>>>()?;
+        if !buffer.is_empty() {
+            return Err(invalid(format!(
+                "{} bytes after the last message of the packet",
+                buffer.len()
+            )));
+        }
+        Ok(Self {
+            session,
+            sequence,
+            messages,
+        })
+    }
+}
+
+/// Ask the retransmission server for `count` messages of `session` from `sequence` on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetransmissionRequest {
+    pub session: String,
+    pub sequence: u64,
+    pub count: u16,
+}
+
+impl RetransmissionRequest {
+    pub fn encode(&self, buffer: &mut BytesMut) -> io::Result<()> {
+        put_header(buffer, &self.session, self.sequence, self.count)
+    }
+
+    pub fn decode(mut buffer: &[u8]) -> io::Result<Self> {
+        let (session, sequence, count) = get_header(&mut buffer)?;
+        Ok(Self {
+            session,
+            sequence,
+            count,
+        })
+    }
+}
+
+pub(crate) fn put_header(
+    buffer: &mut BytesMut,
+    session: &str,
+    sequence: u64,
+    count: u16,
+) -> io::Result<()> {
+    if session.len() > SESSION_BYTES || !session.is_ascii() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "session {:?} is not ASCII of at most {} bytes",
+                session, SESSION_BYTES
+            ),
+        ));
+    }
+    buffer.reserve(PACKET_HEADER_BYTES);
+    buffer.put_slice(session.as_bytes());
+    buffer.put_bytes(0, SESSION_BYTES - session.len());
+    buffer.put_u64_le(sequence);
+    buffer.put_u16_le(count);
+    Ok(())
+}
+
+fn get_header(buffer: &mut &[u8]) -> io::Result<(String, u64, u16)> {
+    if buffer.len() < PACKET_HEADER_BYTES {
+        return Err(invalid("truncated packet header"));
+    }
+    let field = &buffer[..SESSION_BYTES];
+    let end = field
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(SESSION_BYTES);
+    let session = std::str::from_utf8(&field[..end])
+        .ok()
+        .filter(|text| text.is_ascii())
+        .map(str::to_string)
+        .ok_or_else(|| invalid("session is not ASCII"))?;
+    buffer.advance(SESSION_BYTES);
+    Ok((session, buffer.get_u64_le(), buffer.get_u16_le()))
+}
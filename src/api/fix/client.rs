@@ -0,0 +1,128 @@
+use actix_codec::Framed;
+use futures_util::{SinkExt, StreamExt};
+use std::io;
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use super::message::{FixCodec, FixMessage, msg_type, tags, utc_timestamp};
+
+/// FIX client of the gateway, for scripted sessions and tests. It numbers the messages it
+/// sends and answers test requests, everything else is up to the caller.
+pub struct FixClient {
+    framed: Framed<TcpStream, FixCodec>,
+    sender_comp_id: String,
+    target_comp_id: String,
+    next_outgoing: u64,
+}
+
+impl std::fmt::Debug for FixClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FixClient")
+            .field("sender_comp_id", &self.sender_comp_id)
+            .field("target_comp_id", &self.target_comp_id)
+            .field("next_outgoing", &self.next_outgoing)
+            .finish()
+    }
+}
+
+impl FixClient {
+    pub async fn connect(
+        addr: impl ToSocketAddrs,
+        sender_comp_id: impl Into<String>,
+        target_comp_id: impl Into<String>,
+    ) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            framed: Framed::new(stream, FixCodec),
+            sender_comp_id: sender_comp_id.into(),
+            target_comp_id: target_comp_id.into(),
+            next_outgoing: 1,
+        })
+    }
+
+    /// Sequence number of the next message sent
+    pub fn next_sequence(&self) -> u64 {
+        self.next_outgoing
+    }
+
+    /// Continue a previous session from `sequence`
+    pub fn set_next_sequence(&mut self, sequence: u64) {
+        self.next_outgoing = sequence;
+    }
+
+    /// Send a Logon and wait for the answer of the gateway, a Logon or a Logout
+    pub async fn logon(&mut self, heartbeat_interval: u64, reset: bool) -> io::Result<FixMessage> {
+        let mut logon = FixMessage::new(msg_type::LOGON)
+            .with(tags::ENCRYPT_METHOD, 0)
+            .with(tags::HEART_BT_INT, heartbeat_interval);
+        if reset {
+            self.next_outgoing = 1;
+            logon.set(tags::RESET_SEQ_NUM_FLAG, "Y");
+        }
+        self.send(logon).await?;
+        self.next_message().await?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed during logon",
+            )
+        })
+    }
+
+    /// Send a message with the next sequence number, returning that number
+    pub async fn send(&mut self, message: FixMessage) -> io::Result<u64> {
+        let sequence = self.next_outgoing;
+        self.send_with_sequence(message, sequence).await?;
+        self.next_outgoing += 1;
+        Ok(sequence)
+    }
+
+    /// Send a message with the given sequence number, leaving the next one alone
+    pub async fn send_with_sequence(
+        &mut self,
+        message: FixMessage,
+        sequence: u64,
+    ) -> io::Result<()> {
+        let mut message = message.with(tags::MSG_SEQ_NUM, sequence);
+        for (tag, value) in [
+            (tags::SENDER_COMP_ID, &self.sender_comp_id),
+            (tags::TARGET_COMP_ID, &self.target_comp_id),
+        ] {
+            if message.get(tag).is_none() {
+                message.set(tag, value);
+            }
+        }
+        if message.get(tags::SENDING_TIME).is_none() {
+            message.set(tags::SENDING_TIME, utc_timestamp());
+        }
+        self.framed.send(message).await
+    }
+
+    /// Next message of the gateway, `None` once the connection is closed. Test requests are
+    /// answered before being returned.
+    pub async fn next_message(&mut self) -> io::Result<Option<FixMessage>> {
+        let Some(message) = self.framed.next().await.transpose()? else {
+            return Ok(None);
+        };
+        if message.msg_type() == msg_type::TEST_REQUEST {
+            let mut heartbeat = FixMessage::new(msg_type::HEARTBEAT);
+            if let Some(id) = message.get(tags::TEST_REQ_ID) {
+                heartbeat.set(tags::TEST_REQ_ID, id);
+            }
+            self.send(heartbeat).await?;
+        }
+        Ok(Some(message))
+    }
+
+    /// Log out and wait for the gateway to confirm, returning the messages received meanwhile
+    pub async fn logout(&mut self) -> io::Result<Vec<FixMessage>> {
+        self.send(FixMessage::new(msg_type::LOGOUT)).await?;
+        let mut received = Vec::new();
+        while let Some(message) = self.next_message().await? {
+            if message.msg_type() == msg_type::LOGOUT {
+                break;
+            }
+            received.push(message);
+        }
+        Ok(received)
+    }
+}
@@ -0,0 +1,400 @@
+use actix_codec::{Decoder, Encoder};
+use bytes::{Buf, BufMut, BytesMut};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use tracing::warn;
+
+/// Version of the protocol spoken by the gateway
+pub const BEGIN_STRING: &str = "FIX.4.4";
+
+/// Field separator
+pub const SOH: u8 = 0x01;
+
+/// Longest message accepted, header and trailer included
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+/// Tags used by the gateway
+pub mod tags {
+    pub const AVG_PX: u32 = 6;
+    pub const BEGIN_SEQ_NO: u32 = 7;
+    pub const BEGIN_STRING: u32 = 8;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CHECK_SUM: u32 = 10;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const CUM_QTY: u32 = 14;
+    pub const END_SEQ_NO: u32 = 16;
+    pub const EXEC_ID: u32 = 17;
+    pub const EXEC_INST: u32 = 18;
+    pub const LAST_PX: u32 = 31;
+    pub const LAST_QTY: u32 = 32;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const NEW_SEQ_NO: u32 = 36;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const POSS_DUP_FLAG: u32 = 43;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const TIME_IN_FORCE: u32 = 59;
+    pub const TRANSACT_TIME: u32 = 60;
+    pub const POSS_RESEND: u32 = 97;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const CXL_REJ_REASON: u32 = 102;
+    pub const ORD_REJ_REASON: u32 = 103;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const ORIG_SENDING_TIME: u32 = 122;
+    pub const GAP_FILL_FLAG: u32 = 123;
+    pub const RESET_SEQ_NUM_FLAG: u32 = 141;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const REF_TAG_ID: u32 = 371;
+    pub const REF_MSG_TYPE: u32 = 372;
+    pub const SESSION_REJECT_REASON: u32 = 373;
+    pub const BUSINESS_REJECT_REASON: u32 = 380;
+    pub const CXL_REJ_RESPONSE_TO: u32 = 434;
+    pub const MASS_CANCEL_REQUEST_TYPE: u32 = 530;
+    pub const MASS_CANCEL_RESPONSE: u32 = 531;
+    pub const MASS_CANCEL_REJECT_REASON: u32 = 532;
+    pub const TOTAL_AFFECTED_ORDERS: u32 = 533;
+}
+
+/// Message types used by the gateway
+pub mod msg_type {
+    pub const HEARTBEAT: &str = "0";
+    pub const TEST_REQUEST: &str = "1";
+    pub const RESEND_REQUEST: &str = "2";
+    pub const REJECT: &str = "3";
+    pub const SEQUENCE_RESET: &str = "4";
+    pub const LOGOUT: &str = "5";
+    pub const EXECUTION_REPORT: &str = "8";
+    pub const ORDER_CANCEL_REJECT: &str = "9";
+    pub const LOGON: &str = "A";
+    pub const NEW_ORDER_SINGLE: &str = "D";
+    pub const ORDER_CANCEL_REQUEST: &str = "F";
+    pub const ORDER_CANCEL_REPLACE_REQUEST: &str = "G";
+    pub const BUSINESS_MESSAGE_REJECT: &str = "j";
+    pub const ORDER_MASS_CANCEL_REQUEST: &str = "q";
+    pub const ORDER_MASS_CANCEL_REPORT: &str = "r";
+
+    /// Returns true for the session level messages, which are never resent
+    pub fn is_admin(msg_type: &str) -> bool {
+        matches!(
+            msg_type,
+            HEARTBEAT | TEST_REQUEST | RESEND_REQUEST | REJECT | SEQUENCE_RESET | LOGOUT | LOGON
+        )
+    }
+}
+
+/// Standard header fields, written right after the message type in this order
+const HEADER_TAGS: [u32; 7] = [
+    tags::SENDER_COMP_ID,
+    tags::TARGET_COMP_ID,
+    tags::MSG_SEQ_NUM,
+    tags::POSS_DUP_FLAG,
+    tags::POSS_RESEND,
+    tags::SENDING_TIME,
+    tags::ORIG_SENDING_TIME,
+];
+
+/// A FIX message: its message type followed by the header and body fields. `BeginString`,
+/// `BodyLength` and `CheckSum` are computed when the message is encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixMessage {
+    fields: Vec<(u32, String)>,
+}
+
+impl FixMessage {
+    /// Create a message of type `msg_type` with no other field
+    pub fn new(msg_type: &str) -> Self {
+        Self {
+            fields: vec![(tags::MSG_TYPE, msg_type.to_string())],
+        }
+    }
+
+    pub fn msg_type(&self) -> &str {
+        self.get(tags::MSG_TYPE).unwrap_or_default()
+    }
+
+    /// Value of the first occurrence of `tag`
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Value of `tag` parsed as a `T`; `Err` holds the tag when the value does not parse
+    pub fn parse<T: FromStr>(&self, tag: u32) -> Result<Option<T>, u32> {
+        self.get(tag)
+            .map(|value| value.parse().map_err(|_| tag))
+            .transpose()
+    }
+
+    /// Returns true if `tag` holds `Y`
+    pub fn flag(&self, tag: u32) -> bool {
+        self.get(tag) == Some("Y")
+    }
+
+    /// Set `tag`, replacing its first occurrence if any
+    pub fn set(&mut self, tag: u32, value: impl ToString) -> &mut Self {
+        let value = value.to_string();
+        match self.fields.iter_mut().find(|(t, _)| *t == tag) {
+            Some(field) => field.1 = value,
+            None => self.fields.push((tag, value)),
+        }
+        self
+    }
+
+    /// Builder form of [`FixMessage::set`]
+    pub fn with(mut self, tag: u32, value: impl ToString) -> Self {
+        self.set(tag, value);
+        self
+    }
+
+    pub fn remove(&mut self, tag: u32) {
+        self.fields.retain(|(t, _)| *t != tag);
+    }
+
+    pub fn fields(&self) -> &[(u32, String)] {
+        &self.fields
+    }
+
+    /// Append the wire form of the message to `buffer`
+    pub fn encode(&self, buffer: &mut BytesMut) {
+        let mut body = Vec::with_capacity(256);
+        let mut push = |tag: u32, value: &str| {
+            body.extend_from_slice(tag.to_string().as_bytes());
+            body.push(b'=');
+            body.extend_from_slice(value.as_bytes());
+            body.push(SOH);
+        };
+        push(tags::MSG_TYPE, self.msg_type());
+        for tag in HEADER_TAGS {
+            if let Some(value) = self.get(tag) {
+                push(tag, value);
+            }
+        }
+        for (tag, value) in &self.fields {
+            if !HEADER_TAGS.contains(tag)
+                && !matches!(
+                    *tag,
+                    tags::MSG_TYPE | tags::BEGIN_STRING | tags::BODY_LENGTH | tags::CHECK_SUM
+                )
+            {
+                push(*tag, value);
+            }
+        }
+
+        let start = buffer.len();
+        buffer.put_slice(format!("8={}\u{1}9={}\u{1}", BEGIN_STRING, body.len()).as_bytes());
+        buffer.put_slice(&body);
+        let checksum = checksum(&buffer[start..]);
+        buffer.put_slice(format!("10={:03}\u{1}", checksum).as_bytes());
+    }
+
+    /// The wire form of the message
+    pub fn to_bytes(&self) -> BytesMut {
+        let mut buffer = BytesMut::new();
+        self.encode(&mut buffer);
+        buffer
+    }
+
+    /// Decode one complete message, including its header and trailer
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut buffer = BytesMut::from(bytes);
+        match FixCodec.decode(&mut buffer) {
+            Ok(Some(message)) if buffer.is_empty() => Some(message),
+            _ => None,
+        }
+    }
+}
+
+/// Parses the body fields of a message written as `tag=value` pairs separated by `|` or SOH,
+/// such as `35=D|11=order-1|55=BTC/USD`. `BeginString`, `BodyLength` and `CheckSum` are
+/// ignored if present.
+impl FromStr for FixMessage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = Vec::new();
+        for field in s
+            .split(['|', SOH as char])
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+        {
+            let (tag, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("invalid field: {}", field))?;
+            let tag: u32 = tag.parse().map_err(|_| format!("invalid tag: {}", tag))?;
+            if !matches!(
+                tag,
+                tags::BEGIN_STRING | tags::BODY_LENGTH | tags::CHECK_SUM
+            ) {
+                fields.push((tag, value.to_string()));
+            }
+        }
+        match fields.iter().position(|(tag, _)| *tag == tags::MSG_TYPE) {
+            Some(position) => {
+                let msg_type = fields.remove(position);
+                fields.insert(0, msg_type);
+                Ok(Self { fields })
+            }
+            None => Err("missing MsgType (35)".to_string()),
+        }
+    }
+}
+
+/// Writes the message with `|` as the separator, for logs
+impl fmt::Display for FixMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (tag, value) in &self.fields {
+            write!(f, "{}={}|", tag, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Frames FIX messages on a byte stream. Garbled messages (bad length, checksum or
+/// `BeginString`) are discarded as the protocol requires, and decoding resumes at the next
+/// `8=FIX.4.4`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixCodec;
+
+impl FixCodec {
+    /// Discard bytes up to the next possible start of a message
+    fn resync(buffer: &mut BytesMut) {
+        let start = buffer[1..]
+            .windows(2)
+            .position(|window| window == b"8=")
+            .map_or(buffer.len(), |position| position + 1);
+        buffer.advance(start);
+    }
+}
+
+impl Decoder for FixCodec {
+    type Item = FixMessage;
+    type Error = io::Error;
+
+    fn decode(&mut self, buffer: &mut BytesMut) -> Result<Option<FixMessage>, io::Error> {
+        loop {
+            if buffer.len() < 2 {
+                return Ok(None);
+            }
+            if !buffer.starts_with(b"8=") {
+                Self::resync(buffer);
+                continue;
+            }
+
+            // 8=FIX.4.4<SOH>9=<length><SOH>
+            let Some(begin_end) = buffer.iter().position(|byte| *byte == SOH) else {
+                if buffer.len() > 32 {
+                    Self::resync(buffer);
+                    continue;
+                }
+                return Ok(None);
+            };
+            let Some(length_end) = buffer[begin_end + 1..]
+                .iter()
+                .position(|byte| *byte == SOH)
+                .map(|position| begin_end + 1 + position)
+            else {
+                if buffer.len() > begin_end + 32 {
+                    Self::resync(buffer);
+                    continue;
+                }
+                return Ok(None);
+            };
+            let body_length = std::str::from_utf8(&buffer[begin_end + 1..length_end])
+                .ok()
+                .and_then(|field| field.strip_prefix("9="))
+                .and_then(|length| length.parse::<usize>().ok());
+            let begin_string = &buffer[2..begin_end];
+            let Some(body_length) = body_length.filter(|length| *length <= MAX_MESSAGE_BYTES)
+            else {
+                warn!("Discarding FIX message with an invalid BodyLength");
+                Self::resync(buffer);
+                continue;
+            };
+
+            let body_start = length_end + 1;
+            let trailer_start = body_start + body_length;
+            if buffer.len() < trailer_start + 7 {
+                return Ok(None);
+            }
+            let trailer = &buffer[trailer_start..trailer_start + 7];
+            let expected = format!("10={:03}\u{1}", checksum(&buffer[..trailer_start]));
+            if !trailer.starts_with(b"10=") || trailer[6] != SOH {
+                warn!("Discarding FIX message whose BodyLength does not match its content");
+                Self::resync(buffer);
+                continue;
+            }
+            let valid = trailer == expected.as_bytes() && begin_string == BEGIN_STRING.as_bytes();
+            let frame = buffer.split_to(trailer_start + 7);
+            if !valid {
+                warn!("Discarding FIX message with a bad CheckSum or BeginString");
+                continue;
+            }
+
+            let body = &frame[body_start..trailer_start];
+            let mut fields = Vec::new();
+            for field in body
+                .split(|byte| *byte == SOH)
+                .filter(|field| !field.is_empty())
+            {
+                let parsed = std::str::from_utf8(field)
+                    .ok()
+                    .and_then(|field| field.split_once('='))
+                    .and_then(|(tag, value)| Some((tag.parse::<u32>().ok()?, value.to_string())));
+                match parsed {
+                    Some(field) => fields.push(field),
+                    None => {
+                        fields.clear();
+                        break;
+                    }
+                }
+            }
+            if fields.first().is_none_or(|(tag, _)| *tag != tags::MSG_TYPE) {
+                warn!("Discarding FIX message with malformed fields");
+                continue;
+            }
+            return Ok(Some(FixMessage { fields }));
+        }
+    }
+}
+
+impl Encoder<FixMessage> for FixCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, message: FixMessage, buffer: &mut BytesMut) -> Result<(), io::Error> {
+        message.encode(buffer);
+        Ok(())
+    }
+}
+
+/// Current time in the `UTCTimestamp` format, with milliseconds
+pub fn utc_timestamp() -> String {
+    chrono::Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string()
+}
+
+/// A time in milliseconds since the epoch in the `UTCTimestamp` format
+pub fn utc_timestamp_from_millis(millis: u64) -> String {
+    chrono::DateTime::from_timestamp_millis(millis as i64)
+        .unwrap_or_default()
+        .format("%Y%m%d-%H:%M:%S%.3f")
+        .to_string()
+}
@@ -0,0 +1,129 @@
+//! FIX 4.4 order entry gateway.
+//!
+//! The gateway accepts FIX sessions over TCP. Each session is identified by the
+//! `SenderCompID` of the client, which maps to the account owning the orders it enters.
+//! Sessions start with a Logon, and support Heartbeat, TestRequest, ResendRequest,
+//! SequenceReset (gap fill and reset), Reject and Logout. Sequence numbers and sent messages
+//! are kept in a [`SessionStore`] per session, so that a session resumes after a disconnect
+//! or a restart of the server and can resend what the client missed.
+//!
+//! NewOrderSingle, OrderCancelRequest, OrderCancelReplaceRequest and OrderMassCancelRequest
+//! go through the [`crate::Sequencer`] of the books, like the REST orders. ExecutionReports
+//! are built from the notifications of the account on the [`UserStreamHub`], so a session
+//! also reports the fills of its resting orders and the orders its account entered over
+//! REST. Prices and quantities are integers in the units of the books.
+
+mod client;
+mod message;
+mod orders;
+mod session;
+mod store;
+
+pub use client::FixClient;
+pub use message::{BEGIN_STRING, FixCodec, FixMessage, SOH, msg_type, tags, utc_timestamp};
+pub use store::{SessionState, SessionStore};
+
+use crate::Sequencer;
+use crate::api::persistence::Outbox;
+use crate::api::stream::UserStreamHub;
+use dashmap::DashMap;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Settings of the FIX gateway
+#[derive(Debug, Clone)]
+pub struct FixGatewayConfig {
+    /// `CompID` of the gateway, the `TargetCompID` of the clients
+    pub comp_id: String,
+    /// Account of each client, by its `SenderCompID`
+    pub sessions: HashMap<String, Uuid>,
+    /// Directory holding a [`SessionStore`] per session
+    pub store_dir: PathBuf,
+    /// Time a new connection has to send its Logon
+    pub logon_timeout: Duration,
+    /// Shortest and longest `HeartBtInt` accepted, in seconds
+    pub heartbeat_range: (u64, u64),
+}
+
+impl FixGatewayConfig {
+    pub fn new(comp_id: impl Into<String>, store_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            comp_id: comp_id.into(),
+            sessions: HashMap::new(),
+            store_dir: store_dir.into(),
+            logon_timeout: Duration::from_secs(10),
+            heartbeat_range: (1, 300),
+        }
+    }
+
+    /// Accept the client `sender_comp_id`, entering orders for `account`
+    pub fn with_session(mut self, sender_comp_id: impl Into<String>, account: Uuid) -> Self {
+        self.sessions.insert(sender_comp_id.into(), account);
+        self
+    }
+}
+
+/// State shared by the sessions of the gateway
+pub struct FixGateway {
+    config: FixGatewayConfig,
+    sequencers: Arc<DashMap<String, Arc<Sequencer>>>,
+    user_streams: Arc<UserStreamHub>,
+    outbox: Arc<Outbox>,
+    /// Sessions currently logged on, by the `SenderCompID` of their client
+    logged_on: Mutex<HashSet<String>>,
+}
+
+impl std::fmt::Debug for FixGateway {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FixGateway")
+            .field("config", &self.config)
+            .field("symbols", &self.sequencers.len())
+            .finish()
+    }
+}
+
+impl FixGateway {
+    pub fn new(
+        config: FixGatewayConfig,
+        sequencers: Arc<DashMap<String, Arc<Sequencer>>>,
+        user_streams: Arc<UserStreamHub>,
+        outbox: Arc<Outbox>,
+    ) -> Self {
+        Self {
+            config,
+            sequencers,
+            user_streams,
+            outbox,
+            logged_on: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn config(&self) -> &FixGatewayConfig {
+        &self.config
+    }
+}
+
+/// Accept FIX connections on `listener` until it fails, serving each one on its own task
+pub async fn run_fix_acceptor(listener: TcpListener, gateway: Arc<FixGateway>) -> io::Result<()> {
+    info!(
+        "FIX gateway {} listening on {}",
+        gateway.config.comp_id,
+        listener.local_addr()?
+    );
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        if let Err(e) = stream.set_nodelay(true) {
+            warn!(
+                "Failed to disable Nagle's algorithm for FIX connection {}: {}",
+                peer, e
+            );
+        }
+        actix_rt::spawn(session::serve_connection(gateway.clone(), stream, peer));
+    }
+}
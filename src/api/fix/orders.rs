@@ -0,0 +1,647 @@
+use crate::api::orders::{self, NewOrder, SubmitError};
+use crate::api::persistence::NewOrderRow;
+use crate::api::stream::{OrderEvent, OrderNotification};
+use crate::{OrderOwner, Sequencer};
+use pricelevel::{OrderId, OrderType, OrderUpdate, Side, TimeInForce};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::FixGateway;
+use super::message::{FixMessage, msg_type, tags, utc_timestamp_from_millis};
+
+/// `SessionRejectReason` values
+pub(super) mod reject_reason {
+    pub const REQUIRED_TAG_MISSING: u32 = 1;
+    pub const VALUE_INCORRECT: u32 = 5;
+}
+
+/// A message refused at the session level with a Reject
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct SessionReject {
+    pub tag: u32,
+    pub reason: u32,
+    pub text: String,
+}
+
+impl SessionReject {
+    fn missing(tag: u32) -> Self {
+        Self {
+            tag,
+            reason: reject_reason::REQUIRED_TAG_MISSING,
+            text: format!("Required tag missing: {}", tag),
+        }
+    }
+
+    fn incorrect(tag: u32) -> Self {
+        Self {
+            tag,
+            reason: reject_reason::VALUE_INCORRECT,
+            text: format!("Value is incorrect for tag {}", tag),
+        }
+    }
+}
+
+fn required(message: &FixMessage, tag: u32) -> Result<&str, SessionReject> {
+    message
+        .get(tag)
+        .filter(|value| !value.is_empty())
+        .ok_or(SessionReject::missing(tag))
+}
+
+fn required_u64(message: &FixMessage, tag: u32) -> Result<u64, SessionReject> {
+    message
+        .parse::<u64>(tag)
+        .map_err(SessionReject::incorrect)?
+        .ok_or(SessionReject::missing(tag))
+}
+
+fn side(message: &FixMessage) -> Result<Side, SessionReject> {
+    match required(message, tags::SIDE)? {
+        "1" => Ok(Side::Buy),
+        "2" => Ok(Side::Sell),
+        _ => Err(SessionReject::incorrect(tags::SIDE)),
+    }
+}
+
+fn side_code(side: Side) -> &'static str {
+    match side {
+        Side::Buy => "1",
+        Side::Sell => "2",
+    }
+}
+
+/// Identifiers a client gave to one of its orders
+#[derive(Debug, Clone)]
+struct ClientOrder {
+    symbol: String,
+    cl_ord_id: String,
+    /// `ClOrdID` being replaced, reported on the next execution report
+    orig_cl_ord_id: Option<String>,
+    /// `ClOrdID` and `OrigClOrdID` of a cancel request accepted by the book
+    cancel: Option<(String, String)>,
+    /// Whether an execution report was sent for the order
+    acknowledged: bool,
+}
+
+/// Turns the order messages of one session into operations of the books, and the
+/// notifications of its account into execution reports
+pub(super) struct OrderRouter {
+    gateway: Arc<FixGateway>,
+    account: Uuid,
+    orders: HashMap<OrderId, ClientOrder>,
+    /// Live `ClOrdID`s of the session, including those given by replace requests
+    cl_ord_ids: HashMap<String, OrderId>,
+}
+
+impl OrderRouter {
+    pub fn new(gateway: Arc<FixGateway>, account: Uuid) -> Self {
+        Self {
+            gateway,
+            account,
+            orders: HashMap::new(),
+            cl_ord_ids: HashMap::new(),
+        }
+    }
+
+    /// Handle an application message, returning the messages to send back right away.
+    /// Execution reports of accepted requests come later, from the account notifications.
+    pub async fn handle(&mut self, message: &FixMessage) -> Result<Vec<FixMessage>, SessionReject> {
+        match message.msg_type() {
+            msg_type::NEW_ORDER_SINGLE => self.new_order(message).await,
+            msg_type::ORDER_CANCEL_REQUEST => self.cancel(message).await,
+            msg_type::ORDER_CANCEL_REPLACE_REQUEST => self.replace(message).await,
+            msg_type::ORDER_MASS_CANCEL_REQUEST => self.mass_cancel(message).await,
+            other => Ok(vec![
+                FixMessage::new(msg_type::BUSINESS_MESSAGE_REJECT)
+                    .with(tags::REF_MSG_TYPE, other)
+                    .with(tags::BUSINESS_REJECT_REASON, 3)
+                    .with(tags::TEXT, "Unsupported message type"),
+            ]),
+        }
+    }
+
+    fn sequencer(&self, symbol: &str) -> Option<Arc<Sequencer>> {
+        self.gateway
+            .sequencers
+            .get(symbol)
+            .map(|item| item.value().clone())
+    }
+
+    async fn new_order(&mut self, message: &FixMessage) -> Result<Vec<FixMessage>, SessionReject> {
+        let cl_ord_id = required(message, tags::CL_ORD_ID)?.to_string();
+        let symbol = required(message, tags::SYMBOL)?.to_string();
+        let side = side(message)?;
+        let quantity = required_u64(message, tags::ORDER_QTY)?;
+        if quantity == 0 {
+            return Err(SessionReject::incorrect(tags::ORDER_QTY));
+        }
+        let time_in_force = match message.get(tags::TIME_IN_FORCE).unwrap_or("0") {
+            "0" => TimeInForce::Day,
+            "1" => TimeInForce::Gtc,
+            "3" => TimeInForce::Ioc,
+            "4" => TimeInForce::Fok,
+            _ => return Err(SessionReject::incorrect(tags::TIME_IN_FORCE)),
+        };
+        let post_only = message
+            .get(tags::EXEC_INST)
+            .is_some_and(|inst| inst.split(' ').any(|inst| inst == "6"));
+        let market = match required(message, tags::ORD_TYPE)? {
+            "1" => true,
+            "2" => false,
+            _ => return Err(SessionReject::incorrect(tags::ORD_TYPE)),
+        };
+        let price = if market {
+            None
+        } else {
+            Some(required_u64(message, tags::PRICE)?)
+        };
+
+        let id = OrderId(Uuid::new_v4());
+        let reject = |reason: u32, text: &str| {
+            vec![rejection(
+                id, &cl_ord_id, &symbol, side, price, quantity, reason, text,
+            )]
+        };
+        if cl_ord_id.len() > 64 {
+            return Ok(reject(99, "ClOrdID must be at most 64 characters"));
+        }
+        let Some(sequencer) = self.sequencer(&symbol) else {
+            return Ok(reject(1, "Unknown symbol"));
+        };
+
+        let timestamp = sequencer.book().clock().now_millis();
+        let order = price.map(|price| match post_only {
+            true => OrderType::PostOnly {
+                id,
+                price,
+                quantity,
+                side,
+                timestamp,
+                time_in_force,
+            },
+            false => OrderType::Standard {
+                id,
+                price,
+                quantity,
+                side,
+                timestamp,
+                time_in_force,
+            },
+        });
+        let order_type = match (market, post_only) {
+            (true, _) => "Market",
+            (false, true) => "PostOnly",
+            (false, false) => "Limit",
+        };
+        let row = NewOrderRow {
+            id: id.0,
+            symbol: symbol.clone(),
+            side: format!("{:?}", side),
+            order_type: order_type.to_string(),
+            quantity,
+            price,
+            time_in_force: format!("{:?}", time_in_force),
+            user_id: self.account,
+            client_order_id: Some(cl_ord_id.clone()),
+            visible_quantity: None,
+            hidden_quantity: None,
+        };
+        self.orders.insert(
+            id,
+            ClientOrder {
+                symbol: symbol.clone(),
+                cl_ord_id: cl_ord_id.clone(),
+                orig_cl_ord_id: None,
+                cancel: None,
+                acknowledged: false,
+            },
+        );
+        let owner = OrderOwner::new(self.account, Some(cl_ord_id.clone()));
+        let gateway = &self.gateway;
+        match orders::submit(
+            &sequencer,
+            &gateway.outbox,
+            &gateway.user_streams,
+            NewOrder {
+                row,
+                order,
+                side,
+                owner,
+                timestamp,
+            },
+        )
+        .await
+        {
+            Err(SubmitError::NotRecorded(e)) => {
+                self.orders.remove(&id);
+                return Ok(reject(99, &format!("Order could not be recorded: {}", e)));
+            }
+            // A duplicate ClOrdID still belongs to the live order
+            Err(SubmitError::Rejected(_)) => {
+                self.orders.remove(&id);
+            }
+            Ok(_) | Err(SubmitError::Failed(_)) => {
+                self.cl_ord_ids.insert(cl_ord_id, id);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    /// Find the live order a request refers to through its `OrigClOrdID`
+    fn find_order(&self, symbol: &str, orig_cl_ord_id: &str) -> Option<(Arc<Sequencer>, OrderId)> {
+        let sequencer = self.sequencer(symbol)?;
+        let order_id = self
+            .cl_ord_ids
+            .get(orig_cl_ord_id)
+            .copied()
+            .filter(|id| {
+                self.orders
+                    .get(id)
+                    .is_some_and(|order| order.symbol == symbol)
+            })
+            .or_else(|| sequencer.book().find_order_id(self.account, orig_cl_ord_id))?;
+        Some((sequencer, order_id))
+    }
+
+    async fn cancel(&mut self, message: &FixMessage) -> Result<Vec<FixMessage>, SessionReject> {
+        let cl_ord_id = required(message, tags::CL_ORD_ID)?.to_string();
+        let orig_cl_ord_id = required(message, tags::ORIG_CL_ORD_ID)?.to_string();
+        let symbol = required(message, tags::SYMBOL)?.to_string();
+        let cancel_reject = |order_id: Option<OrderId>, reason: u32, text: &str| {
+            vec![cancel_reject(
+                order_id,
+                &cl_ord_id,
+                &orig_cl_ord_id,
+                "1",
+                reason,
+                text,
+            )]
+        };
+
+        let Some((sequencer, order_id)) = self.find_order(&symbol, &orig_cl_ord_id) else {
+            return Ok(cancel_reject(None, 1, "Unknown order"));
+        };
+        match sequencer.cancel_order_async(order_id).await {
+            Ok(Some(_)) => {
+                let order = self.track(order_id, &symbol, &orig_cl_ord_id);
+                order.cancel = Some((cl_ord_id, orig_cl_ord_id));
+                Ok(Vec::new())
+            }
+            Ok(None) => Ok(cancel_reject(Some(order_id), 0, "Order is no longer live")),
+            Err(e) => Ok(cancel_reject(Some(order_id), 99, &e.to_string())),
+        }
+    }
+
+    async fn replace(&mut self, message: &FixMessage) -> Result<Vec<FixMessage>, SessionReject> {
+        let cl_ord_id = required(message, tags::CL_ORD_ID)?.to_string();
+        let orig_cl_ord_id = required(message, tags::ORIG_CL_ORD_ID)?.to_string();
+        let symbol = required(message, tags::SYMBOL)?.to_string();
+        let quantity = required_u64(message, tags::ORDER_QTY)?;
+        let price = message
+            .parse::<u64>(tags::PRICE)
+            .map_err(SessionReject::incorrect)?;
+        let cancel_reject = |order_id: Option<OrderId>, reason: u32, text: &str| {
+            vec![cancel_reject(
+                order_id,
+                &cl_ord_id,
+                &orig_cl_ord_id,
+                "2",
+                reason,
+                text,
+            )]
+        };
+
+        let Some((sequencer, order_id)) = self.find_order(&symbol, &orig_cl_ord_id) else {
+            return Ok(cancel_reject(None, 1, "Unknown order"));
+        };
+        if cl_ord_id != orig_cl_ord_id && self.cl_ord_ids.contains_key(&cl_ord_id) {
+            return Ok(cancel_reject(Some(order_id), 6, "Duplicate ClOrdID"));
+        }
+        // OrderQty is the new total quantity, the book amends the quantity left to execute
+        let filled = sequencer
+            .book()
+            .order_state(order_id)
+            .map_or(0, |record| record.filled_quantity);
+        let Some(remaining) = quantity
+            .checked_sub(filled)
+            .filter(|remaining| *remaining > 0)
+        else {
+            return Ok(cancel_reject(
+                Some(order_id),
+                99,
+                "OrderQty is not above the executed quantity",
+            ));
+        };
+        let update = match price {
+            Some(new_price) => OrderUpdate::UpdatePriceAndQuantity {
+                order_id,
+                new_price,
+                new_quantity: remaining,
+            },
+            None => OrderUpdate::UpdateQuantity {
+                order_id,
+                new_quantity: remaining,
+            },
+        };
+        match sequencer.update_order_async(update).await {
+            Ok(Some(_)) => {
+                let order = self.track(order_id, &symbol, &orig_cl_ord_id);
+                order.cl_ord_id = cl_ord_id.clone();
+                order.orig_cl_ord_id = Some(orig_cl_ord_id);
+                self.cl_ord_ids.insert(cl_ord_id, order_id);
+                Ok(Vec::new())
+            }
+            Ok(None) => Ok(cancel_reject(Some(order_id), 0, "Order is no longer live")),
+            Err(e) => Ok(cancel_reject(Some(order_id), 99, &e.to_string())),
+        }
+    }
+
+    async fn mass_cancel(
+        &mut self,
+        message: &FixMessage,
+    ) -> Result<Vec<FixMessage>, SessionReject> {
+        let cl_ord_id = required(message, tags::CL_ORD_ID)?.to_string();
+        let request_type = required(message, tags::MASS_CANCEL_REQUEST_TYPE)?.to_string();
+        let report = FixMessage::new(msg_type::ORDER_MASS_CANCEL_REPORT)
+            .with(tags::ORDER_ID, Uuid::new_v4())
+            .with(tags::CL_ORD_ID, &cl_ord_id)
+            .with(tags::MASS_CANCEL_REQUEST_TYPE, &request_type);
+
+        let sequencers: Vec<Arc<Sequencer>> = match request_type.as_str() {
+            // Cancel orders for a security
+            "1" => {
+                let symbol = required(message, tags::SYMBOL)?;
+                match self.sequencer(symbol) {
+                    Some(sequencer) => vec![sequencer],
+                    None => {
+                        return Ok(vec![
+                            report
+                                .with(tags::SYMBOL, symbol)
+                                .with(tags::MASS_CANCEL_RESPONSE, 0)
+                                .with(tags::MASS_CANCEL_REJECT_REASON, 1)
+                                .with(tags::TEXT, "Unknown symbol"),
+                        ]);
+                    }
+                }
+            }
+            // Cancel all orders
+            "7" => self
+                .gateway
+                .sequencers
+                .iter()
+                .map(|item| item.value().clone())
+                .collect(),
+            _ => {
+                return Ok(vec![
+                    report
+                        .with(tags::MASS_CANCEL_RESPONSE, 0)
+                        .with(tags::MASS_CANCEL_REJECT_REASON, 99)
+                        .with(tags::TEXT, "Unsupported MassCancelRequestType"),
+                ]);
+            }
+        };
+
+        let mut affected = 0;
+        for sequencer in sequencers {
+            match sequencer.cancel_account_orders_async(self.account).await {
+                Ok(cancelled) => affected += cancelled.len(),
+                Err(e) => {
+                    return Ok(vec![
+                        report
+                            .with(tags::MASS_CANCEL_RESPONSE, 0)
+                            .with(tags::MASS_CANCEL_REJECT_REASON, 99)
+                            .with(tags::TEXT, e),
+                    ]);
+                }
+            }
+        }
+        let mut report = report
+            .with(tags::MASS_CANCEL_RESPONSE, &request_type)
+            .with(tags::TOTAL_AFFECTED_ORDERS, affected);
+        if let Some(symbol) = message.get(tags::SYMBOL) {
+            report.set(tags::SYMBOL, symbol);
+        }
+        Ok(vec![report])
+    }
+
+    /// The identifiers of an order, registering orders entered elsewhere (over REST or
+    /// before a restart) under the `ClOrdID` the request used
+    fn track(&mut self, order_id: OrderId, symbol: &str, cl_ord_id: &str) -> &mut ClientOrder {
+        self.cl_ord_ids
+            .entry(cl_ord_id.to_string())
+            .or_insert(order_id);
+        self.orders.entry(order_id).or_insert_with(|| ClientOrder {
+            symbol: symbol.to_string(),
+            cl_ord_id: cl_ord_id.to_string(),
+            orig_cl_ord_id: None,
+            cancel: None,
+            acknowledged: true,
+        })
+    }
+
+    fn forget(&mut self, order_id: OrderId) {
+        if self.orders.remove(&order_id).is_some() {
+            self.cl_ord_ids.retain(|_, id| *id != order_id);
+        }
+    }
+
+    /// Execution reports for a notification of the account: one per fill, then one for the
+    /// change of status if it is not a fill
+    pub fn execution_reports(&mut self, notification: &OrderNotification) -> Vec<FixMessage> {
+        let n = notification;
+        let mut client = self.orders.get(&n.order_id).cloned();
+        let cl_ord_id = client
+            .as_ref()
+            .map(|order| order.cl_ord_id.clone())
+            .or_else(|| n.client_order_id.clone())
+            .unwrap_or_else(|| n.order_id.to_string());
+        let report = |exec_type: &str,
+                      ord_status: &str,
+                      cumulative: u64,
+                      leaves: u64,
+                      average: Option<f64>| {
+            let mut report = FixMessage::new(msg_type::EXECUTION_REPORT)
+                .with(tags::ORDER_ID, n.order_id)
+                .with(tags::CL_ORD_ID, &cl_ord_id)
+                .with(tags::EXEC_ID, Uuid::new_v4())
+                .with(tags::EXEC_TYPE, exec_type)
+                .with(tags::ORD_STATUS, ord_status)
+                .with(tags::SYMBOL, &n.symbol)
+                .with(tags::SIDE, side_code(n.side))
+                .with(tags::ORDER_QTY, n.quantity)
+                .with(tags::LEAVES_QTY, leaves)
+                .with(tags::CUM_QTY, cumulative)
+                .with(tags::AVG_PX, average.unwrap_or(0.0))
+                .with(tags::TRANSACT_TIME, utc_timestamp_from_millis(n.timestamp));
+            if let Some(price) = n.price {
+                report.set(tags::PRICE, price);
+            }
+            report
+        };
+        let working_status = |cumulative: u64| if cumulative > 0 { "1" } else { "0" };
+
+        let mut reports = Vec::new();
+        let filled: u64 = n.fills.iter().map(|fill| fill.quantity).sum();
+        let mut cumulative = n.cumulative_quantity.saturating_sub(filled);
+        if client.as_ref().is_some_and(|order| !order.acknowledged)
+            && !matches!(n.event, OrderEvent::Accepted | OrderEvent::Rejected)
+        {
+            reports.push(report(
+                "0",
+                working_status(cumulative),
+                cumulative,
+                n.quantity.saturating_sub(cumulative),
+                None,
+            ));
+        }
+
+        // Running average price, from the value executed before these fills
+        let mut value = n.average_price.unwrap_or(0.0) * n.cumulative_quantity as f64
+            - n.fills
+                .iter()
+                .map(|fill| fill.price as f64 * fill.quantity as f64)
+                .sum::<f64>();
+        let fill_ends_report = matches!(n.event, OrderEvent::PartiallyFilled | OrderEvent::Filled);
+        for (index, fill) in n.fills.iter().enumerate() {
+            cumulative += fill.quantity;
+            value += fill.price as f64 * fill.quantity as f64;
+            let last = fill_ends_report && index + 1 == n.fills.len();
+            let (status, leaves) = match (last, n.event) {
+                (true, OrderEvent::Filled) => ("2", 0),
+                (true, _) => ("1", n.remaining_quantity),
+                (false, _) => ("1", n.quantity.saturating_sub(cumulative)),
+            };
+            let role = match fill.role {
+                crate::api::stream::LiquidityRole::Maker => "M",
+                crate::api::stream::LiquidityRole::Taker => "T",
+            };
+            reports.push(
+                report(
+                    "F",
+                    status,
+                    cumulative,
+                    leaves,
+                    Some(value / cumulative as f64),
+                )
+                .with(tags::EXEC_ID, format!("{}-{}", fill.trade_id, role))
+                .with(tags::LAST_QTY, fill.quantity)
+                .with(tags::LAST_PX, fill.price),
+            );
+        }
+
+        let text = n.reason.map(|reason| format!("{:?}", reason));
+        match n.event {
+            OrderEvent::Accepted => reports.push(report(
+                "0",
+                working_status(n.cumulative_quantity),
+                n.cumulative_quantity,
+                n.remaining_quantity,
+                n.average_price,
+            )),
+            OrderEvent::Replaced => {
+                let mut replaced = report(
+                    "5",
+                    working_status(n.cumulative_quantity),
+                    n.cumulative_quantity,
+                    n.remaining_quantity,
+                    n.average_price,
+                );
+                if let Some(orig) = client
+                    .as_mut()
+                    .and_then(|order| order.orig_cl_ord_id.take())
+                {
+                    replaced.set(tags::ORIG_CL_ORD_ID, orig);
+                }
+                reports.push(replaced);
+            }
+            OrderEvent::PartiallyFilled | OrderEvent::Filled => {}
+            OrderEvent::Cancelled | OrderEvent::Expired | OrderEvent::Rejected => {
+                let code = match n.event {
+                    OrderEvent::Cancelled => "4",
+                    OrderEvent::Expired => "C",
+                    _ => "8",
+                };
+                let mut last = report(code, code, n.cumulative_quantity, 0, n.average_price);
+                if let Some((cancel_cl_ord_id, orig)) =
+                    client.as_ref().and_then(|order| order.cancel.clone())
+                {
+                    last.set(tags::CL_ORD_ID, cancel_cl_ord_id)
+                        .set(tags::ORIG_CL_ORD_ID, orig);
+                }
+                if n.event == OrderEvent::Rejected {
+                    last.set(tags::ORD_REJ_REASON, 99);
+                }
+                if let Some(text) = &text {
+                    last.set(tags::TEXT, text);
+                }
+                reports.push(last);
+            }
+        }
+
+        if matches!(
+            n.event,
+            OrderEvent::Filled | OrderEvent::Cancelled | OrderEvent::Expired | OrderEvent::Rejected
+        ) {
+            self.forget(n.order_id);
+        } else if let Some(mut order) = client {
+            order.acknowledged = true;
+            self.orders.insert(n.order_id, order);
+        }
+        reports
+    }
+}
+
+/// Execution report rejecting a new order that never reached a book
+#[allow(clippy::too_many_arguments)]
+fn rejection(
+    order_id: OrderId,
+    cl_ord_id: &str,
+    symbol: &str,
+    side: Side,
+    price: Option<u64>,
+    quantity: u64,
+    reason: u32,
+    text: &str,
+) -> FixMessage {
+    let mut report = FixMessage::new(msg_type::EXECUTION_REPORT)
+        .with(tags::ORDER_ID, order_id)
+        .with(tags::CL_ORD_ID, cl_ord_id)
+        .with(tags::EXEC_ID, Uuid::new_v4())
+        .with(tags::EXEC_TYPE, "8")
+        .with(tags::ORD_STATUS, "8")
+        .with(tags::ORD_REJ_REASON, reason)
+        .with(tags::SYMBOL, symbol)
+        .with(tags::SIDE, side_code(side))
+        .with(tags::ORDER_QTY, quantity)
+        .with(tags::LEAVES_QTY, 0)
+        .with(tags::CUM_QTY, 0)
+        .with(tags::AVG_PX, 0)
+        .with(tags::TRANSACT_TIME, super::message::utc_timestamp())
+        .with(tags::TEXT, text);
+    if let Some(price) = price {
+        report.set(tags::PRICE, price);
+    }
+    report
+}
+
+fn cancel_reject(
+    order_id: Option<OrderId>,
+    cl_ord_id: &str,
+    orig_cl_ord_id: &str,
+    response_to: &str,
+    reason: u32,
+    text: &str,
+) -> FixMessage {
+    FixMessage::new(msg_type::ORDER_CANCEL_REJECT)
+        .with(
+            tags::ORDER_ID,
+            order_id.map_or_else(|| "NONE".to_string(), |id| id.to_string()),
+        )
+        .with(tags::CL_ORD_ID, cl_ord_id)
+        .with(tags::ORIG_CL_ORD_ID, orig_cl_ord_id)
+        .with(tags::ORD_STATUS, "8")
+        .with(tags::CXL_REJ_RESPONSE_TO, response_to)
+        .with(tags::CXL_REJ_REASON, reason)
+        .with(tags::TEXT, text)
+}
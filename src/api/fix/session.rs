@@ -0,0 +1,514 @@
+use actix_codec::Framed;
+use futures_util::{SinkExt, StreamExt};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use super::FixGateway;
+use super::message::{FixCodec, FixMessage, msg_type, tags, utc_timestamp};
+use super::orders::{OrderRouter, SessionReject};
+use super::store::SessionStore;
+use crate::api::stream::UserStreamSession;
+
+/// `SessionRejectReason` for a wrong `SenderCompID` or `TargetCompID`
+const COMP_ID_PROBLEM: u32 = 9;
+
+/// Removes the session from the logged on ones when the connection ends
+struct LogonGuard {
+    gateway: Arc<FixGateway>,
+    sender_comp_id: String,
+}
+
+impl Drop for LogonGuard {
+    fn drop(&mut self) {
+        self.gateway
+            .logged_on
+            .lock()
+            .unwrap()
+            .remove(&self.sender_comp_id);
+    }
+}
+
+/// A logged on FIX session
+struct Session {
+    gateway: Arc<FixGateway>,
+    framed: Framed<TcpStream, FixCodec>,
+    /// `SenderCompID` of the client
+    client: String,
+    store: SessionStore,
+    router: OrderRouter,
+    notifications: UserStreamSession,
+    heartbeat: Duration,
+    last_sent: Instant,
+    last_received: Instant,
+    /// Test request waiting for its heartbeat, and when it was sent
+    test_request: Option<(String, Instant)>,
+    /// Highest sequence number received while a resend request is outstanding
+    resend_target: Option<u64>,
+    _logon: LogonGuard,
+}
+
+/// Serve a FIX connection: wait for its Logon, then run the session until it logs out or the
+/// connection drops
+pub(super) async fn serve_connection(
+    gateway: Arc<FixGateway>,
+    stream: TcpStream,
+    peer: SocketAddr,
+) {
+    let mut framed = Framed::new(stream, FixCodec);
+    let logon = match tokio::time::timeout(gateway.config.logon_timeout, framed.next()).await {
+        Ok(Some(Ok(message))) => message,
+        _ => {
+            warn!("FIX connection {} closed without logging on", peer);
+            return;
+        }
+    };
+    match Session::logon(gateway, framed, logon, peer).await {
+        Ok(Some(mut session)) => {
+            if let Err(e) = session.run().await {
+                warn!("FIX session {} failed: {}", session.client, e);
+            }
+            info!("FIX session {} from {} ended", session.client, peer);
+        }
+        Ok(None) => {}
+        Err(e) => warn!("FIX logon from {} failed: {}", peer, e),
+    }
+}
+
+impl Session {
+    /// Validate a Logon and answer it. Returns `None` if the connection was refused.
+    async fn logon(
+        gateway: Arc<FixGateway>,
+        framed: Framed<TcpStream, FixCodec>,
+        logon: FixMessage,
+        peer: SocketAddr,
+    ) -> io::Result<Option<Self>> {
+        // A first message that is not a valid Logon is answered by closing the connection
+        let client = logon
+            .get(tags::SENDER_COMP_ID)
+            .unwrap_or_default()
+            .to_string();
+        let Some(account) = gateway.config.sessions.get(&client).copied() else {
+            warn!(
+                "FIX connection {} refused: unknown SenderCompID {:?}",
+                peer, client
+            );
+            return Ok(None);
+        };
+        if logon.msg_type() != msg_type::LOGON
+            || logon.get(tags::TARGET_COMP_ID) != Some(gateway.config.comp_id.as_str())
+        {
+            warn!(
+                "FIX connection {} refused: first message is not a Logon to {}",
+                peer, gateway.config.comp_id
+            );
+            return Ok(None);
+        }
+        if !gateway.logged_on.lock().unwrap().insert(client.clone()) {
+            warn!(
+                "FIX connection {} refused: session {} is already logged on",
+                peer, client
+            );
+            return Ok(None);
+        }
+        let guard = LogonGuard {
+            gateway: gateway.clone(),
+            sender_comp_id: client.clone(),
+        };
+
+        let mut store = SessionStore::open(gateway.config.store_dir.join(&client))?;
+        let reset = logon.flag(tags::RESET_SEQ_NUM_FLAG);
+        if reset {
+            store.reset()?;
+        }
+        let (min_heartbeat, max_heartbeat) = gateway.config.heartbeat_range;
+        let heartbeat = logon
+            .parse::<u64>(tags::HEART_BT_INT)
+            .ok()
+            .flatten()
+            .filter(|interval| (min_heartbeat..=max_heartbeat).contains(interval));
+        let sequence = logon.parse::<u64>(tags::MSG_SEQ_NUM).ok().flatten();
+
+        let now = Instant::now();
+        let (hub, state) = (gateway.user_streams.clone(), store.state());
+        let from_sequence = (state.stream_epoch == hub.epoch()).then_some(state.next_notification);
+        let mut session = Session {
+            router: OrderRouter::new(gateway.clone(), account),
+            notifications: hub.open_session(account, from_sequence),
+            gateway,
+            framed,
+            client,
+            store,
+            heartbeat: Duration::from_secs(heartbeat.unwrap_or(30)),
+            last_sent: now,
+            last_received: now,
+            test_request: None,
+            resend_target: None,
+            _logon: guard,
+        };
+
+        let (Some(sequence), Some(heartbeat)) = (sequence, heartbeat) else {
+            session
+                .logout(&format!(
+                    "Logon needs MsgSeqNum and a HeartBtInt between {} and {}",
+                    min_heartbeat, max_heartbeat
+                ))
+                .await?;
+            return Ok(None);
+        };
+        let expected = session.store.state().next_incoming;
+        if sequence < expected {
+            session
+                .logout(&format!(
+                    "MsgSeqNum too low, expecting {} but received {}",
+                    expected, sequence
+                ))
+                .await?;
+            return Ok(None);
+        }
+
+        let mut response = FixMessage::new(msg_type::LOGON)
+            .with(tags::ENCRYPT_METHOD, 0)
+            .with(tags::HEART_BT_INT, heartbeat);
+        if reset {
+            response.set(tags::RESET_SEQ_NUM_FLAG, "Y");
+        }
+        session.send(response).await?;
+        if sequence > expected {
+            session.request_resend(expected, sequence).await?;
+        } else {
+            session
+                .store
+                .update(|state| state.next_incoming = sequence + 1)?;
+        }
+        session.save_stream_position()?;
+        info!(
+            "FIX session {} logged on from {} for account {}",
+            session.client, peer, account
+        );
+        Ok(Some(session))
+    }
+
+    async fn run(&mut self) -> io::Result<()> {
+        let mut ticker = tokio::time::interval(Duration::from_millis(200));
+        loop {
+            tokio::select! {
+                frame = self.framed.next() => {
+                    let message = match frame {
+                        Some(Ok(message)) => message,
+                        Some(Err(e)) => return Err(e),
+                        None => return Ok(()),
+                    };
+                    self.last_received = Instant::now();
+                    self.test_request = None;
+                    if !self.on_message(message).await? {
+                        return Ok(());
+                    }
+                }
+                batch = self.notifications.next_batch() => match batch {
+                    Ok(batch) => {
+                        for notification in &batch {
+                            for report in self.router.execution_reports(notification) {
+                                self.send(report).await?;
+                            }
+                        }
+                        self.save_stream_position()?;
+                    }
+                    Err(gap) => {
+                        warn!(
+                            "FIX session {} missed notifications {} to {}",
+                            self.client,
+                            gap.from_sequence,
+                            gap.first_available - 1
+                        );
+                        self.notifications = self
+                            .gateway
+                            .user_streams
+                            .open_session(self.notifications.account(), Some(gap.first_available));
+                    }
+                },
+                _ = ticker.tick() => {
+                    let now = Instant::now();
+                    if let Some((_, sent_at)) = &self.test_request {
+                        if now - *sent_at >= self.heartbeat {
+                            self.logout("Heartbeat timeout").await?;
+                            return Ok(());
+                        }
+                    } else if now - self.last_received >= self.heartbeat + self.heartbeat / 5 {
+                        let id = format!("TEST-{}", crate::current_time_millis());
+                        self.send(FixMessage::new(msg_type::TEST_REQUEST).with(tags::TEST_REQ_ID, &id))
+                            .await?;
+                        self.test_request = Some((id, now));
+                    }
+                    if now - self.last_sent >= self.heartbeat {
+                        self.send(FixMessage::new(msg_type::HEARTBEAT)).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Handle a message of the client. Returns false once the session is over.
+    async fn on_message(&mut self, message: FixMessage) -> io::Result<bool> {
+        let msg_type = message.msg_type().to_string();
+        let Ok(Some(sequence)) = message.parse::<u64>(tags::MSG_SEQ_NUM) else {
+            self.logout("MsgSeqNum is missing or invalid").await?;
+            return Ok(false);
+        };
+        if message.get(tags::SENDER_COMP_ID) != Some(self.client.as_str())
+            || message.get(tags::TARGET_COMP_ID) != Some(self.gateway.config.comp_id.as_str())
+        {
+            let reject = SessionReject {
+                tag: tags::SENDER_COMP_ID,
+                reason: COMP_ID_PROBLEM,
+                text: "CompID problem".to_string(),
+            };
+            self.reject(&msg_type, sequence, reject).await?;
+            self.logout("CompID problem").await?;
+            return Ok(false);
+        }
+
+        // SequenceReset in reset mode moves the expected sequence number whatever its own
+        if msg_type == msg_type::SEQUENCE_RESET && !message.flag(tags::GAP_FILL_FLAG) {
+            return self.sequence_reset(&message, sequence).await.map(|_| true);
+        }
+        // Resend requests are honored even when out of sequence
+        if msg_type == msg_type::RESEND_REQUEST {
+            self.resend(&message, sequence).await?;
+        }
+
+        let expected = self.store.state().next_incoming;
+        if sequence > expected {
+            if msg_type == msg_type::LOGOUT {
+                self.logout("Logout acknowledged").await?;
+                return Ok(false);
+            }
+            self.request_resend(expected, sequence).await?;
+            return Ok(true);
+        }
+        if sequence < expected {
+            if message.flag(tags::POSS_DUP_FLAG) {
+                return Ok(true);
+            }
+            self.logout(&format!(
+                "MsgSeqNum too low, expecting {} but received {}",
+                expected, sequence
+            ))
+            .await?;
+            return Ok(false);
+        }
+
+        if msg_type == msg_type::SEQUENCE_RESET {
+            return self.sequence_reset(&message, sequence).await.map(|_| true);
+        }
+        self.accept_sequence(sequence + 1)?;
+        match msg_type.as_str() {
+            msg_type::HEARTBEAT | msg_type::RESEND_REQUEST => {}
+            msg_type::TEST_REQUEST => {
+                let mut heartbeat = FixMessage::new(msg_type::HEARTBEAT);
+                if let Some(id) = message.get(tags::TEST_REQ_ID) {
+                    heartbeat.set(tags::TEST_REQ_ID, id);
+                }
+                self.send(heartbeat).await?;
+            }
+            msg_type::REJECT => warn!(
+                "FIX session {} rejected message {}: {}",
+                self.client,
+                message.get(tags::REF_SEQ_NUM).unwrap_or("?"),
+                message.get(tags::TEXT).unwrap_or_default()
+            ),
+            msg_type::LOGOUT => {
+                self.logout("Logout acknowledged").await?;
+                return Ok(false);
+            }
+            msg_type::LOGON => {
+                let reject = SessionReject {
+                    tag: tags::MSG_TYPE,
+                    reason: 5,
+                    text: "Session is already logged on".to_string(),
+                };
+                self.reject(&msg_type, sequence, reject).await?;
+            }
+            _ => match self.router.handle(&message).await {
+                Ok(responses) => {
+                    for response in responses {
+                        self.send(response).await?;
+                    }
+                }
+                Err(reject) => self.reject(&msg_type, sequence, reject).await?,
+            },
+        }
+        Ok(true)
+    }
+
+    /// Expect `next` as the sequence number of the next message of the client
+    fn accept_sequence(&mut self, next: u64) -> io::Result<()> {
+        self.store.update(|state| state.next_incoming = next)?;
+        if self.resend_target.is_some_and(|target| next > target) {
+            self.resend_target = None;
+        }
+        Ok(())
+    }
+
+    async fn sequence_reset(&mut self, message: &FixMessage, sequence: u64) -> io::Result<()> {
+        let expected = self.store.state().next_incoming;
+        match message.parse::<u64>(tags::NEW_SEQ_NO) {
+            Ok(Some(new_sequence))
+                if new_sequence >= expected
+                    && (message.flag(tags::GAP_FILL_FLAG) || new_sequence > 0) =>
+            {
+                self.accept_sequence(new_sequence)
+            }
+            Ok(Some(_)) => {
+                let reject = SessionReject {
+                    tag: tags::NEW_SEQ_NO,
+                    reason: 5,
+                    text: format!("NewSeqNo must not be lower than {}", expected),
+                };
+                self.reject(msg_type::SEQUENCE_RESET, sequence, reject)
+                    .await
+            }
+            _ => {
+                let reject = SessionReject {
+                    tag: tags::NEW_SEQ_NO,
+                    reason: 1,
+                    text: "Required tag missing: 36".to_string(),
+                };
+                self.reject(msg_type::SEQUENCE_RESET, sequence, reject)
+                    .await
+            }
+        }
+    }
+
+    /// Ask the client for the messages from `expected` on, once per gap
+    async fn request_resend(&mut self, expected: u64, received: u64) -> io::Result<()> {
+        let outstanding = self.resend_target.is_some();
+        self.resend_target = Some(
+            self.resend_target
+                .map_or(received, |target| target.max(received)),
+        );
+        if outstanding {
+            return Ok(());
+        }
+        self.send(
+            FixMessage::new(msg_type::RESEND_REQUEST)
+                .with(tags::BEGIN_SEQ_NO, expected)
+                .with(tags::END_SEQ_NO, 0),
+        )
+        .await
+    }
+
+    /// Answer a resend request: application messages are sent again as possible duplicates,
+    /// session messages and missing ones are skipped with gap fills
+    async fn resend(&mut self, request: &FixMessage, sequence: u64) -> io::Result<()> {
+        let last_sent = self.store.state().next_outgoing - 1;
+        let begin = request
+            .parse::<u64>(tags::BEGIN_SEQ_NO)
+            .ok()
+            .flatten()
+            .unwrap_or(0);
+        let end = match request.parse::<u64>(tags::END_SEQ_NO).ok().flatten() {
+            Some(0) | None => last_sent,
+            Some(end) => end.min(last_sent),
+        };
+        if begin == 0 || begin > end {
+            let reject = SessionReject {
+                tag: tags::BEGIN_SEQ_NO,
+                reason: 5,
+                text: format!("Cannot resend from {} to {}", begin, end),
+            };
+            return self
+                .reject(msg_type::RESEND_REQUEST, sequence, reject)
+                .await;
+        }
+
+        let mut next = begin;
+        for (sent_sequence, mut message) in self.store.sent_messages(begin, end)? {
+            if sent_sequence < next || msg_type::is_admin(message.msg_type()) {
+                continue;
+            }
+            if sent_sequence > next {
+                self.gap_fill(next, sent_sequence).await?;
+            }
+            let original_time = message
+                .get(tags::SENDING_TIME)
+                .unwrap_or_default()
+                .to_string();
+            message
+                .set(tags::POSS_DUP_FLAG, "Y")
+                .set(tags::ORIG_SENDING_TIME, original_time)
+                .set(tags::SENDING_TIME, utc_timestamp());
+            self.write(message).await?;
+            next = sent_sequence + 1;
+        }
+        if next <= end {
+            self.gap_fill(next, end + 1).await?;
+        }
+        Ok(())
+    }
+
+    async fn gap_fill(&mut self, sequence: u64, new_sequence: u64) -> io::Result<()> {
+        let gap_fill = self
+            .header(FixMessage::new(msg_type::SEQUENCE_RESET), sequence)
+            .with(tags::POSS_DUP_FLAG, "Y")
+            .with(tags::ORIG_SENDING_TIME, utc_timestamp())
+            .with(tags::GAP_FILL_FLAG, "Y")
+            .with(tags::NEW_SEQ_NO, new_sequence);
+        self.write(gap_fill).await
+    }
+
+    async fn reject(
+        &mut self,
+        ref_msg_type: &str,
+        ref_sequence: u64,
+        reject: SessionReject,
+    ) -> io::Result<()> {
+        let message = FixMessage::new(msg_type::REJECT)
+            .with(tags::REF_SEQ_NUM, ref_sequence)
+            .with(tags::REF_TAG_ID, reject.tag)
+            .with(tags::REF_MSG_TYPE, ref_msg_type)
+            .with(tags::SESSION_REJECT_REASON, reject.reason)
+            .with(tags::TEXT, reject.text);
+        self.send(message).await
+    }
+
+    async fn logout(&mut self, text: &str) -> io::Result<()> {
+        self.send(FixMessage::new(msg_type::LOGOUT).with(tags::TEXT, text))
+            .await
+    }
+
+    fn header(&self, message: FixMessage, sequence: u64) -> FixMessage {
+        message
+            .with(tags::SENDER_COMP_ID, &self.gateway.config.comp_id)
+            .with(tags::TARGET_COMP_ID, &self.client)
+            .with(tags::MSG_SEQ_NUM, sequence)
+            .with(tags::SENDING_TIME, utc_timestamp())
+    }
+
+    /// Send a message with the next sequence number, keeping it for resend requests
+    async fn send(&mut self, message: FixMessage) -> io::Result<()> {
+        let message = self.header(message, self.store.state().next_outgoing);
+        self.store.record_sent(&message)?;
+        self.write(message).await
+    }
+
+    async fn write(&mut self, message: FixMessage) -> io::Result<()> {
+        self.last_sent = Instant::now();
+        self.framed.send(message).await
+    }
+
+    fn save_stream_position(&mut self) -> io::Result<()> {
+        let (epoch, next) = (
+            self.notifications.epoch(),
+            self.notifications.next_sequence(),
+        );
+        self.store.update(|state| {
+            state.stream_epoch = epoch;
+            state.next_notification = next;
+        })
+    }
+}
@@ -0,0 +1,158 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::message::{FixMessage, tags};
+
+/// Persisted position of a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionState {
+    /// Sequence number expected on the next message of the counterparty
+    pub next_incoming: u64,
+    /// Sequence number of the next message sent
+    pub next_outgoing: u64,
+    /// Epoch of the user stream the execution reports were taken from, 0 if none
+    pub stream_epoch: u64,
+    /// Next notification of the user stream to turn into execution reports
+    pub next_notification: u64,
+}
+
+impl Default for SessionState {
+    fn default() -> Self {
+        Self {
+            next_incoming: 1,
+            next_outgoing: 1,
+            stream_epoch: 0,
+            next_notification: 1,
+        }
+    }
+}
+
+/// Sequence numbers and sent messages of a FIX session, kept in a directory so that they
+/// survive restarts. Sent messages are kept for resend requests until the sequence numbers
+/// are reset.
+pub struct SessionStore {
+    state_path: PathBuf,
+    messages_path: PathBuf,
+    state: SessionState,
+    messages: BufWriter<File>,
+}
+
+impl SessionStore {
+    /// Open the store in `dir`, creating it if needed
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let state_path = dir.join("session.state");
+        let messages_path = dir.join("messages.log");
+
+        let state = match fs::read_to_string(&state_path) {
+            Ok(state) => {
+                let values: Vec<u64> = state
+                    .split_whitespace()
+                    .map(|value| value.parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let [
+                    next_incoming,
+                    next_outgoing,
+                    stream_epoch,
+                    next_notification,
+                ] = values[..]
+                else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid session state in {}", state_path.display()),
+                    ));
+                };
+                SessionState {
+                    next_incoming,
+                    next_outgoing,
+                    stream_epoch,
+                    next_notification,
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => SessionState::default(),
+            Err(e) => return Err(e),
+        };
+        let messages = BufWriter::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&messages_path)?,
+        );
+        Ok(Self {
+            state_path,
+            messages_path,
+            state,
+            messages,
+        })
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Update the state and write it to disk
+    pub fn update(&mut self, update: impl FnOnce(&mut SessionState)) -> io::Result<()> {
+        update(&mut self.state);
+        let state = &self.state;
+        let tmp = self.state_path.with_extension("tmp");
+        fs::write(
+            &tmp,
+            format!(
+                "{} {} {} {}\n",
+                state.next_incoming,
+                state.next_outgoing,
+                state.stream_epoch,
+                state.next_notification
+            ),
+        )?;
+        fs::rename(&tmp, &self.state_path)
+    }
+
+    /// Record a message sent with the next outgoing sequence number
+    pub fn record_sent(&mut self, message: &FixMessage) -> io::Result<()> {
+        let mut line = message.to_bytes();
+        line.extend_from_slice(b"\n");
+        self.messages.write_all(&line)?;
+        self.messages.flush()?;
+        self.update(|state| state.next_outgoing += 1)
+    }
+
+    /// Start over from sequence number 1 in both directions, forgetting the sent messages
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.messages.flush()?;
+        self.messages.get_ref().set_len(0)?;
+        self.update(|state| {
+            state.next_incoming = 1;
+            state.next_outgoing = 1;
+        })
+    }
+
+    /// Sent messages whose sequence number is within `begin..=end`, in order
+    pub fn sent_messages(&self, begin: u64, end: u64) -> io::Result<Vec<(u64, FixMessage)>> {
+        let reader = BufReader::new(File::open(&self.messages_path)?);
+        let mut messages = Vec::new();
+        for line in reader.split(b'\n') {
+            let Some(message) = FixMessage::from_bytes(&line?) else {
+                continue;
+            };
+            if let Ok(Some(sequence)) = message.parse::<u64>(tags::MSG_SEQ_NUM)
+                && (begin..=end).contains(&sequence)
+            {
+                messages.push((sequence, message));
+            }
+        }
+        Ok(messages)
+    }
+}
+
+impl std::fmt::Debug for SessionStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionStore")
+            .field("path", &self.state_path)
+            .field("state", &self.state)
+            .finish()
+    }
+}
@@ -1,13 +1,16 @@
 use actix_web::{web, HttpResponse, Result};
 use dashmap::DashMap;
-use crate::OrderBook;
+use crate::{ExecutionReport, OrderBook, OrderBookError, OrderOwner, Sequencer};
 use pricelevel::{OrderId, OrderUpdate, Side, TimeInForce};
 use std::sync::Arc;
 
 use crate::api::{
     database::Database,
     models::{order::*, response::ApiResponse},
+    orders::{self, NewOrder, SubmitError},
+    persistence::{NewOrderRow, Outbox},
     redis::RedisClient,
+    stream::UserStreamHub,
 };
 use crate::orderbook::modifications::OrderQuantity;
 
@@ -17,82 +20,145 @@ pub struct PathOrderId { pub order_id: String }
 #[derive(serde::Deserialize)]
 pub struct PathUserId { pub user_id: String }
 
+#[derive(serde::Deserialize)]
+pub struct PathClientOrderId {
+    pub user_id: String,
+    pub client_order_id: String,
+}
+
 pub async fn create_order(
-    orderbooks: web::Data<Arc<DashMap<String, Arc<OrderBook>>>>,
-    db: web::Data<Database>,
+    sequencers: web::Data<Arc<DashMap<String, Arc<Sequencer>>>>,
+    outbox: web::Data<Arc<Outbox>>,
+    user_streams: web::Data<Arc<UserStreamHub>>,
     _redis: web::Data<RedisClient>,
     payload: web::Json<CreateOrderRequest>,
 ) -> Result<HttpResponse> {
     let req = payload.into_inner();
-    let Some(orderbook) = orderbooks.get(&req.symbol) else {
+    let Some(sequencer) = sequencers.get(&req.symbol).map(|item| item.value().clone()) else {
         return Ok(HttpResponse::NotFound().json(ApiResponse::<()>::error(format!(
             "Order book for symbol {} not found", req.symbol
         ))));
     };
 
-    let id = OrderId(req.user_id); // not ideal; generate real order id
+    if req
+        .client_order_id
+        .as_ref()
+        .is_some_and(|c| c.is_empty() || c.len() > 64)
+    {
+        return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+            "client_order_id must be 1 to 64 characters".to_string(),
+        )));
+    }
+
+    // Order ids are always generated by the server; clients refer to their orders through client_order_id
+    let id = OrderId(uuid::Uuid::new_v4());
+    let owner = OrderOwner::new(req.user_id, req.client_order_id.clone());
     let side: Side = req.side.clone().into();
     let tif: TimeInForce = req.time_in_force.clone().into();
+    let timestamp = sequencer.book().clock().now_millis();
 
-    match req.order_type {
-        OrderType::Market => {
-            let qty = req.quantity;
-            match orderbook.submit_market_order(id, qty, side) {
-                Ok(result) => {
-                    let body = serde_json::json!({
-                        "executed": result.executed_quantity(),
-                        "remaining": result.remaining_quantity,
-                        "complete": result.is_complete,
-                        "transactions": result.transactions.transactions.len()
-                    });
-                    Ok(HttpResponse::Ok().json(ApiResponse::success(body)))
-                }
-                Err(e) => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e.to_string()))),
-            }
-        }
+    // Market orders have no limit order to rest in the book
+    let (order, total_qty) = match req.order_type {
+        OrderType::Market => (None, req.quantity),
         OrderType::Limit | OrderType::ImmediateOrCancel | OrderType::FillOrKill => {
-            let price = req.price.ok_or_else(|| actix_web::error::ErrorBadRequest("price is required for limit/IOC/FOK"))?;
-            match orderbook.add_limit_order(id, price, req.quantity, side, tif) {
-                Ok(order_arc) => {
-                    // Persist order (best-effort)
-                    let _ = persist_order(&db, &req, order_arc.id().0.to_string(), price, req.quantity).await;
-                    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
-                        "order_id": order_arc.id(),
-                        "status": "PENDING"
-                    }))))
-                }
-                Err(e) => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e.to_string()))),
-            }
+            let price = req.price.ok_or_else(|| {
+                actix_web::error::ErrorBadRequest("price is required for limit/IOC/FOK")
+            })?;
+            (
+                Some(pricelevel::OrderType::Standard {
+                    id,
+                    price,
+                    quantity: req.quantity,
+                    side,
+                    timestamp,
+                    time_in_force: tif,
+                }),
+                req.quantity,
+            )
         }
         OrderType::PostOnly => {
-            let price = req.price.ok_or_else(|| actix_web::error::ErrorBadRequest("price is required for post-only"))?;
-            match orderbook.add_post_only_order(id, price, req.quantity, side, tif) {
-                Ok(order_arc) => {
-                    let _ = persist_order(&db, &req, order_arc.id().0.to_string(), price, req.quantity).await;
-                    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
-                        "order_id": order_arc.id(),
-                        "status": "PENDING"
-                    }))))
-                }
-                Err(e) => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e.to_string()))),
-            }
+            let price = req.price.ok_or_else(|| {
+                actix_web::error::ErrorBadRequest("price is required for post-only")
+            })?;
+            (
+                Some(pricelevel::OrderType::PostOnly {
+                    id,
+                    price,
+                    quantity: req.quantity,
+                    side,
+                    timestamp,
+                    time_in_force: tif,
+                }),
+                req.quantity,
+            )
         }
         OrderType::Iceberg => {
-            let price = req.price.ok_or_else(|| actix_web::error::ErrorBadRequest("price is required for iceberg"))?;
-            let vis = req.visible_quantity.ok_or_else(|| actix_web::error::ErrorBadRequest("visible_quantity required"))?;
-            let hid = req.hidden_quantity.ok_or_else(|| actix_web::error::ErrorBadRequest("hidden_quantity required"))?;
-            match orderbook.add_iceberg_order(id, price, vis, hid, side, tif) {
-                Ok(order_arc) => {
-                    let _ = persist_order(&db, &req, order_arc.id().0.to_string(), price, vis + hid).await;
-                    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
-                        "order_id": order_arc.id(),
-                        "status": "PENDING"
-                    }))))
-                }
-                Err(e) => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e.to_string()))),
-            }
+            let price = req.price.ok_or_else(|| {
+                actix_web::error::ErrorBadRequest("price is required for iceberg")
+            })?;
+            let vis = req
+                .visible_quantity
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("visible_quantity required"))?;
+            let hid = req
+                .hidden_quantity
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("hidden_quantity required"))?;
+            (
+                Some(pricelevel::OrderType::IcebergOrder {
+                    id,
+                    price,
+                    visible_quantity: vis,
+                    hidden_quantity: hid,
+                    side,
+                    timestamp,
+                    time_in_force: tif,
+                }),
+                vis + hid,
+            )
+        }
+        _ => {
+            return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+                "unsupported order type for this endpoint".to_string(),
+            )));
         }
-        _ => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error("unsupported order type for this endpoint".to_string()))),
+    };
+
+    let row = NewOrderRow {
+        id: id.0,
+        symbol: req.symbol.clone(),
+        side: format!("{:?}", req.side),
+        order_type: format!("{:?}", req.order_type),
+        quantity: total_qty,
+        price: order.map(|o| o.price()),
+        time_in_force: format!("{:?}", req.time_in_force),
+        user_id: req.user_id,
+        client_order_id: req.client_order_id.clone(),
+        visible_quantity: req.visible_quantity,
+        hidden_quantity: req.hidden_quantity,
+    };
+    match orders::submit(
+        &sequencer,
+        &outbox,
+        &user_streams,
+        NewOrder {
+            row,
+            order,
+            side,
+            owner,
+            timestamp,
+        },
+    )
+    .await
+    {
+        Ok(report) => Ok(
+            HttpResponse::Ok().json(ApiResponse::success(execution_report_json(
+                &report,
+                &req.client_order_id,
+            ))),
+        ),
+        Err(SubmitError::NotRecorded(e)) => Ok(HttpResponse::ServiceUnavailable().json(
+            ApiResponse::<()>::error(format!("order could not be recorded: {e}")),
+        )),
+        Err(SubmitError::Rejected(e) | SubmitError::Failed(e)) => Ok(engine_error_response(e)),
     }
 }
 
@@ -100,71 +166,112 @@ pub async fn get_order(
     path: web::Path<PathOrderId>,
     orderbooks: web::Data<Arc<DashMap<String, Arc<OrderBook>>>>,
 ) -> Result<HttpResponse> {
-    let _order_id = &path.order_id;
+    let order_uuid = uuid::Uuid::parse_str(&path.order_id)
+        .map_err(|_| actix_web::error::ErrorBadRequest("invalid order_id"))?;
+    let id = OrderId(order_uuid);
     // Without symbol lookup, we cannot efficiently find; iterate
     for item in orderbooks.iter() {
-        if let Ok(uuid) = uuid::Uuid::parse_str(&_order_id) {
-            let id = OrderId(uuid);
-            if let Some(order) = item.value().get_order(id) {
-                let resp = serde_json::json!({
-                    "order_id": order.id(),
-                    "symbol": item.key().clone(),
-                    "price": order.price(),
-                    "quantity": order.quantity(),
-                    "side": format!("{:?}", order.side()),
-                    "time_in_force": format!("{:?}", order.time_in_force()),
-                });
-                return Ok(HttpResponse::Ok().json(ApiResponse::success(resp)));
-            }
+        if let Some(resp) = order_details(item.key(), item.value(), id) {
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(resp)));
         }
     }
     Ok(HttpResponse::NotFound().json(ApiResponse::<()>::error("order not found".to_string())))
 }
 
+pub async fn get_order_by_client_id(
+    path: web::Path<PathClientOrderId>,
+    orderbooks: web::Data<Arc<DashMap<String, Arc<OrderBook>>>>,
+) -> Result<HttpResponse> {
+    if let Some((symbol, orderbook, id)) = find_client_order(&orderbooks, &path)?
+        && let Some(resp) = order_details(&symbol, &orderbook, id)
+    {
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(resp)));
+    }
+    Ok(HttpResponse::NotFound().json(ApiResponse::<()>::error("order not found".to_string())))
+}
+
 pub async fn update_order(
     path: web::Path<PathOrderId>,
-    orderbooks: web::Data<Arc<DashMap<String, Arc<OrderBook>>>>,
+    sequencers: web::Data<Arc<DashMap<String, Arc<Sequencer>>>>,
     payload: web::Json<UpdateOrderRequest>,
 ) -> Result<HttpResponse> {
     let order_uuid = uuid::Uuid::parse_str(&path.order_id).map_err(|_| actix_web::error::ErrorBadRequest("invalid order_id"))?;
     let id = OrderId(order_uuid);
-    let req = payload.into_inner();
-
-    for item in orderbooks.iter() {
-        let ob = item.value();
-        let result = if let (Some(price), Some(qty)) = (req.price, req.quantity) {
-            ob.update_order(OrderUpdate::UpdatePriceAndQuantity { order_id: id, new_price: price, new_quantity: qty })
-        } else if let Some(price) = req.price {
-            ob.update_order(OrderUpdate::UpdatePrice { order_id: id, new_price: price })
-        } else if let Some(qty) = req.quantity {
-            ob.update_order(OrderUpdate::UpdateQuantity { order_id: id, new_quantity: qty })
-        } else {
-            return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error("nothing to update".to_string())));
-        };
-
-        match result {
-            Ok(_) => return Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({"updated": true})))) ,
-            Err(_) => continue,
-        }
-    }
+    let Some(sequencer) = find_resting_order(&sequencers, id) else {
+        return Ok(
+            HttpResponse::NotFound().json(ApiResponse::<()>::error("order not found".to_string()))
+        );
+    };
+    Ok(amend_order(&sequencer, id, payload.into_inner()).await)
+}
 
-    Ok(HttpResponse::NotFound().json(ApiResponse::<()>::error("order not found".to_string())))
+pub async fn update_order_by_client_id(
+    path: web::Path<PathClientOrderId>,
+    orderbooks: web::Data<Arc<DashMap<String, Arc<OrderBook>>>>,
+    sequencers: web::Data<Arc<DashMap<String, Arc<Sequencer>>>>,
+    payload: web::Json<UpdateOrderRequest>,
+) -> Result<HttpResponse> {
+    let Some((symbol, _, id)) = find_client_order(&orderbooks, &path)? else {
+        return Ok(
+            HttpResponse::NotFound().json(ApiResponse::<()>::error("order not found".to_string()))
+        );
+    };
+    let Some(sequencer) = sequencers.get(&symbol).map(|item| item.value().clone()) else {
+        return Ok(
+            HttpResponse::NotFound().json(ApiResponse::<()>::error("order not found".to_string()))
+        );
+    };
+    Ok(amend_order(&sequencer, id, payload.into_inner()).await)
 }
 
 pub async fn cancel_order(
     path: web::Path<PathOrderId>,
-    orderbooks: web::Data<Arc<DashMap<String, Arc<OrderBook>>>>,
+    sequencers: web::Data<Arc<DashMap<String, Arc<Sequencer>>>>,
 ) -> Result<HttpResponse> {
     let order_uuid = uuid::Uuid::parse_str(&path.order_id).map_err(|_| actix_web::error::ErrorBadRequest("invalid order_id"))?;
     let id = OrderId(order_uuid);
-    for item in orderbooks.iter() {
-        match item.value().cancel_order(id) {
-            Ok(Some(_)) => return Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({"cancelled": true})))) ,
-            Ok(None) => continue,
-            Err(e) => return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e.to_string()))),
+    let Some(sequencer) = find_resting_order(&sequencers, id) else {
+        return Ok(
+            HttpResponse::NotFound().json(ApiResponse::<()>::error("order not found".to_string()))
+        );
+    };
+    match sequencer.cancel_order_async(id).await {
+        Ok(Some(_)) => Ok(HttpResponse::Ok().json(ApiResponse::success(
+            serde_json::json!({"cancelled": true, "order_id": id}),
+        ))),
+        Ok(None) => {
+            Ok(HttpResponse::NotFound()
+                .json(ApiResponse::<()>::error("order not found".to_string())))
         }
+        Err(e) => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e.to_string()))),
+    }
+}
+
+pub async fn cancel_order_by_client_id(
+    path: web::Path<PathClientOrderId>,
+    orderbooks: web::Data<Arc<DashMap<String, Arc<OrderBook>>>>,
+    sequencers: web::Data<Arc<DashMap<String, Arc<Sequencer>>>>,
+) -> Result<HttpResponse> {
+    let Some((symbol, _, id)) = find_client_order(&orderbooks, &path)? else {
+        return Ok(
+            HttpResponse::NotFound().json(ApiResponse::<()>::error("order not found".to_string()))
+        );
+    };
+    let Some(sequencer) = sequencers.get(&symbol).map(|item| item.value().clone()) else {
+        return Ok(
+            HttpResponse::NotFound().json(ApiResponse::<()>::error("order not found".to_string()))
+        );
+    };
+    match sequencer.cancel_order_async(id).await {
+        Ok(Some(_)) => Ok(HttpResponse::Ok().json(ApiResponse::success(
+            serde_json::json!({"cancelled": true, "order_id": id}),
+        ))),
+        Ok(None) => {
+            Ok(HttpResponse::NotFound()
+                .json(ApiResponse::<()>::error("order not found".to_string())))
+        }
+        Err(e) => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e.to_string()))),
     }
-    Ok(HttpResponse::NotFound().json(ApiResponse::<()>::error("order not found".to_string())))
 }
 
 pub async fn get_user_orders(
@@ -178,32 +285,118 @@ pub async fn get_user_orders(
     }))))
 }
 
-async fn persist_order(
-    db: &Database,
-    req: &CreateOrderRequest,
-    order_id: String,
-    price: u64,
-    total_qty: u64,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let client = db.pool.get().await?;
-    let _ = client.execute(
-        "INSERT INTO orders (id, symbol, side, order_type, quantity, price, time_in_force, status, user_id, remaining_quantity, visible_quantity, hidden_quantity) VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12)",
-        &[
-            &uuid::Uuid::parse_str(&order_id)?,
-            &req.symbol,
-            &format!("{:?}", req.side),
-            &format!("{:?}", req.order_type),
-            &(total_qty as i64),
-            &(price as i64),
-            &format!("{:?}", req.time_in_force),
-            &"PENDING",
-            &req.user_id,
-            &(total_qty as i64),
-            &req.visible_quantity.map(|v| v as i64),
-            &req.hidden_quantity.map(|v| v as i64),
-        ],
-    ).await?;
-    Ok(())
+/// Resolve a (user, client order id) pair to the book and engine id of the live order carrying it
+fn find_client_order(
+    orderbooks: &DashMap<String, Arc<OrderBook>>,
+    path: &PathClientOrderId,
+) -> Result<Option<(String, Arc<OrderBook>, OrderId)>> {
+    let account = uuid::Uuid::parse_str(&path.user_id)
+        .map_err(|_| actix_web::error::ErrorBadRequest("invalid user_id"))?;
+    Ok(orderbooks.iter().find_map(|item| {
+        item.value()
+            .find_order_id(account, &path.client_order_id)
+            .map(|id| (item.key().clone(), item.value().clone(), id))
+    }))
+}
+
+/// The sequencer of the book an order rests in; mutations of the books go through their sequencer
+fn find_resting_order(
+    sequencers: &DashMap<String, Arc<Sequencer>>,
+    id: OrderId,
+) -> Option<Arc<Sequencer>> {
+    sequencers
+        .iter()
+        .find(|item| item.value().book().get_order(id).is_some())
+        .map(|item| item.value().clone())
 }
 
+fn order_details(symbol: &str, orderbook: &OrderBook, id: OrderId) -> Option<serde_json::Value> {
+    let state = orderbook.order_state(id)?;
+    // Resting orders also report their current time in force and displayed quantity
+    let resting = orderbook.get_order(id);
+    Some(serde_json::json!({
+        "order_id": state.order_id,
+        "client_order_id": state.owner.as_ref().and_then(|o| o.client_order_id.clone()),
+        "user_id": state.owner.as_ref().map(|o| o.account),
+        "symbol": symbol,
+        "side": format!("{:?}", state.side),
+        "price": state.price,
+        "quantity": state.original_quantity,
+        "filled_quantity": state.filled_quantity,
+        "remaining_quantity": state.remaining_quantity(),
+        "average_fill_price": state.average_fill_price(),
+        "status": OrderStatus::from(state.status),
+        "reason": state.reason.map(|r| format!("{:?}", r)),
+        "visible_quantity": resting.as_ref().map(|o| o.quantity()),
+        "time_in_force": resting.as_ref().map(|o| format!("{:?}", o.time_in_force())),
+    }))
+}
 
+fn execution_report_json(
+    report: &ExecutionReport,
+    client_order_id: &Option<String>,
+) -> serde_json::Value {
+    serde_json::json!({
+        "order_id": report.order_id,
+        "client_order_id": client_order_id,
+        "status": OrderStatus::from(report.status),
+        "fills": report.fills,
+        "filled_quantity": report.filled_quantity(),
+        "average_price": report.average_price(),
+        "total_fee": report.total_fee(),
+        "resting_quantity": report.resting_quantity(),
+        "cancelled": report.cancelled,
+    })
+}
+
+async fn amend_order(sequencer: &Sequencer, id: OrderId, req: UpdateOrderRequest) -> HttpResponse {
+    let update = if let (Some(price), Some(qty)) = (req.price, req.quantity) {
+        OrderUpdate::UpdatePriceAndQuantity {
+            order_id: id,
+            new_price: price,
+            new_quantity: qty,
+        }
+    } else if let Some(price) = req.price {
+        OrderUpdate::UpdatePrice {
+            order_id: id,
+            new_price: price,
+        }
+    } else if let Some(qty) = req.quantity {
+        OrderUpdate::UpdateQuantity {
+            order_id: id,
+            new_quantity: qty,
+        }
+    } else {
+        return HttpResponse::BadRequest()
+            .json(ApiResponse::<()>::error("nothing to update".to_string()));
+    };
+    let result = sequencer.update_order_async(update).await;
+
+    match result {
+        // An amendment that crosses the book reports its fills like a new order would
+        Ok(Some(report)) => {
+            let client_order_id = sequencer
+                .book()
+                .order_state(id)
+                .and_then(|state| state.owner)
+                .and_then(|owner| owner.client_order_id);
+            HttpResponse::Ok().json(ApiResponse::success(execution_report_json(
+                &report,
+                &client_order_id,
+            )))
+        }
+        Ok(None) => {
+            HttpResponse::NotFound().json(ApiResponse::<()>::error("order not found".to_string()))
+        }
+        Err(e) => engine_error_response(e),
+    }
+}
+
+fn engine_error_response(err: OrderBookError) -> HttpResponse {
+    match err {
+        OrderBookError::DuplicateClientOrderId { .. } => {
+            HttpResponse::Conflict().json(ApiResponse::<()>::error(err.to_string()))
+        }
+        _ => HttpResponse::BadRequest().json(ApiResponse::<()>::error(err.to_string())),
+    }
+}
@@ -1,9 +1,9 @@
 use actix_web::{web, HttpResponse, Result};
 use dashmap::DashMap;
 use std::sync::Arc;
-use crate::OrderBook;
+use crate::{DepthBucket, Liquidity, OrderBook};
 use crate::api::{
-    models::{orderbook::*, response::*},
+    models::{order::OrderSide, orderbook::*, response::*},
     redis::RedisClient,
 };
 
@@ -146,7 +146,11 @@ pub async fn get_orderbook(
 }
 
 #[derive(serde::Deserialize)]
-pub struct DepthQuery { pub depth: Option<usize> }
+pub struct DepthQuery {
+    pub depth: Option<usize>,
+    pub group: Option<u64>,
+    pub cumulative: Option<bool>,
+}
 
 pub async fn get_snapshot(
     path: web::Path<String>,
@@ -161,20 +165,31 @@ pub async fn get_snapshot(
         
         let response = OrderBookSnapshot {
             symbol: snapshot.symbol,
+            sequence: snapshot.sequence,
             timestamp: chrono::DateTime::from_timestamp_millis(snapshot.timestamp as i64)
-                .unwrap_or_else(|| chrono::Utc::now()),
-            bids: snapshot.bids.into_iter().map(|level| PriceLevel {
-                price: level.price,
-                visible_quantity: level.visible_quantity,
-                hidden_quantity: level.hidden_quantity,
-                order_count: level.order_count,
-            }).collect(),
-            asks: snapshot.asks.into_iter().map(|level| PriceLevel {
-                price: level.price,
-                visible_quantity: level.visible_quantity,
-                hidden_quantity: level.hidden_quantity,
-                order_count: level.order_count,
-            }).collect(),
+                .unwrap_or_else(chrono::Utc::now),
+            bids: snapshot
+                .bids
+                .into_iter()
+                .map(|level| PriceLevel {
+                    price: level.price,
+                    visible_quantity: level.visible_quantity,
+                    hidden_quantity: level.hidden_quantity,
+                    order_count: level.order_count,
+                    cumulative_quantity: None,
+                })
+                .collect(),
+            asks: snapshot
+                .asks
+                .into_iter()
+                .map(|level| PriceLevel {
+                    price: level.price,
+                    visible_quantity: level.visible_quantity,
+                    hidden_quantity: level.hidden_quantity,
+                    order_count: level.order_count,
+                    cumulative_quantity: None,
+                })
+                .collect(),
         };
 
         Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
@@ -192,26 +207,74 @@ pub async fn get_depth(
 ) -> Result<HttpResponse> {
     let symbol = path.into_inner();
     let depth = query.depth.unwrap_or(10);
+    let cumulative = query.cumulative.unwrap_or(false);
+
+    if query.group == Some(0) {
+        return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+            "group must be greater than zero".to_string(),
+        )));
+    }
 
     if let Some(orderbook) = orderbooks.get(&symbol) {
+        if query.group.is_some() || cumulative {
+            // Bucket the whole book so that depth counts buckets rather than raw levels
+            let aggregated = orderbook
+                .create_snapshot(usize::MAX)
+                .aggregate(query.group.unwrap_or(1));
+            let to_levels = |buckets: Vec<DepthBucket>| -> Vec<PriceLevel> {
+                buckets
+                    .into_iter()
+                    .take(depth)
+                    .map(|bucket| PriceLevel {
+                        price: bucket.price,
+                        visible_quantity: bucket.visible_quantity,
+                        hidden_quantity: bucket.hidden_quantity,
+                        order_count: bucket.order_count,
+                        cumulative_quantity: cumulative.then_some(bucket.cumulative_quantity),
+                    })
+                    .collect()
+            };
+
+            let response = DepthResponse {
+                symbol: aggregated.symbol,
+                sequence: aggregated.sequence,
+                bids: to_levels(aggregated.bids),
+                asks: to_levels(aggregated.asks),
+                timestamp: chrono::DateTime::from_timestamp_millis(aggregated.timestamp as i64)
+                    .unwrap_or_else(chrono::Utc::now),
+            };
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(response)));
+        }
+
         let snapshot = orderbook.create_snapshot(depth);
         
         let response = DepthResponse {
             symbol: snapshot.symbol,
-            bids: snapshot.bids.into_iter().map(|level| PriceLevel {
-                price: level.price,
-                visible_quantity: level.visible_quantity,
-                hidden_quantity: level.hidden_quantity,
-                order_count: level.order_count,
-            }).collect(),
-            asks: snapshot.asks.into_iter().map(|level| PriceLevel {
-                price: level.price,
-                visible_quantity: level.visible_quantity,
-                hidden_quantity: level.hidden_quantity,
-                order_count: level.order_count,
-            }).collect(),
+            sequence: snapshot.sequence,
+            bids: snapshot
+                .bids
+                .into_iter()
+                .map(|level| PriceLevel {
+                    price: level.price,
+                    visible_quantity: level.visible_quantity,
+                    hidden_quantity: level.hidden_quantity,
+                    order_count: level.order_count,
+                    cumulative_quantity: None,
+                })
+                .collect(),
+            asks: snapshot
+                .asks
+                .into_iter()
+                .map(|level| PriceLevel {
+                    price: level.price,
+                    visible_quantity: level.visible_quantity,
+                    hidden_quantity: level.hidden_quantity,
+                    order_count: level.order_count,
+                    cumulative_quantity: None,
+                })
+                .collect(),
             timestamp: chrono::DateTime::from_timestamp_millis(snapshot.timestamp as i64)
-                .unwrap_or_else(|| chrono::Utc::now()),
+                .unwrap_or_else(chrono::Utc::now),
         };
 
         Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
@@ -221,3 +284,46 @@ pub async fn get_depth(
         )))
     }
 }
+
+#[derive(serde::Deserialize)]
+pub struct QuoteQuery {
+    pub side: OrderSide,
+    pub quantity: Option<u64>,
+    pub notional: Option<u64>,
+    pub include_hidden: Option<bool>,
+}
+
+pub async fn get_quote(
+    path: web::Path<String>,
+    orderbooks: web::Data<Arc<DashMap<String, Arc<OrderBook>>>>,
+    query: web::Query<QuoteQuery>,
+) -> Result<HttpResponse> {
+    let symbol = path.into_inner();
+    let Some(orderbook) = orderbooks.get(&symbol) else {
+        return Ok(
+            HttpResponse::NotFound().json(ApiResponse::<()>::error(format!(
+                "Order book for symbol {} not found",
+                symbol
+            ))),
+        );
+    };
+
+    let side: pricelevel::Side = query.side.clone().into();
+    let liquidity = if query.include_hidden.unwrap_or(false) {
+        Liquidity::VisibleAndHidden
+    } else {
+        Liquidity::Visible
+    };
+
+    match (query.quantity, query.notional) {
+        (Some(quantity), None) => Ok(HttpResponse::Ok().json(ApiResponse::success(
+            orderbook.market_impact(side, quantity, liquidity),
+        ))),
+        (None, Some(notional)) => Ok(HttpResponse::Ok().json(ApiResponse::success(
+            orderbook.quantity_for_notional(side, notional as u128, liquidity),
+        ))),
+        _ => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+            "exactly one of quantity or notional is required".to_string(),
+        ))),
+    }
+}
@@ -1,10 +1,14 @@
 use actix_web::{web, HttpResponse, Result};
 use dashmap::DashMap;
 use std::sync::Arc;
-use crate::OrderBook;
+use crate::{CandleInterval, OrderBook};
 use crate::api::{
-    models::{orderbook::BestPricesResponse, response::ApiResponse, trade::{TradeResponse, VolumeStats}},
-    redis::RedisClient,
+    database::Database,
+    models::{
+        orderbook::BestPricesResponse,
+        response::ApiResponse,
+        trade::{CandlesResponse, Trade, TradeResponse, VolumeStats},
+    },
 };
 
 #[derive(serde::Deserialize)]
@@ -32,57 +36,173 @@ pub async fn get_best_prices(
     }
 }
 
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
 pub async fn get_recent_trades(
     path: web::Path<String>,
-    _pagination: web::Query<Pagination>,
-    redis: web::Data<RedisClient>,
+    pagination: web::Query<Pagination>,
+    orderbooks: web::Data<Arc<DashMap<String, Arc<OrderBook>>>>,
 ) -> Result<HttpResponse> {
     let symbol = path.into_inner();
-    if let Ok(Some(trades)) = redis.get_recent_trades(&symbol).await {
-        let response = TradeResponse { trades: trades.into_iter().map(|t| crate::api::models::trade::Trade {
-            id: uuid::Uuid::parse_str(&t.id).unwrap_or_else(|_| uuid::Uuid::new_v4()),
-            symbol: t.symbol,
+    let Some(orderbook) = orderbooks.get(&symbol).map(|item| item.value().clone()) else {
+        return Ok(
+            HttpResponse::NotFound().json(ApiResponse::<()>::error(format!(
+                "Order book for symbol {} not found",
+                symbol
+            ))),
+        );
+    };
+
+    let page = pagination.page.unwrap_or(1).max(1);
+    let page_size = pagination
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = (page as usize - 1) * page_size as usize;
+
+    let tape = orderbook.trade_tape();
+    let total = tape.len();
+    let account = |order_id| {
+        orderbook
+            .order_state(order_id)
+            .and_then(|record| record.owner)
+            .map(|owner| owner.account)
+    };
+    let trades = tape
+        .recent(offset, page_size as usize)
+        .into_iter()
+        .map(|t| Trade {
+            id: t.transaction_id,
+            symbol: symbol.clone(),
             price: t.price,
             quantity: t.quantity,
-            side: match t.side.as_str() { "Buy" => crate::api::models::trade::TradeSide::Buy, _ => crate::api::models::trade::TradeSide::Sell },
-            taker_order_id: uuid::Uuid::new_v4(),
-            maker_order_id: uuid::Uuid::new_v4(),
-            taker_user_id: uuid::Uuid::new_v4(),
-            maker_user_id: uuid::Uuid::new_v4(),
-            timestamp: t.timestamp,
-        }).collect(), total: 0, page: 1, page_size: 50 };
-        return Ok(HttpResponse::Ok().json(ApiResponse::success(response)));
-    }
+            side: t.taker_side.into(),
+            taker_order_id: t.taker_order_id.0,
+            maker_order_id: t.maker_order_id.0,
+            taker_user_id: account(t.taker_order_id),
+            maker_user_id: account(t.maker_order_id),
+            timestamp: chrono::DateTime::from_timestamp_millis(t.timestamp as i64)
+                .unwrap_or_else(chrono::Utc::now),
+        })
+        .collect();
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(TradeResponse { trades: vec![], total: 0, page: 1, page_size: 50 })))
+    Ok(HttpResponse::Ok().json(ApiResponse::success(TradeResponse {
+        trades,
+        total,
+        page,
+        page_size,
+    })))
 }
 
 pub async fn get_volume_stats(
     path: web::Path<String>,
-    redis: web::Data<RedisClient>,
+    orderbooks: web::Data<Arc<DashMap<String, Arc<OrderBook>>>>,
 ) -> Result<HttpResponse> {
     let symbol = path.into_inner();
-    if let Ok(Some(stats)) = redis.get_volume_stats(&symbol).await {
-        let response = VolumeStats {
-            symbol: symbol.clone(),
-            total_volume: stats.get("total_volume").and_then(|v| v.parse().ok()).unwrap_or(0),
-            total_trades: stats.get("total_trades").and_then(|v| v.parse().ok()).unwrap_or(0),
-            avg_price: stats.get("avg_price").and_then(|v| v.parse().ok()).unwrap_or(0.0),
-            high_price: stats.get("high_price").and_then(|v| v.parse().ok()).unwrap_or(0),
-            low_price: stats.get("low_price").and_then(|v| v.parse().ok()).unwrap_or(0),
-            timestamp: chrono::Utc::now(),
-        };
-        Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
-    } else {
-        Ok(HttpResponse::Ok().json(ApiResponse::success(VolumeStats {
-            symbol,
-            total_volume: 0,
-            total_trades: 0,
-            avg_price: 0.0,
-            high_price: 0,
-            low_price: 0,
-            timestamp: chrono::Utc::now(),
-        })))
-    }
+    let Some(orderbook) = orderbooks.get(&symbol).map(|item| item.value().clone()) else {
+        return Ok(
+            HttpResponse::NotFound().json(ApiResponse::<()>::error(format!(
+                "Order book for symbol {} not found",
+                symbol
+            ))),
+        );
+    };
+
+    let stats = orderbook.trade_stats();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(VolumeStats {
+        symbol,
+        total_volume: stats.volume,
+        quote_volume: stats.quote_volume,
+        total_trades: stats.trade_count,
+        avg_price: stats.vwap.unwrap_or(0.0),
+        high_price: stats.high.unwrap_or(0),
+        low_price: stats.low.unwrap_or(0),
+        open_price: stats.open.unwrap_or(0),
+        last_price: stats.last.unwrap_or(0),
+        price_change: stats.price_change.unwrap_or(0),
+        price_change_percent: stats.price_change_percent.unwrap_or(0.0),
+        window_ms: stats.window_ms,
+        timestamp: chrono::Utc::now(),
+    })))
 }
 
+#[derive(serde::Deserialize)]
+pub struct CandleQuery {
+    pub interval: Option<String>,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+const DEFAULT_CANDLE_LIMIT: usize = 500;
+const MAX_CANDLE_LIMIT: usize = 1000;
+
+pub async fn get_candles(
+    path: web::Path<String>,
+    query: web::Query<CandleQuery>,
+    orderbooks: web::Data<Arc<DashMap<String, Arc<OrderBook>>>>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse> {
+    let symbol = path.into_inner();
+    let Some(orderbook) = orderbooks.get(&symbol).map(|item| item.value().clone()) else {
+        return Ok(
+            HttpResponse::NotFound().json(ApiResponse::<()>::error(format!(
+                "Order book for symbol {} not found",
+                symbol
+            ))),
+        );
+    };
+
+    let interval = match query
+        .interval
+        .as_deref()
+        .unwrap_or("1m")
+        .parse::<CandleInterval>()
+    {
+        Ok(interval) => interval,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e))),
+    };
+    let from = query.from.unwrap_or(0);
+    let to = query.to.unwrap_or(u64::MAX);
+    if from >= to {
+        return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+            "from must be before to".to_string(),
+        )));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_CANDLE_LIMIT)
+        .clamp(1, MAX_CANDLE_LIMIT);
+
+    let mut candles = match db.get_candles(&symbol, interval, from, to, limit).await {
+        Ok(candles) => candles,
+        Err(e) => {
+            return Ok(
+                HttpResponse::InternalServerError().json(ApiResponse::<()>::error(e.to_string()))
+            );
+        }
+    };
+
+    // The candle still being built is only in the engine
+    if let Some(current) = orderbook.candles().current(interval)
+        && current.open_time >= from
+        && current.open_time < to
+        && candles
+            .last()
+            .is_none_or(|last| last.open_time < current.open_time)
+    {
+        candles.push(current);
+        if candles.len() > limit {
+            candles.remove(0);
+        }
+    }
+
+    Ok(
+        HttpResponse::Ok().json(ApiResponse::success(CandlesResponse {
+            symbol,
+            interval,
+            candles,
+        })),
+    )
+}
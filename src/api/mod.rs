@@ -1,5 +1,13 @@
+pub mod auth;
+pub mod binary;
 pub mod database;
+pub mod feed;
+pub mod fix;
 pub mod handlers;
 pub mod middleware;
 pub mod models;
+pub mod orders;
+pub mod persistence;
 pub mod redis;
+pub mod sink;
+pub mod stream;
@@ -11,6 +11,8 @@ pub struct CreateOrderRequest {
     pub price: Option<u64>,
     pub time_in_force: TimeInForce,
     pub user_id: Uuid,
+    // Optional client-assigned id, unique per user among open orders
+    pub client_order_id: Option<String>,
     // For iceberg orders
     pub visible_quantity: Option<u64>,
     pub hidden_quantity: Option<u64>,
@@ -83,6 +85,25 @@ pub enum OrderStatus {
     Expired,
 }
 
+impl OrderStatus {
+    /// Value stored in the `status` column of `orders`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Pending => "PENDING",
+            OrderStatus::PartiallyFilled => "PARTIALLY_FILLED",
+            OrderStatus::Filled => "FILLED",
+            OrderStatus::Cancelled => "CANCELLED",
+            OrderStatus::Rejected => "REJECTED",
+            OrderStatus::Expired => "EXPIRED",
+        }
+    }
+
+    /// Returns true for orders that may still rest in the book
+    pub fn is_open(&self) -> bool {
+        matches!(self, OrderStatus::Pending | OrderStatus::PartiallyFilled)
+    }
+}
+
 impl From<pricelevel::Side> for OrderSide {
     fn from(side: pricelevel::Side) -> Self {
         match side {
@@ -101,6 +122,19 @@ impl From<OrderSide> for pricelevel::Side {
     }
 }
 
+impl From<crate::OrderStatus> for OrderStatus {
+    fn from(status: crate::OrderStatus) -> Self {
+        match status {
+            crate::OrderStatus::New => OrderStatus::Pending,
+            crate::OrderStatus::PartiallyFilled => OrderStatus::PartiallyFilled,
+            crate::OrderStatus::Filled => OrderStatus::Filled,
+            crate::OrderStatus::Cancelled => OrderStatus::Cancelled,
+            crate::OrderStatus::Rejected => OrderStatus::Rejected,
+            crate::OrderStatus::Expired => OrderStatus::Expired,
+        }
+    }
+}
+
 impl From<pricelevel::TimeInForce> for TimeInForce {
     fn from(tif: pricelevel::TimeInForce) -> Self {
         match tif {
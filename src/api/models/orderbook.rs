@@ -20,6 +20,7 @@ pub struct OrderBookResponse {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderBookSnapshot {
     pub symbol: String,
+    pub sequence: u64,
     pub timestamp: DateTime<Utc>,
     pub bids: Vec<PriceLevel>,
     pub asks: Vec<PriceLevel>,
@@ -31,11 +32,14 @@ pub struct PriceLevel {
     pub visible_quantity: u64,
     pub hidden_quantity: u64,
     pub order_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cumulative_quantity: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DepthResponse {
     pub symbol: String,
+    pub sequence: u64,
     pub bids: Vec<PriceLevel>,
     pub asks: Vec<PriceLevel>,
     pub timestamp: DateTime<Utc>,
@@ -11,8 +11,8 @@ pub struct Trade {
     pub side: TradeSide,
     pub taker_order_id: Uuid,
     pub maker_order_id: Uuid,
-    pub taker_user_id: Uuid,
-    pub maker_user_id: Uuid,
+    pub taker_user_id: Option<Uuid>,
+    pub maker_user_id: Option<Uuid>,
     pub timestamp: DateTime<Utc>,
 }
 
@@ -34,13 +34,26 @@ pub struct TradeResponse {
 pub struct VolumeStats {
     pub symbol: String,
     pub total_volume: u64,
+    pub quote_volume: u128,
     pub total_trades: u64,
     pub avg_price: f64,
     pub high_price: u64,
     pub low_price: u64,
+    pub open_price: u64,
+    pub last_price: u64,
+    pub price_change: i64,
+    pub price_change_percent: f64,
+    pub window_ms: u64,
     pub timestamp: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CandlesResponse {
+    pub symbol: String,
+    pub interval: crate::CandleInterval,
+    pub candles: Vec<crate::Candle>,
+}
+
 impl From<pricelevel::Side> for TradeSide {
     fn from(side: pricelevel::Side) -> Self {
         match side {
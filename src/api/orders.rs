@@ -0,0 +1,75 @@
+//! Order entry shared by the REST handlers and the FIX and binary gateways
+use crate::api::persistence::{NewOrderRow, Outbox, OutboxRecord};
+use crate::api::stream::UserStreamHub;
+use crate::{
+    ExecutionReport, OrderBookError, OrderOwner, OrderRecord, OrderStatus, Sequencer,
+    TerminalReason,
+};
+use pricelevel::{OrderId, OrderType, Side};
+use std::io;
+
+/// An order entered by a client, with the row persisted for it
+pub struct NewOrder {
+    pub row: NewOrderRow,
+    /// The order to add to the book, `None` for a market order
+    pub order: Option<OrderType>,
+    pub side: Side,
+    pub owner: OrderOwner,
+    pub timestamp: u64,
+}
+
+/// Why an order was not accepted
+#[derive(Debug)]
+pub enum SubmitError {
+    /// The order could not be written to the outbox, so it never reached the engine
+    NotRecorded(io::Error),
+    /// The engine refused the order before recording it; the rejection was reported to the account
+    Rejected(OrderBookError),
+    /// The engine recorded the order and then failed it; its state change events report it
+    Failed(OrderBookError),
+}
+
+/// Record an order in the outbox and submit it through the sequencer of its book.
+///
+/// The order row goes to the outbox first, so it is persisted ahead of its fills and state
+/// changes. An order refused before the engine recorded it gets no state change event, so it is
+/// closed in the outbox and published as rejected on the user stream of its account here.
+pub async fn submit(
+    sequencer: &Sequencer,
+    outbox: &Outbox,
+    user_streams: &UserStreamHub,
+    new_order: NewOrder,
+) -> Result<ExecutionReport, SubmitError> {
+    let NewOrder {
+        row,
+        order,
+        side,
+        owner,
+        timestamp,
+    } = new_order;
+    let (id, symbol, price, quantity) =
+        (OrderId(row.id), row.symbol.clone(), row.price, row.quantity);
+    outbox
+        .append(&OutboxRecord::NewOrder(row))
+        .map_err(SubmitError::NotRecorded)?;
+
+    let result = match order {
+        Some(order) => sequencer.add_order_async(order, Some(owner.clone())).await,
+        None => {
+            sequencer
+                .submit_market_order_async(id, quantity, side, Some(owner.clone()))
+                .await
+        }
+    };
+    result.map_err(|e| {
+        if sequencer.book().order_state(id).is_some() {
+            return SubmitError::Failed(e);
+        }
+        let _ = outbox.append(&OutboxRecord::OrderRejected { order_id: id.0 });
+        let mut record = OrderRecord::new(id, side, price, quantity, timestamp).with_owner(owner);
+        record.status = OrderStatus::Rejected;
+        record.reason = Some(TerminalReason::from(&e));
+        user_streams.publish_rejection(&symbol, &record);
+        SubmitError::Rejected(e)
+    })
+}
@@ -0,0 +1,528 @@
+//! Asynchronous persistence of orders and trades.
+//!
+//! The engine never waits on Postgres. Everything to persist is first appended to a local
+//! outbox file (new orders by the handlers, trades and order changes by the books through
+//! `EventSink`), and a background worker moves it to Postgres in batches, one transaction
+//! per batch. A batch is only marked as done once its transaction committed, so a failed
+//! write is retried and a restart resumes where the last committed batch ended. Every write
+//! is idempotent, as a batch may be written again after a crash.
+//!
+//! Book events are tagged with the sequence number of their book, and the outbox keeps the
+//! latest one it holds for each symbol. The books are subscribed before the journal is
+//! replayed, and the events a replay publishes again are skipped, so only those the outbox
+//! missed (e.g. lost in a crash of the machine) are appended. An event that cannot be
+//! appended fails the outbox: nothing is appended any more, which refuses new orders, and the
+//! event is written again by the replay after a restart. Checkpoints are taken once the
+//! outbox is synced, as the commands before them are no longer replayed.
+//!
+//! Only connection and transient errors are retried. A batch refused by the database for
+//! its data (e.g. a constraint violation) is written again record by record, and the records
+//! that are refused again are moved to a dead letter file so the records after them go on.
+
+use crate::BookEvent;
+use crate::EventSink;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use super::database::Database;
+
+/// Outbox files are truncated once fully persisted and larger than this, by default
+const COMPACT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Attempts to append a book event before the outbox is failed
+const PUBLISH_ATTEMPTS: u32 = 3;
+
+/// A new order as stored in the `orders` table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewOrderRow {
+    pub id: Uuid,
+    pub symbol: String,
+    pub side: String,
+    pub order_type: String,
+    pub quantity: u64,
+    pub price: Option<u64>,
+    pub time_in_force: String,
+    pub user_id: Uuid,
+    pub client_order_id: Option<String>,
+    pub visible_quantity: Option<u64>,
+    pub hidden_quantity: Option<u64>,
+}
+
+/// An entry of the outbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OutboxRecord {
+    /// An order submitted to the engine, written before it is submitted
+    NewOrder(NewOrderRow),
+    /// An order the engine refused before recording it (e.g. duplicate client order id)
+    OrderRejected { order_id: Uuid },
+    /// A trade or order change published by a book
+    Book(BookEvent),
+}
+
+/// A record the database refused, as stored in the dead letter file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// Why the database refused the record
+    pub error: String,
+    pub record: OutboxRecord,
+}
+
+/// Latest book sequence number appended for each symbol, with the number of events of that
+/// sequence number appended
+type Sequences = HashMap<String, (u64, usize)>;
+
+struct OutboxFile {
+    file: File,
+    len: u64,
+    committed: u64,
+    sequences: Sequences,
+    /// `sequences` when the outbox was opened: the events a replay of the journal publishes
+    /// again, counted down as they are skipped
+    replayed: Sequences,
+    /// Why an event could not be appended, after which nothing is appended any more
+    failed: Option<String>,
+}
+
+impl OutboxFile {
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if let Some(error) = &self.failed {
+            return Err(stopped(error));
+        }
+        if let Err(e) = self.file.write_all(line) {
+            // Drop what was written of the line, so the next one starts on a line of its own
+            let _ = self.file.set_len(self.len);
+            return Err(e);
+        }
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    /// Whether `event` is one the outbox held when it was opened, published again by a replay
+    fn is_replayed(&mut self, event: &BookEvent) -> bool {
+        let Some((sequence, count)) = self.replayed.get_mut(event.symbol()) else {
+            return false;
+        };
+        if event.sequence() == *sequence && *count > 0 {
+            *count -= 1;
+            return true;
+        }
+        event.sequence() < *sequence
+    }
+}
+
+/// Durable local queue of the records waiting to be written to Postgres
+pub struct Outbox {
+    path: PathBuf,
+    offset_path: PathBuf,
+    sequences_path: PathBuf,
+    dead_letter_path: PathBuf,
+    compact_bytes: u64,
+    file: Mutex<OutboxFile>,
+}
+
+impl Outbox {
+    /// Open the outbox stored in `dir`, resuming after the last committed batch
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let path = dir.join("outbox.jsonl");
+        let offset_path = dir.join("outbox.offset");
+        let sequences_path = dir.join("outbox.sequences");
+        let dead_letter_path = dir.join("dead_letter.jsonl");
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let committed = match fs::read_to_string(&offset_path) {
+            Ok(offset) => offset.trim().parse().unwrap_or(0),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        // The file may have been truncated by a compaction whose offset was not written
+        let committed = committed.min(file.metadata()?.len());
+        // The sequences of the events truncated by the last compaction, then those of the file
+        let mut sequences = match fs::read(&sequences_path) {
+            Ok(content) => serde_json::from_slice(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Sequences::new(),
+            Err(e) => return Err(e),
+        };
+        // A crash in the middle of an append leaves a partial line, which the next record would extend
+        let len = scan(&path, &mut sequences)?;
+        file.set_len(len)?;
+
+        Ok(Self {
+            path,
+            offset_path,
+            sequences_path,
+            dead_letter_path,
+            compact_bytes: COMPACT_BYTES,
+            file: Mutex::new(OutboxFile {
+                file,
+                len,
+                committed,
+                replayed: sequences.clone(),
+                sequences,
+                failed: None,
+            }),
+        })
+    }
+
+    /// Truncate the outbox file once it is fully persisted and larger than `bytes`
+    pub fn with_compact_bytes(mut self, bytes: u64) -> Self {
+        self.compact_bytes = bytes;
+        self
+    }
+
+    /// Path of the file holding the records the database refused
+    pub fn dead_letter_path(&self) -> &Path {
+        &self.dead_letter_path
+    }
+
+    /// Append a record and write it to the operating system. Fails once the outbox failed.
+    pub fn append(&self, record: &OutboxRecord) -> io::Result<()> {
+        let line = encode(record)?;
+        let mut file = self.file.lock().unwrap();
+        file.write_line(&line)?;
+        if let OutboxRecord::Book(event) = record {
+            appended(&mut file.sequences, event);
+        }
+        Ok(())
+    }
+
+    /// Make the records appended so far durable. Fails once the outbox failed, as it misses
+    /// an event.
+    pub fn sync(&self) -> io::Result<()> {
+        let file = {
+            let file = self.file.lock().unwrap();
+            if let Some(error) = &file.failed {
+                return Err(stopped(error));
+            }
+            file.file.try_clone()?
+        };
+        file.sync_data()
+    }
+
+    /// Forget the book sequence numbers of the events in the outbox, for books that start
+    /// over from sequence 0 (e.g. rebuilt from the database). Every record must be persisted.
+    pub fn restart_sequences(&self) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        if file.committed != file.len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the outbox holds records not persisted yet",
+            ));
+        }
+        file.sequences.clear();
+        file.replayed.clear();
+        self.truncate(&mut file)?;
+        self.write_offset(file.committed)
+    }
+
+    /// Number of bytes appended but not committed yet
+    pub fn pending_bytes(&self) -> u64 {
+        let file = self.file.lock().unwrap();
+        file.len - file.committed
+    }
+
+    /// Read up to `max` records after the last committed batch. Returns them with the
+    /// offset to commit once they are persisted.
+    pub fn read_batch(&self, max: usize) -> io::Result<(Vec<OutboxRecord>, u64)> {
+        let (start, end) = {
+            let file = self.file.lock().unwrap();
+            (file.committed, file.len)
+        };
+        let mut reader = File::open(&self.path)?;
+        reader.seek(SeekFrom::Start(start))?;
+        let mut reader = BufReader::new(reader.take(end - start));
+
+        let mut records = Vec::new();
+        let mut offset = start;
+        let mut line = String::new();
+        while records.len() < max {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            records.push(
+                serde_json::from_str(&line)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            );
+            offset += read as u64;
+        }
+        Ok((records, offset))
+    }
+
+    /// Mark everything before `offset` as persisted
+    pub fn commit(&self, offset: u64) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        file.committed = offset;
+        if file.committed == file.len && file.len > self.compact_bytes {
+            self.truncate(&mut file)?;
+        }
+        self.write_offset(file.committed)
+    }
+
+    /// Empty the fully persisted outbox file, keeping the sequences of its events aside
+    fn truncate(&self, file: &mut OutboxFile) -> io::Result<()> {
+        let tmp = self.sequences_path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&file.sequences)?)?;
+        fs::rename(&tmp, &self.sequences_path)?;
+        file.file.set_len(0)?;
+        file.len = 0;
+        file.committed = 0;
+        Ok(())
+    }
+
+    fn write_offset(&self, offset: u64) -> io::Result<()> {
+        let tmp = self.offset_path.with_extension("tmp");
+        fs::write(&tmp, offset.to_string())?;
+        fs::rename(&tmp, &self.offset_path)
+    }
+
+    /// Set aside a record the database refused, with the reason, so it can be inspected and
+    /// written by hand. The file is synced, as the record is committed past right after.
+    pub fn dead_letter(&self, record: &OutboxRecord, error: &str) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&DeadLetter {
+            error: error.to_string(),
+            record: record.clone(),
+        })?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.dead_letter_path)?;
+        file.write_all(&line)?;
+        file.sync_data()
+    }
+}
+
+/// The error of an append or sync to an outbox that failed for `error`
+fn stopped(error: &str) -> io::Error {
+    io::Error::other(format!(
+        "the outbox stopped after an event could not be appended: {}",
+        error
+    ))
+}
+
+/// A record as a line of the outbox file
+fn encode(record: &OutboxRecord) -> io::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Length of the outbox file up to the end of its last complete line, adding the book events
+/// of the file to `sequences`
+fn scan(path: &Path, sequences: &mut Sequences) -> io::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut len = 0;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 || line.last() != Some(&b'\n') {
+            return Ok(len);
+        }
+        if let OutboxRecord::Book(event) = serde_json::from_slice(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        {
+            appended(sequences, &event);
+        }
+        len += read as u64;
+    }
+}
+
+/// Count `event` in the sequences appended
+fn appended(sequences: &mut Sequences, event: &BookEvent) {
+    match sequences.get_mut(event.symbol()) {
+        Some((sequence, count)) if *sequence == event.sequence() => *count += 1,
+        Some(latest) => *latest = (event.sequence(), 1),
+        None => {
+            sequences.insert(event.symbol().to_string(), (event.sequence(), 1));
+        }
+    }
+}
+
+impl std::fmt::Debug for Outbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Outbox")
+            .field("path", &self.path)
+            .field("pending_bytes", &self.pending_bytes())
+            .finish()
+    }
+}
+
+impl EventSink for Outbox {
+    fn publish(&self, event: &BookEvent) {
+        let mut file = self.file.lock().unwrap();
+        if file.is_replayed(event) {
+            return;
+        }
+        let error = match encode(&OutboxRecord::Book(event.clone())) {
+            Ok(line) => {
+                let mut attempt = 1;
+                loop {
+                    match file.write_line(&line) {
+                        Ok(()) => {
+                            appended(&mut file.sequences, event);
+                            return;
+                        }
+                        Err(e) if attempt < PUBLISH_ATTEMPTS && file.failed.is_none() => {
+                            warn!(
+                                "Failed to append {} event {} to the outbox, retrying: {}",
+                                event.symbol(),
+                                event.sequence(),
+                                e
+                            );
+                            attempt += 1;
+                        }
+                        Err(e) => break e,
+                    }
+                }
+            }
+            Err(e) => e,
+        };
+        error!(
+            "Failed to append {} event {} to the outbox, refusing records until a restart \
+             replays it from the journal: {}",
+            event.symbol(),
+            event.sequence(),
+            error
+        );
+        file.failed.get_or_insert_with(|| error.to_string());
+    }
+}
+
+/// Settings of the persistence worker
+#[derive(Debug, Clone, Copy)]
+pub struct PersistenceConfig {
+    /// Maximum number of records written in one transaction
+    pub batch_size: usize,
+    /// Wait between two reads of an empty outbox
+    pub poll_interval: Duration,
+    /// Longest wait before retrying a failed batch
+    pub max_retry_delay: Duration,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 500,
+            poll_interval: Duration::from_millis(50),
+            max_retry_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Write everything in the outbox to Postgres and return, failing on the first batch that
+/// cannot be written. Records the database refuses are moved to the dead letter file. Used
+/// before reading the database back, so it reflects every record.
+pub async fn drain_outbox(
+    database: &Database,
+    outbox: &Outbox,
+    config: PersistenceConfig,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut persisted = 0;
+    loop {
+        let (records, offset) = outbox.read_batch(config.batch_size.max(1))?;
+        if records.is_empty() {
+            return Ok(persisted);
+        }
+        persist(database, outbox, &records).await?;
+        outbox.commit(offset)?;
+        persisted += records.len();
+    }
+}
+
+/// Move the outbox to Postgres forever, retrying batches that failed for a transient
+/// reason with exponential backoff
+pub async fn run_persistence_worker(
+    database: Database,
+    outbox: Arc<Outbox>,
+    config: PersistenceConfig,
+) {
+    let mut retry_delay = config.poll_interval;
+    loop {
+        let (records, offset) = match outbox.read_batch(config.batch_size.max(1)) {
+            Ok(batch) => batch,
+            Err(e) => {
+                error!("Failed to read the outbox: {}", e);
+                tokio::time::sleep(config.max_retry_delay).await;
+                continue;
+            }
+        };
+        if records.is_empty() {
+            tokio::time::sleep(config.poll_interval).await;
+            continue;
+        }
+
+        match persist(&database, &outbox, &records).await {
+            Ok(()) => {
+                retry_delay = config.poll_interval;
+                if let Err(e) = outbox.commit(offset) {
+                    error!("Failed to commit the outbox offset: {}", e);
+                }
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to persist {} outbox records, retrying in {:?}: {}",
+                    records.len(),
+                    retry_delay,
+                    e
+                );
+                tokio::time::sleep(retry_delay).await;
+                retry_delay = (retry_delay * 2).min(config.max_retry_delay);
+            }
+        }
+    }
+}
+
+/// Write a batch in one transaction. If the database refuses it for its data, write it
+/// record by record and move the refused records to the dead letter file. Fails only on
+/// errors worth retrying.
+async fn persist(
+    database: &Database,
+    outbox: &Outbox,
+    records: &[OutboxRecord],
+) -> Result<(), Box<dyn std::error::Error>> {
+    match database.persist_batch(records).await {
+        Err(e) if is_permanent(e.as_ref()) => warn!(
+            "The database refused a batch of {} outbox records, writing them one by one: {}",
+            records.len(),
+            e
+        ),
+        result => return result,
+    }
+    for record in records {
+        match database.persist_batch(std::slice::from_ref(record)).await {
+            Err(e) if is_permanent(e.as_ref()) => {
+                error!(
+                    "The database refused an outbox record, moving it to {}: {}",
+                    outbox.dead_letter_path().display(),
+                    e
+                );
+                outbox.dead_letter(record, &e.to_string())?;
+            }
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+/// Whether a write failed because of the data itself (the database refused it and will
+/// refuse it again) rather than because of the connection or a transient condition
+fn is_permanent(error: &(dyn std::error::Error + 'static)) -> bool {
+    let code = match error.downcast_ref::<tokio_postgres::Error>() {
+        Some(error) => error.code(),
+        None => return false,
+    };
+    // Data exceptions and integrity constraint violations
+    code.is_some_and(|code| matches!(&code.code()[..2], "22" | "23"))
+}
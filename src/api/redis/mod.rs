@@ -1,11 +1,21 @@
-use redis::{aio::Connection, AsyncCommands, Client};
+use redis::{aio::MultiplexedConnection, AsyncCommands, Client};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 
+mod publisher;
+
+pub use publisher::{
+    orderbook_cache, run_redis_publisher, RedisEventSink, RedisPublisherConfig,
+    RedisPublisherReceiver,
+};
+
+/// Redis access shared by the handlers and the market data publisher. Every command goes
+/// through one multiplexed connection, which is cheap to clone.
 #[derive(Clone)]
 pub struct RedisClient {
     pub client: Client,
+    conn: MultiplexedConnection,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,12 +58,10 @@ impl RedisClient {
             .unwrap_or_else(|_| "redis://localhost:6379".to_string());
 
         let client = Client::open(redis_url)?;
-        // Lazily create connections per operation to avoid needing ConnectionManager in this redis version
-        // Validate connectivity once
-        let mut conn = client.get_async_connection().await?;
+        let mut conn = client.get_multiplexed_tokio_connection().await?;
         let _: () = redis::cmd("PING").query_async(&mut conn).await?;
 
-        Ok(RedisClient { client })
+        Ok(RedisClient { client, conn })
     }
 
     // OrderBook cache operations
@@ -61,14 +69,14 @@ impl RedisClient {
         let key = format!("orderbook:{}", symbol);
         let value = serde_json::to_string(orderbook)?;
         
-        let mut conn = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         let _: () = conn.set_ex(key, value, 60).await?; // Cache for 60 seconds
         Ok(())
     }
 
     pub async fn get_orderbook(&self, symbol: &str) -> Result<Option<OrderBookCache>, Box<dyn std::error::Error>> {
         let key = format!("orderbook:{}", symbol);
-        let mut conn: Connection = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         
         let value: Option<String> = conn.get(key).await?;
         match value {
@@ -85,14 +93,14 @@ impl RedisClient {
         let key = format!("price_levels:{}:{}", symbol, side);
         let value = serde_json::to_string(levels)?;
         
-        let mut conn = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         let _: () = conn.set_ex(key, value, 30).await?; // Cache for 30 seconds
         Ok(())
     }
 
     pub async fn get_price_levels(&self, symbol: &str, side: &str) -> Result<Option<Vec<PriceLevelCache>>, Box<dyn std::error::Error>> {
         let key = format!("price_levels:{}:{}", symbol, side);
-        let mut conn = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         
         let value: Option<String> = conn.get(key).await?;
         match value {
@@ -109,14 +117,14 @@ impl RedisClient {
         let key = format!("trades:{}", symbol);
         let value = serde_json::to_string(trades)?;
         
-        let mut conn = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         let _: () = conn.set_ex(key, value, 300).await?; // Cache for 5 minutes
         Ok(())
     }
 
     pub async fn get_recent_trades(&self, symbol: &str) -> Result<Option<Vec<TradeCache>>, Box<dyn std::error::Error>> {
         let key = format!("trades:{}", symbol);
-        let mut conn = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         
         let value: Option<String> = conn.get(key).await?;
         match value {
@@ -131,7 +139,7 @@ impl RedisClient {
     // Market data cache operations
     pub async fn cache_market_data(&self, symbol: &str, data: &HashMap<String, String>) -> Result<(), Box<dyn std::error::Error>> {
         let key = format!("market_data:{}", symbol);
-        let mut conn = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         
         for (field, value) in data {
             let _: () = conn.hset(key.clone(), field, value).await?;
@@ -142,7 +150,7 @@ impl RedisClient {
 
     pub async fn get_market_data(&self, symbol: &str) -> Result<Option<HashMap<String, String>>, Box<dyn std::error::Error>> {
         let key = format!("market_data:{}", symbol);
-        let mut conn = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         
         let data: HashMap<String, String> = conn.hgetall(key).await?;
         if data.is_empty() { return Ok(None); }
@@ -152,7 +160,7 @@ impl RedisClient {
     // Volume statistics cache
     pub async fn cache_volume_stats(&self, symbol: &str, stats: &HashMap<String, String>) -> Result<(), Box<dyn std::error::Error>> {
         let key = format!("volume_stats:{}", symbol);
-        let mut conn = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         
         for (field, value) in stats {
             let _: () = conn.hset(key.clone(), field, value).await?;
@@ -163,7 +171,7 @@ impl RedisClient {
 
     pub async fn get_volume_stats(&self, symbol: &str) -> Result<Option<HashMap<String, String>>, Box<dyn std::error::Error>> {
         let key = format!("volume_stats:{}", symbol);
-        let mut conn = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         
         let stats: HashMap<String, String> = conn.hgetall(key).await?;
         if stats.is_empty() { return Ok(None); }
@@ -175,24 +183,42 @@ impl RedisClient {
         let channel = format!("trades:{}", symbol);
         let message = serde_json::to_string(trade)?;
         
-        let mut conn = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         let _: () = conn.publish(channel, message).await?;
         Ok(())
     }
 
+    // Several trades of a symbol streamed in one round trip
+    pub async fn publish_trades(
+        &self,
+        symbol: &str,
+        trades: &[TradeCache],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let channel = format!("trades:{}", symbol);
+        let mut pipe = redis::pipe();
+        for trade in trades {
+            pipe.publish(&channel, serde_json::to_string(trade)?)
+                .ignore();
+        }
+
+        let mut conn = self.conn.clone();
+        let _: () = pipe.query_async(&mut conn).await?;
+        Ok(())
+    }
+
     // Order book updates streaming
     pub async fn publish_orderbook_update(&self, symbol: &str, update: &OrderBookCache) -> Result<(), Box<dyn std::error::Error>> {
         let channel = format!("orderbook:{}", symbol);
         let message = serde_json::to_string(update)?;
         
-        let mut conn = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         let _: () = conn.publish(channel, message).await?;
         Ok(())
     }
 
     // Clear cache for a symbol
     pub async fn clear_symbol_cache(&self, symbol: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut conn = self.client.get_async_connection().await?;
+        let mut conn = self.conn.clone();
         
         let patterns = vec![
             format!("orderbook:{}", symbol),
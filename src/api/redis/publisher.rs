@@ -0,0 +1,301 @@
+//! Market data publishing to Redis, driven by the engine events.
+//!
+//! Trades are published on `trades:{symbol}` as soon as they are executed, every trade queued
+//! at that point in one round trip. Book changes only mark the symbol as changed: its top of
+//! book is published on `orderbook:{symbol}` at most once per throttle interval, together with
+//! a refresh of the cached book, price levels, recent trades and volume statistics.
+//!
+//! Trades wait for the publisher in a bounded queue. Trades that do not fit are not published,
+//! and the caches of every book are refreshed instead so they catch up.
+
+use crate::{BookEvent, EventSink, OrderBook, OrderBookSnapshot, TradeRecord};
+use dashmap::DashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+use tracing::warn;
+
+use super::{OrderBookCache, PriceLevelCache, RedisClient, TradeCache};
+
+/// Settings of the Redis publisher
+#[derive(Debug, Clone, Copy)]
+pub struct RedisPublisherConfig {
+    /// Shortest delay between two book updates of the same symbol
+    pub throttle: Duration,
+    /// Number of price levels cached per side
+    pub depth: usize,
+    /// Number of trades kept in the recent trades cache
+    pub recent_trades: usize,
+    /// Number of trades waiting to be published before new ones are dropped
+    pub trade_capacity: usize,
+    /// Most trades published in one round trip
+    pub trade_batch: usize,
+}
+
+impl Default for RedisPublisherConfig {
+    fn default() -> Self {
+        Self {
+            throttle: Duration::from_millis(100),
+            depth: 20,
+            recent_trades: 100,
+            trade_capacity: 10_000,
+            trade_batch: 500,
+        }
+    }
+}
+
+/// State shared by a [`RedisEventSink`] and its receiver
+#[derive(Debug, Default)]
+struct Changes {
+    /// Symbols whose book changed since the publisher last took them
+    books: Mutex<HashSet<String>>,
+    /// Whether trades were dropped since the publisher last checked
+    lagged: AtomicBool,
+}
+
+/// Event sink handing the book events over to [`run_redis_publisher`]
+#[derive(Debug, Clone)]
+pub struct RedisEventSink {
+    trades: Sender<(String, TradeRecord)>,
+    changes: Arc<Changes>,
+}
+
+impl RedisEventSink {
+    /// Create a sink queueing up to `trade_capacity` trades and the receiver to give to
+    /// [`run_redis_publisher`]
+    pub fn channel(trade_capacity: usize) -> (Self, RedisPublisherReceiver) {
+        let (trades, receiver) = channel(trade_capacity.max(1));
+        let changes = Arc::new(Changes::default());
+        (
+            Self {
+                trades,
+                changes: changes.clone(),
+            },
+            RedisPublisherReceiver {
+                trades: receiver,
+                changes,
+            },
+        )
+    }
+}
+
+/// Receiving end of a [`RedisEventSink`]
+#[derive(Debug)]
+pub struct RedisPublisherReceiver {
+    trades: Receiver<(String, TradeRecord)>,
+    changes: Arc<Changes>,
+}
+
+impl RedisPublisherReceiver {
+    /// Wait for a trade, then take it with the trades queued behind it, up to `max` in all.
+    /// Returns `None` once every sink is dropped.
+    pub async fn recv_trades(&mut self, max: usize) -> Option<Vec<(String, TradeRecord)>> {
+        let mut trades = vec![self.trades.recv().await?];
+        while trades.len() < max {
+            match self.trades.try_recv() {
+                Ok(trade) => trades.push(trade),
+                Err(_) => break,
+            }
+        }
+        Some(trades)
+    }
+
+    /// Take the symbols whose book changed since the last call, each once however many
+    /// times it changed
+    pub fn take_changed(&self) -> HashSet<String> {
+        std::mem::take(&mut *self.changes.books.lock().unwrap())
+    }
+
+    /// Whether trades were dropped because the queue was full since the last call
+    pub fn take_lagged(&self) -> bool {
+        self.changes.lagged.swap(false, Ordering::AcqRel)
+    }
+}
+
+impl EventSink for RedisEventSink {
+    fn publish(&self, event: &BookEvent) {
+        if let BookEvent::Trade { symbol, trade, .. } = event
+            && self.trades.try_send((symbol.clone(), *trade)).is_err()
+        {
+            self.changes.lagged.store(true, Ordering::Release);
+        }
+        let mut books = self.changes.books.lock().unwrap();
+        if !books.contains(event.symbol()) {
+            books.insert(event.symbol().to_string());
+        }
+    }
+}
+
+/// Publish trades and throttled book updates to Redis until every sink is dropped
+pub async fn run_redis_publisher(
+    redis: RedisClient,
+    orderbooks: Arc<DashMap<String, Arc<OrderBook>>>,
+    mut receiver: RedisPublisherReceiver,
+    config: RedisPublisherConfig,
+) {
+    let mut ticker = tokio::time::interval(config.throttle.max(Duration::from_millis(1)));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut traded_books = HashSet::new();
+
+    loop {
+        tokio::select! {
+            trades = receiver.recv_trades(config.trade_batch.max(1)) => {
+                let Some(trades) = trades else { break };
+                let mut by_symbol: HashMap<String, Vec<TradeCache>> = HashMap::new();
+                for (symbol, trade) in &trades {
+                    by_symbol
+                        .entry(symbol.clone())
+                        .or_default()
+                        .push(trade_cache(symbol, trade));
+                }
+                for (symbol, trades) in by_symbol {
+                    if let Err(e) = redis.publish_trades(&symbol, &trades).await {
+                        warn!(
+                            "Failed to publish {} trades of {} to Redis: {}",
+                            trades.len(),
+                            symbol,
+                            e
+                        );
+                    }
+                    traded_books.insert(symbol);
+                }
+            },
+            _ = ticker.tick() => {
+                let mut changed_books = receiver.take_changed();
+                if receiver.take_lagged() {
+                    warn!(
+                        "Trades were dropped before reaching the Redis publisher, refreshing every book"
+                    );
+                    for item in orderbooks.iter() {
+                        changed_books.insert(item.key().clone());
+                        traded_books.insert(item.key().clone());
+                    }
+                }
+                for symbol in changed_books {
+                    let Some(orderbook) = orderbooks.get(&symbol).map(|item| item.value().clone())
+                    else {
+                        continue;
+                    };
+                    if let Err(e) = publish_book(&redis, &orderbook, &config).await {
+                        warn!("Failed to publish the order book of {} to Redis: {}", symbol, e);
+                    }
+                    if traded_books.remove(&symbol)
+                        && let Err(e) = refresh_trades(&redis, &orderbook, &config).await
+                    {
+                        warn!("Failed to cache the trades of {} in Redis: {}", symbol, e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn trade_cache(symbol: &str, trade: &TradeRecord) -> TradeCache {
+    TradeCache {
+        id: trade.transaction_id.to_string(),
+        symbol: symbol.to_string(),
+        price: trade.price,
+        quantity: trade.quantity,
+        side: format!("{:?}", trade.taker_side),
+        timestamp: chrono::DateTime::from_timestamp_millis(trade.timestamp as i64)
+            .unwrap_or_else(chrono::Utc::now),
+    }
+}
+
+/// Cached summary of a book, taken from a snapshot of all its levels
+pub fn orderbook_cache(
+    snapshot: &OrderBookSnapshot,
+    last_trade_price: Option<u64>,
+) -> OrderBookCache {
+    OrderBookCache {
+        symbol: snapshot.symbol.clone(),
+        best_bid: snapshot.best_bid().map(|(price, _)| price),
+        best_ask: snapshot.best_ask().map(|(price, _)| price),
+        spread: snapshot.spread(),
+        mid_price: snapshot.mid_price(),
+        last_trade_price,
+        total_orders: snapshot
+            .bids
+            .iter()
+            .chain(&snapshot.asks)
+            .map(|level| level.order_count)
+            .sum(),
+        bid_levels: snapshot.bids.len(),
+        ask_levels: snapshot.asks.len(),
+        total_bid_quantity: snapshot.total_bid_volume(),
+        total_ask_quantity: snapshot.total_ask_volume(),
+        timestamp: chrono::DateTime::from_timestamp_millis(snapshot.timestamp as i64)
+            .unwrap_or_else(chrono::Utc::now),
+    }
+}
+
+async fn publish_book(
+    redis: &RedisClient,
+    orderbook: &OrderBook,
+    config: &RedisPublisherConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let symbol = orderbook.symbol();
+    let snapshot = orderbook.create_snapshot(usize::MAX);
+    let update = orderbook_cache(&snapshot, orderbook.last_trade_price());
+    redis.publish_orderbook_update(symbol, &update).await?;
+    redis.cache_orderbook(symbol, &update).await?;
+
+    let to_levels = |levels: &[pricelevel::PriceLevelSnapshot]| -> Vec<PriceLevelCache> {
+        levels
+            .iter()
+            .take(config.depth)
+            .map(|level| PriceLevelCache {
+                price: level.price,
+                visible_quantity: level.visible_quantity,
+                hidden_quantity: level.hidden_quantity,
+                order_count: level.order_count,
+            })
+            .collect()
+    };
+    redis
+        .cache_price_levels(symbol, "bids", &to_levels(&snapshot.bids))
+        .await?;
+    redis
+        .cache_price_levels(symbol, "asks", &to_levels(&snapshot.asks))
+        .await?;
+    Ok(())
+}
+
+async fn refresh_trades(
+    redis: &RedisClient,
+    orderbook: &OrderBook,
+    config: &RedisPublisherConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let symbol = orderbook.symbol();
+    let trades: Vec<TradeCache> = orderbook
+        .trade_tape()
+        .recent(0, config.recent_trades)
+        .iter()
+        .map(|trade| trade_cache(symbol, trade))
+        .collect();
+    redis.cache_recent_trades(symbol, &trades).await?;
+
+    let stats = orderbook.trade_stats();
+    let fields: HashMap<String, String> = [
+        ("total_volume", stats.volume.to_string()),
+        ("quote_volume", stats.quote_volume.to_string()),
+        ("total_trades", stats.trade_count.to_string()),
+        ("avg_price", stats.vwap.unwrap_or(0.0).to_string()),
+        ("high_price", stats.high.unwrap_or(0).to_string()),
+        ("low_price", stats.low.unwrap_or(0).to_string()),
+        ("open_price", stats.open.unwrap_or(0).to_string()),
+        ("last_price", stats.last.unwrap_or(0).to_string()),
+        ("price_change", stats.price_change.unwrap_or(0).to_string()),
+        (
+            "price_change_percent",
+            stats.price_change_percent.unwrap_or(0.0).to_string(),
+        ),
+        ("window_ms", stats.window_ms.to_string()),
+    ]
+    .into_iter()
+    .map(|(field, value)| (field.to_string(), value))
+    .collect();
+    redis.cache_volume_stats(symbol, &fields).await
+}
@@ -0,0 +1,46 @@
+//! Event sink handing the book events over to an async task
+use crate::{BookEvent, EventSink};
+use std::fmt::Debug;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+
+/// Event sink sending each book event, as a `T`, to the task holding its [`ChannelReceiver`]
+#[derive(Debug)]
+pub struct ChannelSink<T> {
+    sender: UnboundedSender<T>,
+}
+
+impl<T> ChannelSink<T> {
+    /// Create a sink and the receiver to give to the task consuming its events
+    pub fn channel() -> (Self, ChannelReceiver<T>) {
+        let (sender, receiver) = unbounded_channel();
+        (Self { sender }, ChannelReceiver(receiver))
+    }
+}
+
+impl<T> Clone for ChannelSink<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+/// Receiving end of a [`ChannelSink`]
+#[derive(Debug)]
+pub struct ChannelReceiver<T>(pub(crate) UnboundedReceiver<T>);
+
+impl<T> EventSink for ChannelSink<T>
+where
+    T: for<'a> From<&'a BookEvent> + Debug + Send,
+{
+    fn publish(&self, event: &BookEvent) {
+        // The consuming tasks only stop with the runtime, after which events have nowhere to go
+        let _ = self.sender.send(T::from(event));
+    }
+}
+
+impl From<&BookEvent> for BookEvent {
+    fn from(event: &BookEvent) -> Self {
+        event.clone()
+    }
+}
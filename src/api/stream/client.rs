@@ -0,0 +1,124 @@
+use actix_codec::Framed;
+use actix_http::ws::{self, CloseCode, CloseReason, Codec, Frame, Message};
+use base64::prelude::*;
+use futures_util::{SinkExt, StreamExt};
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use uuid::Uuid;
+
+use serde::de::DeserializeOwned;
+
+use super::ClientRequest;
+
+/// Longest HTTP response accepted during the handshake
+const MAX_HANDSHAKE_BYTES: usize = 8 * 1024;
+
+/// Minimal client of the market data and user streams, for tools and tests
+pub struct StreamClient {
+    framed: Framed<TcpStream, Codec>,
+}
+
+impl StreamClient {
+    /// Connect to the WebSocket served at `path` (such as `/ws/market-data`) on `addr`. The
+    /// path may carry a query string, such as the token of the user stream.
+    pub async fn connect(addr: impl ToSocketAddrs, path: &str) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(addr).await?;
+        let key = BASE64_STANDARD.encode(Uuid::new_v4().as_bytes());
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path, key
+        );
+        stream.write_all(request.as_bytes()).await?;
+
+        // Read the response byte by byte, so that no frame sent right after it is consumed
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() >= MAX_HANDSHAKE_BYTES {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "handshake response too long",
+                ));
+            }
+            response.push(stream.read_u8().await?);
+        }
+        let response = String::from_utf8_lossy(&response);
+        let accept = ws::hash_key(key.as_bytes());
+        let accepted = response.lines().any(|line| {
+            line.split_once(':').is_some_and(|(name, value)| {
+                name.eq_ignore_ascii_case("sec-websocket-accept")
+                    && value.trim().as_bytes() == accept
+            })
+        });
+        if !response.starts_with("HTTP/1.1 101") || !accepted {
+            let status = response.lines().next().unwrap_or_default().to_string();
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("WebSocket handshake failed: {}", status),
+            ));
+        }
+
+        Ok(Self {
+            framed: Framed::new(stream, Codec::new().client_mode()),
+        })
+    }
+
+    /// Send a request
+    pub async fn send(&mut self, request: &ClientRequest) -> io::Result<()> {
+        let text = serde_json::to_string(request)?;
+        self.framed
+            .send(Message::Text(text.into()))
+            .await
+            .map_err(io::Error::other)
+    }
+
+    /// Subscribe to channels such as `depth:BTC/USD`
+    pub async fn subscribe(&mut self, channels: &[&str]) -> io::Result<()> {
+        self.send(&ClientRequest::Subscribe {
+            channels: channels.iter().map(|channel| channel.to_string()).collect(),
+        })
+        .await
+    }
+
+    /// Unsubscribe from channels
+    pub async fn unsubscribe(&mut self, channels: &[&str]) -> io::Result<()> {
+        self.send(&ClientRequest::Unsubscribe {
+            channels: channels.iter().map(|channel| channel.to_string()).collect(),
+        })
+        .await
+    }
+
+    /// Wait for the next message, answering pings on the way. Returns `None` once the server
+    /// closed the stream.
+    pub async fn next_message<M: DeserializeOwned>(&mut self) -> io::Result<Option<M>> {
+        while let Some(frame) = self.framed.next().await {
+            match frame.map_err(io::Error::other)? {
+                Frame::Text(text) => {
+                    return serde_json::from_slice(&text)
+                        .map(Some)
+                        .map_err(io::Error::from);
+                }
+                Frame::Ping(data) => self
+                    .framed
+                    .send(Message::Pong(data))
+                    .await
+                    .map_err(io::Error::other)?,
+                Frame::Close(_) => return Ok(None),
+                Frame::Pong(_) | Frame::Binary(_) | Frame::Continuation(_) => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Close the connection
+    pub async fn close(mut self) -> io::Result<()> {
+        let reason = CloseReason {
+            code: CloseCode::Normal,
+            description: None,
+        };
+        self.framed
+            .send(Message::Close(Some(reason)))
+            .await
+            .map_err(io::Error::other)
+    }
+}
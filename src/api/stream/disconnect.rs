@@ -0,0 +1,270 @@
+use crate::Sequencer;
+use dashmap::DashMap;
+use pricelevel::OrderId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Settings of cancel-on-disconnect
+#[derive(Debug, Clone, Copy)]
+pub struct CancelOnDisconnectConfig {
+    /// Grace period of sessions that do not choose one
+    pub default_grace_period: Duration,
+    /// Longest grace period a session may choose
+    pub max_grace_period: Duration,
+}
+
+impl Default for CancelOnDisconnectConfig {
+    fn default() -> Self {
+        Self {
+            default_grace_period: Duration::from_secs(2),
+            max_grace_period: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Which orders of an account are cancelled when its session drops
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CancelScope {
+    AllSymbols,
+    Symbols(Vec<String>),
+}
+
+/// What a session asked to happen when it drops
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancelOnDisconnect {
+    /// Time left to the account to open a new session before its orders are cancelled
+    pub grace_period: Duration,
+    pub scope: CancelScope,
+}
+
+/// An order cancelled because the session of its account dropped
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CancelledOrder {
+    pub symbol: String,
+    pub order_id: OrderId,
+    pub client_order_id: Option<String>,
+}
+
+/// Orders cancelled after a session dropped, reported to the next session of the account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisconnectReport {
+    pub disconnected_at: u64,
+    pub cancelled_at: u64,
+    pub orders: Vec<CancelledOrder>,
+}
+
+#[derive(Debug, Default)]
+struct AccountSessions {
+    open: usize,
+    /// Incremented whenever a session opens, so that a timer can tell the account reconnected
+    generation: u64,
+    reports: Vec<DisconnectReport>,
+}
+
+/// Cancels the orders of accounts whose sessions dropped, once their grace period elapsed
+/// without a new session
+pub struct DisconnectCanceller {
+    sequencers: Arc<DashMap<String, Arc<Sequencer>>>,
+    config: CancelOnDisconnectConfig,
+    accounts: Mutex<HashMap<Uuid, AccountSessions>>,
+}
+
+impl std::fmt::Debug for DisconnectCanceller {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DisconnectCanceller")
+            .field("symbols", &self.sequencers.len())
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl DisconnectCanceller {
+    /// Create a canceller cancelling orders through the sequencers of the books
+    pub fn new(
+        sequencers: Arc<DashMap<String, Arc<Sequencer>>>,
+        config: CancelOnDisconnectConfig,
+    ) -> Self {
+        Self {
+            sequencers,
+            config,
+            accounts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &CancelOnDisconnectConfig {
+        &self.config
+    }
+
+    /// Build the policy of a session, checking its grace period and symbols
+    pub fn policy(
+        &self,
+        grace_period: Option<Duration>,
+        scope: CancelScope,
+    ) -> Result<CancelOnDisconnect, String> {
+        let grace_period = grace_period.unwrap_or(self.config.default_grace_period);
+        if grace_period > self.config.max_grace_period {
+            return Err(format!(
+                "grace period is longer than {} ms",
+                self.config.max_grace_period.as_millis()
+            ));
+        }
+        if let CancelScope::Symbols(symbols) = &scope {
+            if symbols.is_empty() {
+                return Err("no symbol to cancel on disconnect".to_string());
+            }
+            if let Some(symbol) = symbols
+                .iter()
+                .find(|symbol| !self.sequencers.contains_key(*symbol))
+            {
+                return Err(format!("unknown symbol: {}", symbol));
+            }
+        }
+        Ok(CancelOnDisconnect {
+            grace_period,
+            scope,
+        })
+    }
+
+    /// Register a new session of `account`, which stops the pending cancellations of its
+    /// earlier sessions. Returns the guard to drop when the session ends, and the reports of
+    /// the orders cancelled since the account was last connected.
+    pub fn connect(self: &Arc<Self>, account: Uuid) -> (SessionGuard, Vec<DisconnectReport>) {
+        let mut accounts = self.accounts.lock().unwrap();
+        let sessions = accounts.entry(account).or_default();
+        sessions.open += 1;
+        sessions.generation += 1;
+        let reports = std::mem::take(&mut sessions.reports);
+        (
+            SessionGuard {
+                canceller: self.clone(),
+                account,
+                policy: None,
+            },
+            reports,
+        )
+    }
+
+    /// Called when a session of `account` ends; returns the generation to compare against
+    /// when the grace period elapsed
+    fn disconnect(&self, account: Uuid) -> u64 {
+        let mut accounts = self.accounts.lock().unwrap();
+        let sessions = accounts.entry(account).or_default();
+        sessions.open = sessions.open.saturating_sub(1);
+        sessions.generation
+    }
+
+    /// Cancel the orders in the scope of a dropped session, unless the account connected
+    /// again since then
+    async fn expire(
+        &self,
+        account: Uuid,
+        generation: u64,
+        policy: &CancelOnDisconnect,
+        disconnected_at: u64,
+    ) {
+        {
+            let accounts = self.accounts.lock().unwrap();
+            match accounts.get(&account) {
+                Some(sessions) if sessions.open == 0 && sessions.generation == generation => {}
+                _ => return,
+            }
+        }
+
+        let sequencers: Vec<(String, Arc<Sequencer>)> = match &policy.scope {
+            CancelScope::AllSymbols => self
+                .sequencers
+                .iter()
+                .map(|item| (item.key().clone(), item.value().clone()))
+                .collect(),
+            CancelScope::Symbols(symbols) => symbols
+                .iter()
+                .filter_map(|symbol| {
+                    self.sequencers
+                        .get(symbol)
+                        .map(|item| (symbol.clone(), item.value().clone()))
+                })
+                .collect(),
+        };
+        let mut orders = Vec::new();
+        for (symbol, sequencer) in sequencers {
+            match sequencer.cancel_account_orders_async(account).await {
+                Ok(cancelled) => {
+                    let book = sequencer.shared_book();
+                    orders.extend(cancelled.into_iter().map(|order_id| {
+                        CancelledOrder {
+                            symbol: symbol.clone(),
+                            order_id,
+                            client_order_id: book
+                                .order_state(order_id)
+                                .and_then(|record| record.owner)
+                                .and_then(|owner| owner.client_order_id),
+                        }
+                    }));
+                }
+                Err(e) => warn!(
+                    "Failed to cancel the orders of {} in {} on disconnect: {}",
+                    account, symbol, e
+                ),
+            }
+        }
+        info!(
+            "Cancelled {} orders of {} after its session dropped",
+            orders.len(),
+            account
+        );
+
+        let report = DisconnectReport {
+            disconnected_at,
+            cancelled_at: crate::current_time_millis(),
+            orders,
+        };
+        self.accounts
+            .lock()
+            .unwrap()
+            .entry(account)
+            .or_default()
+            .reports
+            .push(report);
+    }
+}
+
+/// A session registered with a [`DisconnectCanceller`]. Dropping it ends the session and,
+/// if cancel-on-disconnect is enabled, starts the grace period of the account.
+#[derive(Debug)]
+pub struct SessionGuard {
+    canceller: Arc<DisconnectCanceller>,
+    account: Uuid,
+    policy: Option<CancelOnDisconnect>,
+}
+
+impl SessionGuard {
+    /// Enable cancel-on-disconnect for the session, or disable it with `None`
+    pub fn set_policy(&mut self, policy: Option<CancelOnDisconnect>) {
+        self.policy = policy;
+    }
+
+    pub fn policy(&self) -> Option<&CancelOnDisconnect> {
+        self.policy.as_ref()
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let generation = self.canceller.disconnect(self.account);
+        let Some(policy) = self.policy.take() else {
+            return;
+        };
+        let disconnected_at = crate::current_time_millis();
+        let (canceller, account) = (self.canceller.clone(), self.account);
+        actix_rt::spawn(async move {
+            tokio::time::sleep(policy.grace_period).await;
+            canceller
+                .expire(account, generation, &policy, disconnected_at)
+                .await;
+        });
+    }
+}
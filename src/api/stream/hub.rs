@@ -0,0 +1,621 @@
+use crate::api::sink::{ChannelReceiver, ChannelSink};
+use crate::{BookEvent, OrderBook, TradeRecord};
+use dashmap::DashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+use super::{Channel, ClientRequest, DepthLevel, StreamConfig, StreamMessage};
+
+/// What the market data hub needs of a book event
+#[derive(Debug)]
+pub enum MarketUpdate {
+    Trade {
+        symbol: String,
+        sequence: u64,
+        trade: TradeRecord,
+    },
+    BookChanged {
+        symbol: String,
+    },
+}
+
+impl From<&BookEvent> for MarketUpdate {
+    fn from(event: &BookEvent) -> Self {
+        match event {
+            BookEvent::Trade {
+                symbol,
+                sequence,
+                trade,
+            } => MarketUpdate::Trade {
+                symbol: symbol.clone(),
+                sequence: *sequence,
+                trade: *trade,
+            },
+            BookEvent::OrderUpdated { symbol, .. } => MarketUpdate::BookChanged {
+                symbol: symbol.clone(),
+            },
+        }
+    }
+}
+
+/// Event sink handing the book events over to [`run_market_data_hub`]
+pub type MarketDataSink = ChannelSink<MarketUpdate>;
+
+/// Receiving end of a [`MarketDataSink`]
+pub type MarketDataReceiver = ChannelReceiver<MarketUpdate>;
+
+/// Forward the engine events to the sessions of `hub` until every sink is dropped
+pub async fn run_market_data_hub(hub: Arc<MarketDataHub>, receiver: MarketDataReceiver) {
+    let mut receiver = receiver.0;
+    let mut ticker = tokio::time::interval(hub.config.throttle.max(Duration::from_millis(1)));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut changed_books = HashSet::new();
+    let mut traded_books = HashSet::new();
+
+    loop {
+        tokio::select! {
+            update = receiver.recv() => match update {
+                Some(MarketUpdate::Trade { symbol, sequence, trade }) => {
+                    hub.publish_trade(&symbol, sequence, trade);
+                    traded_books.insert(symbol.clone());
+                    changed_books.insert(symbol);
+                }
+                Some(MarketUpdate::BookChanged { symbol }) => {
+                    changed_books.insert(symbol);
+                }
+                None => break,
+            },
+            _ = ticker.tick() => {
+                for symbol in changed_books.drain() {
+                    hub.publish_book(&symbol, traded_books.remove(&symbol));
+                }
+            }
+        }
+    }
+}
+
+/// Top levels of a book as last published
+#[derive(Debug, Clone)]
+struct DepthView {
+    sequence: u64,
+    bids: Vec<DepthLevel>,
+    asks: Vec<DepthLevel>,
+}
+
+impl DepthView {
+    fn capture(book: &OrderBook, depth: usize) -> Self {
+        let snapshot = book.create_snapshot(depth);
+        let levels = |levels: Vec<pricelevel::PriceLevelSnapshot>| -> Vec<DepthLevel> {
+            levels
+                .into_iter()
+                .filter(|level| level.visible_quantity > 0)
+                .map(|level| DepthLevel {
+                    price: level.price,
+                    quantity: level.visible_quantity,
+                    order_count: level.order_count,
+                })
+                .collect()
+        };
+        Self {
+            sequence: snapshot.sequence,
+            bids: levels(snapshot.bids),
+            asks: levels(snapshot.asks),
+        }
+    }
+
+    fn bbo(&self) -> (Option<DepthLevel>, Option<DepthLevel>) {
+        (self.bids.first().copied(), self.asks.first().copied())
+    }
+}
+
+/// Levels of `new` that differ from `old`, with the levels gone from `new` at quantity zero
+fn changed_levels(old: &[DepthLevel], new: &[DepthLevel]) -> Vec<DepthLevel> {
+    let old: HashMap<u64, &DepthLevel> = old.iter().map(|level| (level.price, level)).collect();
+    let new_prices: HashSet<u64> = new.iter().map(|level| level.price).collect();
+    let mut changed: Vec<DepthLevel> = new
+        .iter()
+        .filter(|level| old.get(&level.price) != Some(level))
+        .copied()
+        .collect();
+    changed.extend(
+        old.keys()
+            .filter(|price| !new_prices.contains(price))
+            .map(|&price| DepthLevel {
+                price,
+                quantity: 0,
+                order_count: 0,
+            }),
+    );
+    changed
+}
+
+#[derive(Debug, Default)]
+struct HubState {
+    next_session: u64,
+    sessions: HashMap<u64, Arc<SessionQueue>>,
+    subscribers: HashMap<Channel, HashSet<u64>>,
+    views: HashMap<String, DepthView>,
+}
+
+impl HubState {
+    fn subscribers(&self, channel: &Channel) -> impl Iterator<Item = &Arc<SessionQueue>> {
+        self.subscribers
+            .get(channel)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.sessions.get(id))
+    }
+
+    fn has_subscribers(&self, channel: &Channel) -> bool {
+        self.subscribers
+            .get(channel)
+            .is_some_and(|ids| !ids.is_empty())
+    }
+}
+
+/// Routes the market data of the books to the subscribed sessions
+pub struct MarketDataHub {
+    orderbooks: Arc<DashMap<String, Arc<OrderBook>>>,
+    config: StreamConfig,
+    state: Mutex<HubState>,
+}
+
+impl MarketDataHub {
+    pub fn new(orderbooks: Arc<DashMap<String, Arc<OrderBook>>>, config: StreamConfig) -> Self {
+        Self {
+            orderbooks,
+            config,
+            state: Mutex::new(HubState::default()),
+        }
+    }
+
+    pub fn config(&self) -> &StreamConfig {
+        &self.config
+    }
+
+    /// Open a session without any subscription
+    pub fn open_session(self: &Arc<Self>) -> MarketDataSession {
+        let queue = Arc::new(SessionQueue::new(self.config.max_queued_trades));
+        let mut state = self.state.lock().unwrap();
+        state.next_session += 1;
+        let id = state.next_session;
+        state.sessions.insert(id, queue.clone());
+        MarketDataSession {
+            id,
+            hub: self.clone(),
+            queue,
+        }
+    }
+
+    fn close_session(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.sessions.remove(&id);
+        state.subscribers.retain(|_, ids| {
+            ids.remove(&id);
+            !ids.is_empty()
+        });
+    }
+
+    fn subscribe(&self, session: u64, queue: &SessionQueue, name: &str) {
+        let channel: Channel = match name.parse() {
+            Ok(channel) => channel,
+            Err(message) => return queue.push(StreamMessage::Error { message }),
+        };
+        let Some(book) = self
+            .orderbooks
+            .get(channel.symbol())
+            .map(|item| item.value().clone())
+        else {
+            return queue.push(StreamMessage::Error {
+                message: format!("Order book for symbol {} not found", channel.symbol()),
+            });
+        };
+        if let Channel::Candles(_, interval) = &channel
+            && !book.candles().intervals().contains(interval)
+        {
+            return queue.push(StreamMessage::Error {
+                message: format!(
+                    "{} candles are not built for {}",
+                    interval,
+                    channel.symbol()
+                ),
+            });
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let name = channel.to_string();
+        queue.push(StreamMessage::Subscribed {
+            channel: name.clone(),
+        });
+        if !state
+            .subscribers
+            .entry(channel.clone())
+            .or_default()
+            .insert(session)
+        {
+            return;
+        }
+        match &channel {
+            Channel::Trades(_) => {}
+            Channel::Depth(symbol) | Channel::Bbo(symbol) => {
+                let view = state
+                    .views
+                    .entry(symbol.clone())
+                    .or_insert_with(|| DepthView::capture(&book, self.config.depth));
+                if matches!(channel, Channel::Depth(_)) {
+                    queue.push_depth_snapshot(&name, view);
+                } else {
+                    let (bid, ask) = view.bbo();
+                    queue.push_latest(
+                        &name,
+                        StreamMessage::Bbo {
+                            channel: name.clone(),
+                            sequence: view.sequence,
+                            bid,
+                            ask,
+                        },
+                    );
+                }
+            }
+            Channel::Candles(_, interval) => {
+                if let Some(candle) = book.candles().current(*interval) {
+                    queue.push_latest(
+                        &name,
+                        StreamMessage::Candle {
+                            channel: name.clone(),
+                            candle,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    fn unsubscribe(&self, session: u64, queue: &SessionQueue, name: &str) {
+        let channel: Channel = match name.parse() {
+            Ok(channel) => channel,
+            Err(message) => return queue.push(StreamMessage::Error { message }),
+        };
+        let mut state = self.state.lock().unwrap();
+        if let Some(ids) = state.subscribers.get_mut(&channel) {
+            ids.remove(&session);
+            if ids.is_empty() {
+                state.subscribers.remove(&channel);
+            }
+        }
+        let name = channel.to_string();
+        queue.remove_channel(&name);
+        queue.push(StreamMessage::Unsubscribed { channel: name });
+    }
+
+    fn publish_trade(&self, symbol: &str, sequence: u64, trade: TradeRecord) {
+        let state = self.state.lock().unwrap();
+        let channel = Channel::Trades(symbol.to_string());
+        let name = channel.to_string();
+        for queue in state.subscribers(&channel) {
+            queue.push_trade(
+                &name,
+                StreamMessage::Trade {
+                    channel: name.clone(),
+                    sequence,
+                    trade,
+                },
+            );
+        }
+    }
+
+    /// Publish the depth, best bid and offer and, after trades, candles of a changed book
+    fn publish_book(&self, symbol: &str, traded: bool) {
+        let Some(book) = self.orderbooks.get(symbol).map(|item| item.value().clone()) else {
+            return;
+        };
+        let mut state = self.state.lock().unwrap();
+        let depth = Channel::Depth(symbol.to_string());
+        let bbo = Channel::Bbo(symbol.to_string());
+
+        if state.has_subscribers(&depth) || state.has_subscribers(&bbo) {
+            let view = DepthView::capture(&book, self.config.depth);
+            let previous = state.views.insert(symbol.to_string(), view.clone());
+            if let Some(previous) = previous {
+                let bids = changed_levels(&previous.bids, &view.bids);
+                let asks = changed_levels(&previous.asks, &view.asks);
+                if bids.is_empty() && asks.is_empty() {
+                    // Keep the sequence of the last published depth, which the next update
+                    // chains to
+                    state.views.insert(symbol.to_string(), previous);
+                    return self.publish_candles(&state, &book, traded);
+                }
+                let name = depth.to_string();
+                for queue in state.subscribers(&depth) {
+                    queue.push_depth_update(&name, previous.sequence, view.sequence, &bids, &asks);
+                }
+                if previous.bbo() != view.bbo() {
+                    let (bid, ask) = view.bbo();
+                    let name = bbo.to_string();
+                    for queue in state.subscribers(&bbo) {
+                        queue.push_latest(
+                            &name,
+                            StreamMessage::Bbo {
+                                channel: name.clone(),
+                                sequence: view.sequence,
+                                bid,
+                                ask,
+                            },
+                        );
+                    }
+                }
+            }
+        } else {
+            // Nobody watches the levels: the view is captured again on the next subscription
+            state.views.remove(symbol);
+        }
+        self.publish_candles(&state, &book, traded);
+    }
+
+    fn publish_candles(&self, state: &HubState, book: &OrderBook, traded: bool) {
+        if !traded {
+            return;
+        }
+        for &interval in book.candles().intervals() {
+            let channel = Channel::Candles(book.symbol().to_string(), interval);
+            if !state.has_subscribers(&channel) {
+                continue;
+            }
+            let Some(candle) = book.candles().current(interval) else {
+                continue;
+            };
+            let name = channel.to_string();
+            for queue in state.subscribers(&channel) {
+                queue.push_latest(
+                    &name,
+                    StreamMessage::Candle {
+                        channel: name.clone(),
+                        candle,
+                    },
+                );
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for MarketDataHub {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MarketDataHub")
+            .field("config", &self.config)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+/// A depth message waiting to be sent, into which later updates are merged
+#[derive(Debug)]
+struct PendingDepth {
+    snapshot: bool,
+    prev_sequence: u64,
+    sequence: u64,
+    bids: BTreeMap<u64, DepthLevel>,
+    asks: BTreeMap<u64, DepthLevel>,
+}
+
+impl PendingDepth {
+    fn apply(&mut self, sequence: u64, bids: &[DepthLevel], asks: &[DepthLevel]) {
+        self.sequence = sequence;
+        for (levels, changes) in [(&mut self.bids, bids), (&mut self.asks, asks)] {
+            for level in changes {
+                if self.snapshot && level.quantity == 0 {
+                    levels.remove(&level.price);
+                } else {
+                    levels.insert(level.price, *level);
+                }
+            }
+        }
+    }
+
+    fn into_message(self, channel: String) -> StreamMessage {
+        let bids = self.bids.into_values().rev().collect();
+        let asks = self.asks.into_values().collect();
+        if self.snapshot {
+            StreamMessage::DepthSnapshot {
+                channel,
+                sequence: self.sequence,
+                bids,
+                asks,
+            }
+        } else {
+            StreamMessage::DepthUpdate {
+                channel,
+                prev_sequence: self.prev_sequence,
+                sequence: self.sequence,
+                bids,
+                asks,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    messages: VecDeque<StreamMessage>,
+    queued_trades: usize,
+    dropped_trades: BTreeMap<String, u64>,
+    depth: BTreeMap<String, PendingDepth>,
+    /// Best bid and offer and candles, of which only the latest matters
+    latest: BTreeMap<String, StreamMessage>,
+}
+
+/// Messages waiting to be sent to a session
+#[derive(Debug)]
+struct SessionQueue {
+    pending: Mutex<Pending>,
+    notify: Notify,
+    max_queued_trades: usize,
+}
+
+impl SessionQueue {
+    fn new(max_queued_trades: usize) -> Self {
+        Self {
+            pending: Mutex::new(Pending::default()),
+            notify: Notify::new(),
+            max_queued_trades,
+        }
+    }
+
+    fn push(&self, message: StreamMessage) {
+        self.pending.lock().unwrap().messages.push_back(message);
+        self.notify.notify_one();
+    }
+
+    fn push_trade(&self, channel: &str, message: StreamMessage) {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.queued_trades >= self.max_queued_trades {
+            *pending
+                .dropped_trades
+                .entry(channel.to_string())
+                .or_default() += 1;
+        } else {
+            pending.queued_trades += 1;
+            pending.messages.push_back(message);
+        }
+        drop(pending);
+        self.notify.notify_one();
+    }
+
+    fn push_depth_snapshot(&self, channel: &str, view: &DepthView) {
+        let depth = PendingDepth {
+            snapshot: true,
+            prev_sequence: view.sequence,
+            sequence: view.sequence,
+            bids: view
+                .bids
+                .iter()
+                .map(|level| (level.price, *level))
+                .collect(),
+            asks: view
+                .asks
+                .iter()
+                .map(|level| (level.price, *level))
+                .collect(),
+        };
+        self.pending
+            .lock()
+            .unwrap()
+            .depth
+            .insert(channel.to_string(), depth);
+        self.notify.notify_one();
+    }
+
+    fn push_depth_update(
+        &self,
+        channel: &str,
+        prev_sequence: u64,
+        sequence: u64,
+        bids: &[DepthLevel],
+        asks: &[DepthLevel],
+    ) {
+        let mut pending = self.pending.lock().unwrap();
+        pending
+            .depth
+            .entry(channel.to_string())
+            .or_insert_with(|| PendingDepth {
+                snapshot: false,
+                prev_sequence,
+                sequence,
+                bids: BTreeMap::new(),
+                asks: BTreeMap::new(),
+            })
+            .apply(sequence, bids, asks);
+        drop(pending);
+        self.notify.notify_one();
+    }
+
+    fn push_latest(&self, channel: &str, message: StreamMessage) {
+        self.pending
+            .lock()
+            .unwrap()
+            .latest
+            .insert(channel.to_string(), message);
+        self.notify.notify_one();
+    }
+
+    fn remove_channel(&self, channel: &str) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.messages.retain(
+            |message| !matches!(message, StreamMessage::Trade { channel: c, .. } if c == channel),
+        );
+        pending.queued_trades = pending
+            .messages
+            .iter()
+            .filter(|message| matches!(message, StreamMessage::Trade { .. }))
+            .count();
+        pending.dropped_trades.remove(channel);
+        pending.depth.remove(channel);
+        pending.latest.remove(channel);
+    }
+
+    fn drain(&self) -> Vec<StreamMessage> {
+        let mut pending = self.pending.lock().unwrap();
+        let pending = std::mem::take(&mut *pending);
+        let mut messages: Vec<StreamMessage> = pending.messages.into();
+        messages.extend(
+            pending
+                .dropped_trades
+                .into_iter()
+                .map(|(channel, count)| StreamMessage::TradesDropped { channel, count }),
+        );
+        messages.extend(
+            pending
+                .depth
+                .into_iter()
+                .map(|(channel, depth)| depth.into_message(channel)),
+        );
+        messages.extend(pending.latest.into_values());
+        messages
+    }
+}
+
+/// A consumer of the market data stream. Closed when dropped.
+#[derive(Debug)]
+pub struct MarketDataSession {
+    id: u64,
+    hub: Arc<MarketDataHub>,
+    queue: Arc<SessionQueue>,
+}
+
+impl MarketDataSession {
+    /// Apply a client request; its acknowledgement or error is queued like any message
+    pub fn handle(&self, request: ClientRequest) {
+        match request {
+            ClientRequest::Subscribe { channels } => channels
+                .iter()
+                .for_each(|name| self.hub.subscribe(self.id, &self.queue, name)),
+            ClientRequest::Unsubscribe { channels } => channels
+                .iter()
+                .for_each(|name| self.hub.unsubscribe(self.id, &self.queue, name)),
+            ClientRequest::Ping => self.queue.push(StreamMessage::Pong),
+        }
+    }
+
+    /// Take every pending message, waiting until there is at least one
+    pub async fn next_batch(&self) -> Vec<StreamMessage> {
+        loop {
+            let batch = self.queue.drain();
+            if !batch.is_empty() {
+                return batch;
+            }
+            self.queue.notify.notified().await;
+        }
+    }
+
+    /// Take every pending message without waiting
+    pub fn try_batch(&self) -> Vec<StreamMessage> {
+        self.queue.drain()
+    }
+}
+
+impl Drop for MarketDataSession {
+    fn drop(&mut self) {
+        self.hub.close_session(self.id);
+    }
+}
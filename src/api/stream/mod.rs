@@ -0,0 +1,212 @@
+//! WebSocket streaming of market data and of the orders of each account.
+//!
+//! Clients connect to `/ws/market-data` and send JSON requests such as
+//! `{"op":"subscribe","channels":["depth:BTC/USD","trades:BTC/USD"]}`. The supported channels are
+//! `trades:{symbol}`, `depth:{symbol}`, `bbo:{symbol}` and `candles:{symbol}:{interval}`.
+//!
+//! The stream is fed by the engine events through [`MarketDataSink`]. Trades are forwarded as
+//! they happen; depth, best bid and offer and candles are published at most once per throttle
+//! interval. A depth subscription starts with a snapshot of the top levels, followed by updates
+//! listing the levels that changed (a quantity of zero removes the level). Every depth message
+//! carries the sequence number of the book it reflects, and every update the sequence number of
+//! the message it applies to, so a client can detect a gap and resubscribe.
+//!
+//! Slow consumers are conflated: while a session has not sent its pending messages, depth
+//! updates are merged into one, and only the latest best bid and offer and candle are kept.
+//! Trades are never merged; past [`StreamConfig::max_queued_trades`] they are dropped and the
+//! session is told how many it missed.
+//!
+//! The private user stream, at `/ws/user`, sends an account the changes of its own orders
+//! (accepted, replaced, filled, cancelled, expired, rejected) with their fills and cumulative
+//! quantity. Clients authenticate with a bearer token (see [`crate::api::auth`]). Every
+//! notification of an account is numbered, and the latest ones are retained so that a client
+//! reconnecting with `from_sequence` gets what it missed.
+//!
+//! A user stream session may opt in to cancel-on-disconnect: if it drops, or stops answering
+//! heartbeats, and the account opens no new session within the grace period, the resting
+//! orders of the account are cancelled through [`crate::Sequencer::cancel_account_orders`],
+//! on every symbol or on the chosen ones. The next session of the account is told which
+//! orders were cancelled.
+
+mod client;
+mod disconnect;
+mod hub;
+mod user;
+mod websocket;
+
+pub use client::StreamClient;
+pub use disconnect::{
+    CancelOnDisconnect, CancelOnDisconnectConfig, CancelScope, CancelledOrder, DisconnectCanceller,
+    DisconnectReport, SessionGuard,
+};
+pub use hub::{
+    MarketDataHub, MarketDataReceiver, MarketDataSession, MarketDataSink, MarketUpdate,
+    run_market_data_hub,
+};
+pub use user::{
+    Fill, LiquidityRole, OrderEvent, OrderNotification, ReplayUnavailable, UserStreamConfig,
+    UserStreamHub, UserStreamMessage, UserStreamReceiver, UserStreamSession, UserStreamSink,
+    run_user_streams,
+};
+pub use websocket::{UserStreamQuery, market_data_ws, user_stream_ws};
+
+use crate::{Candle, CandleInterval, TradeRecord};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Settings of the market data stream
+#[derive(Debug, Clone, Copy)]
+pub struct StreamConfig {
+    /// Shortest delay between two depth, best bid and offer or candle messages of a symbol
+    pub throttle: Duration,
+    /// Number of price levels per side of a depth channel
+    pub depth: usize,
+    /// Trades a session may have waiting to be sent before new ones are dropped
+    pub max_queued_trades: usize,
+    /// Delay between two pings sent to a client
+    pub heartbeat_interval: Duration,
+    /// A client that sent nothing for this long is disconnected
+    pub client_timeout: Duration,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            throttle: Duration::from_millis(50),
+            depth: 50,
+            max_queued_trades: 10_000,
+            heartbeat_interval: Duration::from_secs(15),
+            client_timeout: Duration::from_secs(45),
+        }
+    }
+}
+
+/// A market data channel
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Channel {
+    /// Every trade of a symbol
+    Trades(String),
+    /// Top price levels of a symbol
+    Depth(String),
+    /// Best bid and offer of a symbol
+    Bbo(String),
+    /// Current candle of a symbol for an interval
+    Candles(String, CandleInterval),
+}
+
+impl Channel {
+    /// Symbol of the channel
+    pub fn symbol(&self) -> &str {
+        match self {
+            Channel::Trades(symbol)
+            | Channel::Depth(symbol)
+            | Channel::Bbo(symbol)
+            | Channel::Candles(symbol, _) => symbol,
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Trades(symbol) => write!(f, "trades:{}", symbol),
+            Channel::Depth(symbol) => write!(f, "depth:{}", symbol),
+            Channel::Bbo(symbol) => write!(f, "bbo:{}", symbol),
+            Channel::Candles(symbol, interval) => write!(f, "candles:{}:{}", symbol, interval),
+        }
+    }
+}
+
+impl FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid channel: {}", s);
+        let (kind, symbol) = s.split_once(':').ok_or_else(invalid)?;
+        let channel = match kind {
+            "trades" => Channel::Trades(symbol.to_string()),
+            "depth" => Channel::Depth(symbol.to_string()),
+            "bbo" => Channel::Bbo(symbol.to_string()),
+            "candles" => {
+                let (symbol, interval) = symbol.rsplit_once(':').ok_or_else(invalid)?;
+                Channel::Candles(symbol.to_string(), interval.parse()?)
+            }
+            _ => return Err(invalid()),
+        };
+        if channel.symbol().is_empty() {
+            return Err(invalid());
+        }
+        Ok(channel)
+    }
+}
+
+/// A request sent by a client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ClientRequest {
+    Subscribe { channels: Vec<String> },
+    Unsubscribe { channels: Vec<String> },
+    Ping,
+}
+
+/// A price level of a depth channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthLevel {
+    pub price: u64,
+    /// Visible quantity, zero in an update when the level was removed
+    pub quantity: u64,
+    pub order_count: usize,
+}
+
+/// A message sent to a client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamMessage {
+    Subscribed {
+        channel: String,
+    },
+    Unsubscribed {
+        channel: String,
+    },
+    Error {
+        message: String,
+    },
+    Pong,
+    Trade {
+        channel: String,
+        sequence: u64,
+        trade: TradeRecord,
+    },
+    /// Trades of the channel dropped because the client did not keep up
+    TradesDropped {
+        channel: String,
+        count: u64,
+    },
+    /// Bids best first, asks best first
+    DepthSnapshot {
+        channel: String,
+        sequence: u64,
+        bids: Vec<DepthLevel>,
+        asks: Vec<DepthLevel>,
+    },
+    /// Levels changed between the depth message of `prev_sequence` and this one
+    DepthUpdate {
+        channel: String,
+        prev_sequence: u64,
+        sequence: u64,
+        bids: Vec<DepthLevel>,
+        asks: Vec<DepthLevel>,
+    },
+    Bbo {
+        channel: String,
+        sequence: u64,
+        bid: Option<DepthLevel>,
+        ask: Option<DepthLevel>,
+    },
+    Candle {
+        channel: String,
+        candle: Candle,
+    },
+}
@@ -0,0 +1,443 @@
+use crate::api::sink::{ChannelReceiver, ChannelSink};
+use crate::{BookEvent, OrderRecord, OrderStatus, TerminalReason};
+use pricelevel::{OrderId, Side};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use super::disconnect::{CancelOnDisconnect, DisconnectCanceller, DisconnectReport, SessionGuard};
+use crate::api::auth::{TokenError, verify_token};
+
+/// Settings of the private user stream
+#[derive(Debug, Clone, Copy)]
+pub struct UserStreamConfig {
+    /// Notifications kept per account for reconnecting clients to replay
+    pub retained_notifications: usize,
+    /// Delay between two pings sent to a client
+    pub heartbeat_interval: Duration,
+    /// A client that sent nothing for this long is disconnected
+    pub client_timeout: Duration,
+}
+
+impl Default for UserStreamConfig {
+    fn default() -> Self {
+        Self {
+            retained_notifications: 1_000,
+            heartbeat_interval: Duration::from_secs(15),
+            client_timeout: Duration::from_secs(45),
+        }
+    }
+}
+
+/// What happened to an order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderEvent {
+    /// The order rests in the book
+    Accepted,
+    /// The price or quantity of a resting order was amended
+    Replaced,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Expired,
+    Rejected,
+}
+
+/// Whether a fill added or removed liquidity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiquidityRole {
+    Maker,
+    Taker,
+}
+
+/// An execution of an order
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Fill {
+    pub trade_id: Uuid,
+    pub price: u64,
+    pub quantity: u64,
+    pub role: LiquidityRole,
+    pub timestamp: u64,
+}
+
+/// A change of an order, sent to the account owning it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderNotification {
+    /// Position of the notification in the stream of the account, starting at 1
+    pub sequence: u64,
+    pub symbol: String,
+    pub event: OrderEvent,
+    pub order_id: OrderId,
+    pub client_order_id: Option<String>,
+    pub side: Side,
+    pub price: Option<u64>,
+    /// Total quantity of the order
+    pub quantity: u64,
+    /// Quantity executed so far, including `fills`
+    pub cumulative_quantity: u64,
+    /// Quantity still working in the book, zero once the order is terminal
+    pub remaining_quantity: u64,
+    pub average_price: Option<f64>,
+    /// Executions since the previous notification of the order
+    pub fills: Vec<Fill>,
+    pub reason: Option<TerminalReason>,
+    pub timestamp: u64,
+    /// Sequence number of the book mutation, absent for orders refused before reaching the book
+    pub book_sequence: Option<u64>,
+}
+
+impl OrderNotification {
+    fn from_record(
+        symbol: String,
+        book_sequence: Option<u64>,
+        event: OrderEvent,
+        record: &OrderRecord,
+        fills: Vec<Fill>,
+    ) -> Self {
+        Self {
+            sequence: 0,
+            symbol,
+            event,
+            order_id: record.order_id,
+            client_order_id: record
+                .owner
+                .as_ref()
+                .and_then(|owner| owner.client_order_id.clone()),
+            side: record.side,
+            price: record.price,
+            quantity: record.original_quantity,
+            cumulative_quantity: record.filled_quantity,
+            remaining_quantity: if record.status.is_terminal() {
+                0
+            } else {
+                record.remaining_quantity()
+            },
+            average_price: record.average_fill_price(),
+            fills,
+            reason: record.reason,
+            timestamp: record.updated_at,
+            book_sequence,
+        }
+    }
+}
+
+/// A message of the private user stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UserStreamMessage {
+    /// First message of a connection. Sequence numbers only compare within one `epoch`.
+    Welcome {
+        account: Uuid,
+        epoch: u64,
+        next_sequence: u64,
+    },
+    Order(OrderNotification),
+    /// The requested notifications are no longer retained; the connection is closed and the
+    /// client should reload its orders over REST before reconnecting without `from_sequence`
+    ReplayUnavailable {
+        from_sequence: u64,
+        first_available: u64,
+    },
+    /// Orders cancelled since the account was last connected, because a session with
+    /// cancel-on-disconnect dropped. Sent right after `Welcome`.
+    CancelledOnDisconnect(DisconnectReport),
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug)]
+struct AccountStream {
+    next_sequence: u64,
+    history: VecDeque<OrderNotification>,
+    published: watch::Sender<u64>,
+}
+
+impl AccountStream {
+    fn new() -> Self {
+        Self {
+            next_sequence: 1,
+            history: VecDeque::new(),
+            published: watch::channel(0).0,
+        }
+    }
+}
+
+/// Notifications of every account, with a bounded history for replays
+#[derive(Debug)]
+pub struct UserStreamHub {
+    config: UserStreamConfig,
+    secret: Option<Vec<u8>>,
+    epoch: u64,
+    accounts: Mutex<HashMap<Uuid, AccountStream>>,
+    canceller: Option<Arc<DisconnectCanceller>>,
+}
+
+impl UserStreamHub {
+    /// Create a hub authenticating clients with tokens signed by `secret`. Without a secret,
+    /// every client is refused. `epoch` identifies this run of the server.
+    pub fn new(config: UserStreamConfig, secret: Option<Vec<u8>>, epoch: u64) -> Self {
+        Self {
+            config,
+            secret,
+            epoch,
+            accounts: Mutex::new(HashMap::new()),
+            canceller: None,
+        }
+    }
+
+    /// Let sessions cancel the orders of their account when they drop
+    pub fn with_cancel_on_disconnect(mut self, canceller: Arc<DisconnectCanceller>) -> Self {
+        self.canceller = Some(canceller);
+        self
+    }
+
+    pub fn disconnect_canceller(&self) -> Option<&Arc<DisconnectCanceller>> {
+        self.canceller.as_ref()
+    }
+
+    pub fn config(&self) -> &UserStreamConfig {
+        &self.config
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns true if clients can authenticate
+    pub fn is_enabled(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    /// Account of a client token
+    pub fn authenticate(&self, token: &str, now: u64) -> Result<Uuid, TokenError> {
+        let secret = self.secret.as_deref().ok_or(TokenError::BadSignature)?;
+        verify_token(secret, token, now)
+    }
+
+    /// Append a notification to the stream of `account`, returning its sequence number
+    pub fn publish(&self, account: Uuid, mut notification: OrderNotification) -> u64 {
+        let mut accounts = self.accounts.lock().unwrap();
+        let stream = accounts.entry(account).or_insert_with(AccountStream::new);
+        notification.sequence = stream.next_sequence;
+        stream.next_sequence += 1;
+        stream.history.push_back(notification);
+        while stream.history.len() > self.config.retained_notifications.max(1) {
+            stream.history.pop_front();
+        }
+        stream.published.send_replace(stream.next_sequence - 1);
+        stream.next_sequence - 1
+    }
+
+    /// Notify the owner of an order the book refused before recording it. `record` holds the
+    /// order as submitted, in its rejected state.
+    pub fn publish_rejection(&self, symbol: &str, record: &OrderRecord) -> Option<u64> {
+        let owner = record.owner.as_ref()?;
+        Some(self.publish(
+            owner.account,
+            OrderNotification::from_record(
+                symbol.to_string(),
+                None,
+                OrderEvent::Rejected,
+                record,
+                Vec::new(),
+            ),
+        ))
+    }
+
+    /// Open the stream of `account`, starting with the notification `from_sequence` if given,
+    /// or with the next one published otherwise
+    pub fn open_session(
+        self: &Arc<Self>,
+        account: Uuid,
+        from_sequence: Option<u64>,
+    ) -> UserStreamSession {
+        let mut accounts = self.accounts.lock().unwrap();
+        let stream = accounts.entry(account).or_insert_with(AccountStream::new);
+        let next = from_sequence.map_or(stream.next_sequence, |from| {
+            from.clamp(1, stream.next_sequence)
+        });
+        let published = stream.published.subscribe();
+        drop(accounts);
+        let (guard, disconnect_reports) = match &self.canceller {
+            Some(canceller) => {
+                let (guard, reports) = canceller.connect(account);
+                (Some(guard), reports)
+            }
+            None => (None, Vec::new()),
+        };
+        UserStreamSession {
+            hub: self.clone(),
+            account,
+            next,
+            published,
+            guard,
+            disconnect_reports,
+        }
+    }
+}
+
+/// The notifications were evicted from the history before the session could send them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayUnavailable {
+    pub from_sequence: u64,
+    pub first_available: u64,
+}
+
+/// The stream of one account, as sent to one connection
+#[derive(Debug)]
+pub struct UserStreamSession {
+    hub: Arc<UserStreamHub>,
+    account: Uuid,
+    next: u64,
+    published: watch::Receiver<u64>,
+    guard: Option<SessionGuard>,
+    disconnect_reports: Vec<DisconnectReport>,
+}
+
+impl UserStreamSession {
+    pub fn account(&self) -> Uuid {
+        self.account
+    }
+
+    /// Epoch of the hub, see [`UserStreamMessage::Welcome`]
+    pub fn epoch(&self) -> u64 {
+        self.hub.epoch
+    }
+
+    /// Sequence number of the next notification the session will return
+    pub fn next_sequence(&self) -> u64 {
+        self.next
+    }
+
+    /// Cancel the orders of the account in the scope of `policy` if this session drops and no
+    /// other one opens within the grace period. Fails if the hub has no canceller.
+    pub fn cancel_on_disconnect(&mut self, policy: CancelOnDisconnect) -> Result<(), String> {
+        let guard = self
+            .guard
+            .as_mut()
+            .ok_or_else(|| "cancel-on-disconnect is not available".to_string())?;
+        guard.set_policy(Some(policy));
+        Ok(())
+    }
+
+    /// Orders cancelled since the account was last connected, see [`DisconnectCanceller::connect`]
+    pub fn disconnect_reports(&self) -> &[DisconnectReport] {
+        &self.disconnect_reports
+    }
+
+    /// Take the notifications not returned yet, waiting until there is at least one
+    pub async fn next_batch(&mut self) -> Result<Vec<OrderNotification>, ReplayUnavailable> {
+        loop {
+            let batch = self.try_batch()?;
+            if !batch.is_empty() {
+                return Ok(batch);
+            }
+            // The hub owns the sender for as long as the session can reach it
+            let _ = self.published.changed().await;
+        }
+    }
+
+    /// Take the notifications not returned yet without waiting
+    pub fn try_batch(&mut self) -> Result<Vec<OrderNotification>, ReplayUnavailable> {
+        let accounts = self.hub.accounts.lock().unwrap();
+        let Some(stream) = accounts.get(&self.account) else {
+            return Ok(Vec::new());
+        };
+        if let Some(first) = stream.history.front()
+            && first.sequence > self.next
+        {
+            return Err(ReplayUnavailable {
+                from_sequence: self.next,
+                first_available: first.sequence,
+            });
+        }
+        let batch: Vec<OrderNotification> = stream
+            .history
+            .iter()
+            .skip_while(|notification| notification.sequence < self.next)
+            .cloned()
+            .collect();
+        if let Some(last) = batch.last() {
+            self.next = last.sequence + 1;
+        }
+        Ok(batch)
+    }
+}
+
+/// Event sink handing the book events over to [`run_user_streams`]
+pub type UserStreamSink = ChannelSink<BookEvent>;
+
+/// Receiving end of a [`UserStreamSink`]
+pub type UserStreamReceiver = ChannelReceiver<BookEvent>;
+
+/// Turn the engine events into notifications of the accounts owning the orders, until every
+/// sink is dropped. A book publishes the trades of a mutation before the order changes they
+/// cause, so each change carries the fills that led to it.
+pub async fn run_user_streams(hub: Arc<UserStreamHub>, receiver: UserStreamReceiver) {
+    let mut receiver = receiver.0;
+    let mut fills: HashMap<OrderId, Vec<Fill>> = HashMap::new();
+    let mut live_orders: HashSet<OrderId> = HashSet::new();
+
+    while let Some(event) = receiver.recv().await {
+        match event {
+            BookEvent::Trade { trade, .. } => {
+                for (order_id, role) in [
+                    (trade.taker_order_id, LiquidityRole::Taker),
+                    (trade.maker_order_id, LiquidityRole::Maker),
+                ] {
+                    fills.entry(order_id).or_default().push(Fill {
+                        trade_id: trade.transaction_id,
+                        price: trade.price,
+                        quantity: trade.quantity,
+                        role,
+                        timestamp: trade.timestamp,
+                    });
+                }
+            }
+            BookEvent::OrderUpdated {
+                symbol,
+                sequence,
+                record,
+                ..
+            } => {
+                let order_fills = fills.remove(&record.order_id).unwrap_or_default();
+                let first_seen = live_orders.insert(record.order_id);
+                let event = match record.status {
+                    OrderStatus::New | OrderStatus::PartiallyFilled if order_fills.is_empty() => {
+                        if first_seen {
+                            OrderEvent::Accepted
+                        } else {
+                            OrderEvent::Replaced
+                        }
+                    }
+                    OrderStatus::New | OrderStatus::PartiallyFilled => OrderEvent::PartiallyFilled,
+                    OrderStatus::Filled => OrderEvent::Filled,
+                    OrderStatus::Cancelled => OrderEvent::Cancelled,
+                    OrderStatus::Expired => OrderEvent::Expired,
+                    OrderStatus::Rejected => OrderEvent::Rejected,
+                };
+                if record.status.is_terminal() {
+                    live_orders.remove(&record.order_id);
+                }
+                if let Some(owner) = &record.owner {
+                    hub.publish(
+                        owner.account,
+                        OrderNotification::from_record(
+                            symbol,
+                            Some(sequence),
+                            event,
+                            &record,
+                            order_fills,
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use actix_codec::{Decoder, Encoder};
+use actix_http::ws::{self, CloseCode, CloseReason, Codec, Frame, Message};
+use actix_web::{HttpRequest, HttpResponse, Result, http::StatusCode, http::header, web};
+use bytes::{Bytes, BytesMut};
+use futures_util::StreamExt;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use super::{
+    CancelScope, ClientRequest, MarketDataHub, MarketDataSession, StreamMessage, UserStreamHub,
+    UserStreamMessage, UserStreamSession,
+};
+use crate::api::models::response::ApiResponse;
+
+/// Encoded frames waiting to be written to the socket. Once full, the session stops draining
+/// its queue, so that its market data is conflated rather than buffered.
+const OUTGOING_FRAMES: usize = 16;
+
+/// What a WebSocket connection streams
+trait SocketSession: 'static {
+    type Message: Serialize;
+
+    /// Messages sent as soon as the connection is open
+    fn greeting(&self) -> Vec<Self::Message> {
+        Vec::new()
+    }
+
+    /// Handle a text frame of the client, returning a message to send back if any
+    fn on_text(&mut self, text: &[u8]) -> Option<Self::Message>;
+
+    /// Wait for the next messages to send. `Err` carries a last message, after which the
+    /// connection is closed.
+    async fn next_batch(&mut self) -> Result<Vec<Self::Message>, Self::Message>;
+}
+
+impl SocketSession for MarketDataSession {
+    type Message = StreamMessage;
+
+    fn on_text(&mut self, text: &[u8]) -> Option<StreamMessage> {
+        match serde_json::from_slice::<ClientRequest>(text) {
+            Ok(request) => {
+                self.handle(request);
+                None
+            }
+            Err(e) => Some(StreamMessage::Error {
+                message: format!("Invalid request: {}", e),
+            }),
+        }
+    }
+
+    async fn next_batch(&mut self) -> Result<Vec<StreamMessage>, StreamMessage> {
+        Ok(MarketDataSession::next_batch(self).await)
+    }
+}
+
+impl SocketSession for UserStreamSession {
+    type Message = UserStreamMessage;
+
+    fn greeting(&self) -> Vec<UserStreamMessage> {
+        let welcome = UserStreamMessage::Welcome {
+            account: self.account(),
+            epoch: self.epoch(),
+            next_sequence: self.next_sequence(),
+        };
+        std::iter::once(welcome)
+            .chain(
+                self.disconnect_reports()
+                    .iter()
+                    .cloned()
+                    .map(UserStreamMessage::CancelledOnDisconnect),
+            )
+            .collect()
+    }
+
+    fn on_text(&mut self, _text: &[u8]) -> Option<UserStreamMessage> {
+        Some(UserStreamMessage::Error {
+            message: "The user stream does not accept requests".to_string(),
+        })
+    }
+
+    async fn next_batch(&mut self) -> Result<Vec<UserStreamMessage>, UserStreamMessage> {
+        match UserStreamSession::next_batch(self).await {
+            Ok(batch) => Ok(batch.into_iter().map(UserStreamMessage::Order).collect()),
+            Err(gap) => Err(UserStreamMessage::ReplayUnavailable {
+                from_sequence: gap.from_sequence,
+                first_available: gap.first_available,
+            }),
+        }
+    }
+}
+
+/// Upgrade the request to a WebSocket streaming the market data of `hub`
+pub async fn market_data_ws(
+    req: HttpRequest,
+    payload: web::Payload,
+    hub: web::Data<Arc<MarketDataHub>>,
+) -> Result<HttpResponse> {
+    let config = hub.config();
+    upgrade(
+        &req,
+        payload,
+        hub.open_session(),
+        config.heartbeat_interval,
+        config.client_timeout,
+    )
+}
+
+#[derive(serde::Deserialize)]
+pub struct UserStreamQuery {
+    pub token: Option<String>,
+    pub from_sequence: Option<u64>,
+    /// `all`, or the comma separated symbols whose orders are cancelled if the session drops
+    pub cancel_on_disconnect: Option<String>,
+    /// Grace period of cancel-on-disconnect, in milliseconds
+    pub grace_period_ms: Option<u64>,
+}
+
+/// Upgrade the request to a WebSocket streaming the order notifications of the account of its
+/// bearer token, given in the `Authorization` header or the `token` query parameter. With
+/// `cancel_on_disconnect`, the orders of the account are cancelled if the session drops.
+pub async fn user_stream_ws(
+    req: HttpRequest,
+    payload: web::Payload,
+    query: web::Query<UserStreamQuery>,
+    hub: web::Data<Arc<UserStreamHub>>,
+) -> Result<HttpResponse> {
+    if !hub.is_enabled() {
+        return Ok(
+            HttpResponse::ServiceUnavailable().json(ApiResponse::<()>::error(
+                "the user stream is not configured".to_string(),
+            )),
+        );
+    }
+    let header_token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let Some(token) = header_token.or(query.token.as_deref()) else {
+        return Ok(HttpResponse::Unauthorized()
+            .json(ApiResponse::<()>::error("missing token".to_string())));
+    };
+    let account = match hub.authenticate(token, crate::current_time_millis()) {
+        Ok(account) => account,
+        Err(e) => {
+            return Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(e.to_string())));
+        }
+    };
+
+    let policy = match &query.cancel_on_disconnect {
+        Some(scope) => {
+            let Some(canceller) = hub.disconnect_canceller() else {
+                return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+                    "cancel-on-disconnect is not available".to_string(),
+                )));
+            };
+            let scope = match scope.as_str() {
+                "all" => CancelScope::AllSymbols,
+                symbols => CancelScope::Symbols(
+                    symbols
+                        .split(',')
+                        .map(|symbol| symbol.trim().to_string())
+                        .filter(|symbol| !symbol.is_empty())
+                        .collect(),
+                ),
+            };
+            match canceller.policy(query.grace_period_ms.map(Duration::from_millis), scope) {
+                Ok(policy) => Some(policy),
+                Err(e) => return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e))),
+            }
+        }
+        None => None,
+    };
+
+    // Refuse plain requests before the session exists, as dropping it may start a grace period
+    ws::verify_handshake(req.head())?;
+    let mut session = hub.open_session(account, query.from_sequence);
+    if let Some(policy) = policy {
+        session
+            .cancel_on_disconnect(policy)
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+    let config = hub.config();
+    upgrade(
+        &req,
+        payload,
+        session,
+        config.heartbeat_interval,
+        config.client_timeout,
+    )
+}
+
+fn upgrade<S: SocketSession>(
+    req: &HttpRequest,
+    payload: web::Payload,
+    session: S,
+    heartbeat: Duration,
+    timeout: Duration,
+) -> Result<HttpResponse> {
+    ws::verify_handshake(req.head())?;
+    let accept = req
+        .headers()
+        .get(header::SEC_WEBSOCKET_KEY)
+        .map(|key| ws::hash_key(key.as_bytes()))
+        .unwrap_or_default();
+
+    let (frames, outgoing) = mpsc::channel::<Bytes>(OUTGOING_FRAMES);
+    actix_rt::spawn(serve(
+        session,
+        payload,
+        FrameWriter {
+            codec: Codec::new(),
+            frames,
+        },
+        heartbeat,
+        timeout,
+    ));
+
+    let body = futures_util::stream::unfold(outgoing, |mut outgoing| async move {
+        outgoing
+            .recv()
+            .await
+            .map(|frame| (Ok::<_, actix_web::Error>(frame), outgoing))
+    });
+    Ok(HttpResponse::build(StatusCode::SWITCHING_PROTOCOLS)
+        .upgrade("websocket")
+        .insert_header((
+            header::SEC_WEBSOCKET_ACCEPT,
+            header::HeaderValue::from_bytes(&accept).unwrap(),
+        ))
+        .streaming(body))
+}
+
+struct FrameWriter {
+    codec: Codec,
+    frames: mpsc::Sender<Bytes>,
+}
+
+impl FrameWriter {
+    /// Encode and queue a frame, returning false once the connection is gone
+    async fn send(&mut self, message: Message) -> bool {
+        let mut buffer = BytesMut::new();
+        if self.codec.encode(message, &mut buffer).is_err() {
+            return false;
+        }
+        self.frames.send(buffer.freeze()).await.is_ok()
+    }
+
+    async fn send_json(&mut self, message: &impl Serialize) -> bool {
+        match serde_json::to_string(message) {
+            Ok(text) => self.send(Message::Text(text.into())).await,
+            Err(_) => false,
+        }
+    }
+
+    async fn close(&mut self, code: CloseCode) {
+        self.send(Message::Close(Some(CloseReason {
+            code,
+            description: None,
+        })))
+        .await;
+    }
+}
+
+async fn serve<S: SocketSession>(
+    mut session: S,
+    mut payload: web::Payload,
+    mut writer: FrameWriter,
+    heartbeat_interval: Duration,
+    timeout: Duration,
+) {
+    let mut decoder = Codec::new();
+    let mut buffer = BytesMut::new();
+    let mut heartbeat = tokio::time::interval(heartbeat_interval);
+    heartbeat.tick().await;
+    let mut last_seen = Instant::now();
+
+    for message in session.greeting() {
+        if !writer.send_json(&message).await {
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            chunk = payload.next() => {
+                let Some(Ok(chunk)) = chunk else { return };
+                last_seen = Instant::now();
+                buffer.extend_from_slice(&chunk);
+                loop {
+                    let frame = match decoder.decode(&mut buffer) {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => break,
+                        Err(_) => return writer.close(CloseCode::Protocol).await,
+                    };
+                    match frame {
+                        Frame::Text(text) => {
+                            if let Some(reply) = session.on_text(&text)
+                                && !writer.send_json(&reply).await
+                            {
+                                return;
+                            }
+                        }
+                        Frame::Ping(data) => {
+                            if !writer.send(Message::Pong(data)).await {
+                                return;
+                            }
+                        }
+                        Frame::Pong(_) => {}
+                        Frame::Close(reason) => {
+                            writer.send(Message::Close(reason)).await;
+                            return;
+                        }
+                        Frame::Binary(_) | Frame::Continuation(_) => {
+                            return writer.close(CloseCode::Unsupported).await;
+                        }
+                    }
+                }
+            }
+            batch = session.next_batch() => match batch {
+                Ok(batch) => {
+                    for message in &batch {
+                        if !writer.send_json(message).await {
+                            return;
+                        }
+                    }
+                }
+                Err(last) => {
+                    if writer.send_json(&last).await {
+                        writer.close(CloseCode::Policy).await;
+                    }
+                    return;
+                }
+            },
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() > timeout {
+                    return writer.close(CloseCode::Away).await;
+                }
+                if !writer.send(Message::Ping(Bytes::new())).await {
+                    return;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Scripted FIX client for the order entry gateway.
+//!
+//! Usage: `fix-client <host:port> <SenderCompID> [TargetCompID] [script] [--reset]`
+//!
+//! The script is read from the file given, or from stdin. Each line is a message to send,
+//! written as `35=D|55=BTCUSDT|11=order-1|54=1|38=10|40=2|44=100`; the header is filled in
+//! by the client. `sleep <ms>` waits while printing what arrives, and lines starting with `#`
+//! are comments. Every message received is printed. The client logs out at the end of the
+//! script.
+
+use orderbook_rs::api::fix::{msg_type, FixClient, FixMessage};
+use std::io::Read;
+use std::time::Duration;
+
+/// Time left for the last answers before logging out
+const DRAIN_PERIOD: Duration = Duration::from_millis(500);
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let reset = args.iter().any(|arg| arg == "--reset");
+    args.retain(|arg| arg != "--reset");
+    let [addr, sender, rest @ ..] = &args[..] else {
+        eprintln!("usage: fix-client <host:port> <SenderCompID> [TargetCompID] [script] [--reset]");
+        std::process::exit(2);
+    };
+    let target = rest.first().cloned().unwrap_or_else(|| "ORDERBOOK".to_string());
+    let script = match rest.get(1) {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => {
+            let mut script = String::new();
+            std::io::stdin().read_to_string(&mut script)?;
+            script
+        }
+    };
+
+    let mut client = FixClient::connect(addr.as_str(), sender.as_str(), target).await?;
+    let logon = client.logon(30, reset).await?;
+    println!("<- {}", logon);
+    if logon.msg_type() != msg_type::LOGON {
+        std::process::exit(1);
+    }
+
+    for (number, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(millis) = line.strip_prefix("sleep") {
+            let Ok(millis) = millis.trim().parse() else {
+                eprintln!("line {}: invalid sleep {:?}", number + 1, line);
+                std::process::exit(2);
+            };
+            print_for(&mut client, Duration::from_millis(millis)).await?;
+            continue;
+        }
+        let message: FixMessage = match line.parse() {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("line {}: {}", number + 1, e);
+                std::process::exit(2);
+            }
+        };
+        let sequence = client.send(message.clone()).await?;
+        println!("-> {} (34={})", message, sequence);
+        print_for(&mut client, Duration::ZERO).await?;
+    }
+
+    print_for(&mut client, DRAIN_PERIOD).await?;
+    for message in client.logout().await? {
+        println!("<- {}", message);
+    }
+    Ok(())
+}
+
+/// Print the messages arriving within `period`, and those already waiting
+async fn print_for(client: &mut FixClient, period: Duration) -> std::io::Result<()> {
+    let deadline = tokio::time::Instant::now() + period.max(Duration::from_millis(50));
+    while let Ok(message) = tokio::time::timeout_at(deadline, client.next_message()).await {
+        match message? {
+            Some(message) => println!("<- {}", message),
+            None => {
+                println!("connection closed");
+                std::process::exit(1);
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,21 +1,39 @@
 use actix_cors::Cors;
 use actix_web::{web, App, HttpServer};
 use dotenv::dotenv;
-use orderbook_rs::OrderBook;
+use orderbook_rs::{
+    Candle, CheckpointConfig, CheckpointManager, OrderBook, OrderBookError, Sequencer,
+    SequencerConfig,
+};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tracing::{info, Level};
-use tracing_subscriber;
+use std::time::Duration;
+use tracing::{error, info, warn, Level};
 
 use orderbook_rs::api as api;
 use api::{
+    binary::{run_binary_acceptor, BinaryGateway, BinaryGatewayConfig},
     database::Database,
+    feed::{
+        run_market_feed, run_retransmission_server, MarketFeed, MarketFeedConfig, MarketFeedSink,
+    },
+    fix::{run_fix_acceptor, FixGateway, FixGatewayConfig},
     handlers::{
         order_handlers, orderbook_handlers, query_handlers,
     },
     middleware::error_handlers,
-    redis::RedisClient,
+    persistence::{drain_outbox, run_persistence_worker, Outbox, PersistenceConfig},
+    redis::{run_redis_publisher, RedisClient, RedisEventSink, RedisPublisherConfig},
+    stream::{
+        market_data_ws, run_market_data_hub, run_user_streams, user_stream_ws,
+        CancelOnDisconnectConfig, DisconnectCanceller, MarketDataHub, MarketDataSink, StreamConfig,
+        UserStreamConfig, UserStreamHub, UserStreamSink,
+    },
 };
 
+/// Most finished candles kept per symbol while the database cannot be written
+const MAX_PENDING_CANDLES: usize = 10_000;
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Load environment variables
@@ -37,18 +55,314 @@ async fn main() -> std::io::Result<()> {
     let redis_client = RedisClient::new().await.expect("Failed to connect to Redis");
     info!("Redis connection established");
 
-    // Create shared order book instances for different symbols
+    // Open the checkpoints and command journal of the books
+    let checkpoint_dir = std::env::var("CHECKPOINT_DIR").unwrap_or_else(|_| "./data".to_string());
+    let checkpoint_interval = std::env::var("CHECKPOINT_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(60u64);
+    let checkpoints = Arc::new(CheckpointManager::open(
+        &checkpoint_dir,
+        CheckpointConfig::default(),
+    )?);
+
+    // Trades and order changes reach Postgres through a local outbox, so matching never waits on the database
+    let outbox = Arc::new(Outbox::open(
+        std::path::Path::new(&checkpoint_dir).join("outbox"),
+    )?);
+
+    // Initialize order books for major trading pairs, restored from the latest checkpoint and journal,
+    // or rebuilt from the open orders of the database with RECOVERY_MODE=database
+    let symbols = ["BTC/USD", "ETH/USD", "LTC/USD"];
+    let books: Vec<OrderBook> = symbols
+        .iter()
+        .map(|symbol| OrderBook::new(symbol))
+        .collect();
+    if std::env::var("RECOVERY_MODE").is_ok_and(|mode| mode == "database") {
+        // The database only holds the open orders once the outbox is written
+        let persisted = drain_outbox(&database, &outbox, PersistenceConfig::default())
+            .await
+            .map_err(|e| {
+                std::io::Error::other(format!(
+                    "Failed to write the outbox before reloading the open orders: {}",
+                    e
+                ))
+            })?;
+        info!("Wrote {} outbox records to the database", persisted);
+        // The reloaded books number their mutations from 0 again
+        outbox.restart_sequences()?;
+        for book in &books {
+            // Orders refused on reload are closed in the database through the outbox
+            book.subscribe(outbox.clone());
+            reload_open_orders(&database, book).await;
+        }
+        // The journal of the previous run must not be replayed on top of the reloaded books
+        let timestamp = orderbook_rs::current_time_millis();
+        checkpoints.checkpoint_with(books.iter(), timestamp, || outbox.sync())?;
+        info!(
+            "Wrote checkpoint of the reloaded order books to {}",
+            checkpoint_dir
+        );
+    } else {
+        // Subscribed first, so the replayed commands append the events the outbox misses
+        for book in &books {
+            book.subscribe(outbox.clone());
+        }
+        let recovery = checkpoints.recover(books.iter())?;
+        info!(
+            "Recovered order books from {}: {:?}",
+            checkpoint_dir, recovery
+        );
+    }
+    actix_rt::spawn(run_persistence_worker(
+        database.clone(),
+        outbox.clone(),
+        PersistenceConfig::default(),
+    ));
+
+    // Trades and book updates are published to Redis from the engine events
+    let redis_config = RedisPublisherConfig::default();
+    let (redis_sink, redis_receiver) = RedisEventSink::channel(redis_config.trade_capacity);
+    let redis_sink = Arc::new(redis_sink);
+
+    // WebSocket market data is fed by the engine events as well
+    let (market_data_sink, market_data_receiver) = MarketDataSink::channel();
+    let market_data_sink = Arc::new(market_data_sink);
+
+    // Each account receives the changes of its own orders, authenticated with tokens signed by USER_STREAM_SECRET
+    let user_stream_secret = std::env::var("USER_STREAM_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty())
+        .map(String::into_bytes);
+    if user_stream_secret.is_none() {
+        warn!("USER_STREAM_SECRET is not set, the user stream is disabled");
+    }
+    // Sessions may opt in to have the orders of their account cancelled when they drop
+    let sequencers = Arc::new(dashmap::DashMap::new());
+    let canceller = Arc::new(DisconnectCanceller::new(
+        sequencers.clone(),
+        CancelOnDisconnectConfig::default(),
+    ));
+    let user_streams = Arc::new(
+        UserStreamHub::new(
+            UserStreamConfig::default(),
+            user_stream_secret,
+            orderbook_rs::current_time_millis(),
+        )
+        .with_cancel_on_disconnect(canceller),
+    );
+    let (user_stream_sink, user_stream_receiver) = UserStreamSink::channel();
+    let user_stream_sink = Arc::new(user_stream_sink);
+    actix_rt::spawn(run_user_streams(user_streams.clone(), user_stream_receiver));
+
+    // Order by order binary feed over UDP, enabled by MARKET_FEED_DESTINATIONS as ip:port,...
+    let market_feed_channel = std::env::var("MARKET_FEED_DESTINATIONS").ok().map(|_| {
+        let (sink, receiver) = MarketFeedSink::channel();
+        (Arc::new(sink), receiver)
+    });
+
+    // Every mutation of a book goes through its sequencer, which journals it
     let orderbooks = Arc::new(dashmap::DashMap::new());
-    
-    // Initialize order books for major trading pairs
-    let symbols = vec!["BTC/USD", "ETH/USD", "LTC/USD"];
-    for symbol in symbols {
-        let orderbook = Arc::new(OrderBook::new(symbol));
-        orderbooks.insert(symbol.to_string(), orderbook);
+    for book in books {
+        let symbol = book.symbol().to_string();
+        book.subscribe(redis_sink.clone());
+        book.subscribe(market_data_sink.clone());
+        book.subscribe(user_stream_sink.clone());
+        if let Some((market_feed_sink, _)) = &market_feed_channel {
+            book.subscribe(market_feed_sink.clone());
+        }
+        let sequencer = Arc::new(Sequencer::with_journal(
+            book,
+            SequencerConfig::default(),
+            checkpoints.journal(),
+        ));
+        orderbooks.insert(symbol.clone(), sequencer.shared_book());
+        sequencers.insert(symbol.clone(), sequencer);
         info!("Initialized order book for {}", symbol);
     }
 
+    actix_rt::spawn(run_redis_publisher(
+        redis_client.clone(),
+        orderbooks.clone(),
+        redis_receiver,
+        redis_config,
+    ));
+    let market_data = Arc::new(MarketDataHub::new(
+        orderbooks.clone(),
+        StreamConfig::default(),
+    ));
+    actix_rt::spawn(run_market_data_hub(
+        market_data.clone(),
+        market_data_receiver,
+    ));
+
+    // MARKET_FEED_SESSION names the feed session, MARKET_FEED_RETRANSMIT_BIND enables retransmissions over TCP
+    if let Some((_, market_feed_receiver)) = market_feed_channel {
+        let mut config = MarketFeedConfig::default();
+        if let Ok(session) = std::env::var("MARKET_FEED_SESSION") {
+            config.session = session;
+        }
+        for destination in std::env::var("MARKET_FEED_DESTINATIONS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|destination| !destination.trim().is_empty())
+        {
+            let address = destination.trim().parse().map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "Invalid destination {:?} in MARKET_FEED_DESTINATIONS, expected ip:port",
+                        destination
+                    ),
+                )
+            })?;
+            config.destinations.push(address);
+        }
+        let feed = Arc::new(MarketFeed::bind(config, orderbooks.clone()).await?);
+        actix_rt::spawn(run_market_feed(feed.clone(), market_feed_receiver));
+        if let Ok(retransmit_bind) = std::env::var("MARKET_FEED_RETRANSMIT_BIND") {
+            let listener = tokio::net::TcpListener::bind(&retransmit_bind).await?;
+            actix_rt::spawn(async move {
+                if let Err(e) = run_retransmission_server(listener, feed).await {
+                    error!("Market data retransmission stopped: {}", e);
+                }
+            });
+        }
+    }
+
+    // FIX order entry, enabled by FIX_BIND; FIX_SESSIONS lists the clients as SENDER=account,...
+    if let Ok(fix_bind) = std::env::var("FIX_BIND") {
+        let comp_id = std::env::var("FIX_COMP_ID").unwrap_or_else(|_| "ORDERBOOK".to_string());
+        let mut config =
+            FixGatewayConfig::new(comp_id, std::path::Path::new(&checkpoint_dir).join("fix"));
+        for (sender, account) in parse_sessions("FIX_SESSIONS")? {
+            config = config.with_session(sender, account);
+        }
+        let gateway = Arc::new(FixGateway::new(
+            config,
+            sequencers.clone(),
+            user_streams.clone(),
+            outbox.clone(),
+        ));
+        let listener = tokio::net::TcpListener::bind(&fix_bind).await?;
+        actix_rt::spawn(async move {
+            if let Err(e) = run_fix_acceptor(listener, gateway).await {
+                error!("FIX gateway stopped: {}", e);
+            }
+        });
+    }
+
+    // Binary order entry, enabled by BINARY_BIND; BINARY_SESSIONS lists the users as USERNAME=account,...
+    if let Ok(binary_bind) = std::env::var("BINARY_BIND") {
+        let mut config = BinaryGatewayConfig::default();
+        for (username, account) in parse_sessions("BINARY_SESSIONS")? {
+            config = config.with_session(username, account);
+        }
+        let gateway = Arc::new(BinaryGateway::new(
+            config,
+            sequencers.clone(),
+            user_streams.clone(),
+            outbox.clone(),
+        ));
+        let listener = tokio::net::TcpListener::bind(&binary_bind).await?;
+        actix_rt::spawn(async move {
+            if let Err(e) = run_binary_acceptor(listener, gateway).await {
+                error!("Binary order entry stopped: {}", e);
+            }
+        });
+    }
+
+    // Checkpoint the books on a schedule
+    let checkpoint_manager = checkpoints.clone();
+    let checkpoint_outbox = outbox.clone();
+    let checkpoint_orderbooks = orderbooks.clone();
+    actix_rt::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(checkpoint_interval.max(1)));
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let manager = checkpoint_manager.clone();
+            let outbox = checkpoint_outbox.clone();
+            let books: Vec<Arc<OrderBook>> = checkpoint_orderbooks
+                .iter()
+                .map(|item| item.value().clone())
+                .collect();
+            let result = web::block(move || write_checkpoint(&manager, &outbox, &books)).await;
+            if let Ok(Err(e)) = result {
+                error!("Failed to write checkpoint: {}", e);
+            }
+        }
+    });
+
+    // Persist finished candles once a second. Candles that could not be written are kept
+    // and written again with the next ones, up to MAX_PENDING_CANDLES per symbol.
+    let candle_database = database.clone();
+    let candle_orderbooks = orderbooks.clone();
+    actix_rt::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(1));
+        let mut pending: HashMap<String, Vec<Candle>> = HashMap::new();
+        loop {
+            ticker.tick().await;
+            let books: Vec<(String, Arc<OrderBook>)> = candle_orderbooks
+                .iter()
+                .map(|item| (item.key().clone(), item.value().clone()))
+                .collect();
+            for (symbol, orderbook) in books {
+                orderbook
+                    .candles()
+                    .close_elapsed(orderbook.clock().now_millis());
+                let candles = pending.entry(symbol.clone()).or_default();
+                candles.extend(orderbook.candles().drain_closed());
+                if candles.is_empty() {
+                    continue;
+                }
+                match candle_database.insert_candles(&symbol, candles).await {
+                    Ok(()) => candles.clear(),
+                    Err(e) => {
+                        warn!(
+                            "Failed to persist {} candles for {}, retrying: {}",
+                            candles.len(),
+                            symbol,
+                            e
+                        );
+                        if candles.len() > MAX_PENDING_CANDLES {
+                            let dropped = candles.len() - MAX_PENDING_CANDLES;
+                            candles.drain(..dropped);
+                            error!(
+                                "Dropped the {} oldest unpersisted candles for {}",
+                                dropped, symbol
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    // Debug builds check the consistency of every book every ten seconds
+    if cfg!(debug_assertions) {
+        let checked_orderbooks = orderbooks.clone();
+        actix_rt::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(10));
+            loop {
+                ticker.tick().await;
+                let books: Vec<(String, Arc<OrderBook>)> = checked_orderbooks
+                    .iter()
+                    .map(|item| (item.key().clone(), item.value().clone()))
+                    .collect();
+                for (symbol, orderbook) in books {
+                    let report = orderbook.check_invariants();
+                    if !report.is_ok() {
+                        error!("Order book {} is inconsistent: {}", symbol, report);
+                    }
+                }
+            }
+        });
+    }
+
     // Start HTTP server
+    let server_orderbooks = orderbooks.clone();
+    let shutdown_outbox = outbox.clone();
     let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
@@ -61,30 +375,80 @@ async fn main() -> std::io::Result<()> {
             .wrap(tracing_actix_web::TracingLogger::default())
             .app_data(web::Data::new(database.clone()))
             .app_data(web::Data::new(redis_client.clone()))
-            .app_data(web::Data::new(orderbooks.clone()))
+            .app_data(web::Data::new(server_orderbooks.clone()))
+            .app_data(web::Data::new(sequencers.clone()))
+            .app_data(web::Data::new(outbox.clone()))
+            .app_data(web::Data::new(market_data.clone()))
+            .app_data(web::Data::new(user_streams.clone()))
+            .route("/ws/market-data", web::get().to(market_data_ws))
+            .route("/ws/user", web::get().to(user_stream_ws))
             .service(
                 web::scope("/api/v1")
                     .service(
                         web::scope("/orderbook")
                             .route("", web::get().to(orderbook_handlers::get_orderbooks))
-                            .route("/{symbol}", web::get().to(orderbook_handlers::get_orderbook))
-                            .route("/{symbol}/snapshot", web::get().to(orderbook_handlers::get_snapshot))
-                            .route("/{symbol}/depth", web::get().to(orderbook_handlers::get_depth))
+                            .route(
+                                "/{symbol}",
+                                web::get().to(orderbook_handlers::get_orderbook),
+                            )
+                            .route(
+                                "/{symbol}/snapshot",
+                                web::get().to(orderbook_handlers::get_snapshot),
+                            )
+                            .route(
+                                "/{symbol}/depth",
+                                web::get().to(orderbook_handlers::get_depth),
+                            )
+                            .route(
+                                "/{symbol}/quote",
+                                web::get().to(orderbook_handlers::get_quote),
+                            ),
                     )
                     .service(
                         web::scope("/orders")
                             .route("", web::post().to(order_handlers::create_order))
                             .route("/{order_id}", web::get().to(order_handlers::get_order))
                             .route("/{order_id}", web::put().to(order_handlers::update_order))
-                            .route("/{order_id}", web::delete().to(order_handlers::cancel_order))
-                            .route("/user/{user_id}", web::get().to(order_handlers::get_user_orders))
+                            .route(
+                                "/{order_id}",
+                                web::delete().to(order_handlers::cancel_order),
+                            )
+                            .route(
+                                "/user/{user_id}",
+                                web::get().to(order_handlers::get_user_orders),
+                            )
+                            .route(
+                                "/client/{user_id}/{client_order_id}",
+                                web::get().to(order_handlers::get_order_by_client_id),
+                            )
+                            .route(
+                                "/client/{user_id}/{client_order_id}",
+                                web::put().to(order_handlers::update_order_by_client_id),
+                            )
+                            .route(
+                                "/client/{user_id}/{client_order_id}",
+                                web::delete().to(order_handlers::cancel_order_by_client_id),
+                            ),
                     )
                     .service(
                         web::scope("/query")
-                            .route("/best-prices/{symbol}", web::get().to(query_handlers::get_best_prices))
-                            .route("/trades/{symbol}", web::get().to(query_handlers::get_recent_trades))
-                            .route("/volume/{symbol}", web::get().to(query_handlers::get_volume_stats))
-                    )
+                            .route(
+                                "/best-prices/{symbol}",
+                                web::get().to(query_handlers::get_best_prices),
+                            )
+                            .route(
+                                "/trades/{symbol}",
+                                web::get().to(query_handlers::get_recent_trades),
+                            )
+                            .route(
+                                "/volume/{symbol}",
+                                web::get().to(query_handlers::get_volume_stats),
+                            )
+                            .route(
+                                "/candles/{symbol}",
+                                web::get().to(query_handlers::get_candles),
+                            ),
+                    ),
             )
             .default_service(web::route().to(error_handlers::not_found))
     })
@@ -92,5 +456,93 @@ async fn main() -> std::io::Result<()> {
     .run();
 
     info!("OrderBook API Server running on http://0.0.0.0:8080");
-    server.await
+    server.await?;
+
+    // Checkpoint once more on graceful shutdown, so the next start replays nothing
+    let books: Vec<Arc<OrderBook>> = orderbooks.iter().map(|item| item.value().clone()).collect();
+    write_checkpoint(&checkpoints, &shutdown_outbox, &books)?;
+    info!("Wrote shutdown checkpoint to {}", checkpoint_dir);
+    Ok(())
+}
+
+/// Rebuild a book from the open orders stored in the database, oldest first. Orders that
+/// would cross the book or have expired are reported and closed rather than matched, and
+/// rows that cannot be read are reported and left out.
+async fn reload_open_orders(database: &Database, book: &OrderBook) {
+    let (orders, skipped) = match database.open_orders(book.symbol()).await {
+        Ok(orders) => orders,
+        Err(e) => {
+            error!("Failed to load open orders of {}: {}", book.symbol(), e);
+            return;
+        }
+    };
+    for e in &skipped {
+        error!(
+            "Order book {}: open {} could not be read and was not reloaded",
+            book.symbol(),
+            e
+        );
+    }
+    let total = orders.len() + skipped.len();
+    let mut reloaded = 0;
+    for (order, record) in orders {
+        let order_id = order.id();
+        match book.reload_order(order, record) {
+            Ok(()) => reloaded += 1,
+            Err(e @ OrderBookError::PriceCrossing { .. }) => warn!(
+                "Order book {}: open order {} crosses the book and was rejected: {}",
+                book.symbol(),
+                order_id,
+                e
+            ),
+            Err(e) => warn!(
+                "Order book {}: open order {} was not reloaded: {}",
+                book.symbol(),
+                order_id,
+                e
+            ),
+        }
+    }
+    info!(
+        "Reloaded {} of {} open orders of {} from the database",
+        reloaded,
+        total,
+        book.symbol()
+    );
+}
+
+fn write_checkpoint(
+    checkpoints: &CheckpointManager,
+    outbox: &Outbox,
+    books: &[Arc<OrderBook>],
+) -> std::io::Result<u64> {
+    let timestamp = orderbook_rs::current_time_millis();
+    checkpoints.checkpoint_with(books.iter().map(|book| book.as_ref()), timestamp, || {
+        outbox.sync()
+    })
+}
+
+/// Sessions of a gateway from the environment variable `var`, written `NAME=account,...`
+fn parse_sessions(var: &str) -> std::io::Result<Vec<(String, uuid::Uuid)>> {
+    let mut sessions = Vec::new();
+    for session in std::env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .filter(|session| !session.trim().is_empty())
+    {
+        let parsed = session.split_once('=').and_then(|(name, account)| {
+            Some((name.trim().to_string(), account.trim().parse().ok()?))
+        });
+        let Some(session) = parsed else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid session {:?} in {}, expected NAME=account",
+                    session, var
+                ),
+            ));
+        };
+        sessions.push(session);
+    }
+    Ok(sessions)
 }
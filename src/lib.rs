@@ -159,5 +159,13 @@ mod utils;
 // Expose API module for the binary to consume
 pub mod api;
 
-pub use orderbook::{OrderBook, OrderBookError, OrderBookSnapshot};
-pub use utils::current_time_millis;
+pub use orderbook::{
+    AggregatedDepth, BookEvent, BookState, BookView, CancelledRemainder, Candle, CandleAggregator,
+    CandleInterval, Checkpoint, CheckpointConfig, CheckpointManager, Command, CommandOutcome,
+    CommandResult, DepthBucket, EventSink, ExecutionReport, FeeSchedule, Fill, InvariantReport,
+    InvariantViolation, Journal, JournalEntry, JournalOutcome, LevelTotals, Liquidity,
+    MarketImpact, NotionalQuote, OrderBook, OrderBookError, OrderBookSnapshot, OrderOwner,
+    OrderRecord, OrderStatus, RecoveryReport, RollingTradeStats, SequencedResult, Sequencer,
+    SequencerConfig, TerminalReason, Ticket, TradeRecord, TradeStats, TradeTape,
+};
+pub use utils::{Clock, ManualClock, ReplayClock, SystemClock, current_time_millis};
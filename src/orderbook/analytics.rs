@@ -0,0 +1,394 @@
+//! Order book analytics: imbalance, microprice and liquidity profiles.
+//!
+//! Every metric is computed from a [`DepthSource`], which is implemented both by a live
+//! [`OrderBook`] and by a historical [`OrderBookSnapshot`]. Only visible quantity is taken
+//! into account, as that is the liquidity other market participants can see.
+
+use super::book::OrderBook;
+use super::quote::Liquidity;
+use super::snapshot::OrderBookSnapshot;
+use pricelevel::Side;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Visible depth of an order book, as `(price, quantity)` levels sorted best price first
+pub trait DepthSource {
+    /// Bid levels, highest price first
+    fn bid_levels(&self) -> Vec<(u64, u64)>;
+
+    /// Ask levels, lowest price first
+    fn ask_levels(&self) -> Vec<(u64, u64)>;
+
+    /// Time the depth refers to (milliseconds since epoch)
+    fn timestamp(&self) -> u64;
+
+    /// Both sides of the depth read at once, so that several metrics computed from them
+    /// describe the same state of the book
+    fn visible_depth(&self) -> VisibleDepth {
+        VisibleDepth {
+            bids: self.bid_levels(),
+            asks: self.ask_levels(),
+            timestamp: self.timestamp(),
+        }
+    }
+}
+
+/// A copy of the visible depth of a book at one point in time
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VisibleDepth {
+    /// Bid levels, highest price first
+    pub bids: Vec<(u64, u64)>,
+    /// Ask levels, lowest price first
+    pub asks: Vec<(u64, u64)>,
+    /// Time the depth refers to (milliseconds since epoch)
+    pub timestamp: u64,
+}
+
+impl DepthSource for VisibleDepth {
+    fn bid_levels(&self) -> Vec<(u64, u64)> {
+        self.bids.clone()
+    }
+
+    fn ask_levels(&self) -> Vec<(u64, u64)> {
+        self.asks.clone()
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn visible_depth(&self) -> VisibleDepth {
+        self.clone()
+    }
+}
+
+impl DepthSource for OrderBook {
+    fn bid_levels(&self) -> Vec<(u64, u64)> {
+        self.opposite_liquidity(Side::Sell, Liquidity::Visible)
+    }
+
+    fn ask_levels(&self) -> Vec<(u64, u64)> {
+        self.opposite_liquidity(Side::Buy, Liquidity::Visible)
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.clock().now_millis()
+    }
+
+    /// Read from a single snapshot, as the book may change between two reads of a side
+    fn visible_depth(&self) -> VisibleDepth {
+        self.create_snapshot(usize::MAX).visible_depth()
+    }
+}
+
+impl DepthSource for OrderBookSnapshot {
+    fn bid_levels(&self) -> Vec<(u64, u64)> {
+        let mut levels: Vec<(u64, u64)> = self
+            .bids
+            .iter()
+            .map(|level| (level.price, level.visible_quantity))
+            .filter(|(_, quantity)| *quantity > 0)
+            .collect();
+        levels.sort_unstable_by_key(|(price, _)| std::cmp::Reverse(*price));
+        levels
+    }
+
+    fn ask_levels(&self) -> Vec<(u64, u64)> {
+        let mut levels: Vec<(u64, u64)> = self
+            .asks
+            .iter()
+            .map(|level| (level.price, level.visible_quantity))
+            .filter(|(_, quantity)| *quantity > 0)
+            .collect();
+        levels.sort_unstable_by_key(|(price, _)| *price);
+        levels
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+/// What it takes to move the best price of one side by a number of basis points
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PriceMoveCost {
+    /// Side of the aggressive orders that move the price
+    pub side: Side,
+    /// Best opposite price before the move
+    pub from_price: u64,
+    /// Price the best opposite price has to reach
+    pub target_price: f64,
+    /// Quantity that has to be traded to reach the target price
+    pub quantity: u64,
+    /// Value of that quantity (sum of price * quantity)
+    pub notional: u128,
+    /// True if the side would be emptied before the target price is reached
+    pub exhausts_side: bool,
+}
+
+/// Visible quantity resting close to the mid price
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DepthWithin {
+    /// Distance from the mid price, in basis points
+    pub bps: f64,
+    /// Bid quantity priced at or above `mid * (1 - bps / 10000)`
+    pub bid_quantity: u64,
+    /// Ask quantity priced at or below `mid * (1 + bps / 10000)`
+    pub ask_quantity: u64,
+}
+
+/// Imbalance of the visible quantity of the best `levels` levels of each side, between
+/// -1.0 (only asks) and 1.0 (only bids). `None` if both sides are empty.
+pub fn depth_imbalance(source: &impl DepthSource, levels: usize) -> Option<f64> {
+    let bid_quantity: u64 = source
+        .bid_levels()
+        .iter()
+        .take(levels)
+        .map(|(_, quantity)| quantity)
+        .sum();
+    let ask_quantity: u64 = source
+        .ask_levels()
+        .iter()
+        .take(levels)
+        .map(|(_, quantity)| quantity)
+        .sum();
+    imbalance(bid_quantity, ask_quantity)
+}
+
+/// Mid price weighted by the size at the top of the book: the best bid weighted by the
+/// best ask quantity plus the best ask weighted by the best bid quantity. It leans
+/// towards the side with less quantity, where the next trade is more likely to move the
+/// price. `None` if either side is empty.
+pub fn microprice(source: &impl DepthSource) -> Option<f64> {
+    let (bid_price, bid_quantity) = source.bid_levels().first().copied()?;
+    let (ask_price, ask_quantity) = source.ask_levels().first().copied()?;
+    let total = bid_quantity as f64 + ask_quantity as f64;
+    Some((bid_price as f64 * ask_quantity as f64 + ask_price as f64 * bid_quantity as f64) / total)
+}
+
+/// Spread between best ask and best bid in basis points of the mid price
+pub fn spread_bps(source: &impl DepthSource) -> Option<f64> {
+    let (bid_price, _) = source.bid_levels().first().copied()?;
+    let (ask_price, _) = source.ask_levels().first().copied()?;
+    let mid = (bid_price as f64 + ask_price as f64) / 2.0;
+    if mid <= 0.0 {
+        return None;
+    }
+    Some(ask_price.saturating_sub(bid_price) as f64 / mid * 10_000.0)
+}
+
+/// Quantity and value that aggressive orders on `side` have to trade to move the best
+/// opposite price by `bps` basis points: up for buys, down for sells. `None` if the
+/// opposite side is empty.
+pub fn cost_to_move(source: &impl DepthSource, side: Side, bps: f64) -> Option<PriceMoveCost> {
+    let levels = match side {
+        Side::Buy => source.ask_levels(),
+        Side::Sell => source.bid_levels(),
+    };
+    let (from_price, _) = levels.first().copied()?;
+    let target_price = match side {
+        Side::Buy => from_price as f64 * (1.0 + bps / 10_000.0),
+        Side::Sell => from_price as f64 * (1.0 - bps / 10_000.0),
+    };
+
+    let mut quantity = 0;
+    let mut notional = 0u128;
+    let mut exhausts_side = true;
+    for (price, available) in levels {
+        let reached = match side {
+            Side::Buy => price as f64 >= target_price,
+            Side::Sell => price as f64 <= target_price,
+        };
+        if reached {
+            exhausts_side = false;
+            break;
+        }
+        quantity += available;
+        notional += price as u128 * available as u128;
+    }
+
+    Some(PriceMoveCost {
+        side,
+        from_price,
+        target_price,
+        quantity,
+        notional,
+        exhausts_side,
+    })
+}
+
+/// Visible quantity on each side within `bps` basis points of the mid price. `None` if
+/// either side is empty.
+pub fn depth_within_bps(source: &impl DepthSource, bps: f64) -> Option<DepthWithin> {
+    let bids = source.bid_levels();
+    let asks = source.ask_levels();
+    let (bid_price, _) = bids.first().copied()?;
+    let (ask_price, _) = asks.first().copied()?;
+    let mid = (bid_price as f64 + ask_price as f64) / 2.0;
+    let lower = mid * (1.0 - bps / 10_000.0);
+    let upper = mid * (1.0 + bps / 10_000.0);
+
+    let bid_quantity = bids
+        .iter()
+        .take_while(|(price, _)| *price as f64 >= lower)
+        .map(|(_, quantity)| quantity)
+        .sum();
+    let ask_quantity = asks
+        .iter()
+        .take_while(|(price, _)| *price as f64 <= upper)
+        .map(|(_, quantity)| quantity)
+        .sum();
+
+    Some(DepthWithin {
+        bps,
+        bid_quantity,
+        ask_quantity,
+    })
+}
+
+fn imbalance(bid_quantity: u64, ask_quantity: u64) -> Option<f64> {
+    let total = bid_quantity as f64 + ask_quantity as f64;
+    if total == 0.0 {
+        None
+    } else {
+        Some((bid_quantity as f64 - ask_quantity as f64) / total)
+    }
+}
+
+/// Parameters of the metrics recorded by an [`AnalyticsSampler`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SamplerConfig {
+    /// Number of levels per side used for the depth imbalance
+    pub imbalance_levels: usize,
+    /// Price move, in basis points, used for the cost to move the price
+    pub move_bps: f64,
+    /// Distance from the mid price, in basis points, used for the depth profile
+    pub depth_bps: f64,
+    /// Minimum time between two samples taken with [`AnalyticsSampler::sample_if_due`]
+    pub interval_ms: u64,
+    /// Maximum number of samples kept (at least one); the oldest are dropped first
+    pub capacity: usize,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            imbalance_levels: 5,
+            move_bps: 10.0,
+            depth_bps: 10.0,
+            interval_ms: 1_000,
+            capacity: 3_600,
+        }
+    }
+}
+
+/// The metrics of a book at one point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalyticsSample {
+    /// Time of the sample (milliseconds since epoch)
+    pub timestamp: u64,
+    /// Best bid price
+    pub best_bid: Option<u64>,
+    /// Best ask price
+    pub best_ask: Option<u64>,
+    /// Spread in basis points of the mid price
+    pub spread_bps: Option<f64>,
+    /// Top-N depth imbalance
+    pub imbalance: Option<f64>,
+    /// Size-weighted microprice
+    pub microprice: Option<f64>,
+    /// Cost for buyers to move the best ask up
+    pub cost_to_move_up: Option<PriceMoveCost>,
+    /// Cost for sellers to move the best bid down
+    pub cost_to_move_down: Option<PriceMoveCost>,
+    /// Visible depth close to the mid price
+    pub depth: Option<DepthWithin>,
+}
+
+impl AnalyticsSample {
+    /// Compute every metric of `source` with the parameters of `config`, all from the
+    /// same copy of its depth
+    pub fn compute(source: &impl DepthSource, config: &SamplerConfig) -> Self {
+        let depth = source.visible_depth();
+        Self {
+            timestamp: depth.timestamp,
+            best_bid: depth.bids.first().map(|(price, _)| *price),
+            best_ask: depth.asks.first().map(|(price, _)| *price),
+            spread_bps: spread_bps(&depth),
+            imbalance: depth_imbalance(&depth, config.imbalance_levels),
+            microprice: microprice(&depth),
+            cost_to_move_up: cost_to_move(&depth, Side::Buy, config.move_bps),
+            cost_to_move_down: cost_to_move(&depth, Side::Sell, config.move_bps),
+            depth: depth_within_bps(&depth, config.depth_bps),
+        }
+    }
+}
+
+/// Records the analytics of a book over time in a bounded buffer
+#[derive(Debug, Clone)]
+pub struct AnalyticsSampler {
+    config: SamplerConfig,
+    samples: VecDeque<AnalyticsSample>,
+}
+
+impl AnalyticsSampler {
+    /// Create an empty sampler
+    pub fn new(config: SamplerConfig) -> Self {
+        Self {
+            config,
+            samples: VecDeque::with_capacity(config.capacity.min(1_024)),
+        }
+    }
+
+    /// The parameters of the sampler
+    pub fn config(&self) -> &SamplerConfig {
+        &self.config
+    }
+
+    /// Record the metrics of `source` now
+    pub fn sample(&mut self, source: &impl DepthSource) -> &AnalyticsSample {
+        while self.samples.len() >= self.config.capacity.max(1) {
+            self.samples.pop_front();
+        }
+        self.samples
+            .push_back(AnalyticsSample::compute(source, &self.config));
+        self.samples.back().expect("a sample was just recorded")
+    }
+
+    /// Record the metrics of `source` if at least `interval_ms` has passed since the
+    /// last sample
+    pub fn sample_if_due(&mut self, source: &impl DepthSource) -> Option<&AnalyticsSample> {
+        let due = match self.samples.back() {
+            Some(last) => {
+                source.timestamp() >= last.timestamp.saturating_add(self.config.interval_ms)
+            }
+            None => true,
+        };
+        if due { Some(self.sample(source)) } else { None }
+    }
+
+    /// Recorded samples, oldest first
+    pub fn samples(&self) -> impl Iterator<Item = &AnalyticsSample> {
+        self.samples.iter()
+    }
+
+    /// The most recent sample
+    pub fn latest(&self) -> Option<&AnalyticsSample> {
+        self.samples.back()
+    }
+
+    /// Number of recorded samples
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns true if nothing has been recorded
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Remove and return every recorded sample, oldest first
+    pub fn drain(&mut self) -> Vec<AnalyticsSample> {
+        self.samples.drain(..).collect()
+    }
+}
@@ -1,14 +1,20 @@
 //! Core OrderBook implementation for managing price levels and orders
 
 use super::cache::PriceLevelCache;
+use super::candles::{CandleAggregator, CandleInterval, DEFAULT_CLOSED_CANDLE_RETENTION};
 use super::error::OrderBookError;
+use super::events::{EventBus, EventSink};
+use super::execution::FeeSchedule;
+use super::order_state::{OrderRecord, OrderStateStore};
+use super::queue::QueuePriorities;
 use super::snapshot::OrderBookSnapshot;
-use crate::utils::current_time_millis;
+use super::trades::{RollingTradeStats, TradeStats, TradeTape};
+use crate::utils::{Clock, SystemClock};
 use dashmap::DashMap;
 use pricelevel::{MatchResult, OrderId, OrderType, PriceLevel, Side, UuidGenerator};
 use std::collections::HashMap;
-use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 use tracing::trace;
 use uuid::Uuid;
 
@@ -30,6 +36,9 @@ pub struct OrderBook {
     /// This avoids having to search through all price levels to find an order
     pub(super) order_locations: DashMap<OrderId, (u64, Side)>,
 
+    /// Time priority of every resting order within its price level
+    pub(super) queue_priorities: QueuePriorities,
+
     /// Generator for unique transaction IDs
     pub(super) transaction_id_generator: UuidGenerator,
 
@@ -45,9 +54,40 @@ pub struct OrderBook {
     /// Flag indicating if market close is set
     pub(super) has_market_close: AtomicBool,
 
-    /// A cache for storing best bid/ask prices to avoid recalculation
+    /// Best bid/ask prices, updated as price levels are added and removed
     pub(super) cache: PriceLevelCache,
 
+    /// Lifecycle state of live orders and a bounded history of terminal ones
+    pub(super) order_states: OrderStateStore,
+
+    /// Fees applied to the fills reported to order submitters
+    pub(super) fee_schedule: FeeSchedule,
+
+    /// OHLCV candles built from the trades of this book
+    pub(super) candles: CandleAggregator,
+
+    /// The most recent trades of this book
+    pub(super) trade_tape: TradeTape,
+
+    /// Rolling 24h statistics of the trades of this book
+    pub(super) trade_stats: RollingTradeStats,
+
+    /// Source of every timestamp assigned by this book
+    pub(super) clock: Arc<dyn Clock>,
+
+    /// Time the command being applied is held at, 0 when time is read from the clock
+    pub(super) command_time: AtomicU64,
+
+    /// Number of mutations applied to this book
+    pub(super) sequence: AtomicU64,
+
+    /// Held shared by mutations and exclusively while the whole book is read at once
+    /// (snapshots, invariant checks)
+    pub(super) snapshot_gate: RwLock<()>,
+
+    /// Consumers of the trades and order changes of this book
+    pub(super) events: EventBus,
+
     /// listens to possible trades when an order is added
     pub trade_listener: Option<TradeListener>,
 }
@@ -66,12 +106,23 @@ impl OrderBook {
             bids: DashMap::new(),
             asks: DashMap::new(),
             order_locations: DashMap::new(),
+            queue_priorities: QueuePriorities::new(),
             transaction_id_generator: UuidGenerator::new(namespace),
             last_trade_price: AtomicU64::new(0),
             has_traded: AtomicBool::new(false),
             market_close_timestamp: AtomicU64::new(0),
             has_market_close: AtomicBool::new(false),
             cache: PriceLevelCache::new(),
+            order_states: OrderStateStore::default(),
+            fee_schedule: FeeSchedule::default(),
+            candles: CandleAggregator::default(),
+            trade_tape: TradeTape::default(),
+            trade_stats: RollingTradeStats::default(),
+            clock: Arc::new(SystemClock),
+            command_time: AtomicU64::new(0),
+            sequence: AtomicU64::new(0),
+            snapshot_gate: RwLock::new(()),
+            events: EventBus::default(),
             trade_listener: None,
         }
     }
@@ -85,16 +136,106 @@ impl OrderBook {
             bids: DashMap::new(),
             asks: DashMap::new(),
             order_locations: DashMap::new(),
+            queue_priorities: QueuePriorities::new(),
             transaction_id_generator: UuidGenerator::new(namespace),
             last_trade_price: AtomicU64::new(0),
             has_traded: AtomicBool::new(false),
             market_close_timestamp: AtomicU64::new(0),
             has_market_close: AtomicBool::new(false),
             cache: PriceLevelCache::new(),
+            order_states: OrderStateStore::default(),
+            fee_schedule: FeeSchedule::default(),
+            candles: CandleAggregator::default(),
+            trade_tape: TradeTape::default(),
+            trade_stats: RollingTradeStats::default(),
+            clock: Arc::new(SystemClock),
+            command_time: AtomicU64::new(0),
+            sequence: AtomicU64::new(0),
+            snapshot_gate: RwLock::new(()),
+            events: EventBus::default(),
             trade_listener: Some(trade_listener),
         }
     }
 
+    /// Publish the trades and order changes of this book to `sink` from now on
+    pub fn subscribe(&self, sink: Arc<dyn EventSink>) {
+        self.order_states.track_changes();
+        self.events.subscribe(sink);
+    }
+
+    /// Read time from `clock` instead of the wall clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Get the clock of this order book
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// The time assigned to what the book does now: the time the running command is held
+    /// at, otherwise the time of its clock
+    pub(super) fn now_millis(&self) -> u64 {
+        match self.command_time.load(Ordering::Acquire) {
+            0 => self.clock.now_millis(),
+            held => held,
+        }
+    }
+
+    /// Apply `command` with every timestamp read at `timestamp`, so that applying it again
+    /// at the same time, when the journal is replayed, gives the same result
+    pub(super) fn at_time<R>(&self, timestamp: u64, command: impl FnOnce() -> R) -> R {
+        self.command_time.store(timestamp, Ordering::Release);
+        let result = command();
+        self.command_time.store(0, Ordering::Release);
+        result
+    }
+
+    /// Set the fees applied to the fills of this order book
+    pub fn with_fee_schedule(mut self, fee_schedule: FeeSchedule) -> Self {
+        self.fee_schedule = fee_schedule;
+        self
+    }
+
+    /// Get the fees applied to the fills of this order book
+    pub fn fee_schedule(&self) -> FeeSchedule {
+        self.fee_schedule
+    }
+
+    /// Set the intervals candles are built for (all of them by default). An empty
+    /// slice disables candle building.
+    pub fn with_candle_intervals(mut self, intervals: &[CandleInterval]) -> Self {
+        self.candles = CandleAggregator::new(intervals, DEFAULT_CLOSED_CANDLE_RETENTION);
+        self
+    }
+
+    /// Get the OHLCV candles built from the trades of this order book
+    pub fn candles(&self) -> &CandleAggregator {
+        &self.candles
+    }
+
+    /// Set the number of recent trades kept in the trade tape
+    pub fn with_trade_tape_capacity(mut self, capacity: usize) -> Self {
+        self.trade_tape = TradeTape::new(capacity);
+        self
+    }
+
+    /// Get the most recent trades of this order book
+    pub fn trade_tape(&self) -> &TradeTape {
+        &self.trade_tape
+    }
+
+    /// Get the rolling trade statistics of this order book
+    pub fn rolling_trade_stats(&self) -> &RollingTradeStats {
+        &self.trade_stats
+    }
+
+    /// Get the trade statistics of the last 24 hours
+    pub fn trade_stats(&self) -> TradeStats {
+        self.trade_stats.stats(self.now_millis())
+    }
+
     /// Get the symbol of this order book
     pub fn symbol(&self) -> &str {
         &self.symbol
@@ -116,30 +257,29 @@ impl OrderBook {
         self.has_market_close.store(false, Ordering::SeqCst);
     }
 
-    /// Get the best bid price, if any
-    pub fn best_bid(&self) -> Option<u64> {
-        if let Some(cached_bid) = self.cache.get_cached_best_bid() {
-            return Some(cached_bid);
-        }
-
-        let best_price = self.bids.iter().map(|item| *item.key()).max();
+    /// Set how many terminal (filled, cancelled, rejected or expired) orders are
+    /// kept for lookup. The oldest terminal orders are evicted first.
+    pub fn set_terminal_order_retention(&self, retention: usize) {
+        self.order_states.set_retention(retention);
+        trace!(
+            "Order book {}: Set terminal order retention to {}",
+            self.symbol, retention
+        );
+    }
 
-        self.cache.update_best_prices(best_price, None);
+    /// Get the lifecycle state of an order, whether it is still resting or already terminal
+    pub fn order_state(&self, order_id: OrderId) -> Option<OrderRecord> {
+        self.order_states.get(&order_id)
+    }
 
-        best_price
+    /// Get the best bid price, if any
+    pub fn best_bid(&self) -> Option<u64> {
+        self.cache.best_bid()
     }
 
     /// Get the best ask price, if any
     pub fn best_ask(&self) -> Option<u64> {
-        if let Some(cached_ask) = self.cache.get_cached_best_ask() {
-            return Some(cached_ask);
-        }
-
-        let best_price = self.asks.iter().map(|item| *item.key()).min();
-
-        self.cache.update_best_prices(None, best_price);
-
-        best_price
+        self.cache.best_ask()
     }
 
     /// Get the mid price (average of best bid and best ask)
@@ -167,7 +307,7 @@ impl OrderBook {
         }
     }
 
-    /// Get all orders at a specific price level
+    /// Get all orders at a specific price level, in the order they will be matched
     pub fn get_orders_at_price(&self, price: u64, side: Side) -> Vec<Arc<OrderType>> {
         trace!(
             "Order book {}: Getting orders at price {} for side {:?}",
@@ -179,12 +319,23 @@ impl OrderBook {
         };
 
         if let Some(price_level) = price_levels.get(&price) {
-            price_level.iter_orders()
+            let mut orders = price_level.iter_orders();
+            self.queue_priorities.sort(&mut orders);
+            orders
         } else {
             Vec::new()
         }
     }
 
+    /// Get the position of a resting order in the queue of its price level,
+    /// where 0 is the next order to be matched
+    pub fn queue_position(&self, order_id: OrderId) -> Option<usize> {
+        let (price, side) = *self.order_locations.get(&order_id)?;
+        self.get_orders_at_price(price, side)
+            .iter()
+            .position(|order| order.id() == order_id)
+    }
+
     /// Get all orders in the book
     pub fn get_all_orders(&self) -> Vec<Arc<OrderType>> {
         trace!("Order book {}: Getting all orders", self.symbol);
@@ -289,8 +440,20 @@ impl OrderBook {
         self.match_order(order_id, side, quantity, Some(limit_price))
     }
 
-    /// Create a snapshot of the current order book state
+    /// Number of mutations (orders, cancels, amendments, matches) applied to this book
+    pub fn sequence(&self) -> u64 {
+        self.sequence.load(Ordering::Acquire)
+    }
+
+    /// Create a snapshot of the current order book state.
+    ///
+    /// Mutations are held back while the snapshot is taken, so it shows the book exactly as
+    /// it was after the mutation numbered by its `sequence`: it is never crossed and never
+    /// holds a level that a match has emptied.
     pub fn create_snapshot(&self, depth: usize) -> OrderBookSnapshot {
+        let _gate = self.snapshot_gate.write().unwrap();
+        let sequence = self.sequence();
+
         // Get all bid prices and sort them in descending order
         let mut bid_prices: Vec<u64> = self.bids.iter().map(|item| *item.key()).collect();
         bid_prices.sort_by(|a, b| b.cmp(a)); // Descending order
@@ -320,7 +483,8 @@ impl OrderBook {
 
         OrderBookSnapshot {
             symbol: self.symbol.clone(),
-            timestamp: current_time_millis(),
+            sequence,
+            timestamp: self.now_millis(),
             bids: bid_levels,
             asks: ask_levels,
         }
@@ -4,58 +4,92 @@
    Date: 15/7/25
 ******************************************************************************/
 
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use pricelevel::Side;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+/// Marks a side without any price level
+const NO_PRICE: u64 = u64::MAX;
+
+/// Prices of the levels of one side and the best of them
+struct SidePrices {
+    prices: Mutex<BTreeSet<u64>>,
+    best: AtomicU64,
+}
+
+impl SidePrices {
+    fn new() -> Self {
+        Self {
+            prices: Mutex::new(BTreeSet::new()),
+            best: AtomicU64::new(NO_PRICE),
+        }
+    }
+
+    fn best(&self) -> Option<u64> {
+        match self.best.load(Ordering::Acquire) {
+            NO_PRICE => None,
+            price => Some(price),
+        }
+    }
+}
+
+/// Best bid and ask prices, maintained as price levels are added to and removed from the
+/// book, so that reading them is exact and O(1).
+///
+/// The book calls `level_added` and `level_removed` while it holds the map entry of the
+/// level, so the prices known here always match the levels in the book.
 pub struct PriceLevelCache {
-    best_bid_price: AtomicU64,
-    best_ask_price: AtomicU64,
-    cache_valid: AtomicBool,
+    bids: SidePrices,
+    asks: SidePrices,
 }
 
 impl PriceLevelCache {
     pub fn new() -> Self {
         Self {
-            best_bid_price: AtomicU64::new(0),
-            best_ask_price: AtomicU64::new(0),
-            cache_valid: AtomicBool::new(false),
+            bids: SidePrices::new(),
+            asks: SidePrices::new(),
         }
     }
 
-    pub fn invalidate(&self) {
-        self.cache_valid.store(false, Ordering::Relaxed);
+    /// Record that a price level was created on `side`
+    pub fn level_added(&self, side: Side, price: u64) {
+        let side_prices = self.side(side);
+        let mut prices = side_prices.prices.lock().unwrap();
+        prices.insert(price);
+        Self::store_best(side, side_prices, &prices);
     }
 
-    pub fn get_cached_best_bid(&self) -> Option<u64> {
-        if self.cache_valid.load(Ordering::Relaxed) {
-            let price = self.best_bid_price.load(Ordering::Relaxed);
-            if price > 0 { Some(price) } else { None }
-        } else {
-            None
-        }
+    /// Record that the price level at `price` was removed from `side`
+    pub fn level_removed(&self, side: Side, price: u64) {
+        let side_prices = self.side(side);
+        let mut prices = side_prices.prices.lock().unwrap();
+        prices.remove(&price);
+        Self::store_best(side, side_prices, &prices);
     }
 
-    pub fn get_cached_best_ask(&self) -> Option<u64> {
-        if self.cache_valid.load(Ordering::Relaxed) {
-            let price = self.best_ask_price.load(Ordering::Relaxed);
-            if price > 0 { Some(price) } else { None }
-        } else {
-            None
-        }
+    pub fn best_bid(&self) -> Option<u64> {
+        self.bids.best()
     }
 
-    pub fn update_best_prices(&self, best_bid: Option<u64>, best_ask: Option<u64>) {
-        if let Some(bid) = best_bid {
-            self.best_bid_price.store(bid, Ordering::Relaxed);
-        } else {
-            self.best_bid_price.store(0, Ordering::Relaxed);
-        }
+    pub fn best_ask(&self) -> Option<u64> {
+        self.asks.best()
+    }
 
-        if let Some(ask) = best_ask {
-            self.best_ask_price.store(ask, Ordering::Relaxed);
-        } else {
-            self.best_ask_price.store(0, Ordering::Relaxed);
+    fn side(&self, side: Side) -> &SidePrices {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
         }
+    }
 
-        self.cache_valid.store(true, Ordering::Relaxed);
+    fn store_best(side: Side, side_prices: &SidePrices, prices: &BTreeSet<u64>) {
+        let best = match side {
+            Side::Buy => prices.last(),
+            Side::Sell => prices.first(),
+        };
+        side_prices
+            .best
+            .store(best.copied().unwrap_or(NO_PRICE), Ordering::Release);
     }
 }
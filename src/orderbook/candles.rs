@@ -0,0 +1,281 @@
+//! OHLCV candles built from the trades executed by the book
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Default number of finished candles kept until they are drained
+pub const DEFAULT_CLOSED_CANDLE_RETENTION: usize = 10_000;
+
+/// Time span covered by a candle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CandleInterval {
+    /// One second
+    #[serde(rename = "1s")]
+    OneSecond,
+    /// One minute
+    #[serde(rename = "1m")]
+    OneMinute,
+    /// Five minutes
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    /// One hour
+    #[serde(rename = "1h")]
+    OneHour,
+    /// One day (UTC)
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl CandleInterval {
+    /// Every supported interval, shortest first
+    pub const ALL: [CandleInterval; 5] = [
+        CandleInterval::OneSecond,
+        CandleInterval::OneMinute,
+        CandleInterval::FiveMinutes,
+        CandleInterval::OneHour,
+        CandleInterval::OneDay,
+    ];
+
+    /// Length of the interval in milliseconds
+    pub fn duration_ms(&self) -> u64 {
+        match self {
+            CandleInterval::OneSecond => 1_000,
+            CandleInterval::OneMinute => 60_000,
+            CandleInterval::FiveMinutes => 300_000,
+            CandleInterval::OneHour => 3_600_000,
+            CandleInterval::OneDay => 86_400_000,
+        }
+    }
+
+    /// Start of the interval containing `timestamp` (milliseconds since epoch)
+    pub fn open_time(&self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.duration_ms()
+    }
+
+    /// Short name of the interval, such as `1m`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CandleInterval::OneSecond => "1s",
+            CandleInterval::OneMinute => "1m",
+            CandleInterval::FiveMinutes => "5m",
+            CandleInterval::OneHour => "1h",
+            CandleInterval::OneDay => "1d",
+        }
+    }
+}
+
+impl fmt::Display for CandleInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CandleInterval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CandleInterval::ALL
+            .into_iter()
+            .find(|interval| interval.as_str() == s)
+            .ok_or_else(|| format!("unsupported candle interval: {s}"))
+    }
+}
+
+/// Open, high, low, close and volume of the trades of one interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Candle {
+    /// Interval the candle covers
+    pub interval: CandleInterval,
+    /// Start of the interval (milliseconds since epoch, inclusive)
+    pub open_time: u64,
+    /// End of the interval (milliseconds since epoch, exclusive)
+    pub close_time: u64,
+    /// Price of the first trade
+    pub open: u64,
+    /// Highest trade price
+    pub high: u64,
+    /// Lowest trade price
+    pub low: u64,
+    /// Price of the last trade
+    pub close: u64,
+    /// Traded quantity
+    pub volume: u64,
+    /// Traded value (sum of price * quantity)
+    pub quote_volume: u128,
+    /// Number of trades
+    pub trade_count: u64,
+}
+
+impl Candle {
+    fn open(interval: CandleInterval, price: u64, quantity: u64, timestamp: u64) -> Self {
+        let open_time = interval.open_time(timestamp);
+        Self {
+            interval,
+            open_time,
+            close_time: open_time + interval.duration_ms(),
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: quantity,
+            quote_volume: price as u128 * quantity as u128,
+            trade_count: 1,
+        }
+    }
+
+    fn update(&mut self, price: u64, quantity: u64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += quantity;
+        self.quote_volume += price as u128 * quantity as u128;
+        self.trade_count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct CandleState {
+    /// The candle currently being built for each interval
+    current: Vec<Candle>,
+    /// Finished candles not drained yet, oldest first
+    closed: VecDeque<Candle>,
+    /// End of the last finished candle of each interval
+    finished_until: Vec<(CandleInterval, u64)>,
+}
+
+impl CandleState {
+    fn finished_until(&self, interval: CandleInterval) -> u64 {
+        self.finished_until
+            .iter()
+            .find(|(finished, _)| *finished == interval)
+            .map_or(0, |(_, close_time)| *close_time)
+    }
+}
+
+/// Builds candles for a set of intervals from a stream of trades.
+///
+/// A candle is finished when a trade falls into a later interval or when
+/// [`CandleAggregator::close_elapsed`] is called after its interval has ended. Intervals
+/// without trades produce no candle.
+#[derive(Debug)]
+pub struct CandleAggregator {
+    intervals: Vec<CandleInterval>,
+    retention: usize,
+    state: Mutex<CandleState>,
+}
+
+impl Default for CandleAggregator {
+    fn default() -> Self {
+        Self::new(&CandleInterval::ALL, DEFAULT_CLOSED_CANDLE_RETENTION)
+    }
+}
+
+impl CandleAggregator {
+    /// Create an aggregator for `intervals`, keeping at most `retention` finished
+    /// candles until they are drained
+    pub fn new(intervals: &[CandleInterval], retention: usize) -> Self {
+        let mut intervals = intervals.to_vec();
+        intervals.sort_unstable();
+        intervals.dedup();
+        Self {
+            intervals,
+            retention,
+            state: Mutex::new(CandleState::default()),
+        }
+    }
+
+    /// The intervals candles are built for
+    pub fn intervals(&self) -> &[CandleInterval] {
+        &self.intervals
+    }
+
+    /// Add a trade to the current candle of every interval
+    pub fn record_trade(&self, price: u64, quantity: u64, timestamp: u64) {
+        if self.intervals.is_empty() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        for &interval in &self.intervals {
+            let open_time = interval.open_time(timestamp);
+            match state
+                .current
+                .iter()
+                .position(|candle| candle.interval == interval)
+            {
+                // A trade stamped before the current candle (clock skew between threads)
+                // is counted in the current candle rather than reopening a finished one
+                Some(index) if state.current[index].open_time >= open_time => {
+                    state.current[index].update(price, quantity);
+                }
+                Some(index) => {
+                    let finished = std::mem::replace(
+                        &mut state.current[index],
+                        Candle::open(interval, price, quantity, timestamp),
+                    );
+                    self.push_closed(&mut state, finished);
+                }
+                // Likewise, a trade stamped in an interval whose candle is finished opens the
+                // next interval, so each interval is finished at most once
+                None => {
+                    let timestamp = timestamp.max(state.finished_until(interval));
+                    state
+                        .current
+                        .push(Candle::open(interval, price, quantity, timestamp))
+                }
+            }
+        }
+    }
+
+    /// Finish every current candle whose interval has ended at `now` (milliseconds
+    /// since epoch)
+    pub fn close_elapsed(&self, now: u64) {
+        let mut state = self.state.lock().unwrap();
+        let (elapsed, current): (Vec<Candle>, Vec<Candle>) = state
+            .current
+            .drain(..)
+            .partition(|candle| candle.close_time <= now);
+        state.current = current;
+        for candle in elapsed {
+            self.push_closed(&mut state, candle);
+        }
+    }
+
+    /// The candle being built for `interval`, if it has any trade
+    pub fn current(&self, interval: CandleInterval) -> Option<Candle> {
+        let state = self.state.lock().unwrap();
+        state
+            .current
+            .iter()
+            .find(|candle| candle.interval == interval)
+            .copied()
+    }
+
+    /// Remove and return the finished candles, oldest first
+    pub fn drain_closed(&self) -> Vec<Candle> {
+        let mut state = self.state.lock().unwrap();
+        state.closed.drain(..).collect()
+    }
+
+    fn push_closed(&self, state: &mut CandleState, candle: Candle) {
+        match state
+            .finished_until
+            .iter_mut()
+            .find(|(interval, _)| *interval == candle.interval)
+        {
+            Some((_, close_time)) => *close_time = candle.close_time,
+            None => state
+                .finished_until
+                .push((candle.interval, candle.close_time)),
+        }
+        if self.retention == 0 {
+            return;
+        }
+        while state.closed.len() >= self.retention {
+            state.closed.pop_front();
+        }
+        state.closed.push_back(candle);
+    }
+}
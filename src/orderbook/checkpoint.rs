@@ -0,0 +1,387 @@
+//! Checkpoints of the full state of a set of order books, and recovery from them.
+//!
+//! A checkpoint holds the resting orders and order records of every book, each tagged with
+//! the sequence number of the last command applied to it. It is taken at a segment boundary
+//! of the [`Journal`]: the commands of earlier segments are all part of the checkpoint, so
+//! recovery loads the latest checkpoint and only replays the segments from that boundary on.
+//!
+//! Checkpoints are written to a temporary file, synced and then renamed into place, so a
+//! crash while writing one leaves the previous checkpoint intact.
+
+use super::book::OrderBook;
+use super::error::OrderBookError;
+use super::journal::{DEFAULT_MAX_SEGMENT_BYTES, Journal};
+use super::order_state::OrderRecord;
+use pricelevel::{OrderType, Side};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+const CHECKPOINT_PREFIX: &str = "checkpoint-";
+const CHECKPOINT_EXTENSION: &str = "json";
+
+/// The complete state of a book, enough to rebuild it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookState {
+    /// Symbol of the book
+    pub symbol: String,
+    /// Sequence number of the last mutation included in the state
+    pub sequence: u64,
+    /// Price of the last trade, if any
+    pub last_trade_price: Option<u64>,
+    /// Market close timestamp, if set
+    pub market_close_timestamp: Option<u64>,
+    /// Resting orders, level by level and in time priority within each level
+    pub orders: Vec<OrderType>,
+    /// Records of live orders and of the retained terminal orders
+    pub order_records: Vec<OrderRecord>,
+}
+
+impl OrderBook {
+    /// Capture the complete state of the book at a single sequence number
+    pub fn export_state(&self) -> BookState {
+        let _gate = self.snapshot_gate.write().unwrap();
+
+        let mut orders = Vec::new();
+        for side in [Side::Buy, Side::Sell] {
+            let price_levels = match side {
+                Side::Buy => &self.bids,
+                Side::Sell => &self.asks,
+            };
+            let mut prices: Vec<u64> = price_levels.iter().map(|entry| *entry.key()).collect();
+            prices.sort_unstable();
+            for price in prices {
+                if let Some(level) = price_levels.get(&price) {
+                    let mut level_orders = level.iter_orders();
+                    self.queue_priorities.sort(&mut level_orders);
+                    orders.extend(level_orders.iter().map(|order| **order));
+                }
+            }
+        }
+
+        BookState {
+            symbol: self.symbol.clone(),
+            sequence: self.sequence(),
+            last_trade_price: self.last_trade_price(),
+            market_close_timestamp: self
+                .has_market_close
+                .load(Ordering::SeqCst)
+                .then(|| self.market_close_timestamp.load(Ordering::SeqCst)),
+            orders,
+            order_records: self.order_states.records(),
+        }
+    }
+
+    /// Rebuild the book from `state`. The book must be empty and have the same symbol.
+    pub fn restore_state(&self, state: BookState) -> Result<(), OrderBookError> {
+        if state.symbol != self.symbol {
+            return Err(OrderBookError::InvalidOperation {
+                message: format!(
+                    "Cannot restore the state of {} into the book of {}",
+                    state.symbol, self.symbol
+                ),
+            });
+        }
+
+        let _gate = self.snapshot_gate.write().unwrap();
+        if !self.bids.is_empty() || !self.asks.is_empty() || !self.order_states.is_empty() {
+            return Err(OrderBookError::InvalidOperation {
+                message: format!("Cannot restore the book of {}: not empty", self.symbol),
+            });
+        }
+
+        for order in state.orders {
+            self.place_order_in_book(Arc::new(order))?;
+        }
+        for record in state.order_records {
+            self.order_states.register(record)?;
+        }
+        if let Some(price) = state.last_trade_price {
+            self.last_trade_price.store(price, Ordering::SeqCst);
+            self.has_traded.store(true, Ordering::SeqCst);
+        }
+        if let Some(timestamp) = state.market_close_timestamp {
+            self.market_close_timestamp
+                .store(timestamp, Ordering::SeqCst);
+            self.has_market_close.store(true, Ordering::SeqCst);
+        }
+        self.sequence.store(state.sequence, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// A checkpoint file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// First journal segment not covered by the checkpoint
+    pub journal_segment: u64,
+    /// Time the checkpoint was taken (milliseconds since epoch)
+    pub timestamp: u64,
+    /// State of every book
+    pub books: Vec<BookState>,
+}
+
+/// Configuration of a [`CheckpointManager`]
+#[derive(Debug, Clone, Copy)]
+pub struct CheckpointConfig {
+    /// Size past which the active journal segment is rotated
+    pub max_segment_bytes: u64,
+    /// Number of checkpoints kept on disk, at least 1. Journal segments are kept from the
+    /// oldest retained checkpoint on.
+    pub retained_checkpoints: usize,
+}
+
+impl Default for CheckpointConfig {
+    fn default() -> Self {
+        Self {
+            max_segment_bytes: DEFAULT_MAX_SEGMENT_BYTES,
+            retained_checkpoints: 2,
+        }
+    }
+}
+
+/// Outcome of [`CheckpointManager::recover`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// Journal segment the loaded checkpoint was taken at, if a checkpoint was found
+    pub checkpoint_segment: Option<u64>,
+    /// Number of books restored from the checkpoint
+    pub restored_books: usize,
+    /// Number of journaled commands applied on top of the checkpoint
+    pub replayed_commands: usize,
+    /// Number of journaled commands already part of the checkpoint
+    pub skipped_commands: usize,
+    /// Number of replayed commands whose result differs from the journaled outcome
+    pub diverged_commands: usize,
+}
+
+/// Writes checkpoints of a set of books, recovers them on startup and prunes the files
+/// that are no longer needed.
+///
+/// The manager owns the [`Journal`] of the books, stored in the `journal` directory next to
+/// the `checkpoints` directory.
+#[derive(Debug)]
+pub struct CheckpointManager {
+    dir: PathBuf,
+    config: CheckpointConfig,
+    journal: Arc<Journal>,
+    /// Serializes checkpoints, so they are written in journal order
+    writing: Mutex<()>,
+}
+
+impl CheckpointManager {
+    /// Open the checkpoints and journal stored under `dir`, creating them if needed
+    pub fn open(dir: impl AsRef<Path>, config: CheckpointConfig) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(dir.join("checkpoints"))?;
+        let journal = Journal::open(dir.join("journal"), config.max_segment_bytes)?;
+
+        Ok(Self {
+            dir,
+            config,
+            journal: Arc::new(journal),
+            writing: Mutex::new(()),
+        })
+    }
+
+    /// The journal the sequencers of the books should write to
+    pub fn journal(&self) -> Arc<Journal> {
+        Arc::clone(&self.journal)
+    }
+
+    /// Journal segments at which the checkpoints on disk were taken, in ascending order
+    pub fn checkpoints(&self) -> io::Result<Vec<u64>> {
+        let mut checkpoints = Vec::new();
+        for entry in fs::read_dir(self.checkpoint_dir())? {
+            let name = entry?.file_name();
+            let segment = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(CHECKPOINT_PREFIX))
+                .and_then(|name| name.strip_suffix(CHECKPOINT_EXTENSION))
+                .and_then(|name| name.strip_suffix('.'))
+                .and_then(|segment| segment.parse().ok());
+            if let Some(segment) = segment {
+                checkpoints.push(segment);
+            }
+        }
+        checkpoints.sort_unstable();
+        Ok(checkpoints)
+    }
+
+    /// Load the checkpoint taken at `segment`
+    pub fn load(&self, segment: u64) -> io::Result<Checkpoint> {
+        let file = File::open(self.checkpoint_path(segment))?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Write a checkpoint of `books`, then prune the checkpoints and journal segments
+    /// beyond the retention policy. Returns the journal segment the checkpoint was taken at.
+    ///
+    /// The journal is rotated first. The rotation waits for the commands the sequencers
+    /// have journaled but not yet applied, so every command of the previous segments is
+    /// part of the captured states. Commands of the new segment that are also part of the
+    /// states are skipped on recovery by sequence number.
+    pub fn checkpoint<'a>(
+        &self,
+        books: impl IntoIterator<Item = &'a OrderBook>,
+        timestamp: u64,
+    ) -> io::Result<u64> {
+        self.checkpoint_with(books, timestamp, || Ok(()))
+    }
+
+    /// Like [`checkpoint`](Self::checkpoint), calling `before_write` once the journal is
+    /// rotated. The commands of the closed segments are no longer replayed once the
+    /// checkpoint is written, so what was derived from them outside of the books (e.g. an
+    /// outbox fed by their events) is made durable there; an error aborts the checkpoint.
+    pub fn checkpoint_with<'a>(
+        &self,
+        books: impl IntoIterator<Item = &'a OrderBook>,
+        timestamp: u64,
+        before_write: impl FnOnce() -> io::Result<()>,
+    ) -> io::Result<u64> {
+        let _writing = self.writing.lock().unwrap();
+
+        let journal_segment = self.journal.rotate()?;
+        before_write()?;
+        let checkpoint = Checkpoint {
+            journal_segment,
+            timestamp,
+            books: books.into_iter().map(OrderBook::export_state).collect(),
+        };
+
+        let path = self.checkpoint_path(journal_segment);
+        let tmp = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp)?);
+            serde_json::to_writer(&mut writer, &checkpoint)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp, &path)?;
+        File::open(self.checkpoint_dir())?.sync_all()?;
+        info!(
+            "Wrote checkpoint of {} books at journal segment {}",
+            checkpoint.books.len(),
+            journal_segment
+        );
+
+        self.prune()?;
+        Ok(journal_segment)
+    }
+
+    /// Restore `books` from the latest readable checkpoint and replay the journal after it.
+    ///
+    /// Books must be empty. Books missing from the checkpoint start empty and get every
+    /// journaled command; journaled commands for unknown symbols are ignored. Commands are
+    /// replayed at the time they were journaled with, and a command whose result differs
+    /// from its journaled outcome is reported.
+    pub fn recover<'a>(
+        &self,
+        books: impl IntoIterator<Item = &'a OrderBook>,
+    ) -> io::Result<RecoveryReport> {
+        let books: HashMap<&str, &OrderBook> = books
+            .into_iter()
+            .map(|book| (book.symbol(), book))
+            .collect();
+        let mut report = RecoveryReport::default();
+
+        let mut checkpoint = None;
+        for segment in self.checkpoints()?.into_iter().rev() {
+            match self.load(segment) {
+                Ok(loaded) => {
+                    checkpoint = Some(loaded);
+                    break;
+                }
+                Err(err) => warn!("Skipping unreadable checkpoint {}: {}", segment, err),
+            }
+        }
+
+        let first_segment = match checkpoint {
+            Some(checkpoint) => {
+                for state in checkpoint.books {
+                    if let Some(book) = books.get(state.symbol.as_str()) {
+                        book.restore_state(state)
+                            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                        report.restored_books += 1;
+                    }
+                }
+                report.checkpoint_segment = Some(checkpoint.journal_segment);
+                checkpoint.journal_segment
+            }
+            None => 0,
+        };
+
+        let active = self.journal.active_segment();
+        for segment in self.journal.segments()? {
+            if segment < first_segment || segment >= active {
+                continue;
+            }
+            for entry in self.journal.read_segment(segment)? {
+                let Some(book) = books.get(entry.symbol.as_str()) else {
+                    continue;
+                };
+                if entry.sequence <= book.sequence() {
+                    report.skipped_commands += 1;
+                    continue;
+                }
+                if entry.sequence != book.sequence() + 1 {
+                    warn!(
+                        "Order book {}: journal jumps from sequence {} to {}",
+                        entry.symbol,
+                        book.sequence(),
+                        entry.sequence
+                    );
+                }
+                // Replayed at the time the command was first applied, as it was journaled
+                let result = match entry.timestamp {
+                    0 => entry.command.apply_to(book),
+                    timestamp => book.at_time(timestamp, || entry.command.apply_to(book)),
+                };
+                let outcome = result.outcome();
+                if entry.outcome.is_some_and(|journaled| journaled != outcome) {
+                    warn!(
+                        "Order book {}: command {} replayed as {:?} instead of {:?}",
+                        entry.symbol, entry.sequence, outcome, entry.outcome
+                    );
+                    report.diverged_commands += 1;
+                }
+                book.sequence.store(entry.sequence, Ordering::Release);
+                report.replayed_commands += 1;
+            }
+        }
+
+        info!(
+            "Recovered {} books from checkpoint {:?} and {} journaled commands",
+            report.restored_books, report.checkpoint_segment, report.replayed_commands
+        );
+        Ok(report)
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let checkpoints = self.checkpoints()?;
+        let retained = self.config.retained_checkpoints.max(1);
+        let (expired, kept) = checkpoints.split_at(checkpoints.len().saturating_sub(retained));
+        for segment in expired {
+            fs::remove_file(self.checkpoint_path(*segment))?;
+        }
+        if let Some(oldest) = kept.first() {
+            self.journal.prune_before(*oldest)?;
+        }
+        Ok(())
+    }
+
+    fn checkpoint_dir(&self) -> PathBuf {
+        self.dir.join("checkpoints")
+    }
+
+    fn checkpoint_path(&self, segment: u64) -> PathBuf {
+        self.checkpoint_dir().join(format!(
+            "{CHECKPOINT_PREFIX}{segment:010}.{CHECKPOINT_EXTENSION}"
+        ))
+    }
+}
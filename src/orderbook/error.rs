@@ -2,6 +2,7 @@
 
 use pricelevel::{PriceLevelError, Side};
 use std::fmt;
+use uuid::Uuid;
 
 /// Errors that can occur within the OrderBook
 #[derive(Debug)]
@@ -40,6 +41,14 @@ pub enum OrderBookError {
         /// Description of the error
         message: String,
     },
+
+    /// The client order id is already used by a live order of the same account
+    DuplicateClientOrderId {
+        /// Account submitting the order
+        account: Uuid,
+        /// The client order id in use
+        client_order_id: String,
+    },
 }
 
 impl fmt::Display for OrderBookError {
@@ -71,6 +80,15 @@ impl fmt::Display for OrderBookError {
             OrderBookError::InvalidOperation { message } => {
                 write!(f, "Invalid operation: {message}")
             }
+            OrderBookError::DuplicateClientOrderId {
+                account,
+                client_order_id,
+            } => {
+                write!(
+                    f,
+                    "Duplicate client order id: {client_order_id} is already live for account {account}"
+                )
+            }
         }
     }
 }
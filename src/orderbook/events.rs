@@ -0,0 +1,136 @@
+//! Events published by an order book as it changes.
+//!
+//! Consumers (persistence, market data feeds) register an [`EventSink`] on the book. Sinks
+//! are called synchronously by the thread that ran the mutation, once the mutation is over,
+//! so they must only hand the event over (to a queue, a local file) and never wait on a
+//! remote service.
+
+use super::order_state::OrderRecord;
+use super::trades::TradeRecord;
+use pricelevel::{MatchResult, OrderId};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// A change of an order book
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BookEvent {
+    /// A trade was executed
+    Trade {
+        /// Symbol of the book
+        symbol: String,
+        /// Sequence number of the mutation that executed the trade
+        sequence: u64,
+        /// The trade
+        trade: TradeRecord,
+    },
+    /// The recorded state of an order changed (accepted, filled, amended, cancelled...)
+    OrderUpdated {
+        /// Symbol of the book
+        symbol: String,
+        /// Sequence number of the mutation that changed the order
+        sequence: u64,
+        /// The new state of the order
+        record: OrderRecord,
+        /// Quantity of the order displayed in the book after the mutation, `None` once the
+        /// order no longer rests in it
+        #[serde(default)]
+        visible_quantity: Option<u64>,
+    },
+}
+
+impl BookEvent {
+    /// Symbol of the book that published the event
+    pub fn symbol(&self) -> &str {
+        match self {
+            BookEvent::Trade { symbol, .. } | BookEvent::OrderUpdated { symbol, .. } => symbol,
+        }
+    }
+
+    /// Sequence number of the mutation that produced the event
+    pub fn sequence(&self) -> u64 {
+        match self {
+            BookEvent::Trade { sequence, .. } | BookEvent::OrderUpdated { sequence, .. } => {
+                *sequence
+            }
+        }
+    }
+}
+
+/// A consumer of book events
+pub trait EventSink: Debug + Send + Sync {
+    /// Receive an event. Called on the thread of the book mutation, so it must not block.
+    fn publish(&self, event: &BookEvent);
+}
+
+/// The sinks registered on a book
+#[derive(Debug, Default)]
+pub(super) struct EventBus {
+    sinks: RwLock<Vec<Arc<dyn EventSink>>>,
+    active: AtomicBool,
+}
+
+impl EventBus {
+    pub(super) fn subscribe(&self, sink: Arc<dyn EventSink>) {
+        self.sinks.write().unwrap().push(sink);
+        self.active.store(true, Ordering::Release);
+    }
+
+    /// Returns true if any sink is registered, so events are worth building
+    pub(super) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+
+    pub(super) fn publish(&self, event: BookEvent) {
+        for sink in self.sinks.read().unwrap().iter() {
+            sink.publish(&event);
+        }
+    }
+}
+
+thread_local! {
+    static PENDING: RefCell<Option<PendingEvents>> = const { RefCell::new(None) };
+}
+
+/// What the mutation running on the current thread produced for the sinks and the trade
+/// listener. Both are called once the mutation has released the snapshot gate, so a slow
+/// consumer never holds up a snapshot.
+#[derive(Debug, Default)]
+pub(super) struct PendingEvents {
+    /// Sequence number of the mutation, given to every event it produces
+    pub(super) sequence: u64,
+    /// Events for the sinks, in the order they were produced
+    pub(super) events: Vec<BookEvent>,
+    /// Executions for the trade listener
+    pub(super) matches: Vec<MatchResult>,
+    /// Orders whose recorded state the mutation changed, in order of change
+    pub(super) changed: Vec<OrderId>,
+}
+
+impl PendingEvents {
+    /// Run `mutation`, the step `sequence` of the book, collecting what it produces
+    pub(super) fn collect<R>(sequence: u64, mutation: impl FnOnce() -> R) -> (R, PendingEvents) {
+        PENDING.with(|pending| {
+            *pending.borrow_mut() = Some(PendingEvents {
+                sequence,
+                ..PendingEvents::default()
+            })
+        });
+        let result = mutation();
+        let pending = PENDING
+            .with(|pending| pending.borrow_mut().take())
+            .unwrap_or_default();
+        (result, pending)
+    }
+
+    /// Add to what the running mutation produced. Nothing is recorded outside a mutation.
+    pub(super) fn record(f: impl FnOnce(&mut PendingEvents)) {
+        PENDING.with(|pending| {
+            if let Some(pending) = pending.borrow_mut().as_mut() {
+                f(pending);
+            }
+        });
+    }
+}
@@ -0,0 +1,199 @@
+//! Execution reports returned to the submitter of an order
+
+use super::order_state::{OrderStatus, TerminalReason};
+use pricelevel::{MatchResult, OrderId, OrderType, Side, Transaction};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Trading fees charged by the book, in basis points of the traded value.
+/// Negative values are rebates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    /// Fee charged to the resting (maker) side of a trade
+    pub maker_fee_bps: i32,
+    /// Fee charged to the aggressive (taker) side of a trade
+    pub taker_fee_bps: i32,
+}
+
+impl FeeSchedule {
+    /// Create a fee schedule from maker and taker fees in basis points
+    pub fn new(maker_fee_bps: i32, taker_fee_bps: i32) -> Self {
+        Self {
+            maker_fee_bps,
+            taker_fee_bps,
+        }
+    }
+
+    /// Fee for the maker side of a trade of `quantity` at `price`
+    pub fn maker_fee(&self, price: u64, quantity: u64) -> i128 {
+        Self::fee(self.maker_fee_bps, price, quantity)
+    }
+
+    /// Fee for the taker side of a trade of `quantity` at `price`
+    pub fn taker_fee(&self, price: u64, quantity: u64) -> i128 {
+        Self::fee(self.taker_fee_bps, price, quantity)
+    }
+
+    fn fee(bps: i32, price: u64, quantity: u64) -> i128 {
+        price as i128 * quantity as i128 * bps as i128 / 10_000
+    }
+}
+
+/// A single execution of the submitted order against a resting order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fill {
+    /// Identifier of the trade
+    pub transaction_id: Uuid,
+    /// The resting order the submitted order traded with
+    pub maker_order_id: OrderId,
+    /// Execution price
+    pub price: u64,
+    /// Executed quantity
+    pub quantity: u64,
+    /// Fee charged to the submitted order for this fill (negative for a rebate)
+    pub fee: i128,
+    /// Time of the execution (milliseconds since epoch)
+    pub timestamp: u64,
+}
+
+impl Fill {
+    /// Create the taker side fill of a transaction
+    pub fn from_transaction(transaction: &Transaction, fee_schedule: &FeeSchedule) -> Self {
+        Self {
+            transaction_id: transaction.transaction_id,
+            maker_order_id: transaction.maker_order_id,
+            price: transaction.price,
+            quantity: transaction.quantity,
+            fee: fee_schedule.taker_fee(transaction.price, transaction.quantity),
+            timestamp: transaction.timestamp,
+        }
+    }
+}
+
+/// Quantity of the submitted order that was dropped instead of resting in the book
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CancelledRemainder {
+    /// Quantity that was not executed
+    pub quantity: u64,
+    /// Why it was dropped
+    pub reason: TerminalReason,
+}
+
+/// The outcome of submitting an order: its fills, what is left resting in the book,
+/// what was cancelled and the resulting order status
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionReport {
+    /// Identifier of the submitted order
+    pub order_id: OrderId,
+    /// Side of the submitted order
+    pub side: Side,
+    /// Status of the order once the submission has been processed
+    pub status: OrderStatus,
+    /// Executions of the order on entry, in execution order
+    pub fills: Vec<Fill>,
+    /// The remainder of the order resting in the book, if any
+    pub resting: Option<Arc<OrderType>>,
+    /// The remainder of the order that was cancelled instead of resting, if any
+    pub cancelled: Option<CancelledRemainder>,
+}
+
+impl ExecutionReport {
+    /// Build the report of an order from its match result. `resting` is the remainder
+    /// added to the book and `cancelled` the remainder that was dropped.
+    pub fn new(
+        order_id: OrderId,
+        side: Side,
+        match_result: &MatchResult,
+        fee_schedule: &FeeSchedule,
+        resting: Option<Arc<OrderType>>,
+        cancelled: Option<CancelledRemainder>,
+    ) -> Self {
+        let fills: Vec<Fill> = match_result
+            .transactions
+            .as_vec()
+            .iter()
+            .map(|transaction| Fill::from_transaction(transaction, fee_schedule))
+            .collect();
+
+        let status = if cancelled.is_some() {
+            OrderStatus::Cancelled
+        } else if resting.is_none() {
+            OrderStatus::Filled
+        } else if fills.is_empty() {
+            OrderStatus::New
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+
+        Self {
+            order_id,
+            side,
+            status,
+            fills,
+            resting,
+            cancelled,
+        }
+    }
+
+    /// Build the report of an amendment that executed nothing and left `order` resting
+    pub fn resting(order: Arc<OrderType>, status: OrderStatus) -> Self {
+        Self {
+            order_id: order.id(),
+            side: order.side(),
+            status,
+            fills: Vec::new(),
+            resting: Some(order),
+            cancelled: None,
+        }
+    }
+
+    /// Build the report of a resting order cancelled by its owner
+    pub fn cancelled(order: &OrderType) -> Self {
+        Self {
+            order_id: order.id(),
+            side: order.side(),
+            status: OrderStatus::Cancelled,
+            fills: Vec::new(),
+            resting: None,
+            cancelled: Some(CancelledRemainder {
+                quantity: order.visible_quantity() + order.hidden_quantity(),
+                reason: TerminalReason::Cancelled,
+            }),
+        }
+    }
+
+    /// Total executed quantity
+    pub fn filled_quantity(&self) -> u64 {
+        self.fills.iter().map(|fill| fill.quantity).sum()
+    }
+
+    /// Total executed value (sum of price * quantity of every fill)
+    pub fn filled_value(&self) -> u128 {
+        self.fills
+            .iter()
+            .map(|fill| fill.price as u128 * fill.quantity as u128)
+            .sum()
+    }
+
+    /// Volume-weighted average execution price, if anything was executed
+    pub fn average_price(&self) -> Option<f64> {
+        match self.filled_quantity() {
+            0 => None,
+            quantity => Some(self.filled_value() as f64 / quantity as f64),
+        }
+    }
+
+    /// Total fees charged for all fills
+    pub fn total_fee(&self) -> i128 {
+        self.fills.iter().map(|fill| fill.fee).sum()
+    }
+
+    /// Quantity left resting in the book
+    pub fn resting_quantity(&self) -> u64 {
+        self.resting
+            .as_ref()
+            .map(|order| order.visible_quantity() + order.hidden_quantity())
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,329 @@
+//! Consistency checks of the internal state of an order book.
+//!
+//! [`OrderBook::check_invariants`] walks the whole book while mutations are held back and
+//! reports everything that does not add up. It is meant for tests and fuzzing, and for
+//! periodic checks of a running engine built in debug mode.
+
+use super::book::OrderBook;
+use pricelevel::{OrderId, PriceLevel, Side};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Quantities and order count of a price level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelTotals {
+    /// Visible quantity
+    pub visible_quantity: u64,
+    /// Hidden quantity
+    pub hidden_quantity: u64,
+    /// Number of orders
+    pub order_count: usize,
+}
+
+/// A broken invariant of the book
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InvariantViolation {
+    /// The location index points to a level that does not hold the order
+    DanglingLocation {
+        /// The indexed order
+        order_id: OrderId,
+        /// Indexed price
+        price: u64,
+        /// Indexed side
+        side: Side,
+    },
+    /// A resting order is missing from the location index
+    UnindexedOrder {
+        /// The resting order
+        order_id: OrderId,
+        /// Price of the level holding it
+        price: u64,
+        /// Side of the level holding it
+        side: Side,
+    },
+    /// A resting order has no time priority
+    MissingQueuePriority {
+        /// The resting order
+        order_id: OrderId,
+    },
+    /// An order that no longer rests in the book still has a time priority
+    StaleQueuePriority {
+        /// The order
+        order_id: OrderId,
+    },
+    /// An order rests at a level that does not match its own price or side
+    MisplacedOrder {
+        /// The order
+        order_id: OrderId,
+        /// Price of the level holding it
+        level_price: u64,
+        /// Side of the level holding it
+        level_side: Side,
+    },
+    /// A price level without any order is still in the book
+    EmptyLevel {
+        /// Side of the level
+        side: Side,
+        /// Price of the level
+        price: u64,
+    },
+    /// The totals kept by a level differ from the sum of its orders
+    LevelAggregateMismatch {
+        /// Side of the level
+        side: Side,
+        /// Price of the level
+        price: u64,
+        /// Totals kept by the level
+        level: LevelTotals,
+        /// Totals computed from its orders
+        orders: LevelTotals,
+    },
+    /// The best bid is at or above the best ask
+    CrossedBook {
+        /// Highest bid price
+        best_bid: u64,
+        /// Lowest ask price
+        best_ask: u64,
+    },
+    /// The maintained best price of a side differs from its actual best level
+    BestPriceMismatch {
+        /// Side of the book
+        side: Side,
+        /// Best price reported by the book
+        cached: Option<u64>,
+        /// Best price of the levels
+        actual: Option<u64>,
+    },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DanglingLocation {
+                order_id,
+                price,
+                side,
+            } => write!(
+                f,
+                "order {order_id} is indexed at {side} {price} but is not there"
+            ),
+            Self::UnindexedOrder {
+                order_id,
+                price,
+                side,
+            } => write!(
+                f,
+                "order {order_id} rests at {side} {price} but is not indexed"
+            ),
+            Self::MissingQueuePriority { order_id } => {
+                write!(f, "order {order_id} rests without a queue priority")
+            }
+            Self::StaleQueuePriority { order_id } => {
+                write!(f, "order {order_id} has a queue priority but does not rest")
+            }
+            Self::MisplacedOrder {
+                order_id,
+                level_price,
+                level_side,
+            } => write!(
+                f,
+                "order {order_id} rests at {level_side} {level_price}, which is not its price and side"
+            ),
+            Self::EmptyLevel { side, price } => write!(f, "level {side} {price} is empty"),
+            Self::LevelAggregateMismatch {
+                side,
+                price,
+                level,
+                orders,
+            } => write!(
+                f,
+                "level {side} {price} totals {level:?} differ from its orders {orders:?}"
+            ),
+            Self::CrossedBook { best_bid, best_ask } => {
+                write!(f, "book is crossed: bid {best_bid} >= ask {best_ask}")
+            }
+            Self::BestPriceMismatch {
+                side,
+                cached,
+                actual,
+            } => write!(
+                f,
+                "best {side} price is {cached:?} but the best level is {actual:?}"
+            ),
+        }
+    }
+}
+
+/// The result of [`OrderBook::check_invariants`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvariantReport {
+    /// Sequence number of the last mutation applied before the check
+    pub sequence: u64,
+    /// Number of price levels checked
+    pub levels_checked: usize,
+    /// Number of resting orders checked
+    pub orders_checked: usize,
+    /// Every violation found
+    pub violations: Vec<InvariantViolation>,
+}
+
+impl InvariantReport {
+    /// Returns true if no invariant is broken
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl fmt::Display for InvariantReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} violation(s) in {} levels and {} orders at sequence {}",
+            self.violations.len(),
+            self.levels_checked,
+            self.orders_checked,
+            self.sequence
+        )?;
+        for violation in &self.violations {
+            write!(f, "\n  - {violation}")?;
+        }
+        Ok(())
+    }
+}
+
+impl OrderBook {
+    /// Check the internal consistency of the book:
+    /// - every entry of the location index points to an order at that price and side;
+    /// - every resting order is indexed and has a time priority;
+    /// - no empty price level is left in the book;
+    /// - the totals of every level match the sum of its orders;
+    /// - the book is not crossed;
+    /// - the maintained best prices match the levels.
+    ///
+    /// Mutations are held back while the book is checked.
+    pub fn check_invariants(&self) -> InvariantReport {
+        let _gate = self.snapshot_gate.write().unwrap();
+
+        let mut violations = Vec::new();
+        let mut resting = HashMap::new();
+        let mut levels_checked = 0;
+
+        for (side, price_levels) in [(Side::Buy, &self.bids), (Side::Sell, &self.asks)] {
+            for entry in price_levels.iter() {
+                levels_checked += 1;
+                self.check_level(
+                    side,
+                    *entry.key(),
+                    entry.value(),
+                    &mut resting,
+                    &mut violations,
+                );
+            }
+        }
+
+        for entry in self.order_locations.iter() {
+            let (price, side) = *entry.value();
+            if resting.get(entry.key()) != Some(&(price, side)) {
+                violations.push(InvariantViolation::DanglingLocation {
+                    order_id: *entry.key(),
+                    price,
+                    side,
+                });
+            }
+        }
+
+        for order_id in self.queue_priorities.order_ids() {
+            if !resting.contains_key(&order_id) {
+                violations.push(InvariantViolation::StaleQueuePriority { order_id });
+            }
+        }
+
+        let best_bid = self.bids.iter().map(|entry| *entry.key()).max();
+        let best_ask = self.asks.iter().map(|entry| *entry.key()).min();
+        if let (Some(best_bid), Some(best_ask)) = (best_bid, best_ask)
+            && best_bid >= best_ask
+        {
+            violations.push(InvariantViolation::CrossedBook { best_bid, best_ask });
+        }
+        for (side, cached, actual) in [
+            (Side::Buy, self.cache.best_bid(), best_bid),
+            (Side::Sell, self.cache.best_ask(), best_ask),
+        ] {
+            if cached != actual {
+                violations.push(InvariantViolation::BestPriceMismatch {
+                    side,
+                    cached,
+                    actual,
+                });
+            }
+        }
+
+        InvariantReport {
+            sequence: self.sequence(),
+            levels_checked,
+            orders_checked: resting.len(),
+            violations,
+        }
+    }
+
+    fn check_level(
+        &self,
+        side: Side,
+        price: u64,
+        level: &Arc<PriceLevel>,
+        resting: &mut HashMap<OrderId, (u64, Side)>,
+        violations: &mut Vec<InvariantViolation>,
+    ) {
+        let orders = level.iter_orders();
+        if orders.is_empty() {
+            violations.push(InvariantViolation::EmptyLevel { side, price });
+        }
+
+        let mut totals = LevelTotals::default();
+        for order in &orders {
+            let order_id = order.id();
+            totals.visible_quantity += order.visible_quantity();
+            totals.hidden_quantity += order.hidden_quantity();
+            totals.order_count += 1;
+            resting.insert(order_id, (price, side));
+
+            if order.price() != price || order.side() != side {
+                violations.push(InvariantViolation::MisplacedOrder {
+                    order_id,
+                    level_price: price,
+                    level_side: side,
+                });
+            }
+            if self
+                .order_locations
+                .get(&order_id)
+                .is_none_or(|location| *location != (price, side))
+            {
+                violations.push(InvariantViolation::UnindexedOrder {
+                    order_id,
+                    price,
+                    side,
+                });
+            }
+            if !self.queue_priorities.contains(&order_id) {
+                violations.push(InvariantViolation::MissingQueuePriority { order_id });
+            }
+        }
+
+        let level_totals = LevelTotals {
+            visible_quantity: level.visible_quantity(),
+            hidden_quantity: level.hidden_quantity(),
+            order_count: level.order_count(),
+        };
+        if level_totals != totals {
+            violations.push(InvariantViolation::LevelAggregateMismatch {
+                side,
+                price,
+                level: level_totals,
+                orders: totals,
+            });
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! Append-only journal of the commands applied by sequencers.
+//!
+//! The journal is a directory of numbered segment files holding one JSON entry per line.
+//! New entries always go to the active segment, which is rotated when it grows past a size
+//! limit or when a checkpoint is taken. A checkpoint taken at the start of segment `K`
+//! covers every entry of the segments before `K`, so those can be pruned once the
+//! checkpoint is durable.
+//!
+//! Each command is written before it is applied, with the time it is applied at. Once it
+//! has been applied, a line with its [`CommandOutcome`] follows, against which a replay of
+//! the command is checked. Writers hold a [`Journal::begin_command`] guard from the write
+//! to the end of the apply, and [`Journal::rotate`] waits for those guards, so every entry
+//! of the closed segments is applied when it returns.
+
+use super::sequencer::{Command, CommandOutcome};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock, RwLockReadGuard};
+use tracing::warn;
+
+/// Default size past which the active segment is rotated
+pub const DEFAULT_MAX_SEGMENT_BYTES: u64 = 64 * 1024 * 1024;
+
+const SEGMENT_PREFIX: &str = "segment-";
+const SEGMENT_EXTENSION: &str = "jsonl";
+
+/// A command applied to a book, as recorded in the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Symbol of the book the command was applied to
+    pub symbol: String,
+    /// Sequence number of the book after the command
+    pub sequence: u64,
+    /// Time of the book while the command was applied (milliseconds since epoch), 0 if
+    /// it was not recorded
+    #[serde(default)]
+    pub timestamp: u64,
+    /// The applied command
+    pub command: Command,
+    /// What applying the command produced, if it was recorded. Read from the outcome line
+    /// following the entry, never written with it.
+    #[serde(skip)]
+    pub outcome: Option<CommandOutcome>,
+}
+
+/// The outcome of a journaled command, written once the command has been applied
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalOutcome {
+    /// Symbol of the book the command was applied to
+    pub symbol: String,
+    /// Sequence number of the command
+    pub sequence: u64,
+    /// What applying the command produced
+    pub outcome: CommandOutcome,
+}
+
+/// A line of a segment
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JournalLine {
+    Entry(JournalEntry),
+    Outcome(JournalOutcome),
+}
+
+struct ActiveSegment {
+    index: u64,
+    writer: BufWriter<File>,
+    bytes: u64,
+}
+
+/// An append-only journal split into numbered segment files
+pub struct Journal {
+    dir: PathBuf,
+    max_segment_bytes: u64,
+    active: Mutex<ActiveSegment>,
+    /// Read by the commands being written and applied, written by `rotate`
+    applying: RwLock<()>,
+}
+
+impl Journal {
+    /// Open the journal stored in `dir`, creating the directory if needed.
+    ///
+    /// Existing segments are left untouched: writing resumes in a new segment, so a
+    /// segment cut short by a crash never gets entries appended after its torn tail.
+    pub fn open(dir: impl AsRef<Path>, max_segment_bytes: u64) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let next = list_segments(&dir)?.last().map_or(0, |last| last + 1);
+        let active = ActiveSegment::create(&dir, next)?;
+
+        Ok(Self {
+            dir,
+            max_segment_bytes: max_segment_bytes.max(1),
+            active: Mutex::new(active),
+            applying: RwLock::new(()),
+        })
+    }
+
+    /// Directory holding the segments
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Index of the segment new entries are written to
+    pub fn active_segment(&self) -> u64 {
+        self.active.lock().unwrap().index
+    }
+
+    /// Mark a command as in flight. Taken before the command is appended and held until it
+    /// is applied to its book, so that `rotate` never closes a segment holding an entry
+    /// that is not yet part of its book.
+    pub fn begin_command(&self) -> RwLockReadGuard<'_, ()> {
+        self.applying.read().unwrap()
+    }
+
+    /// Append `entry` to the active segment and flush it to the operating system.
+    ///
+    /// A full segment is rotated before the entry is written, so an entry that could not
+    /// be appended is never left in the journal.
+    pub fn append(&self, entry: &JournalEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let mut active = self.active.lock().unwrap();
+        if active.bytes >= self.max_segment_bytes {
+            self.rotate_locked(&mut active)?;
+        }
+        active.writer.write_all(&line)?;
+        active.writer.flush()?;
+        active.bytes += line.len() as u64;
+        Ok(())
+    }
+
+    /// Append `outcome` to the active segment. It is flushed with the next entry, as it
+    /// is only needed to check a replay.
+    pub fn append_outcome(&self, outcome: &JournalOutcome) -> io::Result<()> {
+        let mut line = serde_json::to_vec(outcome)?;
+        line.push(b'\n');
+
+        let mut active = self.active.lock().unwrap();
+        active.writer.write_all(&line)?;
+        active.bytes += line.len() as u64;
+        Ok(())
+    }
+
+    /// Make the entries written so far durable
+    pub fn sync(&self) -> io::Result<()> {
+        let mut active = self.active.lock().unwrap();
+        active.writer.flush()?;
+        active.writer.get_ref().sync_data()
+    }
+
+    /// Close the active segment and start a new one, once the commands in flight are
+    /// applied. Returns the index of the new segment.
+    pub fn rotate(&self) -> io::Result<u64> {
+        let _applied = self.applying.write().unwrap();
+        let mut active = self.active.lock().unwrap();
+        self.rotate_locked(&mut active)?;
+        Ok(active.index)
+    }
+
+    /// Indexes of the segments on disk, in ascending order
+    pub fn segments(&self) -> io::Result<Vec<u64>> {
+        list_segments(&self.dir)
+    }
+
+    /// Read every entry of a segment, with the outcome recorded after it if any.
+    ///
+    /// An unreadable last line is taken as a write cut short by a crash and ignored; an
+    /// unreadable line followed by others is reported as invalid data.
+    pub fn read_segment(&self, index: u64) -> io::Result<Vec<JournalEntry>> {
+        let path = segment_path(&self.dir, index);
+        let mut lines = BufReader::new(File::open(&path)?).lines().peekable();
+        let mut entries = Vec::new();
+
+        while let Some(line) = lines.next() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(JournalLine::Entry(entry)) => entries.push(entry),
+                Ok(JournalLine::Outcome(recorded)) => {
+                    // Entries of other books may have been written in between
+                    let entry = entries.iter_mut().rev().find(|entry: &&mut JournalEntry| {
+                        entry.symbol == recorded.symbol && entry.sequence == recorded.sequence
+                    });
+                    if let Some(entry) = entry {
+                        entry.outcome = Some(recorded.outcome);
+                    }
+                }
+                Err(err) if lines.peek().is_none() => {
+                    warn!(
+                        "Ignoring torn entry at the end of {}: {}",
+                        path.display(),
+                        err
+                    );
+                }
+                Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Delete the segments before `index`, never the active one. Returns how many were deleted.
+    pub fn prune_before(&self, index: u64) -> io::Result<usize> {
+        let active = self.active_segment();
+        let mut pruned = 0;
+        for segment in self.segments()? {
+            if segment >= index || segment >= active {
+                break;
+            }
+            fs::remove_file(segment_path(&self.dir, segment))?;
+            pruned += 1;
+        }
+        Ok(pruned)
+    }
+
+    fn rotate_locked(&self, active: &mut ActiveSegment) -> io::Result<()> {
+        active.writer.flush()?;
+        active.writer.get_ref().sync_data()?;
+        *active = ActiveSegment::create(&self.dir, active.index + 1)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Journal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Journal")
+            .field("dir", &self.dir)
+            .field("max_segment_bytes", &self.max_segment_bytes)
+            .field("active_segment", &self.active_segment())
+            .finish()
+    }
+}
+
+impl ActiveSegment {
+    fn create(dir: &Path, index: u64) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(segment_path(dir, index))?;
+        let bytes = file.metadata()?.len();
+        Ok(Self {
+            index,
+            writer: BufWriter::new(file),
+            bytes,
+        })
+    }
+}
+
+fn segment_path(dir: &Path, index: u64) -> PathBuf {
+    dir.join(format!("{SEGMENT_PREFIX}{index:010}.{SEGMENT_EXTENSION}"))
+}
+
+fn list_segments(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let index = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(SEGMENT_PREFIX))
+            .and_then(|name| name.strip_suffix(SEGMENT_EXTENSION))
+            .and_then(|name| name.strip_suffix('.'))
+            .and_then(|index| index.parse().ok());
+        if let Some(index) = index {
+            segments.push(index);
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
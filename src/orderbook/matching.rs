@@ -1,12 +1,14 @@
 //! Contains the core matching engine logic for the order book.
 
+use crate::orderbook::events::{BookEvent, PendingEvents};
 use crate::orderbook::pool::MatchingPool;
-use crate::{OrderBook, OrderBookError};
-use pricelevel::{MatchResult, OrderId, Side};
+use crate::{OrderBook, OrderBookError, TradeRecord};
+use pricelevel::{MatchResult, OrderId, Side, Transaction};
 use std::sync::atomic::Ordering;
 
 impl OrderBook {
-    /// Highly optimized internal matching function
+    /// Match an incoming order against the opposite side of the book, as one step of
+    /// its sequence
     pub fn match_order(
         &self,
         order_id: OrderId,
@@ -14,7 +16,17 @@ impl OrderBook {
         quantity: u64,
         limit_price: Option<u64>,
     ) -> Result<MatchResult, OrderBookError> {
-        self.cache.invalidate();
+        self.sequenced(|| self.match_against_book(order_id, side, quantity, limit_price))
+    }
+
+    /// Highly optimized internal matching function
+    pub(super) fn match_against_book(
+        &self,
+        order_id: OrderId,
+        side: Side,
+        quantity: u64,
+        limit_price: Option<u64>,
+    ) -> Result<MatchResult, OrderBookError> {
         let mut match_result = MatchResult::new(order_id, quantity);
         let mut remaining_quantity = quantity;
 
@@ -26,13 +38,6 @@ impl OrderBook {
 
         // Early exit if the opposite side is empty
         if match_side.is_empty() {
-            if limit_price.is_none() {
-                return Err(OrderBookError::InsufficientLiquidity {
-                    side,
-                    requested: quantity,
-                    available: 0,
-                });
-            }
             match_result.remaining_quantity = remaining_quantity;
             return Ok(match_result);
         }
@@ -93,8 +98,55 @@ impl OrderBook {
                 self.last_trade_price.store(price, Ordering::Relaxed);
                 self.has_traded.store(true, Ordering::Relaxed);
 
-                // Add transactions to result
+                // Trades are stamped by the book clock rather than the wall clock
+                let now = self.now_millis();
+
+                // Add transactions to result and update the state of both counterparties
                 for transaction in price_level_match.transactions.as_vec() {
+                    let transaction = &Transaction {
+                        timestamp: now,
+                        ..*transaction
+                    };
+                    self.order_states.record_fill(
+                        &transaction.maker_order_id,
+                        transaction.price,
+                        transaction.quantity,
+                        transaction.timestamp,
+                    );
+                    self.order_states.record_fill(
+                        &transaction.taker_order_id,
+                        transaction.price,
+                        transaction.quantity,
+                        transaction.timestamp,
+                    );
+                    self.candles.record_trade(
+                        transaction.price,
+                        transaction.quantity,
+                        transaction.timestamp,
+                    );
+                    self.trade_stats.record(
+                        transaction.price,
+                        transaction.quantity,
+                        transaction.timestamp,
+                    );
+                    self.trade_tape.push(TradeRecord::from(transaction));
+                    if self.events.is_active() {
+                        // Published once the mutation is over, under its sequence number
+                        PendingEvents::record(|pending| {
+                            pending.events.push(BookEvent::Trade {
+                                symbol: self.symbol.clone(),
+                                sequence: pending.sequence,
+                                trade: TradeRecord::from(transaction),
+                            })
+                        });
+                    }
+                    // A maker that is not completely filled goes back to the end of the queue
+                    if !price_level_match
+                        .filled_order_ids
+                        .contains(&transaction.maker_order_id)
+                    {
+                        self.queue_priorities.push_back(transaction.maker_order_id);
+                    }
                     match_result.add_transaction(*transaction);
                 }
             }
@@ -124,12 +176,13 @@ impl OrderBook {
 
         // Batch remove empty price levels
         for price in &empty_price_levels {
-            match_side.remove(price);
+            self.remove_empty_level(side.opposite(), *price);
         }
 
         // Batch remove filled orders from tracking
         for order_id in &filled_orders {
             self.order_locations.remove(order_id);
+            self.queue_priorities.remove(order_id);
         }
 
         // Return vectors to pool for reuse
@@ -139,15 +192,6 @@ impl OrderBook {
             pool.return_price_vec(sorted_prices);
         });
 
-        // Check for insufficient liquidity in market orders
-        if limit_price.is_none() && remaining_quantity == quantity {
-            return Err(OrderBookError::InsufficientLiquidity {
-                side,
-                requested: quantity,
-                available: 0,
-            });
-        }
-
         // Set final result properties
         match_result.remaining_quantity = remaining_quantity;
         match_result.is_complete = remaining_quantity == 0;
@@ -1,18 +1,43 @@
 //! OrderBook implementation for managing multiple price levels and order matching.
 
+pub mod analytics;
 pub mod book;
+pub mod candles;
+pub mod checkpoint;
 pub mod error;
+pub mod events;
+pub mod execution;
+pub mod invariants;
+pub mod journal;
 pub mod matching;
 
 mod cache;
 /// Contains the core logic for modifying the order book state, such as adding, canceling, or updating orders.
 pub mod modifications;
 pub mod operations;
+pub mod order_state;
 mod pool;
 mod private;
+mod queue;
+pub mod quote;
+pub mod sequencer;
 pub mod snapshot;
 mod tests;
+pub mod trades;
 
 pub use book::OrderBook;
+pub use candles::{Candle, CandleAggregator, CandleInterval};
+pub use checkpoint::{BookState, Checkpoint, CheckpointConfig, CheckpointManager, RecoveryReport};
 pub use error::OrderBookError;
-pub use snapshot::OrderBookSnapshot;
+pub use events::{BookEvent, EventSink};
+pub use execution::{CancelledRemainder, ExecutionReport, FeeSchedule, Fill};
+pub use invariants::{InvariantReport, InvariantViolation, LevelTotals};
+pub use journal::{Journal, JournalEntry, JournalOutcome};
+pub use order_state::{OrderOwner, OrderRecord, OrderStatus, TerminalReason};
+pub use quote::{Liquidity, MarketImpact, NotionalQuote};
+pub use sequencer::{
+    BookView, Command, CommandOutcome, CommandResult, SequencedResult, Sequencer, SequencerConfig,
+    Ticket,
+};
+pub use snapshot::{AggregatedDepth, DepthBucket, OrderBookSnapshot};
+pub use trades::{RollingTradeStats, TradeRecord, TradeStats, TradeTape};
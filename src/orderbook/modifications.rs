@@ -1,8 +1,13 @@
 use crate::orderbook::book::OrderBook;
 use crate::orderbook::error::OrderBookError;
+use crate::orderbook::events::PendingEvents;
+use crate::orderbook::execution::{CancelledRemainder, ExecutionReport};
+use crate::orderbook::order_state::{OrderOwner, OrderRecord, OrderStatus, TerminalReason};
+use dashmap::mapref::entry::Entry;
 use pricelevel::{OrderId, OrderType, OrderUpdate, PriceLevel, Side};
 use std::sync::Arc;
-use tracing::trace;
+use tracing::{trace, warn};
+use uuid::Uuid;
 
 /// A trait to abstract quantity access and modification for different order types.
 pub trait OrderQuantity {
@@ -103,104 +108,79 @@ impl OrderQuantity for OrderType {
 }
 
 impl OrderBook {
-    /// Update an order's price and/or quantity
+    /// Update an order's price and/or quantity.
+    ///
+    /// Amendments follow these queue priority rules:
+    /// - reducing the quantity of an order without changing its price keeps its position
+    ///   in the queue of its price level;
+    /// - increasing the quantity, changing the price or replacing the order sends it to the
+    ///   back of the queue of its (new) price level, as if it had just been submitted.
+    ///
+    /// Returns the execution report of the amended order, with the fills of an amendment
+    /// that crossed the book, or `None` if the order was not found.
     pub fn update_order(
         &self,
         update: OrderUpdate,
-    ) -> Result<Option<Arc<OrderType>>, OrderBookError> {
-        self.cache.invalidate();
+    ) -> Result<Option<ExecutionReport>, OrderBookError> {
+        self.sequenced(|| self.apply_update(update))
+    }
+
+    /// Apply an amendment, see `update_order`
+    fn apply_update(&self, update: OrderUpdate) -> Result<Option<ExecutionReport>, OrderBookError> {
         trace!("Order book {}: Updating order {:?}", self.symbol, update);
+        // An order of zero quantity would stay in the book with its record open: orders are
+        // taken out by cancelling them
+        if let OrderUpdate::UpdateQuantity {
+            new_quantity: 0, ..
+        }
+        | OrderUpdate::UpdatePriceAndQuantity {
+            new_quantity: 0, ..
+        }
+        | OrderUpdate::Replace { quantity: 0, .. } = update
+        {
+            return Err(OrderBookError::InvalidOperation {
+                message: "Cannot update quantity to zero, cancel the order instead".to_string(),
+            });
+        }
         match update {
             OrderUpdate::UpdatePrice {
                 order_id,
                 new_price,
             } => {
-                // Get the order location without locking
-                let location = self.order_locations.get(&order_id).map(|val| *val);
-
-                if let Some((old_price, _)) = location {
-                    // If price doesn't change, do nothing
-                    if old_price == new_price {
-                        return Err(OrderBookError::InvalidOperation {
-                            message: "Cannot update price to the same value".to_string(),
-                        });
-                    }
-
-                    // Get the original order without holding locks
-                    let original_order = if let Some(order) = self.get_order(order_id) {
-                        // Create a copy of the order
-                        Arc::try_unwrap(order.clone()).unwrap_or_else(|arc| (*arc))
-                    } else {
-                        return Ok(None); // Order not found
-                    };
-
-                    // Cancel the original order
-                    self.cancel_order(order_id)?;
-
-                    // Create a new order with the updated price
-                    let mut new_order = original_order;
-
-                    // Update the price based on order type
-                    match &mut new_order {
-                        OrderType::Standard { price, .. } => *price = new_price,
-                        OrderType::IcebergOrder { price, .. } => *price = new_price,
-                        OrderType::PostOnly { price, .. } => *price = new_price,
-                        OrderType::TrailingStop { price, .. } => *price = new_price,
-                        OrderType::PeggedOrder { price, .. } => *price = new_price,
-                        OrderType::MarketToLimit { price, .. } => *price = new_price,
-                        OrderType::ReserveOrder { price, .. } => *price = new_price,
-                    }
-
-                    // Add the updated order
-                    let result = self.add_order(new_order)?;
-                    Ok(Some(result))
-                } else {
-                    Ok(None) // Order not found
+                // Get the order without holding locks
+                let original_order = match self.get_order(order_id) {
+                    Some(order) => *order,
+                    None => return Ok(None), // Order not found
+                };
+
+                // If price doesn't change, do nothing
+                if original_order.price() == new_price {
+                    return Err(OrderBookError::InvalidOperation {
+                        message: "Cannot update price to the same value".to_string(),
+                    });
                 }
+
+                let mut new_order = original_order;
+                set_price(&mut new_order, new_price);
+                self.requeue_order(new_order)
             }
 
             OrderUpdate::UpdateQuantity {
                 order_id,
                 new_quantity,
             } => {
-                // Get order location without locking
-                let location = self.order_locations.get(&order_id).map(|val| *val);
-
-                if let Some((price, side)) = location {
-                    // Get the appropriate price levels map
-                    let price_levels = match side {
-                        Side::Buy => &self.bids,
-                        Side::Sell => &self.asks,
-                    };
+                let original_order = match self.get_order(order_id) {
+                    Some(order) => *order,
+                    None => return Ok(None), // Order not found
+                };
 
-                    // Use entry() to safely modify the price level without deadlocks
-                    let mut result = None;
-                    let mut is_empty = false;
-
-                    price_levels.entry(price).and_modify(|price_level| {
-                        // Create update operation
-                        let update = OrderUpdate::UpdateQuantity {
-                            order_id,
-                            new_quantity,
-                        };
-
-                        // Try to update the order
-                        if let Ok(updated_order) = price_level.update_order(update) {
-                            result = updated_order;
-                            is_empty = price_level.order_count() == 0;
-                        }
-                        self.cache.invalidate();
-                    });
-
-                    // If the price level is now empty, remove it
-                    if is_empty {
-                        price_levels.remove(&price);
-                        self.order_locations.remove(&order_id);
-                    }
-
-                    Ok(result)
+                if new_quantity > original_order.visible_quantity() {
+                    // Increasing the quantity loses time priority
+                    let mut new_order = original_order;
+                    set_visible_quantity(&mut new_order, new_quantity);
+                    self.requeue_order(new_order)
                 } else {
-                    Ok(None) // Order not found
+                    Ok(self.reduce_order_quantity(order_id, new_quantity))
                 }
             }
 
@@ -209,122 +189,36 @@ impl OrderBook {
                 new_price,
                 new_quantity,
             } => {
-                // Get order location without locking
-                let location = self.order_locations.get(&order_id).map(|val| *val);
-
-                if let Some((_, _)) = location {
-                    // Get the original order without holding locks
-                    let original_order = if let Some(order) = self.get_order(order_id) {
-                        // Create a copy of the order
-                        Arc::try_unwrap(order.clone()).unwrap_or_else(|arc| (*arc))
-                    } else {
-                        return Ok(None); // Order not found
-                    };
-
-                    // Cancel the original order
-                    self.cancel_order(order_id)?;
-
-                    // Create a new order with the updated price and quantity
-                    let mut new_order = original_order;
-
-                    // Update the price and quantity based on order type
-                    match &mut new_order {
-                        OrderType::Standard {
-                            price, quantity, ..
-                        } => {
-                            *price = new_price;
-                            *quantity = new_quantity;
-                        }
-                        OrderType::IcebergOrder {
-                            price,
-                            visible_quantity,
-                            ..
-                        } => {
-                            *price = new_price;
-                            *visible_quantity = new_quantity;
-                        }
-                        OrderType::PostOnly {
-                            price, quantity, ..
-                        } => {
-                            *price = new_price;
-                            *quantity = new_quantity;
-                        }
-                        OrderType::TrailingStop {
-                            price, quantity, ..
-                        } => {
-                            *price = new_price;
-                            *quantity = new_quantity;
-                        }
-                        OrderType::PeggedOrder {
-                            price, quantity, ..
-                        } => {
-                            *price = new_price;
-                            *quantity = new_quantity;
-                        }
-                        OrderType::MarketToLimit {
-                            price, quantity, ..
-                        } => {
-                            *price = new_price;
-                            *quantity = new_quantity;
-                        }
-                        OrderType::ReserveOrder {
-                            price,
-                            visible_quantity,
-                            ..
-                        } => {
-                            *price = new_price;
-                            *visible_quantity = new_quantity;
-                        }
-                    }
-
-                    // Add the updated order
-                    let result = self.add_order(new_order)?;
-                    Ok(Some(result))
-                } else {
-                    Ok(None) // Order not found
+                let original_order = match self.get_order(order_id) {
+                    Some(order) => *order,
+                    None => return Ok(None), // Order not found
+                };
+
+                // Without a price change this is a plain quantity amendment
+                if original_order.price() == new_price {
+                    return self.apply_update(OrderUpdate::UpdateQuantity {
+                        order_id,
+                        new_quantity,
+                    });
                 }
+
+                let mut new_order = original_order;
+                set_price(&mut new_order, new_price);
+                set_visible_quantity(&mut new_order, new_quantity);
+                self.requeue_order(new_order)
             }
 
             OrderUpdate::Cancel { order_id } => {
-                // Get order location without locking
-                let location = self.order_locations.get(&order_id).map(|val| *val);
-
-                if let Some((price, side)) = location {
-                    // Get the appropriate price levels map
-                    let price_levels = match side {
-                        Side::Buy => &self.bids,
-                        Side::Sell => &self.asks,
-                    };
-
-                    // Use entry() to safely modify the price level
-                    let mut result = None;
-                    let mut is_empty = false;
-
-                    price_levels.entry(price).and_modify(|price_level| {
-                        // Create cancel operation
-                        let update = OrderUpdate::Cancel { order_id };
-
-                        // Try to cancel the order
-                        if let Ok(cancelled_order) = price_level.update_order(update) {
-                            result = cancelled_order;
-                            is_empty = price_level.order_count() == 0;
-                        }
-                    });
-
-                    // If we cancelled an order, remove it from tracking
-                    if result.is_some() {
-                        self.order_locations.remove(&order_id);
-
-                        // If price level is empty, remove it
-                        if is_empty {
-                            price_levels.remove(&price);
-                        }
-                    }
-
-                    Ok(result)
-                } else {
-                    Ok(None) // Order not found
+                let cancelled = self.remove_order(order_id)?;
+                if cancelled.is_some() {
+                    self.order_states.finish(
+                        &order_id,
+                        OrderStatus::Cancelled,
+                        TerminalReason::Cancelled,
+                        self.now_millis(),
+                    );
                 }
+                Ok(cancelled.map(|order| ExecutionReport::cancelled(&order)))
             }
 
             OrderUpdate::Replace {
@@ -397,12 +291,7 @@ impl OrderBook {
                         }
                     };
 
-                    // Cancel the original order
-                    self.cancel_order(order_id)?;
-
-                    // Add the new order
-                    let result = self.add_order(new_order)?;
-                    Ok(Some(result))
+                    self.requeue_order(new_order)
                 } else {
                     Ok(None) // Original order not found
                 }
@@ -415,7 +304,25 @@ impl OrderBook {
         &self,
         order_id: OrderId,
     ) -> Result<Option<Arc<OrderType>>, OrderBookError> {
-        self.cache.invalidate();
+        self.sequenced(|| {
+            let result = self.remove_order(order_id)?;
+            if result.is_some() {
+                self.order_states.finish(
+                    &order_id,
+                    OrderStatus::Cancelled,
+                    TerminalReason::Cancelled,
+                    self.now_millis(),
+                );
+            }
+            Ok(result)
+        })
+    }
+
+    /// Remove a resting order from the book without touching its lifecycle state
+    pub(super) fn remove_order(
+        &self,
+        order_id: OrderId,
+    ) -> Result<Option<Arc<OrderType>>, OrderBookError> {
         // First, we find the order's location (price and side) without locking
         let location = self.order_locations.get(&order_id).map(|val| *val);
 
@@ -443,15 +350,15 @@ impl OrderBook {
                 }
             });
 
-            self.cache.invalidate();
             // If we got a result and the order was canceled
             if result.is_some() {
                 // Remove the order from the locations map
                 self.order_locations.remove(&order_id);
+                self.queue_priorities.remove(&order_id);
 
                 // If the level became empty, remove it
                 if empty_level {
-                    price_levels.remove(&price);
+                    self.remove_empty_level(side, price);
                 }
             }
 
@@ -462,9 +369,324 @@ impl OrderBook {
     }
 
     /// Add a new order to the book, automatically matching it if it's aggressive.
-    pub fn add_order(&self, mut order: OrderType) -> Result<Arc<OrderType>, OrderBookError> {
-        self.cache.invalidate();
+    pub fn add_order(&self, order: OrderType) -> Result<ExecutionReport, OrderBookError> {
+        self.sequenced(|| {
+            self.order_states
+                .insert(OrderRecord::from_order(&order, self.now_millis()));
+            self.submit_registered_order(order)
+        })
+    }
+
+    /// Add a new order on behalf of an account. If the owner carries a client order id,
+    /// it must not be used by another live order of the same account.
+    pub fn add_order_with_owner(
+        &self,
+        order: OrderType,
+        owner: OrderOwner,
+    ) -> Result<ExecutionReport, OrderBookError> {
+        self.sequenced(|| {
+            self.order_states
+                .register(OrderRecord::from_order(&order, self.now_millis()).with_owner(owner))?;
+            self.submit_registered_order(order)
+        })
+    }
+
+    /// Find the id of a live order from the client order id its account assigned to it
+    pub fn find_order_id(&self, account: Uuid, client_order_id: &str) -> Option<OrderId> {
+        self.order_states
+            .find_by_client_order_id(account, client_order_id)
+    }
+
+    /// Cancel a live order identified by its account and client order id
+    pub fn cancel_order_by_client_id(
+        &self,
+        account: Uuid,
+        client_order_id: &str,
+    ) -> Result<Option<Arc<OrderType>>, OrderBookError> {
+        match self.find_order_id(account, client_order_id) {
+            Some(order_id) => self.cancel_order(order_id),
+            None => Ok(None),
+        }
+    }
+
+    /// Cancel every order of `account` resting in the book, as a single mutation.
+    /// Returns the ids of the cancelled orders, oldest first.
+    ///
+    /// An order that cannot be removed is logged and left out, so that it does not keep the
+    /// other orders of the account in the book.
+    pub fn cancel_account_orders(&self, account: Uuid) -> Result<Vec<OrderId>, OrderBookError> {
+        self.sequenced(|| {
+            let timestamp = self.now_millis();
+            let mut cancelled = Vec::new();
+            for order_id in self.order_states.live_orders_of(account) {
+                match self.remove_order(order_id) {
+                    Ok(Some(_)) => {
+                        self.order_states.finish(
+                            &order_id,
+                            OrderStatus::Cancelled,
+                            TerminalReason::Cancelled,
+                            timestamp,
+                        );
+                        cancelled.push(order_id);
+                    }
+                    Ok(None) => {}
+                    Err(err) => warn!(
+                        "Order book {}: order {} of account {} could not be cancelled: {}",
+                        self.symbol, order_id, account, err
+                    ),
+                }
+            }
+            Ok(cancelled)
+        })
+    }
+
+    /// Put an order accepted in an earlier run back in the book without matching it, to
+    /// rebuild a book from storage. `order` carries the remaining quantity and `record` the
+    /// recorded state of the order.
+    ///
+    /// Orders must be reloaded in their original entry order to keep their time priority.
+    /// An order that would cross the book, or whose time in force has elapsed, is refused
+    /// and left out of the book; its record is kept as rejected or expired.
+    pub fn reload_order(
+        &self,
+        order: OrderType,
+        record: OrderRecord,
+    ) -> Result<(), OrderBookError> {
+        self.sequenced(|| {
+            let (order_id, price, side) = (order.id(), order.price(), order.side());
+            if record.order_id != order_id {
+                return Err(OrderBookError::InvalidOperation {
+                    message: format!(
+                        "Record of {} does not belong to order {}",
+                        record.order_id, order_id
+                    ),
+                });
+            }
+            if self.order_locations.contains_key(&order_id) {
+                return Err(OrderBookError::InvalidOperation {
+                    message: format!("Order {order_id} is already in the book"),
+                });
+            }
+            let refusal = if self.has_expired(&order) {
+                Some((
+                    OrderStatus::Expired,
+                    TerminalReason::Expired,
+                    OrderBookError::InvalidOperation {
+                        message: "Order has already expired".to_string(),
+                    },
+                ))
+            } else if self.will_cross_market(price, side) {
+                let opposite_price = match side {
+                    Side::Buy => self.best_ask(),
+                    Side::Sell => self.best_bid(),
+                };
+                let err = OrderBookError::PriceCrossing {
+                    price,
+                    side,
+                    opposite_price: opposite_price.unwrap_or(price),
+                };
+                Some((OrderStatus::Rejected, TerminalReason::from(&err), err))
+            } else {
+                None
+            };
+            if let Some((status, reason, err)) = refusal {
+                // Keep the refusal on record, so that storage learns the order is closed
+                let mut record = record;
+                record.status = status;
+                record.reason = Some(reason);
+                record.updated_at = self.now_millis();
+                self.order_states.insert(record);
+                return Err(err);
+            }
+
+            self.order_states.register(record)?;
+            self.place_order_in_book(Arc::new(order))?;
+            Ok(())
+        })
+    }
+
+    /// Process an order whose lifecycle record has already been created
+    fn submit_registered_order(&self, order: OrderType) -> Result<ExecutionReport, OrderBookError> {
+        let order_id = order.id();
+        let result = self.process_order(order);
+        if let Err(ref err) = result {
+            self.reject_order(&order_id, err);
+        }
+        result
+    }
+
+    /// Reduce the quantity of a resting order in place, keeping its queue position.
+    ///
+    /// The level is rebuilt with the reduced order in its own queue slot: amending it through
+    /// the level would leave the old slot and push a second one at the back of the queue.
+    fn reduce_order_quantity(
+        &self,
+        order_id: OrderId,
+        new_quantity: u64,
+    ) -> Option<ExecutionReport> {
+        // Get order location without locking
+        let (price, side) = self.order_locations.get(&order_id).map(|val| *val)?;
+
+        // Get the appropriate price levels map
+        let price_levels = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+
+        let mut result = None;
+        if let Some(mut price_level) = price_levels.get_mut(&price) {
+            let original = price_level
+                .iter_orders()
+                .into_iter()
+                .find(|order| order.id() == order_id);
+
+            if let Some(original) = original {
+                let reduced = original.with_reduced_quantity(new_quantity);
+                let rebuilt = self
+                    .queue_priorities
+                    .rebuild_replacing(&price_level, reduced);
+                *price_level = Arc::new(rebuilt);
+                result = Some(Arc::new(reduced));
+            }
+        }
+
+        let updated_order = result?;
+        self.order_states.amend(&updated_order, self.now_millis());
+        Some(ExecutionReport::resting(
+            updated_order,
+            self.amended_status(order_id),
+        ))
+    }
+
+    /// Atomically cancel a resting order and replace it with the amended `order`, which joins
+    /// the back of the queue of its price level. The lifecycle record is kept, so fills before
+    /// and after the amendment accumulate.
+    ///
+    /// If the replacement is rejected (it has expired, a post-only order would cross, a
+    /// fill-or-kill order cannot be filled) the original order is restored untouched at its
+    /// original queue position and the error is returned.
+    fn requeue_order(
+        &self,
+        mut order: OrderType,
+    ) -> Result<Option<ExecutionReport>, OrderBookError> {
+        let order_id = order.id();
+        // The original is taken out before validating, as it may be the very order
+        // a replacement on the other side would cross
+        let (original, sequence) = match self.detach_order(order_id) {
+            Some(detached) => detached,
+            None => return Ok(None), // Order not found, removed by another thread
+        };
+
+        set_timestamp(&mut order, self.now_millis());
+        if let Err(err) = self.validate_order(&order) {
+            trace!(
+                "Order book {}: Replacement of order {} rejected, restoring original",
+                self.symbol, order_id
+            );
+            self.restore_order(original, sequence);
+            return Err(err);
+        }
+
+        self.order_states.amend(&order, self.now_millis());
+
+        match self.execute_order(order) {
+            Ok(mut report) => {
+                // The status accounts for the fills before the amendment too
+                report.status = self.amended_status(order_id);
+                Ok(Some(report))
+            }
+            Err(err) => {
+                self.order_states.finish(
+                    &order_id,
+                    OrderStatus::Cancelled,
+                    TerminalReason::from(&err),
+                    self.now_millis(),
+                );
+                Err(err)
+            }
+        }
+    }
+
+    /// Status of an amended order, from its lifecycle record
+    fn amended_status(&self, order_id: OrderId) -> OrderStatus {
+        self.order_states
+            .get(&order_id)
+            .map_or(OrderStatus::New, |record| record.status)
+    }
+
+    /// Remove a resting order by rebuilding its price level without it, so the level
+    /// keeps no queue slot for the order if it comes back at the same price.
+    /// Returns the order and its queue sequence.
+    fn detach_order(&self, order_id: OrderId) -> Option<(Arc<OrderType>, Option<u64>)> {
+        let (price, side) = self.order_locations.get(&order_id).map(|val| *val)?;
+
+        let price_levels = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+
+        let mut result = None;
+        let mut empty_level = false;
+
+        if let Some(mut price_level) = price_levels.get_mut(&price) {
+            result = price_level
+                .iter_orders()
+                .into_iter()
+                .find(|order| order.id() == order_id);
+
+            if result.is_some() {
+                let rebuilt = self
+                    .queue_priorities
+                    .rebuild_without(&price_level, order_id);
+                empty_level = rebuilt.order_count() == 0;
+                *price_level = Arc::new(rebuilt);
+            }
+        }
+
+        let order = result?;
+        self.order_locations.remove(&order_id);
+        let sequence = self.queue_priorities.remove(&order_id);
+
+        if empty_level {
+            self.remove_empty_level(side, price);
+        }
 
+        Some((order, sequence))
+    }
+
+    /// Put back an order taken out by `detach_order` at its original queue position
+    fn restore_order(&self, order: Arc<OrderType>, sequence: Option<u64>) {
+        let (order_id, price, side) = (order.id(), order.price(), order.side());
+
+        // Indexed before the order is visible in its level, so a match cannot fill it and
+        // drop it from the indexes before it was added to them
+        match sequence {
+            Some(sequence) => self.queue_priorities.restore(order_id, sequence),
+            None => self.queue_priorities.push_back(order_id),
+        }
+        self.order_locations.insert(order_id, (price, side));
+
+        let price_levels = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+
+        match price_levels.entry(price) {
+            Entry::Occupied(mut entry) => {
+                let rebuilt = self.queue_priorities.rebuild_with(entry.get(), *order);
+                entry.insert(Arc::new(rebuilt));
+            }
+            Entry::Vacant(entry) => {
+                let price_level = PriceLevel::new(price);
+                price_level.add_order(*order);
+                self.cache.level_added(side, price);
+                entry.insert(Arc::new(price_level));
+            }
+        }
+    }
+
+    /// Match an order against the book and rest any remainder, without registering it
+    fn process_order(&self, order: OrderType) -> Result<ExecutionReport, OrderBookError> {
         trace!(
             "Order book {}: Adding order {} at price {}",
             self.symbol,
@@ -472,89 +694,126 @@ impl OrderBook {
             order.price()
         );
 
-        if self.has_expired(&order) {
-            return Err(OrderBookError::InvalidOperation {
-                message: "Order has already expired".to_string(),
-            });
-        }
-
-        if order.is_post_only() && self.will_cross_market(order.price(), order.side()) {
-            return Err(OrderBookError::PriceCrossing {
-                price: order.price(),
-                side: order.side(),
-                opposite_price: if order.side() == Side::Buy {
-                    self.best_ask().unwrap_or(0)
-                } else {
-                    self.best_bid().unwrap_or(0)
-                },
-            });
-        }
-
-        // For FOK orders, first check if the entire quantity can be matched without altering the book.
-        if order.is_fill_or_kill() {
-            let potential_match =
-                self.peek_match(order.side(), order.total_quantity(), Some(order.price()));
-            if potential_match < order.total_quantity() {
-                return Err(OrderBookError::InsufficientLiquidity {
-                    side: order.side(),
-                    requested: order.total_quantity(),
-                    available: potential_match,
-                });
+        if let Err(err) = self.validate_order(&order) {
+            // An order whose time in force has elapsed expires rather than being rejected
+            if self.has_expired(&order) {
+                self.order_states.finish(
+                    &order.id(),
+                    OrderStatus::Expired,
+                    TerminalReason::Expired,
+                    self.now_millis(),
+                );
             }
+            return Err(err);
         }
 
-        self.cache.invalidate();
+        self.execute_order(order)
+    }
+
+    /// Match an order that passed validation and rest any remainder
+    fn execute_order(&self, mut order: OrderType) -> Result<ExecutionReport, OrderBookError> {
         // Attempt to match the order immediately
-        let match_result = self.match_order(
+        let match_result = self.match_against_book(
             order.id(),
             order.side(),
             order.total_quantity(), // Use total quantity for matching
             Some(order.price()),
         )?;
 
-        if !match_result.transactions.transactions.is_empty()
-            && let Some(ref listener) = self.trade_listener
-        {
-            listener(&match_result) // emit trade events to listener
+        if !match_result.transactions.transactions.is_empty() && self.trade_listener.is_some() {
+            // The listener is called once the mutation is over
+            PendingEvents::record(|pending| pending.matches.push(match_result.clone()));
         }
 
+        let mut resting = None;
+        let mut cancelled = None;
+
         // If the order was not fully filled, add the remainder to the book
         if match_result.remaining_quantity > 0 {
             if order.is_immediate() {
                 // IOC/FOK orders should not have a resting part.
                 // If FOK, it should have been fully filled or cancelled before this point.
                 // If IOC, this is the remaining part that couldn't be filled, so we just drop it.
-                return Err(OrderBookError::InsufficientLiquidity {
-                    side: order.side(),
-                    requested: order.quantity(), // Now uses the trait method
-                    available: order.quantity() - match_result.remaining_quantity,
+                self.order_states.finish(
+                    &order.id(),
+                    OrderStatus::Cancelled,
+                    TerminalReason::NoLiquidity,
+                    self.now_millis(),
+                );
+                cancelled = Some(CancelledRemainder {
+                    quantity: match_result.remaining_quantity,
+                    reason: TerminalReason::NoLiquidity,
+                });
+            } else {
+                // Update the order with the remaining quantity
+                order.set_quantity(match_result.remaining_quantity); // Now uses the trait method
+
+                let price = order.price();
+                let side = order.side();
+
+                // Indexed while the level is locked, so a concurrent match cannot fill the
+                // order and drop it from the indexes before it was added to them
+                let order_arc = self.with_price_level(side, price, |price_level| {
+                    let order_arc = price_level.add_order(order);
+                    self.order_locations.insert(order_arc.id(), (price, side));
+                    self.queue_priorities.push_back(order_arc.id());
+                    order_arc
                 });
+                resting = Some(order_arc);
             }
+        }
 
-            // Update the order with the remaining quantity
-            order.set_quantity(match_result.remaining_quantity); // Now uses the trait method
-
-            let price = order.price();
-            let side = order.side();
-
-            let price_levels = match side {
-                Side::Buy => &self.bids,
-                Side::Sell => &self.asks,
-            };
-
-            let price_level = price_levels
-                .entry(price)
-                .or_insert_with(|| Arc::new(PriceLevel::new(price)));
+        Ok(ExecutionReport::new(
+            order.id(),
+            order.side(),
+            &match_result,
+            &self.fee_schedule,
+            resting,
+            cancelled,
+        ))
+    }
+}
 
-            let order_arc = price_level.add_order(order);
-            self.order_locations.insert(order_arc.id(), (price, side));
+/// Set the limit price of an order
+fn set_price(order: &mut OrderType, new_price: u64) {
+    match order {
+        OrderType::Standard { price, .. }
+        | OrderType::IcebergOrder { price, .. }
+        | OrderType::PostOnly { price, .. }
+        | OrderType::TrailingStop { price, .. }
+        | OrderType::PeggedOrder { price, .. }
+        | OrderType::MarketToLimit { price, .. }
+        | OrderType::ReserveOrder { price, .. } => *price = new_price,
+    }
+}
 
-            Ok(order_arc)
-        } else {
-            // The order was fully matched, create an Arc from the matched result
-            // Note: The original order object is consumed, but we can reconstruct its essence if needed.
-            // For now, we return a representation of the completed order.
-            Ok(Arc::new(order))
+/// Set the displayed quantity of an order; the hidden quantity of iceberg and reserve
+/// orders is left unchanged
+fn set_visible_quantity(order: &mut OrderType, new_quantity: u64) {
+    match order {
+        OrderType::Standard { quantity, .. }
+        | OrderType::PostOnly { quantity, .. }
+        | OrderType::TrailingStop { quantity, .. }
+        | OrderType::PeggedOrder { quantity, .. }
+        | OrderType::MarketToLimit { quantity, .. } => *quantity = new_quantity,
+        OrderType::IcebergOrder {
+            visible_quantity, ..
         }
+        | OrderType::ReserveOrder {
+            visible_quantity, ..
+        } => *visible_quantity = new_quantity,
+    }
+}
+
+/// Set the time an order entered the book
+fn set_timestamp(order: &mut OrderType, new_timestamp: u64) {
+    match order {
+        OrderType::Standard { timestamp, .. }
+        | OrderType::IcebergOrder { timestamp, .. }
+        | OrderType::PostOnly { timestamp, .. }
+        | OrderType::TrailingStop { timestamp, .. }
+        | OrderType::PeggedOrder { timestamp, .. }
+        | OrderType::MarketToLimit { timestamp, .. }
+        | OrderType::ReserveOrder { timestamp, .. } => *timestamp = new_timestamp,
     }
 }
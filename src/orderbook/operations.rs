@@ -2,8 +2,9 @@
 
 use super::book::OrderBook;
 use super::error::OrderBookError;
-use pricelevel::{MatchResult, OrderId, OrderType, Side, TimeInForce};
-use std::sync::Arc;
+use super::execution::{CancelledRemainder, ExecutionReport};
+use super::order_state::{OrderOwner, OrderRecord, OrderStatus, TerminalReason};
+use pricelevel::{OrderId, OrderType, Side, TimeInForce};
 use tracing::trace;
 
 impl OrderBook {
@@ -15,13 +16,13 @@ impl OrderBook {
         quantity: u64,
         side: Side,
         time_in_force: TimeInForce,
-    ) -> Result<Arc<OrderType>, OrderBookError> {
+    ) -> Result<ExecutionReport, OrderBookError> {
         let order = OrderType::Standard {
             id,
             price,
             quantity,
             side,
-            timestamp: crate::utils::current_time_millis(),
+            timestamp: self.now_millis(),
             time_in_force,
         };
         trace!(
@@ -40,14 +41,14 @@ impl OrderBook {
         hidden_quantity: u64,
         side: Side,
         time_in_force: TimeInForce,
-    ) -> Result<Arc<OrderType>, OrderBookError> {
+    ) -> Result<ExecutionReport, OrderBookError> {
         let order = OrderType::IcebergOrder {
             id,
             price,
             visible_quantity,
             hidden_quantity,
             side,
-            timestamp: crate::utils::current_time_millis(),
+            timestamp: self.now_millis(),
             time_in_force,
         };
         trace!(
@@ -65,13 +66,13 @@ impl OrderBook {
         quantity: u64,
         side: Side,
         time_in_force: TimeInForce,
-    ) -> Result<Arc<OrderType>, OrderBookError> {
+    ) -> Result<ExecutionReport, OrderBookError> {
         let order = OrderType::PostOnly {
             id,
             price,
             quantity,
             side,
-            timestamp: crate::utils::current_time_millis(),
+            timestamp: self.now_millis(),
             time_in_force,
         };
         trace!(
@@ -87,8 +88,77 @@ impl OrderBook {
         id: OrderId,
         quantity: u64,
         side: Side,
-    ) -> Result<MatchResult, OrderBookError> {
+    ) -> Result<ExecutionReport, OrderBookError> {
         trace!("Submitting market order {} {} {}", id, quantity, side);
-        self.match_market_order(id, quantity, side)
+        self.sequenced(|| {
+            self.order_states.insert(OrderRecord::new(
+                id,
+                side,
+                None,
+                quantity,
+                self.now_millis(),
+            ));
+            self.execute_market_order(id, quantity, side)
+        })
+    }
+
+    /// Submit a market order on behalf of an account
+    pub fn submit_market_order_with_owner(
+        &self,
+        id: OrderId,
+        quantity: u64,
+        side: Side,
+        owner: OrderOwner,
+    ) -> Result<ExecutionReport, OrderBookError> {
+        trace!(
+            "Submitting market order {} {} {} for account {}",
+            id, quantity, side, owner.account
+        );
+        self.sequenced(|| {
+            self.order_states.register(
+                OrderRecord::new(id, side, None, quantity, self.now_millis()).with_owner(owner),
+            )?;
+            self.execute_market_order(id, quantity, side)
+        })
+    }
+
+    fn execute_market_order(
+        &self,
+        id: OrderId,
+        quantity: u64,
+        side: Side,
+    ) -> Result<ExecutionReport, OrderBookError> {
+        let match_result = match self.match_against_book(id, side, quantity, None) {
+            Ok(match_result) => match_result,
+            Err(err) => {
+                self.reject_order(&id, &err);
+                return Err(err);
+            }
+        };
+
+        // A market order never rests, whatever could not be executed is cancelled
+        let cancelled = if match_result.remaining_quantity > 0 {
+            self.order_states.finish(
+                &id,
+                OrderStatus::Cancelled,
+                TerminalReason::NoLiquidity,
+                self.now_millis(),
+            );
+            Some(CancelledRemainder {
+                quantity: match_result.remaining_quantity,
+                reason: TerminalReason::NoLiquidity,
+            })
+        } else {
+            None
+        };
+
+        Ok(ExecutionReport::new(
+            id,
+            side,
+            &match_result,
+            &self.fee_schedule,
+            None,
+            cancelled,
+        ))
     }
 }
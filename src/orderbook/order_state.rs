@@ -0,0 +1,430 @@
+//! Order state tracking for the full lifecycle of orders submitted to the book
+
+use dashmap::DashMap;
+use pricelevel::{OrderId, OrderType, Side};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use uuid::Uuid;
+
+use super::error::OrderBookError;
+use super::events::PendingEvents;
+use super::modifications::OrderQuantity;
+
+/// Default number of terminal (filled, cancelled, rejected or expired) orders kept in the store
+pub const DEFAULT_TERMINAL_ORDER_RETENTION: usize = 10_000;
+
+/// Lifecycle status of an order known to the book
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OrderStatus {
+    /// Accepted by the book and not yet executed
+    New,
+    /// Some, but not all, of the quantity has been executed
+    PartiallyFilled,
+    /// The whole quantity has been executed
+    Filled,
+    /// Removed from the book before being completely filled
+    Cancelled,
+    /// Refused by the book before it was accepted
+    Rejected,
+    /// The time in force elapsed before the order could rest in the book
+    Expired,
+}
+
+impl OrderStatus {
+    /// Returns true when no further state transitions are possible
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            OrderStatus::Filled
+                | OrderStatus::Cancelled
+                | OrderStatus::Rejected
+                | OrderStatus::Expired
+        )
+    }
+}
+
+/// Why an order reached a terminal status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TerminalReason {
+    /// The whole quantity was executed
+    Filled,
+    /// The order was cancelled on request
+    Cancelled,
+    /// There was not enough opposite liquidity for an immediate order (market, IOC or FOK)
+    NoLiquidity,
+    /// A post-only order would have crossed the book
+    WouldCross,
+    /// The time in force of the order had elapsed
+    Expired,
+    /// The order was refused for any other reason
+    Rejected,
+}
+
+impl From<&OrderBookError> for TerminalReason {
+    fn from(err: &OrderBookError) -> Self {
+        match err {
+            OrderBookError::InsufficientLiquidity { .. } => TerminalReason::NoLiquidity,
+            OrderBookError::PriceCrossing { .. } => TerminalReason::WouldCross,
+            _ => TerminalReason::Rejected,
+        }
+    }
+}
+
+/// The account owning an order and the identifier its client assigned to it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OrderOwner {
+    /// Account (user) that submitted the order
+    pub account: Uuid,
+    /// Client order id, unique per account among live orders
+    pub client_order_id: Option<String>,
+}
+
+impl OrderOwner {
+    /// Create an owner for `account` with an optional client order id
+    pub fn new(account: Uuid, client_order_id: Option<String>) -> Self {
+        Self {
+            account,
+            client_order_id,
+        }
+    }
+}
+
+/// The recorded state of a single order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderRecord {
+    /// Identifier of the order
+    pub order_id: OrderId,
+    /// Side of the order
+    pub side: Side,
+    /// Limit price of the order, `None` for market orders
+    pub price: Option<u64>,
+    /// Total quantity requested, including any hidden quantity
+    pub original_quantity: u64,
+    /// Cumulative executed quantity
+    pub filled_quantity: u64,
+    /// Cumulative executed value (sum of price * quantity of every fill)
+    pub filled_value: u128,
+    /// Current status
+    pub status: OrderStatus,
+    /// Reason for the terminal status, if the order is terminal
+    pub reason: Option<TerminalReason>,
+    /// Time the order was first seen by the book (milliseconds since epoch)
+    pub created_at: u64,
+    /// Time of the last state change (milliseconds since epoch)
+    pub updated_at: u64,
+    /// Owner of the order, if it was submitted on behalf of an account
+    pub owner: Option<OrderOwner>,
+}
+
+impl OrderRecord {
+    /// Create a new record for an order that has just been submitted
+    pub fn new(
+        order_id: OrderId,
+        side: Side,
+        price: Option<u64>,
+        quantity: u64,
+        timestamp: u64,
+    ) -> Self {
+        Self {
+            order_id,
+            side,
+            price,
+            original_quantity: quantity,
+            filled_quantity: 0,
+            filled_value: 0,
+            status: OrderStatus::New,
+            reason: None,
+            created_at: timestamp,
+            updated_at: timestamp,
+            owner: None,
+        }
+    }
+
+    /// Attach an owner to the record
+    pub fn with_owner(mut self, owner: OrderOwner) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    /// Create a new record from a limit order
+    pub fn from_order(order: &OrderType, timestamp: u64) -> Self {
+        Self::new(
+            order.id(),
+            order.side(),
+            Some(order.price()),
+            order.total_quantity(),
+            timestamp,
+        )
+    }
+
+    /// Quantity that has not been executed yet
+    pub fn remaining_quantity(&self) -> u64 {
+        self.original_quantity.saturating_sub(self.filled_quantity)
+    }
+
+    /// Volume-weighted average price of all fills, if any
+    pub fn average_fill_price(&self) -> Option<f64> {
+        if self.filled_quantity == 0 {
+            None
+        } else {
+            Some(self.filled_value as f64 / self.filled_quantity as f64)
+        }
+    }
+}
+
+/// Concurrent registry holding the state of every live order and a bounded
+/// history of terminal ones.
+pub struct OrderStateStore {
+    records: DashMap<OrderId, OrderRecord>,
+    client_order_ids: DashMap<(Uuid, String), OrderId>,
+    terminal_order: Mutex<VecDeque<OrderId>>,
+    retention: AtomicUsize,
+    /// Whether changed orders are recorded in the events of the running mutation
+    track_changes: AtomicBool,
+}
+
+impl OrderStateStore {
+    /// Create a store keeping at most `retention` terminal orders
+    pub fn new(retention: usize) -> Self {
+        Self {
+            records: DashMap::new(),
+            client_order_ids: DashMap::new(),
+            terminal_order: Mutex::new(VecDeque::new()),
+            retention: AtomicUsize::new(retention),
+            track_changes: AtomicBool::new(false),
+        }
+    }
+
+    /// Start recording which orders each mutation changes, in its pending events
+    pub(super) fn track_changes(&self) {
+        self.track_changes.store(true, Ordering::Release);
+    }
+
+    /// Record a change of `order_id` for the mutation running on this thread, so that
+    /// concurrent mutations only publish the orders they changed themselves
+    fn mark_changed(&self, order_id: OrderId) {
+        if self.track_changes.load(Ordering::Acquire) {
+            PendingEvents::record(|pending| pending.changed.push(order_id));
+        }
+    }
+
+    /// Number of terminal orders kept before the oldest are evicted
+    pub fn retention(&self) -> usize {
+        self.retention.load(Ordering::Relaxed)
+    }
+
+    /// Change the number of terminal orders kept, evicting immediately if needed
+    pub fn set_retention(&self, retention: usize) {
+        self.retention.store(retention, Ordering::Relaxed);
+        self.evict_terminal();
+    }
+
+    /// Get a copy of the record for an order
+    pub fn get(&self, order_id: &OrderId) -> Option<OrderRecord> {
+        self.records.get(order_id).map(|record| record.clone())
+    }
+
+    /// Copies of all the records: live orders first, then terminal orders from the oldest
+    pub fn records(&self) -> Vec<OrderRecord> {
+        let terminal: Vec<OrderId> = self
+            .terminal_order
+            .lock()
+            .map(|terminal| terminal.iter().copied().collect())
+            .unwrap_or_default();
+
+        let mut records: Vec<OrderRecord> = self
+            .records
+            .iter()
+            .filter(|record| !record.status.is_terminal())
+            .map(|record| record.clone())
+            .collect();
+        records.extend(
+            terminal
+                .iter()
+                .filter_map(|order_id| self.get(order_id))
+                .filter(|record| record.status.is_terminal()),
+        );
+        records
+    }
+
+    /// Number of records currently held
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns true if no records are held
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Insert (or replace) the record of an order
+    pub fn insert(&self, record: OrderRecord) {
+        let order_id = record.order_id;
+        let terminal = record.status.is_terminal();
+        let previous = self.records.insert(order_id, record);
+        self.mark_changed(order_id);
+        // A record that was terminal already holds its place in the eviction order
+        if terminal && !previous.is_some_and(|previous| previous.status.is_terminal()) {
+            self.push_terminal(order_id);
+        }
+    }
+
+    /// Insert the record of a newly submitted order, claiming its client order id.
+    /// Fails if the owner's account already has a live order with the same client order id.
+    pub fn register(&self, record: OrderRecord) -> Result<(), OrderBookError> {
+        if let Some(OrderOwner {
+            account,
+            client_order_id: Some(client_order_id),
+        }) = &record.owner
+        {
+            match self
+                .client_order_ids
+                .entry((*account, client_order_id.clone()))
+            {
+                dashmap::Entry::Occupied(entry) if *entry.get() != record.order_id => {
+                    return Err(OrderBookError::DuplicateClientOrderId {
+                        account: *account,
+                        client_order_id: client_order_id.clone(),
+                    });
+                }
+                dashmap::Entry::Occupied(_) => {}
+                dashmap::Entry::Vacant(entry) => {
+                    entry.insert(record.order_id);
+                }
+            }
+        }
+        self.insert(record);
+        Ok(())
+    }
+
+    /// Find the live order of `account` carrying `client_order_id`
+    pub fn find_by_client_order_id(&self, account: Uuid, client_order_id: &str) -> Option<OrderId> {
+        self.client_order_ids
+            .get(&(account, client_order_id.to_string()))
+            .map(|entry| *entry.value())
+    }
+
+    /// Ids of the live orders of `account`, oldest first
+    pub fn live_orders_of(&self, account: Uuid) -> Vec<OrderId> {
+        let mut orders: Vec<(u64, OrderId)> = self
+            .records
+            .iter()
+            .filter(|record| {
+                !record.status.is_terminal()
+                    && record
+                        .owner
+                        .as_ref()
+                        .is_some_and(|owner| owner.account == account)
+            })
+            .map(|record| (record.created_at, record.order_id))
+            .collect();
+        orders.sort_by_key(|(created_at, _)| *created_at);
+        orders.into_iter().map(|(_, order_id)| order_id).collect()
+    }
+
+    /// Record an execution of `quantity` at `price` for an order.
+    /// Unknown orders are ignored.
+    pub fn record_fill(&self, order_id: &OrderId, price: u64, quantity: u64, timestamp: u64) {
+        let became_terminal = match self.records.get_mut(order_id) {
+            Some(mut record) if !record.status.is_terminal() => {
+                self.mark_changed(*order_id);
+                record.filled_quantity = record.filled_quantity.saturating_add(quantity);
+                record.filled_value += price as u128 * quantity as u128;
+                record.updated_at = timestamp;
+                if record.filled_quantity >= record.original_quantity {
+                    record.status = OrderStatus::Filled;
+                    record.reason = Some(TerminalReason::Filled);
+                    true
+                } else {
+                    record.status = OrderStatus::PartiallyFilled;
+                    false
+                }
+            }
+            _ => false,
+        };
+
+        if became_terminal {
+            self.push_terminal(*order_id);
+        }
+    }
+
+    /// Move a live order to a terminal status. Already terminal orders are left untouched.
+    pub fn finish(
+        &self,
+        order_id: &OrderId,
+        status: OrderStatus,
+        reason: TerminalReason,
+        timestamp: u64,
+    ) {
+        let became_terminal = match self.records.get_mut(order_id) {
+            Some(mut record) if !record.status.is_terminal() => {
+                self.mark_changed(*order_id);
+                record.status = status;
+                record.reason = Some(reason);
+                record.updated_at = timestamp;
+                true
+            }
+            _ => false,
+        };
+
+        if became_terminal {
+            self.push_terminal(*order_id);
+        }
+    }
+
+    /// Apply an amendment to a live order. `order` is the amended order as it will rest
+    /// in the book; its total quantity is the new remaining quantity.
+    pub fn amend(&self, order: &OrderType, timestamp: u64) {
+        if let Some(mut record) = self.records.get_mut(&order.id())
+            && !record.status.is_terminal()
+        {
+            self.mark_changed(order.id());
+            record.side = order.side();
+            record.price = Some(order.price());
+            record.original_quantity = record.filled_quantity + order.total_quantity();
+            record.updated_at = timestamp;
+        }
+    }
+
+    fn push_terminal(&self, order_id: OrderId) {
+        // Terminal orders no longer hold their client order id
+        let owner = self
+            .records
+            .get(&order_id)
+            .and_then(|record| record.owner.clone());
+        if let Some(OrderOwner {
+            account,
+            client_order_id: Some(client_order_id),
+        }) = owner
+        {
+            self.client_order_ids
+                .remove_if(&(account, client_order_id), |_, id| *id == order_id);
+        }
+
+        if let Ok(mut terminal) = self.terminal_order.lock() {
+            terminal.push_back(order_id);
+        }
+        self.evict_terminal();
+    }
+
+    fn evict_terminal(&self) {
+        let retention = self.retention();
+        if let Ok(mut terminal) = self.terminal_order.lock() {
+            while terminal.len() > retention {
+                if let Some(order_id) = terminal.pop_front() {
+                    // Only evict if the order is still terminal; it may have been re-submitted
+                    self.records
+                        .remove_if(&order_id, |_, record| record.status.is_terminal());
+                }
+            }
+        }
+    }
+}
+
+impl Default for OrderStateStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_TERMINAL_ORDER_RETENTION)
+    }
+}
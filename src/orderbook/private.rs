@@ -1,5 +1,10 @@
-use crate::{OrderBook, OrderBookError, current_time_millis};
-use pricelevel::{OrderType, PriceLevel, Side};
+use crate::orderbook::events::{BookEvent, PendingEvents};
+use crate::orderbook::modifications::OrderQuantity;
+use crate::orderbook::order_state::{OrderStatus, TerminalReason};
+use crate::{OrderBook, OrderBookError};
+use dashmap::mapref::entry::Entry;
+use pricelevel::{OrderId, OrderType, PriceLevel, Side};
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::atomic::Ordering;
 
@@ -7,7 +12,7 @@ impl OrderBook {
     /// Check if an order has expired
     pub(super) fn has_expired(&self, order: &OrderType) -> bool {
         let time_in_force = order.time_in_force();
-        let current_time = current_time_millis();
+        let current_time = self.now_millis();
 
         // Only check market close timestamp if we have one set
         let market_close = if self.has_market_close.load(Ordering::Relaxed) {
@@ -19,6 +24,54 @@ impl OrderBook {
         time_in_force.is_expired(current_time, market_close)
     }
 
+    /// Mark an order that the book refused as rejected. Orders that already reached
+    /// a terminal status (e.g. expired) keep it.
+    pub(super) fn reject_order(&self, order_id: &OrderId, err: &OrderBookError) {
+        self.order_states.finish(
+            order_id,
+            OrderStatus::Rejected,
+            TerminalReason::from(err),
+            self.now_millis(),
+        );
+    }
+
+    /// Check that an order can be accepted by the book without changing it: it must not have
+    /// expired, a post-only order must not cross and a fill-or-kill order must be fully fillable
+    pub(super) fn validate_order(&self, order: &OrderType) -> Result<(), OrderBookError> {
+        if self.has_expired(order) {
+            return Err(OrderBookError::InvalidOperation {
+                message: "Order has already expired".to_string(),
+            });
+        }
+
+        if order.is_post_only() && self.will_cross_market(order.price(), order.side()) {
+            return Err(OrderBookError::PriceCrossing {
+                price: order.price(),
+                side: order.side(),
+                opposite_price: if order.side() == Side::Buy {
+                    self.best_ask().unwrap_or(0)
+                } else {
+                    self.best_bid().unwrap_or(0)
+                },
+            });
+        }
+
+        // For FOK orders, check if the entire quantity can be matched without altering the book.
+        if order.is_fill_or_kill() {
+            let potential_match =
+                self.peek_match(order.side(), order.total_quantity(), Some(order.price()));
+            if potential_match < order.total_quantity() {
+                return Err(OrderBookError::InsufficientLiquidity {
+                    side: order.side(),
+                    requested: order.total_quantity(),
+                    available: potential_match,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     /// Check if there would be a price crossing
     pub(super) fn will_cross_market(&self, price: u64, side: Side) -> bool {
         match side {
@@ -28,37 +81,119 @@ impl OrderBook {
     }
 
     /// Places a resting order in the book, updates its location.
-    #[allow(dead_code)]
     pub(super) fn place_order_in_book(
         &self,
         order: Arc<OrderType>,
     ) -> Result<Arc<OrderType>, OrderBookError> {
         let (side, price, order_id) = (order.side(), order.price(), order.id());
 
-        let book_side = match side {
+        // The `add_order` method on PriceLevel expects an `OrderType`, not an `Arc`.
+        self.with_price_level(side, price, |price_level| {
+            price_level.add_order(*order.clone());
+            // The location is stored as (price, side) for efficient retrieval in cancel_order
+            self.order_locations.insert(order_id, (price, side));
+            self.queue_priorities.push_back(order_id);
+        });
+
+        Ok(order)
+    }
+
+    /// Run one mutation of the book as a step of its sequence.
+    ///
+    /// Mutations run concurrently with each other, but never while a snapshot is taken, so
+    /// a snapshot always shows the book between two steps. Mutations must not be nested.
+    /// The sequence number is taken when the mutation starts, so every event it produces
+    /// carries its own number whatever runs alongside it, and the events are handed to the
+    /// sinks and the trade listener once it has released the snapshot gate.
+    pub(super) fn sequenced<R>(&self, mutation: impl FnOnce() -> R) -> R {
+        let (result, mut pending) = {
+            let _gate = self.snapshot_gate.read().unwrap();
+            let sequence = self.sequence.fetch_add(1, Ordering::AcqRel) + 1;
+            let (result, mut pending) = PendingEvents::collect(sequence, mutation);
+            if self.events.is_active() {
+                self.collect_order_updates(&mut pending);
+            }
+            (result, pending)
+        };
+
+        if let Some(ref listener) = self.trade_listener {
+            for match_result in &pending.matches {
+                listener(match_result);
+            }
+        }
+        for event in pending.events.drain(..) {
+            self.events.publish(event);
+        }
+        result
+    }
+
+    /// Add the new state of every order changed by the mutation to its events, each once.
+    /// The states are read before the gate is released, so they are those the mutation left.
+    fn collect_order_updates(&self, pending: &mut PendingEvents) {
+        let mut seen = HashSet::with_capacity(pending.changed.len());
+        for order_id in std::mem::take(&mut pending.changed) {
+            if !seen.insert(order_id) {
+                continue;
+            }
+            if let Some(record) = self.order_states.get(&order_id) {
+                pending.events.push(BookEvent::OrderUpdated {
+                    symbol: self.symbol.clone(),
+                    sequence: pending.sequence,
+                    record,
+                    visible_quantity: self
+                        .get_order(order_id)
+                        .map(|order| order.visible_quantity()),
+                });
+            }
+        }
+    }
+
+    /// Run `f` on the price level at `price`, creating the level if there is none.
+    /// The level stays locked while `f` runs, so it cannot be removed as empty before
+    /// `f` has added to it.
+    pub(super) fn with_price_level<R>(
+        &self,
+        side: Side,
+        price: u64,
+        f: impl FnOnce(&Arc<PriceLevel>) -> R,
+    ) -> R {
+        let price_levels = match side {
             Side::Buy => &self.bids,
             Side::Sell => &self.asks,
         };
 
-        // Get or create the price level
-        let price_level = book_side
-            .entry(price)
-            .or_insert_with(|| PriceLevel::new(price).into())
-            .value()
-            .clone();
+        match price_levels.entry(price) {
+            Entry::Occupied(entry) => f(entry.get()),
+            Entry::Vacant(entry) => {
+                let price_level = Arc::new(PriceLevel::new(price));
+                let result = f(&price_level);
+                self.cache.level_added(side, price);
+                entry.insert(price_level);
+                result
+            }
+        }
+    }
 
-        // The `add_order` method on PriceLevel expects an `OrderType`, not an `Arc`.
-        price_level.add_order(*order.clone());
-        // The location is stored as (price, side) for efficient retrieval in cancel_order
-        self.order_locations.insert(order_id, (price, side));
+    /// Remove the price level at `price` if it no longer holds any order
+    pub(super) fn remove_empty_level(&self, side: Side, price: u64) {
+        let price_levels = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
 
-        Ok(order)
+        // Checked under the entry lock: an order may have joined the level since it
+        // was seen empty
+        if let Entry::Occupied(entry) = price_levels.entry(price)
+            && entry.get().order_count() == 0
+        {
+            self.cache.level_removed(side, price);
+            entry.remove();
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::OrderBookError; // Import the error type
     use crate::orderbook::book::OrderBook;
     use crate::utils::current_time_millis; // Import the time utility
     use pricelevel::{OrderId, OrderType, Side, TimeInForce};
@@ -200,21 +335,11 @@ mod tests {
 
         // Attempt to match a market order on an empty book
         let id = create_order_id();
-        let result = book.match_market_order(id, 10, Side::Buy);
-
-        // Should return an error since there are no matching orders
-        assert!(result.is_err());
-        match result {
-            Err(OrderBookError::InsufficientLiquidity {
-                side,
-                requested,
-                available,
-            }) => {
-                assert_eq!(side, Side::Buy);
-                assert_eq!(requested, 10);
-                assert_eq!(available, 0);
-            }
-            _ => panic!("Expected InsufficientLiquidity error"),
-        }
+        let result = book.match_market_order(id, 10, Side::Buy).unwrap();
+
+        // Nothing is matched
+        assert_eq!(result.executed_quantity(), 0);
+        assert_eq!(result.remaining_quantity, 10);
+        assert!(!result.is_complete);
     }
 }
@@ -0,0 +1,100 @@
+//! Time priority of resting orders within their price level.
+//!
+//! Every time an order joins the back of a price level queue it receives a new, strictly
+//! increasing sequence number, so ordering the orders of a level by sequence gives the
+//! order in which they will be matched.
+
+use dashmap::DashMap;
+use pricelevel::{OrderId, OrderType, PriceLevel};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Queue sequence numbers of the resting orders of a book
+pub(super) struct QueuePriorities {
+    sequences: DashMap<OrderId, u64>,
+    next_sequence: AtomicU64,
+}
+
+impl QueuePriorities {
+    pub(super) fn new() -> Self {
+        Self {
+            sequences: DashMap::new(),
+            next_sequence: AtomicU64::new(0),
+        }
+    }
+
+    /// Record that an order has just joined the back of its price level queue
+    pub(super) fn push_back(&self, order_id: OrderId) {
+        let sequence = self.next_sequence.fetch_add(1, Ordering::Relaxed);
+        self.sequences.insert(order_id, sequence);
+    }
+
+    /// Forget an order that no longer rests in the book, returning its sequence
+    pub(super) fn remove(&self, order_id: &OrderId) -> Option<u64> {
+        self.sequences
+            .remove(order_id)
+            .map(|(_, sequence)| sequence)
+    }
+
+    /// Returns true if the order has a queue sequence
+    pub(super) fn contains(&self, order_id: &OrderId) -> bool {
+        self.sequences.contains_key(order_id)
+    }
+
+    /// Ids of all the orders that have a queue sequence
+    pub(super) fn order_ids(&self) -> Vec<OrderId> {
+        self.sequences.iter().map(|entry| *entry.key()).collect()
+    }
+
+    /// Give an order back the sequence it had before it was taken out of the book
+    pub(super) fn restore(&self, order_id: OrderId, sequence: u64) {
+        self.sequences.insert(order_id, sequence);
+    }
+
+    /// Sort orders of a price level into matching order
+    pub(super) fn sort(&self, orders: &mut [Arc<OrderType>]) {
+        orders.sort_by_key(|order| {
+            self.sequences
+                .get(&order.id())
+                .map(|sequence| *sequence)
+                .unwrap_or(u64::MAX)
+        });
+    }
+
+    /// Build a copy of `level` without `order_id`, keeping the queue order of the others.
+    ///
+    /// Removing an order from a `PriceLevel` leaves its id in the level queue, and an order
+    /// re-added later with the same id would be matched from that old slot. Rebuilding the
+    /// level drops the slot, so an order that re-enters it really goes to the back.
+    pub(super) fn rebuild_without(&self, level: &PriceLevel, order_id: OrderId) -> PriceLevel {
+        let mut orders = level.iter_orders();
+        orders.retain(|order| order.id() != order_id);
+        self.rebuild(level.price(), orders)
+    }
+
+    /// Build a copy of `level` with `order` inserted at the position given by its sequence
+    pub(super) fn rebuild_with(&self, level: &PriceLevel, order: OrderType) -> PriceLevel {
+        let mut orders = level.iter_orders();
+        orders.push(Arc::new(order));
+        self.rebuild(level.price(), orders)
+    }
+
+    /// Build a copy of `level` with `order` in place of the resting order with its id, in the
+    /// queue slot of that order
+    pub(super) fn rebuild_replacing(&self, level: &PriceLevel, order: OrderType) -> PriceLevel {
+        let mut orders = level.iter_orders();
+        orders.retain(|resting| resting.id() != order.id());
+        orders.push(Arc::new(order));
+        self.rebuild(level.price(), orders)
+    }
+
+    fn rebuild(&self, price: u64, mut orders: Vec<Arc<OrderType>>) -> PriceLevel {
+        self.sort(&mut orders);
+
+        let rebuilt = PriceLevel::new(price);
+        for order in orders {
+            rebuilt.add_order(*order);
+        }
+        rebuilt
+    }
+}
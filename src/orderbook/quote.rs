@@ -0,0 +1,197 @@
+//! Read-only pre-trade quotes: what a market order would cost if it were submitted now
+
+use super::book::OrderBook;
+use pricelevel::Side;
+use serde::{Deserialize, Serialize};
+
+/// Which resting liquidity a quote may consume
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Liquidity {
+    /// Only the displayed quantity of each level
+    #[default]
+    Visible,
+    /// The displayed and the hidden (iceberg and reserve) quantity of each level
+    VisibleAndHidden,
+}
+
+/// Estimated execution of a market order of a given size
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketImpact {
+    /// Side of the hypothetical order
+    pub side: Side,
+    /// Quantity that was quoted
+    pub requested_quantity: u64,
+    /// Quantity the book can currently fill, at most `requested_quantity`
+    pub filled_quantity: u64,
+    /// Total value of the fills (sum of price * quantity)
+    pub total_value: u128,
+    /// Volume-weighted average fill price, if anything can be filled
+    pub average_price: Option<f64>,
+    /// Price of the first level consumed
+    pub best_price: Option<u64>,
+    /// Price of the last level consumed
+    pub worst_price: Option<u64>,
+    /// Number of price levels the order would consume, fully or partially
+    pub levels_consumed: usize,
+    /// Mid price the slippage is measured against
+    pub mid_price: Option<f64>,
+    /// Average price minus mid for a buy, mid minus average price for a sell
+    pub slippage: Option<f64>,
+    /// Slippage in basis points of the mid price
+    pub slippage_bps: Option<f64>,
+}
+
+impl MarketImpact {
+    /// Returns true if the whole requested quantity can be filled
+    pub fn is_complete(&self) -> bool {
+        self.filled_quantity == self.requested_quantity
+    }
+}
+
+/// Quantity a notional budget buys (or sells) when spent against the book
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotionalQuote {
+    /// Side of the hypothetical order
+    pub side: Side,
+    /// Budget that was quoted
+    pub notional: u128,
+    /// Quantity that can be traded without exceeding the budget
+    pub quantity: u64,
+    /// Value actually traded, at most `notional`
+    pub spent: u128,
+    /// Volume-weighted average fill price, if anything can be traded
+    pub average_price: Option<f64>,
+    /// Price of the last level consumed
+    pub worst_price: Option<u64>,
+    /// Number of price levels consumed, fully or partially
+    pub levels_consumed: usize,
+}
+
+impl OrderBook {
+    /// Estimate the execution of a market order of `quantity` on `side` without
+    /// touching the book
+    pub fn market_impact(&self, side: Side, quantity: u64, liquidity: Liquidity) -> MarketImpact {
+        let mut remaining = quantity;
+        let mut total_value = 0u128;
+        let mut best_price = None;
+        let mut worst_price = None;
+        let mut levels_consumed = 0;
+
+        for (price, available) in self.opposite_liquidity(side, liquidity) {
+            if remaining == 0 {
+                break;
+            }
+            let take = remaining.min(available);
+            remaining -= take;
+            total_value += price as u128 * take as u128;
+            best_price.get_or_insert(price);
+            worst_price = Some(price);
+            levels_consumed += 1;
+        }
+
+        let filled_quantity = quantity - remaining;
+        let average_price = average(total_value, filled_quantity);
+        let mid_price = self.mid_price();
+        let slippage = match (average_price, mid_price) {
+            (Some(average), Some(mid)) => Some(match side {
+                Side::Buy => average - mid,
+                Side::Sell => mid - average,
+            }),
+            _ => None,
+        };
+        let slippage_bps = match (slippage, mid_price) {
+            (Some(slippage), Some(mid)) if mid > 0.0 => Some(slippage / mid * 10_000.0),
+            _ => None,
+        };
+
+        MarketImpact {
+            side,
+            requested_quantity: quantity,
+            filled_quantity,
+            total_value,
+            average_price,
+            best_price,
+            worst_price,
+            levels_consumed,
+            mid_price,
+            slippage,
+            slippage_bps,
+        }
+    }
+
+    /// Estimate the quantity a market order on `side` can trade for at most `notional`,
+    /// without touching the book
+    pub fn quantity_for_notional(
+        &self,
+        side: Side,
+        notional: u128,
+        liquidity: Liquidity,
+    ) -> NotionalQuote {
+        let mut budget = notional;
+        let mut quantity = 0u64;
+        let mut worst_price = None;
+        let mut levels_consumed = 0;
+
+        for (price, available) in self.opposite_liquidity(side, liquidity) {
+            if price == 0 {
+                continue;
+            }
+            // Orders are matched level by level, so stop at the first level the
+            // remaining budget cannot buy a single unit of
+            let affordable = (budget / price as u128).min(available as u128) as u64;
+            if affordable == 0 {
+                break;
+            }
+            budget -= price as u128 * affordable as u128;
+            quantity += affordable;
+            worst_price = Some(price);
+            levels_consumed += 1;
+        }
+
+        let spent = notional - budget;
+        NotionalQuote {
+            side,
+            notional,
+            quantity,
+            spent,
+            average_price: average(spent, quantity),
+            worst_price,
+            levels_consumed,
+        }
+    }
+
+    /// Price and available quantity of each level an order on `side` would match against,
+    /// best price first
+    pub(super) fn opposite_liquidity(&self, side: Side, liquidity: Liquidity) -> Vec<(u64, u64)> {
+        let price_levels = match side {
+            Side::Buy => &self.asks,
+            Side::Sell => &self.bids,
+        };
+
+        let mut levels: Vec<(u64, u64)> = price_levels
+            .iter()
+            .map(|item| {
+                let available = match liquidity {
+                    Liquidity::Visible => item.value().visible_quantity(),
+                    Liquidity::VisibleAndHidden => item.value().total_quantity(),
+                };
+                (*item.key(), available)
+            })
+            .filter(|(_, available)| *available > 0)
+            .collect();
+
+        match side {
+            Side::Buy => levels.sort_unstable_by_key(|(price, _)| *price), // Ascending for asks
+            Side::Sell => levels.sort_unstable_by_key(|(price, _)| std::cmp::Reverse(*price)), // Descending for bids
+        }
+        levels
+    }
+}
+
+fn average(value: u128, quantity: u64) -> Option<f64> {
+    if quantity == 0 {
+        None
+    } else {
+        Some(value as f64 / quantity as f64)
+    }
+}
@@ -0,0 +1,644 @@
+//! Single-writer sequencer mode.
+//!
+//! Used directly, an [`OrderBook`] can be mutated from many threads at once, and concurrent
+//! operations interleave at the granularity of single price levels. A [`Sequencer`] owns a
+//! book and applies every mutation on one dedicated writer thread, in the order the
+//! commands were taken from a lock-free command ring. Each command is given a sequence
+//! number, which gives a total order of events and strict price-time priority. The writer
+//! publishes a consistent, versioned [`BookView`] for readers once it has drained the ring,
+//! and while it stays busy at most every [`SequencerConfig::view_interval`], so that bursts
+//! of commands do not pay for a snapshot each.
+//!
+//! Given a [`Journal`], the writer also records every command before applying it, so that
+//! the book can be rebuilt after a restart by replaying them on top of a checkpoint. A
+//! command that cannot be journaled is not applied: its submitter gets an error and the
+//! sequencer stops.
+
+use super::book::OrderBook;
+use super::error::OrderBookError;
+use super::execution::ExecutionReport;
+use super::journal::{Journal, JournalEntry, JournalOutcome};
+use super::order_state::{OrderOwner, OrderStatus};
+use super::snapshot::OrderBookSnapshot;
+use crossbeam::queue::ArrayQueue;
+use crossbeam::utils::Backoff;
+use pricelevel::{OrderId, OrderType, OrderUpdate, Side};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::{Pin, pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, JoinHandle, Thread};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tracing::{error, trace, warn};
+use uuid::Uuid;
+
+/// Settings of a [`Sequencer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequencerConfig {
+    /// Number of commands the ring can hold before submitters have to wait
+    pub ring_capacity: usize,
+    /// Number of price levels per side included in each published [`BookView`]
+    pub view_depth: usize,
+    /// Longest time the published [`BookView`] lags behind while commands keep coming
+    pub view_interval: Duration,
+}
+
+impl Default for SequencerConfig {
+    fn default() -> Self {
+        Self {
+            ring_capacity: 65_536,
+            view_depth: 20,
+            view_interval: Duration::from_millis(10),
+        }
+    }
+}
+
+/// A mutation of the book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Command {
+    /// Add an order, matching it if it is aggressive
+    AddOrder {
+        /// The order to add
+        order: OrderType,
+        /// Account submitting the order, if any
+        owner: Option<OrderOwner>,
+    },
+    /// Submit a market order
+    SubmitMarketOrder {
+        /// Identifier of the order
+        id: OrderId,
+        /// Quantity to trade
+        quantity: u64,
+        /// Side of the order
+        side: Side,
+        /// Account submitting the order, if any
+        owner: Option<OrderOwner>,
+    },
+    /// Cancel a resting order
+    CancelOrder(OrderId),
+    /// Amend a resting order
+    UpdateOrder(OrderUpdate),
+    /// Cancel every resting order of an account
+    CancelAccountOrders(Uuid),
+}
+
+impl Command {
+    /// Apply the command to `book`
+    pub fn apply_to(self, book: &OrderBook) -> CommandResult {
+        match self {
+            Command::AddOrder { order, owner } => CommandResult::Execution(match owner {
+                Some(owner) => book.add_order_with_owner(order, owner),
+                None => book.add_order(order),
+            }),
+            Command::SubmitMarketOrder {
+                id,
+                quantity,
+                side,
+                owner,
+            } => CommandResult::Execution(match owner {
+                Some(owner) => book.submit_market_order_with_owner(id, quantity, side, owner),
+                None => book.submit_market_order(id, quantity, side),
+            }),
+            Command::CancelOrder(order_id) => CommandResult::Order(book.cancel_order(order_id)),
+            Command::UpdateOrder(update) => CommandResult::Amendment(book.update_order(update)),
+            Command::CancelAccountOrders(account) => {
+                CommandResult::Cancelled(book.cancel_account_orders(account))
+            }
+        }
+    }
+}
+
+/// The result of applying a [`Command`]
+#[derive(Debug)]
+pub enum CommandResult {
+    /// Result of [`Command::AddOrder`] and [`Command::SubmitMarketOrder`]
+    Execution(Result<ExecutionReport, OrderBookError>),
+    /// Result of [`Command::CancelOrder`]
+    Order(Result<Option<Arc<OrderType>>, OrderBookError>),
+    /// Result of [`Command::UpdateOrder`]
+    Amendment(Result<Option<ExecutionReport>, OrderBookError>),
+    /// Result of [`Command::CancelAccountOrders`]: the ids of the cancelled orders
+    Cancelled(Result<Vec<OrderId>, OrderBookError>),
+}
+
+impl CommandResult {
+    /// Summary of the result, as journaled to check a replay of the command against
+    pub fn outcome(&self) -> CommandOutcome {
+        let succeeded = CommandOutcome {
+            succeeded: true,
+            ..CommandOutcome::default()
+        };
+        match self {
+            CommandResult::Execution(Ok(report)) | CommandResult::Amendment(Ok(Some(report))) => {
+                CommandOutcome {
+                    status: Some(report.status),
+                    filled_quantity: report.filled_quantity(),
+                    ..succeeded
+                }
+            }
+            CommandResult::Amendment(Ok(None)) => succeeded,
+            CommandResult::Order(Ok(order)) => CommandOutcome {
+                cancelled_orders: order.iter().count(),
+                ..succeeded
+            },
+            CommandResult::Cancelled(Ok(order_ids)) => CommandOutcome {
+                cancelled_orders: order_ids.len(),
+                ..succeeded
+            },
+            CommandResult::Execution(Err(_))
+            | CommandResult::Amendment(Err(_))
+            | CommandResult::Order(Err(_))
+            | CommandResult::Cancelled(Err(_)) => CommandOutcome::default(),
+        }
+    }
+
+    fn into_execution(self) -> Result<ExecutionReport, OrderBookError> {
+        match self {
+            CommandResult::Execution(result) => result,
+            _ => unreachable!("an added or market order produces an execution"),
+        }
+    }
+
+    fn into_order(self) -> Result<Option<Arc<OrderType>>, OrderBookError> {
+        match self {
+            CommandResult::Order(result) => result,
+            _ => unreachable!("a cancel produces an order"),
+        }
+    }
+
+    fn into_amendment(self) -> Result<Option<ExecutionReport>, OrderBookError> {
+        match self {
+            CommandResult::Amendment(result) => result,
+            _ => unreachable!("an update produces an amendment"),
+        }
+    }
+
+    fn into_cancelled(self) -> Result<Vec<OrderId>, OrderBookError> {
+        match self {
+            CommandResult::Cancelled(result) => result,
+            _ => unreachable!("an account cancel produces the cancelled orders"),
+        }
+    }
+}
+
+/// What applying a command produced, in short
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandOutcome {
+    /// False if the command failed with an error
+    pub succeeded: bool,
+    /// Status of the order the command submitted or amended, if any
+    pub status: Option<OrderStatus>,
+    /// Quantity the command executed
+    pub filled_quantity: u64,
+    /// Number of orders the command cancelled
+    pub cancelled_orders: usize,
+}
+
+/// A command result together with the position of the command in the sequence
+#[derive(Debug)]
+pub struct SequencedResult {
+    /// Sequence number of the command, starting at 1
+    pub sequence: u64,
+    /// What applying the command produced
+    pub result: CommandResult,
+}
+
+/// The state of the book after a given command
+#[derive(Debug, Clone)]
+pub struct BookView {
+    /// Sequence number of the last command applied, 0 before the first one
+    pub version: u64,
+    /// Best levels of each side
+    pub snapshot: OrderBookSnapshot,
+    /// Best bid price
+    pub best_bid: Option<u64>,
+    /// Best ask price
+    pub best_ask: Option<u64>,
+    /// Price of the last trade
+    pub last_trade_price: Option<u64>,
+}
+
+impl BookView {
+    fn capture(book: &OrderBook, version: u64, depth: usize) -> Self {
+        Self {
+            version,
+            snapshot: book.create_snapshot(depth),
+            best_bid: book.best_bid(),
+            best_ask: book.best_ask(),
+            last_trade_price: book.last_trade_price(),
+        }
+    }
+}
+
+/// Handle on a submitted command, used to wait for its result. Async code awaits the
+/// ticket; [`Ticket::wait`] blocks the calling thread instead.
+#[derive(Debug)]
+pub struct Ticket(oneshot::Receiver<Result<SequencedResult, OrderBookError>>);
+
+impl Ticket {
+    /// Block until the command has been applied
+    pub fn wait(self) -> Result<SequencedResult, OrderBookError> {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut context = Context::from_waker(&waker);
+        let mut ticket = pin!(self);
+        loop {
+            if let Poll::Ready(result) = ticket.as_mut().poll(&mut context) {
+                return result;
+            }
+            thread::park();
+        }
+    }
+}
+
+impl Future for Ticket {
+    type Output = Result<SequencedResult, OrderBookError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|reply| reply.map_err(|_| stopped())?)
+    }
+}
+
+/// Wakes a thread blocked in [`Ticket::wait`]
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+type Reply = oneshot::Sender<Result<SequencedResult, OrderBookError>>;
+
+struct Envelope {
+    command: Command,
+    reply: Reply,
+}
+
+/// Funnels every mutation of an order book through a single writer thread
+pub struct Sequencer {
+    book: Arc<OrderBook>,
+    ring: Arc<ArrayQueue<Envelope>>,
+    view: Arc<RwLock<Arc<BookView>>>,
+    running: Arc<AtomicBool>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Sequencer {
+    /// Take ownership of `book` and start its writer thread with the default settings
+    pub fn start(book: OrderBook) -> Self {
+        Self::with_config(book, SequencerConfig::default())
+    }
+
+    /// Take ownership of `book` and start its writer thread
+    pub fn with_config(book: OrderBook, config: SequencerConfig) -> Self {
+        Self::spawn(book, config, None)
+    }
+
+    /// Take ownership of `book` and start its writer thread, recording every applied
+    /// command in `journal`
+    pub fn with_journal(book: OrderBook, config: SequencerConfig, journal: Arc<Journal>) -> Self {
+        Self::spawn(book, config, Some(journal))
+    }
+
+    fn spawn(book: OrderBook, config: SequencerConfig, journal: Option<Arc<Journal>>) -> Self {
+        let book = Arc::new(book);
+        let ring = Arc::new(ArrayQueue::new(config.ring_capacity.max(1)));
+        // A book restored from a checkpoint continues its sequence
+        let view = Arc::new(RwLock::new(Arc::new(BookView::capture(
+            &book,
+            book.sequence(),
+            config.view_depth,
+        ))));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let writer = {
+            let book = Arc::clone(&book);
+            let ring = Arc::clone(&ring);
+            let view = Arc::clone(&view);
+            let running = Arc::clone(&running);
+            thread::Builder::new()
+                .name(format!("sequencer-{}", book.symbol()))
+                .spawn(move || {
+                    run_writer(&book, &ring, &view, &running, journal.as_deref(), &config)
+                })
+                .expect("failed to spawn the sequencer thread")
+        };
+
+        Self {
+            book,
+            ring,
+            view,
+            running,
+            writer: Some(writer),
+        }
+    }
+
+    /// The sequenced book, for read-only queries. It must not be mutated directly.
+    pub fn book(&self) -> &OrderBook {
+        &self.book
+    }
+
+    /// A shared handle on the sequenced book, for read-only queries
+    pub fn shared_book(&self) -> Arc<OrderBook> {
+        Arc::clone(&self.book)
+    }
+
+    /// The last published view. It is current once the commands queued so far have been
+    /// applied, and lags behind by at most `view_interval` while more keep coming.
+    pub fn view(&self) -> Arc<BookView> {
+        Arc::clone(&self.view.read().unwrap())
+    }
+
+    /// Number of commands waiting in the ring
+    pub fn pending(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// Queue a command, waiting for room in the ring if it is full
+    pub fn submit(&self, command: Command) -> Result<Ticket, OrderBookError> {
+        let (reply, ticket) = oneshot::channel();
+        let mut envelope = Envelope { command, reply };
+        let backoff = Backoff::new();
+        loop {
+            if !self.running.load(Ordering::Acquire) {
+                return Err(stopped());
+            }
+            match self.ring.push(envelope) {
+                Ok(()) => break,
+                Err(rejected) => {
+                    envelope = rejected;
+                    backoff.snooze();
+                }
+            }
+        }
+        self.wake_writer();
+        Ok(Ticket(ticket))
+    }
+
+    /// Queue a command, failing instead of waiting if the ring is full
+    pub fn try_submit(&self, command: Command) -> Result<Ticket, OrderBookError> {
+        if !self.running.load(Ordering::Acquire) {
+            return Err(stopped());
+        }
+        let (reply, ticket) = oneshot::channel();
+        self.ring.push(Envelope { command, reply }).map_err(|_| {
+            OrderBookError::InvalidOperation {
+                message: "sequencer command ring is full".to_string(),
+            }
+        })?;
+        self.wake_writer();
+        Ok(Ticket(ticket))
+    }
+
+    /// Queue a command and wait until it has been applied
+    pub fn execute(&self, command: Command) -> Result<SequencedResult, OrderBookError> {
+        self.submit(command)?.wait()
+    }
+
+    /// Queue a command and wait asynchronously until it has been applied
+    pub async fn execute_async(&self, command: Command) -> Result<SequencedResult, OrderBookError> {
+        self.submit(command)?.await
+    }
+
+    /// Add an order through the sequencer
+    pub fn add_order(
+        &self,
+        order: OrderType,
+        owner: Option<OrderOwner>,
+    ) -> Result<ExecutionReport, OrderBookError> {
+        self.execute(Command::AddOrder { order, owner })?
+            .result
+            .into_execution()
+    }
+
+    /// Add an order through the sequencer, waiting asynchronously
+    pub async fn add_order_async(
+        &self,
+        order: OrderType,
+        owner: Option<OrderOwner>,
+    ) -> Result<ExecutionReport, OrderBookError> {
+        self.execute_async(Command::AddOrder { order, owner })
+            .await?
+            .result
+            .into_execution()
+    }
+
+    /// Submit a market order through the sequencer
+    pub fn submit_market_order(
+        &self,
+        id: OrderId,
+        quantity: u64,
+        side: Side,
+        owner: Option<OrderOwner>,
+    ) -> Result<ExecutionReport, OrderBookError> {
+        let command = Command::SubmitMarketOrder {
+            id,
+            quantity,
+            side,
+            owner,
+        };
+        self.execute(command)?.result.into_execution()
+    }
+
+    /// Submit a market order through the sequencer, waiting asynchronously
+    pub async fn submit_market_order_async(
+        &self,
+        id: OrderId,
+        quantity: u64,
+        side: Side,
+        owner: Option<OrderOwner>,
+    ) -> Result<ExecutionReport, OrderBookError> {
+        let command = Command::SubmitMarketOrder {
+            id,
+            quantity,
+            side,
+            owner,
+        };
+        self.execute_async(command).await?.result.into_execution()
+    }
+
+    /// Cancel an order through the sequencer
+    pub fn cancel_order(
+        &self,
+        order_id: OrderId,
+    ) -> Result<Option<Arc<OrderType>>, OrderBookError> {
+        self.execute(Command::CancelOrder(order_id))?
+            .result
+            .into_order()
+    }
+
+    /// Cancel an order through the sequencer, waiting asynchronously
+    pub async fn cancel_order_async(
+        &self,
+        order_id: OrderId,
+    ) -> Result<Option<Arc<OrderType>>, OrderBookError> {
+        self.execute_async(Command::CancelOrder(order_id))
+            .await?
+            .result
+            .into_order()
+    }
+
+    /// Amend an order through the sequencer
+    pub fn update_order(
+        &self,
+        update: OrderUpdate,
+    ) -> Result<Option<ExecutionReport>, OrderBookError> {
+        self.execute(Command::UpdateOrder(update))?
+            .result
+            .into_amendment()
+    }
+
+    /// Amend an order through the sequencer, waiting asynchronously
+    pub async fn update_order_async(
+        &self,
+        update: OrderUpdate,
+    ) -> Result<Option<ExecutionReport>, OrderBookError> {
+        self.execute_async(Command::UpdateOrder(update))
+            .await?
+            .result
+            .into_amendment()
+    }
+
+    /// Cancel every resting order of an account through the sequencer
+    pub fn cancel_account_orders(&self, account: Uuid) -> Result<Vec<OrderId>, OrderBookError> {
+        self.execute(Command::CancelAccountOrders(account))?
+            .result
+            .into_cancelled()
+    }
+
+    /// Cancel every resting order of an account through the sequencer, waiting asynchronously
+    pub async fn cancel_account_orders_async(
+        &self,
+        account: Uuid,
+    ) -> Result<Vec<OrderId>, OrderBookError> {
+        self.execute_async(Command::CancelAccountOrders(account))
+            .await?
+            .result
+            .into_cancelled()
+    }
+
+    /// Stop accepting commands, apply the ones already queued and stop the writer thread
+    pub fn shutdown(&mut self) {
+        self.running.store(false, Ordering::Release);
+        if let Some(writer) = self.writer.take() {
+            writer.thread().unpark();
+            let _ = writer.join();
+        }
+        // Commands queued after the writer's last look at the ring are dropped, which
+        // fails their tickets instead of leaving them waiting forever
+        while self.ring.pop().is_some() {}
+    }
+
+    fn wake_writer(&self) {
+        if let Some(writer) = &self.writer {
+            writer.thread().unpark();
+        }
+    }
+}
+
+impl Drop for Sequencer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn run_writer(
+    book: &OrderBook,
+    ring: &ArrayQueue<Envelope>,
+    view: &RwLock<Arc<BookView>>,
+    running: &AtomicBool,
+    journal: Option<&Journal>,
+    config: &SequencerConfig,
+) {
+    let mut sequence = book.sequence();
+    let mut published = Instant::now();
+    let mut failed = false;
+    let backoff = Backoff::new();
+    loop {
+        match ring.pop() {
+            // Commands queued behind one that could not be journaled are refused
+            Some(Envelope { reply, .. }) if failed => {
+                let _ = reply.send(Err(stopped()));
+            }
+            Some(Envelope { command, reply }) => {
+                backoff.reset();
+                trace!(
+                    "Order book {}: sequencing command {} {:?}",
+                    book.symbol(),
+                    sequence + 1,
+                    command
+                );
+                // Every timestamp of the command is its journaled time, so that a replay
+                // takes the same decisions (expiry, trade times)
+                let timestamp = book.clock().now_millis();
+                // Held until the command is applied, so that a checkpoint never rotates
+                // the journal between the two
+                let _applying = journal.map(Journal::begin_command);
+                // Written ahead: nothing of a command is seen (results, events, views)
+                // before it is in the journal
+                if let Some(journal) = journal {
+                    let entry = JournalEntry {
+                        symbol: book.symbol().to_string(),
+                        sequence: sequence + 1,
+                        timestamp,
+                        command: command.clone(),
+                        outcome: None,
+                    };
+                    if let Err(err) = journal.append(&entry) {
+                        error!(
+                            "Order book {}: failed to journal command {}, stopping: {}",
+                            book.symbol(),
+                            sequence + 1,
+                            err
+                        );
+                        running.store(false, Ordering::Release);
+                        failed = true;
+                        let _ = reply.send(Err(OrderBookError::InvalidOperation {
+                            message: format!("command could not be journaled: {err}"),
+                        }));
+                        continue;
+                    }
+                }
+                sequence += 1;
+                let result = book.at_time(timestamp, || command.apply_to(book));
+                if let Some(journal) = journal {
+                    let outcome = JournalOutcome {
+                        symbol: book.symbol().to_string(),
+                        sequence,
+                        outcome: result.outcome(),
+                    };
+                    // Only used to check a replay, a lost outcome is not worth stopping for
+                    if let Err(err) = journal.append_outcome(&outcome) {
+                        warn!(
+                            "Order book {}: failed to journal the outcome of command {}: {}",
+                            book.symbol(),
+                            sequence,
+                            err
+                        );
+                    }
+                }
+                if ring.is_empty() || published.elapsed() >= config.view_interval {
+                    *view.write().unwrap() =
+                        Arc::new(BookView::capture(book, sequence, config.view_depth));
+                    published = Instant::now();
+                }
+                // The submitter may have stopped waiting for the result
+                let _ = reply.send(Ok(SequencedResult { sequence, result }));
+            }
+            None if !running.load(Ordering::Acquire) => break,
+            None if backoff.is_completed() => thread::park_timeout(Duration::from_millis(1)),
+            None => backoff.snooze(),
+        }
+    }
+}
+
+fn stopped() -> OrderBookError {
+    OrderBookError::InvalidOperation {
+        message: "sequencer is stopped".to_string(),
+    }
+}
@@ -10,6 +10,10 @@ pub struct OrderBookSnapshot {
     /// The symbol or identifier for this order book
     pub symbol: String,
 
+    /// Sequence number of the last book mutation included in the snapshot
+    #[serde(default)]
+    pub sequence: u64,
+
     /// Timestamp when the snapshot was created (milliseconds since epoch)
     pub timestamp: u64,
 
@@ -102,4 +106,110 @@ impl OrderBookSnapshot {
         trace!("total_ask_value: {:?}", value);
         value
     }
+
+    /// Group the levels of the snapshot into price buckets of `bucket_size` price units.
+    ///
+    /// Bid prices are rounded down and ask prices up to a multiple of `bucket_size`, so a
+    /// bucket never shows a better price than the levels it contains. A `bucket_size` of 0
+    /// is treated as 1. Levels beyond the depth of the snapshot are not included, so the
+    /// last bucket of each side may be incomplete.
+    pub fn aggregate(&self, bucket_size: u64) -> AggregatedDepth {
+        let bucket_size = bucket_size.max(1);
+
+        let mut bids: Vec<&PriceLevelSnapshot> = self.bids.iter().collect();
+        bids.sort_by_key(|level| std::cmp::Reverse(level.price));
+        let bids = aggregate_levels(&bids, |price| price / bucket_size * bucket_size);
+
+        let mut asks: Vec<&PriceLevelSnapshot> = self.asks.iter().collect();
+        asks.sort_by_key(|level| level.price);
+        let asks = aggregate_levels(&asks, |price| {
+            price.div_ceil(bucket_size).saturating_mul(bucket_size)
+        });
+        trace!(
+            "aggregate: {} bid buckets, {} ask buckets of size {}",
+            bids.len(),
+            asks.len(),
+            bucket_size
+        );
+
+        AggregatedDepth {
+            symbol: self.symbol.clone(),
+            sequence: self.sequence,
+            timestamp: self.timestamp,
+            bucket_size,
+            bids,
+            asks,
+        }
+    }
+}
+
+/// Price levels of one side of the book grouped into a single price bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthBucket {
+    /// Bucket price: rounded down for bids, rounded up for asks
+    pub price: u64,
+
+    /// Sum of the visible quantity of the levels in the bucket
+    pub visible_quantity: u64,
+
+    /// Sum of the hidden quantity of the levels in the bucket
+    pub hidden_quantity: u64,
+
+    /// Number of orders in the bucket
+    pub order_count: usize,
+
+    /// Visible quantity of this bucket and all better priced buckets of the same side
+    pub cumulative_quantity: u64,
+}
+
+/// Order book depth grouped into price buckets, as used by depth charts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AggregatedDepth {
+    /// The symbol or identifier for this order book
+    pub symbol: String,
+
+    /// Sequence number of the snapshot the depth was built from
+    pub sequence: u64,
+
+    /// Timestamp of the snapshot the depth was built from (milliseconds since epoch)
+    pub timestamp: u64,
+
+    /// Width of each bucket in price units
+    pub bucket_size: u64,
+
+    /// Bid buckets, best (highest) price first
+    pub bids: Vec<DepthBucket>,
+
+    /// Ask buckets, best (lowest) price first
+    pub asks: Vec<DepthBucket>,
+}
+
+/// Group levels sorted best price first into buckets keyed by `bucket_price`
+fn aggregate_levels(
+    levels: &[&PriceLevelSnapshot],
+    bucket_price: impl Fn(u64) -> u64,
+) -> Vec<DepthBucket> {
+    let mut buckets: Vec<DepthBucket> = Vec::new();
+    let mut cumulative_quantity = 0;
+
+    for level in levels {
+        let price = bucket_price(level.price);
+        cumulative_quantity += level.visible_quantity;
+        match buckets.last_mut() {
+            Some(bucket) if bucket.price == price => {
+                bucket.visible_quantity += level.visible_quantity;
+                bucket.hidden_quantity += level.hidden_quantity;
+                bucket.order_count += level.order_count;
+                bucket.cumulative_quantity = cumulative_quantity;
+            }
+            _ => buckets.push(DepthBucket {
+                price,
+                visible_quantity: level.visible_quantity,
+                hidden_quantity: level.hidden_quantity,
+                order_count: level.order_count,
+                cumulative_quantity,
+            }),
+        }
+    }
+    buckets
 }
@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod tests {
+    use crate::orderbook::analytics::{
+        AnalyticsSample, AnalyticsSampler, DepthSource, SamplerConfig, cost_to_move,
+        depth_imbalance, depth_within_bps, microprice, spread_bps,
+    };
+    use crate::{ManualClock, OrderBook};
+    use pricelevel::{OrderId, Side, TimeInForce};
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    // Helper function to create a unique order ID
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    // Bids: 30 @ 9990, 20 @ 9980, 50 @ 9900
+    // Asks: 10 @ 10010, 20 @ 10020, iceberg 5 + 15 hidden @ 10100
+    fn setup_book() -> OrderBook {
+        let book = OrderBook::new("TEST");
+        book.add_limit_order(create_order_id(), 9990, 30, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 9980, 20, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 9900, 50, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 10010, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 10020, 20, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.add_iceberg_order(
+            create_order_id(),
+            10100,
+            5,
+            15,
+            Side::Sell,
+            TimeInForce::Gtc,
+        )
+        .unwrap();
+        book
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("metric should be defined");
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn test_depth_imbalance() {
+        let book = setup_book();
+
+        // Top 1: 30 bid vs 10 ask
+        assert_close(depth_imbalance(&book, 1), 0.5);
+        // Top 2: 50 bid vs 30 ask
+        assert_close(depth_imbalance(&book, 2), 0.25);
+        // All levels, visible only: 100 bid vs 35 ask
+        assert_close(depth_imbalance(&book, 10), 65.0 / 135.0);
+
+        assert_eq!(depth_imbalance(&OrderBook::new("EMPTY"), 5), None);
+    }
+
+    #[test]
+    fn test_microprice_leans_towards_thin_side() {
+        let book = setup_book();
+
+        // (9990 * 10 + 10010 * 30) / 40
+        assert_close(microprice(&book), 10005.0);
+        assert!(microprice(&book).unwrap() > book.mid_price().unwrap());
+    }
+
+    #[test]
+    fn test_spread_bps() {
+        let book = setup_book();
+
+        assert_close(spread_bps(&book), 20.0 / 10000.0 * 10_000.0);
+
+        let one_sided = OrderBook::new("TEST");
+        one_sided
+            .add_limit_order(create_order_id(), 100, 1, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        assert_eq!(spread_bps(&one_sided), None);
+        assert_eq!(microprice(&one_sided), None);
+    }
+
+    #[test]
+    fn test_cost_to_move_price() {
+        let book = setup_book();
+
+        // 15 bps above 10010 is 10025.015: both 10010 and 10020 have to be taken
+        let up = cost_to_move(&book, Side::Buy, 15.0).unwrap();
+        assert_eq!(up.from_price, 10010);
+        assert_eq!(up.quantity, 30);
+        assert_eq!(up.notional, 10 * 10010 + 20 * 10020);
+        assert!(!up.exhausts_side);
+
+        // 5 bps below 9990 is 9985.005: only 9990 has to be taken
+        let down = cost_to_move(&book, Side::Sell, 5.0).unwrap();
+        assert_eq!(down.from_price, 9990);
+        assert_eq!(down.quantity, 30);
+        assert!(!down.exhausts_side);
+
+        // 500 bps below the best bid takes the whole side
+        let through = cost_to_move(&book, Side::Sell, 500.0).unwrap();
+        assert_eq!(through.quantity, 100);
+        assert!(through.exhausts_side);
+
+        assert_eq!(
+            cost_to_move(&OrderBook::new("EMPTY"), Side::Buy, 10.0),
+            None
+        );
+    }
+
+    #[test]
+    fn test_depth_within_bps() {
+        let book = setup_book();
+
+        // Mid is 10000: 20 bps is [9980, 10020]
+        let depth = depth_within_bps(&book, 20.0).unwrap();
+        assert_eq!(depth.bid_quantity, 50);
+        assert_eq!(depth.ask_quantity, 30);
+
+        // 11 bps is [9989, 10011]
+        let depth = depth_within_bps(&book, 11.0).unwrap();
+        assert_eq!(depth.bid_quantity, 30);
+        assert_eq!(depth.ask_quantity, 10);
+    }
+
+    #[test]
+    fn test_snapshot_matches_live_book() {
+        let book = setup_book();
+        let snapshot = book.create_snapshot(usize::MAX);
+
+        assert_eq!(snapshot.bid_levels(), book.bid_levels());
+        assert_eq!(snapshot.ask_levels(), book.ask_levels());
+        assert_eq!(depth_imbalance(&snapshot, 2), depth_imbalance(&book, 2));
+        assert_eq!(microprice(&snapshot), microprice(&book));
+        assert_eq!(
+            cost_to_move(&snapshot, Side::Buy, 15.0),
+            cost_to_move(&book, Side::Buy, 15.0)
+        );
+        assert_eq!(DepthSource::timestamp(&snapshot), snapshot.timestamp);
+    }
+
+    #[test]
+    fn test_sample_of_live_book_matches_its_snapshot() {
+        let clock = Arc::new(ManualClock::new(5_000));
+        let book = setup_book().with_clock(clock);
+        let config = SamplerConfig::default();
+
+        let depth = book.visible_depth();
+        assert_eq!(depth.bids, book.bid_levels());
+        assert_eq!(depth.asks, book.ask_levels());
+        assert_eq!(depth.timestamp, 5_000);
+        assert_eq!(
+            AnalyticsSample::compute(&book, &config),
+            AnalyticsSample::compute(&book.create_snapshot(usize::MAX), &config)
+        );
+    }
+
+    #[test]
+    fn test_sampler_interval_and_capacity() {
+        let book = setup_book();
+        let mut snapshot = book.create_snapshot(10);
+        let mut sampler = AnalyticsSampler::new(SamplerConfig {
+            interval_ms: 1_000,
+            capacity: 2,
+            ..SamplerConfig::default()
+        });
+        assert!(sampler.is_empty());
+
+        snapshot.timestamp = 10_000;
+        let first = sampler.sample_if_due(&snapshot).unwrap();
+        assert_eq!(first.timestamp, 10_000);
+        assert_eq!(first.best_bid, Some(9990));
+        assert_eq!(first.best_ask, Some(10010));
+        assert!(first.cost_to_move_up.is_some());
+
+        // Not due yet
+        snapshot.timestamp = 10_500;
+        assert!(sampler.sample_if_due(&snapshot).is_none());
+
+        snapshot.timestamp = 11_000;
+        assert!(sampler.sample_if_due(&snapshot).is_some());
+        snapshot.timestamp = 12_000;
+        assert!(sampler.sample_if_due(&snapshot).is_some());
+
+        // The oldest sample has been dropped
+        let timestamps: Vec<u64> = sampler.samples().map(|sample| sample.timestamp).collect();
+        assert_eq!(timestamps, vec![11_000, 12_000]);
+        assert_eq!(sampler.latest().unwrap().timestamp, 12_000);
+
+        let drained = sampler.drain();
+        assert_eq!(drained.len(), 2);
+        assert!(sampler.is_empty());
+    }
+}
@@ -841,7 +841,7 @@ mod test_book_remaining {
 #[cfg(test)]
 mod test_book_specific {
     use crate::OrderBook;
-    use pricelevel::{OrderId, Side, TimeInForce};
+    use pricelevel::{OrderId, OrderUpdate, Side, TimeInForce};
     use uuid::Uuid;
 
     fn create_order_id() -> OrderId {
@@ -908,21 +908,54 @@ mod test_book_specific {
 
         // Try to match a market order on an empty book
         let id = create_order_id();
-        let result = book.match_market_order(id, 10, Side::Buy);
+        let result = book.match_market_order(id, 10, Side::Buy).unwrap();
 
-        // Should fail with insufficient liquidity
-        assert!(result.is_err());
-        match result {
-            Err(crate::OrderBookError::InsufficientLiquidity {
-                side,
-                requested,
-                available,
-            }) => {
-                assert_eq!(side, Side::Buy);
-                assert_eq!(requested, 10);
-                assert_eq!(available, 0);
-            }
-            _ => panic!("Expected InsufficientLiquidity error"),
-        }
+        // Nothing is matched
+        assert_eq!(result.executed_quantity(), 0);
+        assert_eq!(result.remaining_quantity, 10);
+        assert!(!result.is_complete);
+    }
+
+    #[test]
+    fn test_best_prices_follow_level_changes() {
+        let book = OrderBook::new("TEST");
+        let bid_low = create_order_id();
+        let bid_high = create_order_id();
+        let ask_low = create_order_id();
+        let ask_high = create_order_id();
+        book.add_limit_order(bid_low, 990, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(ask_high, 1020, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        // Reading one side must not disturb the other
+        assert_eq!(book.best_bid(), Some(990));
+        assert_eq!(book.best_ask(), Some(1020));
+
+        book.add_limit_order(bid_high, 995, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(ask_low, 1010, 5, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        assert_eq!(book.best_bid(), Some(995));
+        assert_eq!(book.best_ask(), Some(1010));
+
+        // Emptying the best level by a trade, a cancel or a price change moves the best price
+        book.submit_market_order(create_order_id(), 5, Side::Buy)
+            .unwrap();
+        assert_eq!(book.best_ask(), Some(1020));
+
+        book.cancel_order(bid_high).unwrap();
+        assert_eq!(book.best_bid(), Some(990));
+
+        book.update_order(OrderUpdate::UpdatePrice {
+            order_id: bid_low,
+            new_price: 1000,
+        })
+        .unwrap();
+        assert_eq!(book.best_bid(), Some(1000));
+
+        book.cancel_order(bid_low).unwrap();
+        book.cancel_order(ask_high).unwrap();
+        assert_eq!(book.best_bid(), None);
+        assert_eq!(book.best_ask(), None);
     }
 }
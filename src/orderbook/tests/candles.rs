@@ -0,0 +1,169 @@
+#[cfg(test)]
+mod tests {
+    use crate::{Candle, CandleAggregator, CandleInterval, OrderBook};
+    use pricelevel::{OrderId, Side, TimeInForce};
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    // Helper function to create a unique order ID
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    #[test]
+    fn test_interval_names_and_open_time() {
+        for interval in CandleInterval::ALL {
+            assert_eq!(CandleInterval::from_str(interval.as_str()), Ok(interval));
+        }
+        assert!(CandleInterval::from_str("2m").is_err());
+
+        assert_eq!(CandleInterval::OneMinute.open_time(125_000), 120_000);
+        assert_eq!(CandleInterval::OneDay.open_time(86_400_000 + 5), 86_400_000);
+        assert_eq!(
+            serde_json::to_string(&CandleInterval::FiveMinutes).unwrap(),
+            "\"5m\""
+        );
+    }
+
+    #[test]
+    fn test_candle_ohlcv() {
+        let aggregator = CandleAggregator::new(&[CandleInterval::OneMinute], 10);
+        aggregator.record_trade(100, 2, 60_000);
+        aggregator.record_trade(110, 1, 70_000);
+        aggregator.record_trade(95, 3, 80_000);
+        aggregator.record_trade(105, 4, 119_999);
+
+        let candle = aggregator.current(CandleInterval::OneMinute).unwrap();
+        assert_eq!(
+            candle,
+            Candle {
+                interval: CandleInterval::OneMinute,
+                open_time: 60_000,
+                close_time: 120_000,
+                open: 100,
+                high: 110,
+                low: 95,
+                close: 105,
+                volume: 10,
+                quote_volume: 200 + 110 + 285 + 420,
+                trade_count: 4,
+            }
+        );
+        assert!(aggregator.drain_closed().is_empty());
+        assert_eq!(aggregator.current(CandleInterval::OneSecond), None);
+    }
+
+    #[test]
+    fn test_candle_closed_by_next_interval_trade() {
+        let aggregator =
+            CandleAggregator::new(&[CandleInterval::OneSecond, CandleInterval::OneMinute], 10);
+        aggregator.record_trade(100, 1, 1_500);
+        aggregator.record_trade(101, 1, 2_100);
+        // Skipped seconds produce no candle
+        aggregator.record_trade(102, 1, 5_000);
+
+        let closed = aggregator.drain_closed();
+        let opens: Vec<(u64, u64)> = closed
+            .iter()
+            .map(|candle| (candle.open_time, candle.open))
+            .collect();
+        assert_eq!(opens, vec![(1_000, 100), (2_000, 101)]);
+        assert!(
+            closed
+                .iter()
+                .all(|candle| candle.interval == CandleInterval::OneSecond)
+        );
+
+        let minute = aggregator.current(CandleInterval::OneMinute).unwrap();
+        assert_eq!(minute.trade_count, 3);
+        assert_eq!(minute.close, 102);
+        assert!(aggregator.drain_closed().is_empty());
+    }
+
+    #[test]
+    fn test_close_elapsed_and_retention() {
+        let aggregator = CandleAggregator::new(&[CandleInterval::OneSecond], 2);
+        aggregator.record_trade(100, 1, 1_000);
+        aggregator.record_trade(100, 1, 2_000);
+        aggregator.record_trade(100, 1, 3_000);
+
+        // Still open: its interval has not ended
+        aggregator.close_elapsed(3_999);
+        assert!(aggregator.current(CandleInterval::OneSecond).is_some());
+
+        aggregator.close_elapsed(4_000);
+        assert_eq!(aggregator.current(CandleInterval::OneSecond), None);
+
+        // Only the two most recent finished candles are kept
+        let opens: Vec<u64> = aggregator
+            .drain_closed()
+            .iter()
+            .map(|candle| candle.open_time)
+            .collect();
+        assert_eq!(opens, vec![2_000, 3_000]);
+    }
+
+    #[test]
+    fn test_late_trade_counted_in_current_candle() {
+        let aggregator = CandleAggregator::new(&[CandleInterval::OneSecond], 10);
+        aggregator.record_trade(100, 1, 2_000);
+        aggregator.record_trade(90, 1, 1_999);
+
+        let candle = aggregator.current(CandleInterval::OneSecond).unwrap();
+        assert_eq!(candle.open_time, 2_000);
+        assert_eq!(candle.low, 90);
+        assert_eq!(candle.trade_count, 2);
+        assert!(aggregator.drain_closed().is_empty());
+    }
+
+    #[test]
+    fn test_late_trade_after_close_does_not_finish_interval_twice() {
+        let aggregator = CandleAggregator::new(&[CandleInterval::OneSecond], 10);
+        aggregator.record_trade(100, 1, 1_500);
+        aggregator.close_elapsed(2_000);
+        aggregator.record_trade(90, 1, 1_999);
+        aggregator.close_elapsed(3_000);
+
+        let opens: Vec<(u64, u64)> = aggregator
+            .drain_closed()
+            .iter()
+            .map(|candle| (candle.open_time, candle.trade_count))
+            .collect();
+        assert_eq!(opens, vec![(1_000, 1), (2_000, 1)]);
+    }
+
+    #[test]
+    fn test_book_builds_candles_from_trades() {
+        let book = OrderBook::new("TEST");
+        book.add_limit_order(create_order_id(), 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 1010, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        assert_eq!(book.candles().current(CandleInterval::OneMinute), None);
+
+        book.submit_market_order(create_order_id(), 15, Side::Buy)
+            .unwrap();
+
+        let candle = book.candles().current(CandleInterval::OneMinute).unwrap();
+        assert_eq!(candle.open, 1000);
+        assert_eq!(candle.high, 1010);
+        assert_eq!(candle.low, 1000);
+        assert_eq!(candle.close, 1010);
+        assert_eq!(candle.volume, 15);
+        assert_eq!(candle.quote_volume, 10 * 1000 + 5 * 1010);
+        assert_eq!(candle.trade_count, 2);
+        assert_eq!(book.candles().intervals(), &CandleInterval::ALL);
+    }
+
+    #[test]
+    fn test_book_without_candle_intervals() {
+        let book = OrderBook::new("TEST").with_candle_intervals(&[]);
+        book.add_limit_order(create_order_id(), 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.submit_market_order(create_order_id(), 5, Side::Buy)
+            .unwrap();
+
+        assert!(book.candles().intervals().is_empty());
+        assert_eq!(book.candles().current(CandleInterval::OneSecond), None);
+    }
+}
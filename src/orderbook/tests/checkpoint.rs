@@ -0,0 +1,381 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        CheckpointConfig, CheckpointManager, Command, CommandOutcome, Journal, JournalEntry,
+        JournalOutcome, ManualClock, OrderBook, OrderOwner, OrderStatus, Sequencer,
+        SequencerConfig,
+    };
+    use pricelevel::{OrderId, OrderType, Side, TimeInForce};
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    // Helper function to create a unique order ID
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("orderbook-checkpoint-{}", Uuid::new_v4()))
+    }
+
+    fn limit_order(id: OrderId, price: u64, quantity: u64, side: Side) -> OrderType {
+        OrderType::Standard {
+            id,
+            price,
+            quantity,
+            side,
+            timestamp: crate::current_time_millis(),
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    fn order_ids(book: &OrderBook) -> Vec<OrderId> {
+        book.export_state()
+            .orders
+            .iter()
+            .map(|order| order.id())
+            .collect()
+    }
+
+    #[test]
+    fn test_export_and_restore_state() {
+        let book = OrderBook::new("TEST");
+        let first = create_order_id();
+        let owner = OrderOwner::new(Uuid::new_v4(), Some("client-1".to_string()));
+        book.add_order_with_owner(limit_order(first, 1000, 10, Side::Sell), owner.clone())
+            .unwrap();
+        book.add_limit_order(create_order_id(), 1000, 5, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 990, 7, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.submit_market_order(create_order_id(), 4, Side::Buy)
+            .unwrap();
+
+        let state = book.export_state();
+        assert_eq!(state.sequence, 4);
+        assert_eq!(state.last_trade_price, Some(1000));
+
+        let restored = OrderBook::new("TEST");
+        restored.restore_state(state).unwrap();
+        assert_eq!(restored.sequence(), 4);
+        assert_eq!(restored.best_ask(), Some(1000));
+        assert_eq!(restored.best_bid(), Some(990));
+        assert_eq!(restored.last_trade_price(), Some(1000));
+        assert_eq!(order_ids(&restored), order_ids(&book));
+        assert_eq!(
+            restored.order_state(first).unwrap().status,
+            OrderStatus::PartiallyFilled
+        );
+        assert_eq!(
+            restored.find_order_id(owner.account, "client-1"),
+            Some(first)
+        );
+        assert!(restored.check_invariants().is_ok());
+
+        // Both books match the next order the same way
+        book.submit_market_order(create_order_id(), 6, Side::Buy)
+            .unwrap();
+        restored
+            .submit_market_order(create_order_id(), 6, Side::Buy)
+            .unwrap();
+        assert_eq!(order_ids(&restored), order_ids(&book));
+        assert_eq!(restored.order_state(first), book.order_state(first));
+
+        // Only empty books of the same symbol can be restored
+        assert!(restored.restore_state(book.export_state()).is_err());
+        assert!(
+            OrderBook::new("OTHER")
+                .restore_state(book.export_state())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_recovery_replays_the_journal_after_the_checkpoint() {
+        let dir = temp_dir();
+        let manager = CheckpointManager::open(&dir, CheckpointConfig::default()).unwrap();
+        let sequencer = Sequencer::with_journal(
+            OrderBook::new("TEST"),
+            SequencerConfig::default(),
+            manager.journal(),
+        );
+        let maker = create_order_id();
+        sequencer
+            .add_order(limit_order(maker, 1000, 10, Side::Sell), None)
+            .unwrap();
+        sequencer
+            .add_order(limit_order(create_order_id(), 990, 3, Side::Buy), None)
+            .unwrap();
+        manager.checkpoint([sequencer.book()], 0).unwrap();
+
+        sequencer
+            .submit_market_order(create_order_id(), 4, Side::Buy, None)
+            .unwrap();
+        sequencer
+            .add_order(limit_order(create_order_id(), 1010, 2, Side::Sell), None)
+            .unwrap();
+        let expected = order_ids(sequencer.book());
+        drop(sequencer);
+        drop(manager);
+
+        let manager = CheckpointManager::open(&dir, CheckpointConfig::default()).unwrap();
+        let book = OrderBook::new("TEST");
+        let report = manager.recover([&book]).unwrap();
+        assert_eq!(report.restored_books, 1);
+        assert_eq!(report.replayed_commands, 2);
+        assert_eq!(book.sequence(), 4);
+        assert_eq!(order_ids(&book), expected);
+        assert_eq!(book.order_state(maker).unwrap().filled_quantity, 4);
+
+        // Sequencing resumes where the journal stopped
+        let sequencer =
+            Sequencer::with_journal(book, SequencerConfig::default(), manager.journal());
+        assert_eq!(sequencer.view().version, 4);
+        let next = sequencer
+            .execute(crate::Command::CancelOrder(maker))
+            .unwrap();
+        assert_eq!(next.sequence, 5);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_old_checkpoints_and_segments_are_pruned() {
+        let dir = temp_dir();
+        let config = CheckpointConfig {
+            retained_checkpoints: 2,
+            ..CheckpointConfig::default()
+        };
+        let manager = CheckpointManager::open(&dir, config).unwrap();
+        let sequencer = Sequencer::with_journal(
+            OrderBook::new("TEST"),
+            SequencerConfig::default(),
+            manager.journal(),
+        );
+
+        let mut taken = Vec::new();
+        for price in 1000..1004 {
+            sequencer
+                .add_order(limit_order(create_order_id(), price, 1, Side::Sell), None)
+                .unwrap();
+            taken.push(manager.checkpoint([sequencer.book()], 0).unwrap());
+        }
+
+        assert_eq!(manager.checkpoints().unwrap(), taken[2..].to_vec());
+        let segments = manager.journal().segments().unwrap();
+        assert_eq!(segments.first(), Some(&taken[2]));
+
+        drop(sequencer);
+        let book = OrderBook::new("TEST");
+        let report = manager.recover([&book]).unwrap();
+        assert_eq!(report.checkpoint_segment, Some(taken[3]));
+        assert_eq!(book.export_state().orders.len(), 4);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_journal_entry_is_ignored() {
+        let dir = temp_dir();
+        let journal = Journal::open(&dir, u64::MAX).unwrap();
+        let sequencer = Sequencer::with_journal(
+            OrderBook::new("TEST"),
+            SequencerConfig::default(),
+            std::sync::Arc::new(journal),
+        );
+        sequencer
+            .add_order(limit_order(create_order_id(), 1000, 1, Side::Sell), None)
+            .unwrap();
+        drop(sequencer);
+
+        let journal = Journal::open(&dir, u64::MAX).unwrap();
+        let segment = journal.segments().unwrap()[0];
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join(format!("segment-{segment:010}.jsonl")))
+            .unwrap();
+        file.write_all(b"{\"symbol\":\"TEST\",\"seq").unwrap();
+
+        let entries = journal.read_segment(segment).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].sequence, 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_command_that_cannot_be_journaled_is_not_applied() {
+        let dir = temp_dir();
+        // Every entry fills its segment, so the next one has to create a new file
+        let journal = Journal::open(&dir, 1).unwrap();
+        let sequencer = Sequencer::with_journal(
+            OrderBook::new("TEST"),
+            SequencerConfig::default(),
+            std::sync::Arc::new(journal),
+        );
+        let first = create_order_id();
+        sequencer
+            .add_order(limit_order(first, 1000, 1, Side::Sell), None)
+            .unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+        let refused = create_order_id();
+        assert!(
+            sequencer
+                .add_order(limit_order(refused, 1001, 1, Side::Sell), None)
+                .is_err()
+        );
+        assert!(sequencer.book().get_order(refused).is_none());
+        assert_eq!(sequencer.book().sequence(), 1);
+
+        // The sequencer has stopped
+        assert!(
+            sequencer
+                .add_order(limit_order(create_order_id(), 1002, 1, Side::Sell), None)
+                .is_err()
+        );
+        assert_eq!(order_ids(sequencer.book()), vec![first]);
+    }
+
+    #[test]
+    fn test_replay_runs_at_the_journaled_time() {
+        let dir = temp_dir();
+        let manager = CheckpointManager::open(&dir, CheckpointConfig::default()).unwrap();
+        let clock = Arc::new(ManualClock::new(1_000));
+        let sequencer = Sequencer::with_journal(
+            OrderBook::new("TEST").with_clock(clock.clone()),
+            SequencerConfig::default(),
+            manager.journal(),
+        );
+        // Good until 2_000, which has passed by the time the journal is replayed
+        let maker = create_order_id();
+        let order = OrderType::Standard {
+            id: maker,
+            price: 1000,
+            quantity: 10,
+            side: Side::Sell,
+            timestamp: 1_000,
+            time_in_force: TimeInForce::Gtd(2_000),
+        };
+        sequencer.add_order(order, None).unwrap();
+        clock.set(1_500);
+        sequencer
+            .submit_market_order(create_order_id(), 4, Side::Buy, None)
+            .unwrap();
+        drop(sequencer);
+        drop(manager);
+
+        let manager = CheckpointManager::open(&dir, CheckpointConfig::default()).unwrap();
+        let book = OrderBook::new("TEST").with_clock(Arc::new(ManualClock::new(5_000)));
+        let report = manager.recover([&book]).unwrap();
+        assert_eq!(report.replayed_commands, 2);
+        assert_eq!(report.diverged_commands, 0);
+        let record = book.order_state(maker).unwrap();
+        assert_eq!(record.status, OrderStatus::PartiallyFilled);
+        assert_eq!(record.created_at, 1_000);
+        assert_eq!(record.updated_at, 1_500);
+        assert_eq!(book.trade_tape().recent(0, 1)[0].timestamp, 1_500);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replay_that_differs_from_the_journal_is_reported() {
+        let dir = temp_dir();
+        let manager = CheckpointManager::open(&dir, CheckpointConfig::default()).unwrap();
+        let command = Command::SubmitMarketOrder {
+            id: create_order_id(),
+            quantity: 5,
+            side: Side::Buy,
+            owner: None,
+        };
+        // Journaled as filled, but the book it is replayed on has nothing to sell
+        manager
+            .journal()
+            .append(&JournalEntry {
+                symbol: "TEST".to_string(),
+                sequence: 1,
+                timestamp: 1_000,
+                command,
+                outcome: None,
+            })
+            .unwrap();
+        manager
+            .journal()
+            .append_outcome(&JournalOutcome {
+                symbol: "TEST".to_string(),
+                sequence: 1,
+                outcome: CommandOutcome {
+                    succeeded: true,
+                    status: Some(OrderStatus::Filled),
+                    filled_quantity: 5,
+                    cancelled_orders: 0,
+                },
+            })
+            .unwrap();
+        manager.journal().rotate().unwrap();
+
+        let book = OrderBook::new("TEST");
+        let report = manager.recover([&book]).unwrap();
+        assert_eq!(report.replayed_commands, 1);
+        assert_eq!(report.diverged_commands, 1);
+        assert_eq!(book.sequence(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_waits_for_journaled_commands_to_be_applied() {
+        let dir = temp_dir();
+        let manager = CheckpointManager::open(&dir, CheckpointConfig::default()).unwrap();
+        let journal = manager.journal();
+        let book = OrderBook::new("TEST");
+        let id = create_order_id();
+        let command = Command::AddOrder {
+            order: limit_order(id, 1000, 10, Side::Sell),
+            owner: None,
+        };
+
+        // What a sequencer writer does, stopped between the journal and the book
+        let applying = journal.begin_command();
+        journal
+            .append(&JournalEntry {
+                symbol: "TEST".to_string(),
+                sequence: 1,
+                timestamp: 0,
+                command: command.clone(),
+                outcome: None,
+            })
+            .unwrap();
+
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let segment = manager.checkpoint([&book], 0).unwrap();
+                done_tx.send(segment).unwrap();
+            });
+            assert!(
+                done_rx
+                    .recv_timeout(std::time::Duration::from_millis(100))
+                    .is_err()
+            );
+
+            command.apply_to(&book);
+            drop(applying);
+            done_rx.recv().unwrap();
+        });
+        drop(journal);
+        drop(manager);
+
+        let manager = CheckpointManager::open(&dir, CheckpointConfig::default()).unwrap();
+        let recovered = OrderBook::new("TEST");
+        manager.recover([&recovered]).unwrap();
+        assert_eq!(recovered.sequence(), 1);
+        assert_eq!(order_ids(&recovered), vec![id]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use crate::{ManualClock, OrderBook, OrderBookError, OrderStatus};
+    use pricelevel::{OrderId, Side, TimeInForce};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    // Helper function to create a unique order ID
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    const START: u64 = 1_700_000_000_000;
+
+    fn book_with_clock() -> (OrderBook, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new(START));
+        let book = OrderBook::new("TEST").with_clock(clock.clone());
+        (book, clock)
+    }
+
+    #[test]
+    fn test_timestamps_come_from_the_clock() {
+        let (book, clock) = book_with_clock();
+        let maker = create_order_id();
+        book.add_limit_order(maker, 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        assert_eq!(book.get_order(maker).unwrap().timestamp(), START);
+
+        clock.advance(Duration::from_secs(5));
+        let taker = create_order_id();
+        let report = book.submit_market_order(taker, 4, Side::Buy).unwrap();
+
+        assert_eq!(report.fills[0].timestamp, START + 5_000);
+        assert_eq!(book.trade_tape().recent(0, 1)[0].timestamp, START + 5_000);
+        assert_eq!(book.order_state(maker).unwrap().created_at, START);
+        assert_eq!(book.order_state(maker).unwrap().updated_at, START + 5_000);
+        assert_eq!(book.create_snapshot(1).timestamp, START + 5_000);
+    }
+
+    #[test]
+    fn test_good_till_date_expiry() {
+        let (book, clock) = book_with_clock();
+        let expiry = START + 60_000;
+
+        let live = create_order_id();
+        book.add_limit_order(live, 1000, 10, Side::Buy, TimeInForce::Gtd(expiry))
+            .unwrap();
+
+        clock.set(expiry);
+        let late = create_order_id();
+        let result = book.add_limit_order(late, 1000, 10, Side::Buy, TimeInForce::Gtd(expiry));
+        assert!(matches!(
+            result,
+            Err(OrderBookError::InvalidOperation { .. })
+        ));
+        assert_eq!(book.order_state(late).unwrap().status, OrderStatus::Expired);
+        assert_eq!(book.order_state(late).unwrap().updated_at, expiry);
+    }
+
+    #[test]
+    fn test_day_orders_after_market_close() {
+        let (book, clock) = book_with_clock();
+        book.set_market_close_timestamp(START + 3_600_000);
+
+        book.add_limit_order(create_order_id(), 1000, 10, Side::Buy, TimeInForce::Day)
+            .unwrap();
+
+        clock.advance(Duration::from_secs(3_600));
+        let result = book.add_limit_order(create_order_id(), 1000, 10, Side::Buy, TimeInForce::Day);
+        assert!(result.is_err());
+        // Orders without expiry are not affected by the close
+        assert!(
+            book.add_limit_order(create_order_id(), 1000, 10, Side::Buy, TimeInForce::Gtc)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_rolling_stats_follow_the_clock() {
+        let (book, clock) = book_with_clock();
+        book.add_limit_order(create_order_id(), 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.submit_market_order(create_order_id(), 4, Side::Buy)
+            .unwrap();
+        assert_eq!(book.trade_stats().volume, 4);
+
+        // A simulated day goes by instantly
+        clock.advance(Duration::from_secs(25 * 3_600));
+        assert_eq!(book.trade_stats().volume, 0);
+    }
+}
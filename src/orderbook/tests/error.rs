@@ -36,6 +36,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_display_duplicate_client_order_id() {
+        let account = uuid::Uuid::nil();
+        let err = OrderBookError::DuplicateClientOrderId {
+            account,
+            client_order_id: "abc-1".to_string(),
+        };
+        assert_eq!(
+            format!("{err}"),
+            format!("Duplicate client order id: abc-1 is already live for account {account}")
+        );
+    }
+
     #[test]
     fn test_display_insufficient_liquidity() {
         let err = OrderBookError::InsufficientLiquidity {
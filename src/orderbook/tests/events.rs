@@ -0,0 +1,225 @@
+#[cfg(test)]
+mod tests {
+    use crate::{BookEvent, EventSink, OrderBook, OrderStatus};
+    use pricelevel::{OrderId, Side, TimeInForce};
+    use std::sync::{Arc, Mutex, OnceLock, Weak};
+    use uuid::Uuid;
+
+    // Helper function to create a unique order ID
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingSink {
+        events: Mutex<Vec<BookEvent>>,
+    }
+
+    impl RecordingSink {
+        fn take(&self) -> Vec<BookEvent> {
+            std::mem::take(&mut *self.events.lock().unwrap())
+        }
+    }
+
+    impl EventSink for RecordingSink {
+        fn publish(&self, event: &BookEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    fn order_update(event: &BookEvent) -> Option<(OrderId, OrderStatus, u64)> {
+        match event {
+            BookEvent::OrderUpdated { record, .. } => {
+                Some((record.order_id, record.status, record.filled_quantity))
+            }
+            BookEvent::Trade { .. } => None,
+        }
+    }
+
+    #[test]
+    fn test_trades_and_order_changes_are_published() {
+        let book = OrderBook::new("TEST");
+        let early = create_order_id();
+        book.add_limit_order(early, 990, 1, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+
+        let sink = Arc::new(RecordingSink::default());
+        book.subscribe(sink.clone());
+        assert!(sink.take().is_empty());
+
+        let maker = create_order_id();
+        book.add_limit_order(maker, 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        let events = sink.take();
+        assert_eq!(
+            events.iter().filter_map(order_update).collect::<Vec<_>>(),
+            vec![(maker, OrderStatus::New, 0)]
+        );
+        assert_eq!(events[0].sequence(), book.sequence());
+
+        let taker = create_order_id();
+        book.submit_market_order(taker, 4, Side::Buy).unwrap();
+        let events = sink.take();
+        let BookEvent::Trade {
+            symbol,
+            sequence,
+            trade,
+        } = &events[0]
+        else {
+            panic!("expected a trade first, got {:?}", events[0]);
+        };
+        assert_eq!(symbol, "TEST");
+        assert_eq!((trade.taker_order_id, trade.maker_order_id), (taker, maker));
+        assert_eq!(trade.quantity, 4);
+        assert!(events.iter().all(|event| event.sequence() == *sequence));
+        assert_eq!(*sequence, book.sequence());
+
+        let updates: Vec<_> = events.iter().filter_map(order_update).collect();
+        assert!(updates.contains(&(maker, OrderStatus::PartiallyFilled, 4)));
+        assert!(updates.contains(&(taker, OrderStatus::Filled, 4)));
+    }
+
+    #[test]
+    fn test_each_changed_order_is_published_once_per_mutation() {
+        let book = OrderBook::new("TEST");
+        let sink = Arc::new(RecordingSink::default());
+        book.subscribe(sink.clone());
+
+        let maker = create_order_id();
+        book.add_limit_order(maker, 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        sink.take();
+
+        // One taker filled against two makers is published once, in its final state
+        let taker = create_order_id();
+        book.submit_market_order(taker, 15, Side::Buy).unwrap();
+        let updates: Vec<_> = sink.take().iter().filter_map(order_update).collect();
+        assert_eq!(updates.len(), 3);
+        assert_eq!(updates.iter().filter(|(id, _, _)| *id == taker).count(), 1);
+
+        book.cancel_order(maker).unwrap();
+        assert!(sink.take().is_empty(), "a filled order cannot be cancelled");
+
+        let resting = create_order_id();
+        book.add_limit_order(resting, 1100, 1, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.cancel_order(resting).unwrap();
+        let updates: Vec<_> = sink.take().iter().filter_map(order_update).collect();
+        assert_eq!(
+            updates,
+            vec![
+                (resting, OrderStatus::New, 0),
+                (resting, OrderStatus::Cancelled, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_order_updates_carry_the_displayed_quantity() {
+        let book = OrderBook::new("TEST");
+        let sink = Arc::new(RecordingSink::default());
+        book.subscribe(sink.clone());
+
+        let visible_quantity = |events: Vec<BookEvent>, id: OrderId| {
+            events.into_iter().find_map(|event| match event {
+                BookEvent::OrderUpdated {
+                    record,
+                    visible_quantity,
+                    ..
+                } if record.order_id == id => Some(visible_quantity),
+                _ => None,
+            })
+        };
+
+        let iceberg = create_order_id();
+        book.add_iceberg_order(iceberg, 1000, 5, 15, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        assert_eq!(visible_quantity(sink.take(), iceberg), Some(Some(5)));
+
+        book.submit_market_order(create_order_id(), 3, Side::Buy)
+            .unwrap();
+        assert_eq!(visible_quantity(sink.take(), iceberg), Some(Some(2)));
+
+        book.cancel_order(iceberg).unwrap();
+        assert_eq!(visible_quantity(sink.take(), iceberg), Some(None));
+    }
+
+    /// Takes a snapshot of the book each time it receives an event
+    #[derive(Debug, Default)]
+    struct SnapshottingSink {
+        book: OnceLock<Weak<OrderBook>>,
+        sequences: Mutex<Vec<u64>>,
+    }
+
+    impl EventSink for SnapshottingSink {
+        fn publish(&self, _event: &BookEvent) {
+            if let Some(book) = self.book.get().and_then(Weak::upgrade) {
+                let snapshot = book.create_snapshot(10);
+                self.sequences.lock().unwrap().push(snapshot.sequence);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sinks_are_called_after_the_mutation_released_the_book() {
+        let book = Arc::new(OrderBook::new("TEST"));
+        let sink = Arc::new(SnapshottingSink::default());
+        sink.book.set(Arc::downgrade(&book)).unwrap();
+        book.subscribe(sink.clone());
+
+        // A sink called while the mutation still held the snapshot gate would deadlock
+        book.add_limit_order(create_order_id(), 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.submit_market_order(create_order_id(), 4, Side::Buy)
+            .unwrap();
+
+        let sequences = sink.sequences.lock().unwrap().clone();
+        assert!(!sequences.is_empty());
+        assert!(
+            sequences
+                .iter()
+                .all(|sequence| *sequence <= book.sequence())
+        );
+    }
+
+    #[test]
+    fn test_concurrent_mutations_publish_their_own_changes() {
+        let book = Arc::new(OrderBook::new("TEST"));
+        let sink = Arc::new(RecordingSink::default());
+        book.subscribe(sink.clone());
+
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let book = Arc::clone(&book);
+                std::thread::spawn(move || {
+                    for i in 0..50 {
+                        let (price, side) = if t % 2 == 0 {
+                            (900 + i, Side::Buy)
+                        } else {
+                            (1100 + i, Side::Sell)
+                        };
+                        book.add_limit_order(create_order_id(), price, 1, side, TimeInForce::Gtc)
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // Each mutation rested one order, published once under its own sequence number
+        let mut sequences: Vec<u64> = sink
+            .take()
+            .iter()
+            .map(|event| {
+                assert!(order_update(event).is_some());
+                event.sequence()
+            })
+            .collect();
+        sequences.sort_unstable();
+        assert_eq!(sequences, (1..=400).collect::<Vec<u64>>());
+    }
+}
@@ -0,0 +1,171 @@
+#[cfg(test)]
+mod tests {
+    use crate::{CancelledRemainder, FeeSchedule, OrderBook, OrderStatus, TerminalReason};
+    use pricelevel::{OrderId, Side, TimeInForce};
+    use uuid::Uuid;
+
+    // Helper function to create a unique order ID
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    // Rest 10 @ 1000 and 10 @ 1010 on the ask side
+    fn setup_asks(book: &OrderBook) -> (OrderId, OrderId) {
+        let (first, second) = (create_order_id(), create_order_id());
+        book.add_limit_order(first, 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(second, 1010, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        (first, second)
+    }
+
+    #[test]
+    fn test_resting_order_report() {
+        let book = OrderBook::new("TEST");
+        let id = create_order_id();
+
+        let report = book
+            .add_limit_order(id, 1000, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+
+        assert_eq!(report.order_id, id);
+        assert_eq!(report.side, Side::Buy);
+        assert_eq!(report.status, OrderStatus::New);
+        assert!(report.fills.is_empty());
+        assert_eq!(report.resting_quantity(), 10);
+        assert_eq!(report.resting.as_ref().unwrap().id(), id);
+        assert!(report.cancelled.is_none());
+        assert_eq!(report.average_price(), None);
+    }
+
+    #[test]
+    fn test_limit_order_partially_executed_on_entry() {
+        let book = OrderBook::new("TEST");
+        let (first, second) = setup_asks(&book);
+        let id = create_order_id();
+
+        let report = book
+            .add_limit_order(id, 1010, 25, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+
+        assert_eq!(report.status, OrderStatus::PartiallyFilled);
+        assert_eq!(report.fills.len(), 2);
+        assert_eq!(report.fills[0].maker_order_id, first);
+        assert_eq!(report.fills[0].price, 1000);
+        assert_eq!(report.fills[0].quantity, 10);
+        assert_eq!(report.fills[1].maker_order_id, second);
+        assert_eq!(report.fills[1].price, 1010);
+        assert_eq!(report.fills[1].quantity, 10);
+        assert_eq!(report.filled_quantity(), 20);
+        assert_eq!(report.average_price(), Some(1005.0));
+        assert_eq!(report.resting_quantity(), 5);
+        assert!(report.cancelled.is_none());
+        assert_eq!(book.best_bid(), Some(1010));
+    }
+
+    #[test]
+    fn test_fully_executed_limit_order() {
+        let book = OrderBook::new("TEST");
+        setup_asks(&book);
+
+        let report = book
+            .add_limit_order(create_order_id(), 1000, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+
+        assert_eq!(report.status, OrderStatus::Filled);
+        assert_eq!(report.filled_quantity(), 10);
+        assert!(report.resting.is_none());
+        assert!(report.cancelled.is_none());
+    }
+
+    #[test]
+    fn test_ioc_reports_fills_and_cancelled_remainder() {
+        let book = OrderBook::new("TEST");
+        let (first, _) = setup_asks(&book);
+
+        let report = book
+            .add_limit_order(create_order_id(), 1000, 15, Side::Buy, TimeInForce::Ioc)
+            .unwrap();
+
+        assert_eq!(report.status, OrderStatus::Cancelled);
+        assert_eq!(report.fills.len(), 1);
+        assert_eq!(report.fills[0].maker_order_id, first);
+        assert!(report.resting.is_none());
+        assert_eq!(
+            report.cancelled,
+            Some(CancelledRemainder {
+                quantity: 5,
+                reason: TerminalReason::NoLiquidity,
+            })
+        );
+    }
+
+    #[test]
+    fn test_ioc_without_liquidity() {
+        let book = OrderBook::new("TEST");
+
+        let report = book
+            .add_limit_order(create_order_id(), 1000, 15, Side::Buy, TimeInForce::Ioc)
+            .unwrap();
+
+        assert_eq!(report.status, OrderStatus::Cancelled);
+        assert!(report.fills.is_empty());
+        assert_eq!(report.cancelled.unwrap().quantity, 15);
+    }
+
+    #[test]
+    fn test_iceberg_resting_quantity_includes_hidden() {
+        let book = OrderBook::new("TEST");
+
+        let report = book
+            .add_iceberg_order(create_order_id(), 1000, 5, 20, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+
+        assert_eq!(report.status, OrderStatus::New);
+        assert_eq!(report.resting_quantity(), 25);
+    }
+
+    #[test]
+    fn test_market_order_report() {
+        let book = OrderBook::new("TEST");
+        let (first, second) = setup_asks(&book);
+
+        let report = book
+            .submit_market_order(create_order_id(), 15, Side::Buy)
+            .unwrap();
+
+        assert_eq!(report.status, OrderStatus::Filled);
+        assert_eq!(
+            report
+                .fills
+                .iter()
+                .map(|fill| (fill.maker_order_id, fill.quantity))
+                .collect::<Vec<_>>(),
+            vec![(first, 10), (second, 5)]
+        );
+        assert!(report.resting.is_none());
+    }
+
+    #[test]
+    fn test_taker_fees() {
+        let book = OrderBook::new("TEST").with_fee_schedule(FeeSchedule::new(-2, 10));
+        setup_asks(&book);
+
+        let report = book
+            .submit_market_order(create_order_id(), 20, Side::Buy)
+            .unwrap();
+
+        // 10 bps of 10 * 1000 and of 10 * 1010
+        assert_eq!(report.fills[0].fee, 10);
+        assert_eq!(report.fills[1].fee, 10);
+        assert_eq!(report.total_fee(), 20);
+    }
+
+    #[test]
+    fn test_fee_schedule() {
+        let fees = FeeSchedule::new(-5, 20);
+        assert_eq!(fees.taker_fee(50_000, 300), 30_000);
+        assert_eq!(fees.maker_fee(50_000, 300), -7_500);
+        assert_eq!(FeeSchedule::default().taker_fee(50_000, 300), 0);
+    }
+}
@@ -0,0 +1,167 @@
+#[cfg(test)]
+mod tests {
+    use crate::{InvariantViolation, OrderBook};
+    use pricelevel::{OrderId, OrderType, OrderUpdate, PriceLevel, Side, TimeInForce};
+    use std::sync::Arc;
+    use std::thread;
+    use uuid::Uuid;
+
+    // Helper function to create a unique order ID
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    fn limit_order(id: OrderId, price: u64, quantity: u64, side: Side) -> OrderType {
+        OrderType::Standard {
+            id,
+            price,
+            quantity,
+            side,
+            timestamp: crate::current_time_millis(),
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    #[test]
+    fn test_book_after_regular_operations_is_consistent() {
+        let book = OrderBook::new("TEST");
+        let bid = create_order_id();
+        let ask = create_order_id();
+        book.add_limit_order(bid, 990, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(ask, 1010, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.add_iceberg_order(create_order_id(), 1010, 5, 20, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.submit_market_order(create_order_id(), 12, Side::Buy)
+            .unwrap();
+        book.update_order(OrderUpdate::UpdatePrice {
+            order_id: bid,
+            new_price: 995,
+        })
+        .unwrap();
+        book.cancel_order(ask).ok();
+
+        let report = book.check_invariants();
+        assert!(report.is_ok(), "{report}");
+        assert_eq!(report.sequence, book.sequence());
+        assert_eq!(report.orders_checked, book.get_all_orders().len());
+        assert_eq!(report.levels_checked, 2);
+    }
+
+    #[test]
+    fn test_concurrent_operations_keep_invariants() {
+        let book = Arc::new(OrderBook::new("TEST"));
+        let handles: Vec<_> = (0..4u64)
+            .map(|t| {
+                let book = Arc::clone(&book);
+                thread::spawn(move || {
+                    let mut own = Vec::new();
+                    for i in 0..300u64 {
+                        let offset = (t * 5 + i) % 8;
+                        let id = create_order_id();
+                        let (price, side) = if i % 2 == 0 {
+                            (996 - offset, Side::Buy)
+                        } else {
+                            (1004 + offset, Side::Sell)
+                        };
+                        book.add_limit_order(id, price, 1 + i % 3, side, TimeInForce::Gtc)
+                            .unwrap();
+                        own.push(id);
+                        match i % 4 {
+                            0 => {
+                                book.cancel_order(own.swap_remove(0)).ok();
+                            }
+                            1 => {
+                                book.submit_market_order(create_order_id(), 2, side.opposite())
+                                    .ok();
+                            }
+                            _ => {}
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let report = book.check_invariants();
+        assert!(report.is_ok(), "{report}");
+    }
+
+    #[test]
+    fn test_index_violations_are_reported() {
+        let book = OrderBook::new("TEST");
+        let resting = create_order_id();
+        book.add_limit_order(resting, 1000, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+
+        let ghost = create_order_id();
+        book.order_locations.insert(ghost, (1000, Side::Buy));
+        book.order_locations.remove(&resting);
+        book.queue_priorities.remove(&resting);
+        book.queue_priorities.push_back(ghost);
+
+        let violations = book.check_invariants().violations;
+        assert!(violations.contains(&InvariantViolation::DanglingLocation {
+            order_id: ghost,
+            price: 1000,
+            side: Side::Buy,
+        }));
+        assert!(violations.contains(&InvariantViolation::UnindexedOrder {
+            order_id: resting,
+            price: 1000,
+            side: Side::Buy,
+        }));
+        assert!(
+            violations.contains(&InvariantViolation::MissingQueuePriority { order_id: resting })
+        );
+        assert!(violations.contains(&InvariantViolation::StaleQueuePriority { order_id: ghost }));
+        assert_eq!(violations.len(), 4);
+    }
+
+    #[test]
+    fn test_level_violations_are_reported() {
+        let book = OrderBook::new("TEST");
+        book.add_limit_order(create_order_id(), 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+
+        // A level left behind empty, unknown to the best price tracking
+        book.asks.insert(990, Arc::new(PriceLevel::new(990)));
+        // A bid resting above the best ask, placed without matching
+        book.place_order_in_book(Arc::new(limit_order(create_order_id(), 1005, 5, Side::Buy)))
+            .unwrap();
+        // An order whose level does not match its price
+        let misplaced = create_order_id();
+        book.bids
+            .get(&1005)
+            .unwrap()
+            .add_order(limit_order(misplaced, 1001, 1, Side::Buy));
+        book.order_locations.insert(misplaced, (1005, Side::Buy));
+        book.queue_priorities.push_back(misplaced);
+
+        let report = book.check_invariants();
+        let violations = &report.violations;
+        assert!(violations.contains(&InvariantViolation::EmptyLevel {
+            side: Side::Sell,
+            price: 990,
+        }));
+        assert!(violations.contains(&InvariantViolation::CrossedBook {
+            best_bid: 1005,
+            best_ask: 990,
+        }));
+        assert!(violations.contains(&InvariantViolation::BestPriceMismatch {
+            side: Side::Sell,
+            cached: Some(1000),
+            actual: Some(990),
+        }));
+        assert!(violations.contains(&InvariantViolation::MisplacedOrder {
+            order_id: misplaced,
+            level_price: 1005,
+            level_side: Side::Buy,
+        }));
+        assert_eq!(violations.len(), 4);
+        assert!(report.to_string().starts_with("4 violation(s) in 3 levels"));
+    }
+}
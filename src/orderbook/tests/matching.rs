@@ -2,7 +2,6 @@
 
 #[cfg(test)]
 mod tests {
-    use crate::orderbook::OrderBookError;
     use crate::orderbook::book::OrderBook;
     use pricelevel::{OrderId, OrderType, Side, TimeInForce};
 
@@ -98,15 +97,16 @@ mod tests {
     }
 
     #[test]
-    fn test_market_order_no_liquidity_error() {
+    fn test_market_order_no_liquidity_matches_nothing() {
         let book = setup_book();
         let taker_order_id = OrderId::new();
-        let result = book.match_order(taker_order_id, Side::Buy, 50, None);
+        let result = book
+            .match_order(taker_order_id, Side::Buy, 50, None)
+            .unwrap();
 
-        assert!(matches!(
-            result,
-            Err(OrderBookError::InsufficientLiquidity { .. })
-        ));
+        assert_eq!(result.remaining_quantity, 50);
+        assert!(!result.is_complete);
+        assert!(result.transactions.as_vec().is_empty());
     }
 
     #[test]
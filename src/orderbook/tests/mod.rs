@@ -1,9 +1,21 @@
+mod analytics;
 mod book;
+mod candles;
+mod checkpoint;
+mod clock;
 mod error;
+mod events;
+mod execution;
+mod invariants;
 mod matching;
 mod modifications;
 mod operations;
 mod order;
+mod order_state;
+mod queue_priority;
+mod quote;
+mod sequencer;
 mod snapshot;
 mod time_in_force;
+mod trades;
 mod uuid;
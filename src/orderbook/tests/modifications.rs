@@ -751,3 +751,306 @@ mod tests {
         }
     }
 }
+
+#[cfg(test)]
+mod test_cancel_replace_rollback {
+    use crate::{OrderBook, OrderBookError, OrderStatus};
+    use pricelevel::{OrderId, OrderUpdate, Side, TimeInForce};
+    use uuid::Uuid;
+
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    fn queue(book: &OrderBook, price: u64, side: Side) -> Vec<OrderId> {
+        book.get_orders_at_price(price, side)
+            .iter()
+            .map(|order| order.id())
+            .collect()
+    }
+
+    #[test]
+    fn test_rejected_price_update_restores_original() {
+        let book = OrderBook::new("TEST");
+        let first = create_order_id();
+        let post_only = create_order_id();
+        let last = create_order_id();
+        book.add_limit_order(first, 1000, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_post_only_order(post_only, 1000, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(last, 1000, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 1010, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        let original = book.get_order(post_only).unwrap();
+
+        let result = book.update_order(OrderUpdate::UpdatePrice {
+            order_id: post_only,
+            new_price: 1010,
+        });
+
+        assert!(matches!(result, Err(OrderBookError::PriceCrossing { .. })));
+        assert_eq!(book.get_order(post_only), Some(original));
+        assert_eq!(queue(&book, 1000, Side::Buy), vec![first, post_only, last]);
+        assert_eq!(book.best_bid(), Some(1000));
+        assert_eq!(book.best_ask(), Some(1010));
+
+        let record = book.order_state(post_only).unwrap();
+        assert_eq!(record.status, OrderStatus::New);
+        assert_eq!(record.price, Some(1000));
+        assert_eq!(record.original_quantity, 10);
+    }
+
+    #[test]
+    fn test_rejected_update_restores_only_order_of_level() {
+        let book = OrderBook::new("TEST");
+        let id = create_order_id();
+        book.add_post_only_order(id, 990, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+
+        let result = book.update_order(OrderUpdate::UpdatePriceAndQuantity {
+            order_id: id,
+            new_price: 1005,
+            new_quantity: 20,
+        });
+
+        assert!(result.is_err());
+        assert_eq!(book.best_bid(), Some(990));
+        assert_eq!(book.queue_position(id), Some(0));
+        assert_eq!(book.get_order(id).unwrap().visible_quantity(), 10);
+
+        // The restored order can still be amended and cancelled
+        book.update_order(OrderUpdate::UpdatePrice {
+            order_id: id,
+            new_price: 995,
+        })
+        .unwrap();
+        assert_eq!(book.best_bid(), Some(995));
+        assert!(book.cancel_order(id).unwrap().is_some());
+        assert_eq!(book.best_bid(), None);
+    }
+
+    #[test]
+    fn test_rejected_replace_of_expired_order_restores_original() {
+        let book = OrderBook::new("TEST");
+        let first = create_order_id();
+        let day = create_order_id();
+        book.add_limit_order(day, 1000, 10, Side::Sell, TimeInForce::Day)
+            .unwrap();
+        book.add_limit_order(first, 1001, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.set_market_close_timestamp(1);
+
+        let result = book.update_order(OrderUpdate::Replace {
+            order_id: day,
+            price: 1001,
+            quantity: 5,
+            side: Side::Sell,
+        });
+
+        assert!(matches!(
+            result,
+            Err(OrderBookError::InvalidOperation { .. })
+        ));
+        assert_eq!(book.get_order(day).unwrap().price(), 1000);
+        assert_eq!(queue(&book, 1001, Side::Sell), vec![first]);
+        assert_eq!(book.order_state(day).unwrap().status, OrderStatus::New);
+    }
+
+    #[test]
+    fn test_replacement_may_cross_the_order_it_replaces() {
+        let book = OrderBook::new("TEST");
+        let id = create_order_id();
+        book.add_post_only_order(id, 1000, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+
+        // The original is the best bid, but it is gone once the replacement is entered
+        book.update_order(OrderUpdate::Replace {
+            order_id: id,
+            price: 1000,
+            quantity: 10,
+            side: Side::Sell,
+        })
+        .unwrap();
+
+        assert_eq!(book.best_bid(), None);
+        assert_eq!(book.best_ask(), Some(1000));
+        assert_eq!(book.get_order(id).unwrap().side(), Side::Sell);
+    }
+
+    #[test]
+    fn test_crossing_amendment_reports_its_fills() {
+        let book = OrderBook::new("TEST");
+        let maker = create_order_id();
+        book.add_limit_order(maker, 1000, 4, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        let id = create_order_id();
+        book.add_limit_order(id, 990, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+
+        let report = book
+            .update_order(OrderUpdate::UpdatePrice {
+                order_id: id,
+                new_price: 1000,
+            })
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(report.order_id, id);
+        assert_eq!(report.status, OrderStatus::PartiallyFilled);
+        assert_eq!(report.fills.len(), 1);
+        assert_eq!(report.fills[0].maker_order_id, maker);
+        assert_eq!(report.filled_quantity(), 4);
+        assert_eq!(report.resting_quantity(), 6);
+    }
+
+    #[test]
+    fn test_amendment_reports_keep_the_order_status() {
+        let book = OrderBook::new("TEST");
+        let id = create_order_id();
+        book.add_limit_order(id, 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.submit_market_order(create_order_id(), 2, Side::Buy)
+            .unwrap();
+
+        // Neither amendment executes, the order stays partially filled
+        let reduced = book
+            .update_order(OrderUpdate::UpdateQuantity {
+                order_id: id,
+                new_quantity: 5,
+            })
+            .unwrap()
+            .unwrap();
+        assert!(reduced.fills.is_empty());
+        assert_eq!(reduced.status, OrderStatus::PartiallyFilled);
+        assert_eq!(reduced.resting_quantity(), 5);
+
+        let moved = book
+            .update_order(OrderUpdate::UpdatePrice {
+                order_id: id,
+                new_price: 1001,
+            })
+            .unwrap()
+            .unwrap();
+        assert!(moved.fills.is_empty());
+        assert_eq!(moved.status, OrderStatus::PartiallyFilled);
+
+        let cancelled = book
+            .update_order(OrderUpdate::Cancel { order_id: id })
+            .unwrap()
+            .unwrap();
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+        assert_eq!(cancelled.cancelled.unwrap().quantity, 5);
+    }
+}
+
+#[cfg(test)]
+mod test_reload_order {
+    use crate::{OrderBook, OrderBookError, OrderOwner, OrderRecord, OrderStatus, TerminalReason};
+    use pricelevel::{OrderId, OrderType, Side, TimeInForce};
+    use uuid::Uuid;
+
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    fn stored_order(
+        price: u64,
+        remaining: u64,
+        filled: u64,
+        side: Side,
+    ) -> (OrderType, OrderRecord) {
+        let id = create_order_id();
+        let order = OrderType::Standard {
+            id,
+            price,
+            quantity: remaining,
+            side,
+            timestamp: crate::current_time_millis(),
+            time_in_force: TimeInForce::Gtc,
+        };
+        let mut record = OrderRecord::new(id, side, Some(price), remaining + filled, 0)
+            .with_owner(OrderOwner::new(Uuid::new_v4(), Some(format!("c-{price}"))));
+        record.filled_quantity = filled;
+        if filled > 0 {
+            record.status = OrderStatus::PartiallyFilled;
+        }
+        (order, record)
+    }
+
+    #[test]
+    fn test_reloaded_orders_keep_their_state_and_priority() {
+        let book = OrderBook::new("TEST");
+        let (first, first_record) = stored_order(1000, 6, 4, Side::Sell);
+        let (second, second_record) = stored_order(1000, 5, 0, Side::Sell);
+        let (first_id, second_id) = (first.id(), second.id());
+        let account = first_record.owner.as_ref().unwrap().account;
+        book.reload_order(first, first_record).unwrap();
+        book.reload_order(second, second_record).unwrap();
+
+        let record = book.order_state(first_id).unwrap();
+        assert_eq!(record.status, OrderStatus::PartiallyFilled);
+        assert_eq!(record.remaining_quantity(), 6);
+        assert_eq!(book.find_order_id(account, "c-1000"), Some(first_id));
+        assert!(book.check_invariants().is_ok());
+
+        // The first reloaded order is matched first
+        book.submit_market_order(create_order_id(), 6, Side::Buy)
+            .unwrap();
+        assert!(book.get_order(first_id).is_none());
+        assert_eq!(
+            book.order_state(first_id).unwrap().status,
+            OrderStatus::Filled
+        );
+        assert!(book.get_order(second_id).is_some());
+    }
+
+    #[test]
+    fn test_crossing_orders_are_refused_not_matched() {
+        let book = OrderBook::new("TEST");
+        let (ask, ask_record) = stored_order(1000, 5, 0, Side::Sell);
+        book.reload_order(ask, ask_record).unwrap();
+
+        let (bid, bid_record) = stored_order(1000, 5, 0, Side::Buy);
+        let bid_id = bid.id();
+        let result = book.reload_order(bid, bid_record);
+        assert!(matches!(
+            result,
+            Err(OrderBookError::PriceCrossing {
+                price: 1000,
+                side: Side::Buy,
+                opposite_price: 1000,
+            })
+        ));
+        assert!(book.get_order(bid_id).is_none());
+        let record = book.order_state(bid_id).unwrap();
+        assert_eq!(record.status, OrderStatus::Rejected);
+        assert_eq!(record.reason, Some(TerminalReason::WouldCross));
+        assert!(book.trade_tape().is_empty());
+        assert_eq!(book.best_ask(), Some(1000));
+    }
+
+    #[test]
+    fn test_expired_orders_are_refused_and_recorded_as_expired() {
+        let book = OrderBook::new("TEST");
+        let (order, record) = stored_order(1000, 5, 0, Side::Sell);
+        let order_id = order.id();
+        let order = OrderType::Standard {
+            id: order_id,
+            price: 1000,
+            quantity: 5,
+            side: Side::Sell,
+            timestamp: 0,
+            time_in_force: TimeInForce::Gtd(1),
+        };
+
+        assert!(book.reload_order(order, record).is_err());
+        assert!(book.get_order(order_id).is_none());
+        let record = book.order_state(order_id).unwrap();
+        assert_eq!(record.status, OrderStatus::Expired);
+        assert_eq!(record.reason, Some(TerminalReason::Expired));
+    }
+}
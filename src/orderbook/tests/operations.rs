@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use crate::{OrderBook, OrderBookError};
+    use crate::{CancelledRemainder, OrderBook, OrderBookError, OrderStatus, TerminalReason};
     use pricelevel::{OrderId, Side, TimeInForce};
     use uuid::Uuid;
 
@@ -26,7 +26,10 @@ mod tests {
         let result = order_book.add_limit_order(id, price, quantity, side, time_in_force);
         assert!(result.is_ok(), "Adding a limit order should succeed");
 
-        let order = result.unwrap();
+        let order = result
+            .unwrap()
+            .resting
+            .expect("Order should rest in the book");
         assert_eq!(order.id(), id, "Order ID should match");
         assert_eq!(order.price(), price, "Price should match");
         assert_eq!(order.visible_quantity(), quantity, "Quantity should match");
@@ -62,7 +65,10 @@ mod tests {
         );
         assert!(result.is_ok(), "Adding an iceberg order should succeed");
 
-        let order = result.unwrap();
+        let order = result
+            .unwrap()
+            .resting
+            .expect("Order should rest in the book");
         assert_eq!(order.id(), id, "Order ID should match");
         assert_eq!(order.price(), price, "Price should match");
         assert_eq!(
@@ -99,7 +105,10 @@ mod tests {
         let result = order_book.add_post_only_order(id, price, quantity, side, time_in_force);
         assert!(result.is_ok(), "Adding a post-only order should succeed");
 
-        let order = result.unwrap();
+        let order = result
+            .unwrap()
+            .resting
+            .expect("Order should rest in the book");
         assert_eq!(order.id(), id, "Order ID should match");
         assert_eq!(order.price(), price, "Price should match");
         assert_eq!(order.visible_quantity(), quantity, "Quantity should match");
@@ -168,46 +177,30 @@ mod tests {
         );
 
         assert!(market_result.is_ok(), "Market order should succeed");
-        let match_result = market_result.unwrap();
+        let report = market_result.unwrap();
 
-        // Check match result
-        assert_eq!(match_result.order_id, buy_id, "Order ID should match");
+        // Check execution report
+        assert_eq!(report.order_id, buy_id, "Order ID should match");
+        assert_eq!(report.side, Side::Buy, "Side should be buy");
         assert_eq!(
-            match_result.executed_quantity(),
+            report.filled_quantity(),
             5,
             "Should execute requested quantity"
         );
-        assert_eq!(match_result.remaining_quantity, 0, "No remaining quantity");
-        assert!(match_result.is_complete, "Order should be complete");
+        assert!(report.cancelled.is_none(), "No remaining quantity");
         assert_eq!(
-            match_result.transactions.len(),
-            1,
-            "Should have one transaction"
+            report.status,
+            OrderStatus::Filled,
+            "Order should be complete"
         );
+        assert_eq!(report.fills.len(), 1, "Should have one fill");
 
-        // Check transaction details
-        let transaction = &match_result.transactions.as_vec()[0];
-        assert_eq!(
-            transaction.taker_order_id, buy_id,
-            "Taker should be market order"
-        );
-        assert_eq!(
-            transaction.maker_order_id, sell_id,
-            "Maker should be limit order"
-        );
-        assert_eq!(
-            transaction.price, 1000,
-            "Price should match limit order price"
-        );
-        assert_eq!(
-            transaction.quantity, 5,
-            "Quantity should match market order size"
-        );
-        assert_eq!(
-            transaction.taker_side,
-            Side::Buy,
-            "Taker side should be buy"
-        );
+        // Check fill details
+        let fill = &report.fills[0];
+        assert_eq!(fill.maker_order_id, sell_id, "Maker should be limit order");
+        assert_eq!(fill.price, 1000, "Price should match limit order price");
+        assert_eq!(fill.quantity, 5, "Quantity should match market order size");
+        assert_eq!(fill.fee, 0, "No fees are charged by default");
 
         // Verify the sell order is still in the book with reduced quantity
         let updated_sell = order_book.get_order(sell_id);
@@ -237,15 +230,12 @@ mod tests {
         let market_result = order_book.submit_market_order(buy_id, 10, Side::Buy);
 
         assert!(market_result.is_ok(), "Market order should succeed");
-        let match_result = market_result.unwrap();
+        let report = market_result.unwrap();
 
+        assert_eq!(report.filled_quantity(), 10, "Should execute full quantity");
         assert_eq!(
-            match_result.executed_quantity(),
-            10,
-            "Should execute full quantity"
-        );
-        assert!(
-            match_result.filled_order_ids.contains(&sell_id),
+            order_book.order_state(sell_id).unwrap().status,
+            OrderStatus::Filled,
             "Sell order should be marked as filled"
         );
 
@@ -276,18 +266,26 @@ mod tests {
             market_result.is_ok(),
             "Market order should succeed with partial fill"
         );
-        let match_result = market_result.unwrap();
+        let report = market_result.unwrap();
 
         assert_eq!(
-            match_result.executed_quantity(),
+            report.filled_quantity(),
             10,
             "Should execute available quantity"
         );
         assert_eq!(
-            match_result.remaining_quantity, 10,
-            "Should have remaining quantity"
+            report.cancelled,
+            Some(CancelledRemainder {
+                quantity: 10,
+                reason: TerminalReason::NoLiquidity,
+            }),
+            "Remaining quantity should be cancelled"
+        );
+        assert_eq!(
+            report.status,
+            OrderStatus::Cancelled,
+            "Order should not be complete"
         );
-        assert!(!match_result.is_complete, "Order should not be complete");
     }
 
     #[test]
@@ -296,24 +294,23 @@ mod tests {
 
         // Submit a market buy order with no matching orders
         let buy_id = new_order_id();
-        let market_result = order_book.submit_market_order(buy_id, 10, Side::Buy);
+        let report = order_book
+            .submit_market_order(buy_id, 10, Side::Buy)
+            .expect("Market order with no liquidity should be reported");
 
-        assert!(
-            market_result.is_err(),
-            "Market order with no liquidity should fail"
-        );
-        match market_result {
-            Err(OrderBookError::InsufficientLiquidity {
-                side,
-                requested,
-                available,
-            }) => {
-                assert_eq!(side, Side::Buy, "Side should be buy");
-                assert_eq!(requested, 10, "Requested quantity should match");
-                assert_eq!(available, 0, "Available should be zero");
-            }
-            _ => panic!("Expected InsufficientLiquidity error"),
-        }
+        assert!(report.fills.is_empty(), "Nothing should be filled");
+        assert_eq!(
+            report.cancelled,
+            Some(CancelledRemainder {
+                quantity: 10,
+                reason: TerminalReason::NoLiquidity,
+            }),
+            "The whole order should be cancelled"
+        );
+        assert_eq!(report.status, OrderStatus::Cancelled);
+        let record = order_book.order_state(buy_id).unwrap();
+        assert_eq!(record.status, OrderStatus::Cancelled);
+        assert_eq!(record.reason, Some(TerminalReason::NoLiquidity));
     }
 
     #[test]
@@ -479,7 +476,10 @@ mod test_operations_remaining {
         assert!(result.is_ok());
 
         // Verify order was added correctly
-        let order = result.unwrap();
+        let order = result
+            .unwrap()
+            .resting
+            .expect("Order should rest in the book");
         assert_eq!(order.id(), id);
         assert_eq!(order.price(), price);
         assert_eq!(order.visible_quantity(), quantity);
@@ -508,7 +508,10 @@ mod test_operations_remaining {
         assert!(result.is_ok());
 
         // Verify order was added correctly
-        let order = result.unwrap();
+        let order = result
+            .unwrap()
+            .resting
+            .expect("Order should rest in the book");
         assert_eq!(order.id(), id);
         assert_eq!(order.price(), price);
         assert_eq!(order.visible_quantity(), visible_quantity);
@@ -530,7 +533,10 @@ mod test_operations_remaining {
         assert!(result.is_ok());
 
         // Verify order was added correctly
-        let order = result.unwrap();
+        let order = result
+            .unwrap()
+            .resting
+            .expect("Order should rest in the book");
         assert_eq!(order.id(), id);
         assert_eq!(order.price(), price);
         assert_eq!(order.visible_quantity(), quantity);
@@ -0,0 +1,460 @@
+#[cfg(test)]
+mod tests {
+    use crate::orderbook::order_state::OrderStateStore;
+    use crate::{OrderBook, OrderRecord, OrderStatus, TerminalReason};
+    use pricelevel::{OrderId, OrderUpdate, Side, TimeInForce};
+    use uuid::Uuid;
+
+    // Helper function to create a unique order ID
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    #[test]
+    fn test_resting_order_is_new() {
+        let book = OrderBook::new("TEST");
+        let id = create_order_id();
+        book.add_limit_order(id, 1000, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+
+        let record = book.order_state(id).unwrap();
+        assert_eq!(record.status, OrderStatus::New);
+        assert_eq!(record.original_quantity, 10);
+        assert_eq!(record.filled_quantity, 0);
+        assert_eq!(record.remaining_quantity(), 10);
+        assert_eq!(record.price, Some(1000));
+        assert_eq!(record.reason, None);
+        assert_eq!(record.average_fill_price(), None);
+    }
+
+    #[test]
+    fn test_maker_and_taker_fills() {
+        let book = OrderBook::new("TEST");
+        let maker_a = create_order_id();
+        let maker_b = create_order_id();
+        book.add_limit_order(maker_a, 1000, 5, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(maker_b, 1010, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+
+        let taker = create_order_id();
+        book.add_limit_order(taker, 1010, 8, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+
+        let record_a = book.order_state(maker_a).unwrap();
+        assert_eq!(record_a.status, OrderStatus::Filled);
+        assert_eq!(record_a.reason, Some(TerminalReason::Filled));
+
+        let record_b = book.order_state(maker_b).unwrap();
+        assert_eq!(record_b.status, OrderStatus::PartiallyFilled);
+        assert_eq!(record_b.filled_quantity, 3);
+        assert_eq!(record_b.remaining_quantity(), 7);
+
+        let taker_record = book.order_state(taker).unwrap();
+        assert_eq!(taker_record.status, OrderStatus::Filled);
+        assert_eq!(taker_record.filled_quantity, 8);
+        let expected_avg = (1000.0 * 5.0 + 1010.0 * 3.0) / 8.0;
+        assert!((taker_record.average_fill_price().unwrap() - expected_avg).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cancelled_order_remains_visible() {
+        let book = OrderBook::new("TEST");
+        let id = create_order_id();
+        book.add_limit_order(id, 1000, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.cancel_order(id).unwrap();
+
+        assert!(book.get_order(id).is_none());
+        let record = book.order_state(id).unwrap();
+        assert_eq!(record.status, OrderStatus::Cancelled);
+        assert_eq!(record.reason, Some(TerminalReason::Cancelled));
+    }
+
+    #[test]
+    fn test_cancel_through_update_order() {
+        let book = OrderBook::new("TEST");
+        let id = create_order_id();
+        book.add_limit_order(id, 1000, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.update_order(OrderUpdate::Cancel { order_id: id })
+            .unwrap();
+
+        assert_eq!(book.order_state(id).unwrap().status, OrderStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_post_only_crossing_is_rejected() {
+        let book = OrderBook::new("TEST");
+        book.add_limit_order(create_order_id(), 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+
+        let id = create_order_id();
+        assert!(
+            book.add_post_only_order(id, 1000, 5, Side::Buy, TimeInForce::Gtc)
+                .is_err()
+        );
+
+        let record = book.order_state(id).unwrap();
+        assert_eq!(record.status, OrderStatus::Rejected);
+        assert_eq!(record.reason, Some(TerminalReason::WouldCross));
+    }
+
+    #[test]
+    fn test_ioc_remainder_is_cancelled() {
+        let book = OrderBook::new("TEST");
+        book.add_limit_order(create_order_id(), 1000, 4, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+
+        let id = create_order_id();
+        let report = book
+            .add_limit_order(id, 1000, 10, Side::Buy, TimeInForce::Ioc)
+            .unwrap();
+        assert_eq!(report.status, OrderStatus::Cancelled);
+
+        let record = book.order_state(id).unwrap();
+        assert_eq!(record.status, OrderStatus::Cancelled);
+        assert_eq!(record.reason, Some(TerminalReason::NoLiquidity));
+        assert_eq!(record.filled_quantity, 4);
+    }
+
+    #[test]
+    fn test_expired_order() {
+        let book = OrderBook::new("TEST");
+        let id = create_order_id();
+        assert!(
+            book.add_limit_order(id, 1000, 10, Side::Buy, TimeInForce::Gtd(1))
+                .is_err()
+        );
+
+        let record = book.order_state(id).unwrap();
+        assert_eq!(record.status, OrderStatus::Expired);
+        assert_eq!(record.reason, Some(TerminalReason::Expired));
+    }
+
+    #[test]
+    fn test_market_order_states() {
+        let book = OrderBook::new("TEST");
+        book.add_limit_order(create_order_id(), 1000, 4, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+
+        let partial = create_order_id();
+        book.submit_market_order(partial, 10, Side::Buy).unwrap();
+        let record = book.order_state(partial).unwrap();
+        assert_eq!(record.status, OrderStatus::Cancelled);
+        assert_eq!(record.reason, Some(TerminalReason::NoLiquidity));
+        assert_eq!(record.filled_quantity, 4);
+        assert_eq!(record.price, None);
+
+        let unfilled = create_order_id();
+        let report = book.submit_market_order(unfilled, 10, Side::Buy).unwrap();
+        assert!(report.fills.is_empty());
+        let record = book.order_state(unfilled).unwrap();
+        assert_eq!(record.status, OrderStatus::Cancelled);
+        assert_eq!(record.reason, Some(TerminalReason::NoLiquidity));
+        assert_eq!(record.filled_quantity, 0);
+    }
+
+    #[test]
+    fn test_amendment_keeps_fill_history() {
+        let book = OrderBook::new("TEST");
+        let id = create_order_id();
+        book.add_limit_order(id, 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.submit_market_order(create_order_id(), 4, Side::Buy)
+            .unwrap();
+
+        book.update_order(OrderUpdate::UpdatePriceAndQuantity {
+            order_id: id,
+            new_price: 1010,
+            new_quantity: 10,
+        })
+        .unwrap();
+
+        let record = book.order_state(id).unwrap();
+        assert_eq!(record.status, OrderStatus::PartiallyFilled);
+        assert_eq!(record.filled_quantity, 4);
+        assert_eq!(record.original_quantity, 14);
+        assert_eq!(record.price, Some(1010));
+    }
+
+    #[test]
+    fn test_terminal_retention() {
+        let book = OrderBook::new("TEST");
+        book.set_terminal_order_retention(2);
+
+        let ids: Vec<OrderId> = (0..3).map(|_| create_order_id()).collect();
+        for id in &ids {
+            book.add_limit_order(*id, 1000, 10, Side::Buy, TimeInForce::Gtc)
+                .unwrap();
+            book.cancel_order(*id).unwrap();
+        }
+
+        assert!(book.order_state(ids[0]).is_none());
+        assert!(book.order_state(ids[1]).is_some());
+        assert!(book.order_state(ids[2]).is_some());
+
+        // Live orders are never evicted
+        let live = create_order_id();
+        book.add_limit_order(live, 1000, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.set_terminal_order_retention(0);
+        assert!(book.order_state(ids[2]).is_none());
+        assert!(book.order_state(live).is_some());
+    }
+
+    #[test]
+    fn test_store_ignores_updates_to_terminal_orders() {
+        let store = OrderStateStore::new(10);
+        let id = create_order_id();
+        store.insert(OrderRecord::new(id, Side::Buy, Some(100), 10, 1));
+        store.finish(&id, OrderStatus::Cancelled, TerminalReason::Cancelled, 2);
+        store.record_fill(&id, 100, 5, 3);
+
+        let record = store.get(&id).unwrap();
+        assert_eq!(record.status, OrderStatus::Cancelled);
+        assert_eq!(record.filled_quantity, 0);
+        assert_eq!(record.updated_at, 2);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_reinserting_a_terminal_record_keeps_its_retention() {
+        let store = OrderStateStore::new(2);
+        let first = create_order_id();
+        let mut record = OrderRecord::new(first, Side::Buy, Some(100), 10, 1);
+        record.status = OrderStatus::Cancelled;
+        record.reason = Some(TerminalReason::Cancelled);
+        store.insert(record.clone());
+        // Restoring the same record again must not give it a second slot
+        store.insert(record);
+
+        let second = create_order_id();
+        store.insert(OrderRecord::new(second, Side::Buy, Some(100), 10, 2));
+        store.finish(
+            &second,
+            OrderStatus::Cancelled,
+            TerminalReason::Cancelled,
+            3,
+        );
+        assert!(store.get(&first).is_some());
+        assert!(store.get(&second).is_some());
+    }
+}
+
+#[cfg(test)]
+mod client_order_id_tests {
+    use crate::{OrderBook, OrderBookError, OrderOwner, OrderStatus};
+    use pricelevel::{OrderId, OrderType, OrderUpdate, Side, TimeInForce};
+    use uuid::Uuid;
+
+    fn limit_order(price: u64, quantity: u64, side: Side) -> OrderType {
+        OrderType::Standard {
+            id: OrderId(Uuid::new_v4()),
+            price,
+            quantity,
+            side,
+            timestamp: crate::utils::current_time_millis(),
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    fn owner(account: Uuid, client_order_id: &str) -> OrderOwner {
+        OrderOwner::new(account, Some(client_order_id.to_string()))
+    }
+
+    #[test]
+    fn test_lookup_by_client_order_id() {
+        let book = OrderBook::new("TEST");
+        let account = Uuid::new_v4();
+        let order = limit_order(1000, 10, Side::Buy);
+        let id = order.id();
+
+        book.add_order_with_owner(order, owner(account, "c-1"))
+            .unwrap();
+
+        assert_eq!(book.find_order_id(account, "c-1"), Some(id));
+        assert_eq!(book.find_order_id(account, "c-2"), None);
+        assert_eq!(book.find_order_id(Uuid::new_v4(), "c-1"), None);
+
+        let record = book.order_state(id).unwrap();
+        assert_eq!(record.owner, Some(owner(account, "c-1")));
+    }
+
+    #[test]
+    fn test_duplicate_live_client_order_id_is_rejected() {
+        let book = OrderBook::new("TEST");
+        let account = Uuid::new_v4();
+        let first = limit_order(1000, 10, Side::Buy);
+        let first_id = first.id();
+        book.add_order_with_owner(first, owner(account, "dup"))
+            .unwrap();
+
+        let second = limit_order(990, 5, Side::Buy);
+        let second_id = second.id();
+        match book.add_order_with_owner(second, owner(account, "dup")) {
+            Err(OrderBookError::DuplicateClientOrderId {
+                account: err_account,
+                client_order_id,
+            }) => {
+                assert_eq!(err_account, account);
+                assert_eq!(client_order_id, "dup");
+            }
+            other => panic!("Expected DuplicateClientOrderId, got {other:?}"),
+        }
+
+        // The rejected order never reached the book and the original keeps its id
+        assert!(book.get_order(second_id).is_none());
+        assert!(book.order_state(second_id).is_none());
+        assert_eq!(book.find_order_id(account, "dup"), Some(first_id));
+
+        // Another account may use the same client order id
+        book.add_order_with_owner(limit_order(980, 5, Side::Buy), owner(Uuid::new_v4(), "dup"))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_client_order_id_is_released_when_terminal() {
+        let book = OrderBook::new("TEST");
+        let account = Uuid::new_v4();
+
+        let cancelled = limit_order(1000, 10, Side::Buy);
+        book.add_order_with_owner(cancelled, owner(account, "reuse"))
+            .unwrap();
+        let cancelled_record = book
+            .cancel_order_by_client_id(account, "reuse")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            book.order_state(cancelled_record.id()).unwrap().status,
+            OrderStatus::Cancelled
+        );
+        assert_eq!(book.find_order_id(account, "reuse"), None);
+
+        // Reuse after cancel, then get filled
+        let filled = limit_order(1000, 10, Side::Buy);
+        let filled_id = filled.id();
+        book.add_order_with_owner(filled, owner(account, "reuse"))
+            .unwrap();
+        assert_eq!(book.find_order_id(account, "reuse"), Some(filled_id));
+        book.submit_market_order(OrderId(Uuid::new_v4()), 10, Side::Sell)
+            .unwrap();
+        assert_eq!(book.find_order_id(account, "reuse"), None);
+
+        // Rejected orders release their id too
+        book.add_limit_order(
+            OrderId(Uuid::new_v4()),
+            1000,
+            10,
+            Side::Sell,
+            TimeInForce::Gtc,
+        )
+        .unwrap();
+        let post_only = OrderType::PostOnly {
+            id: OrderId(Uuid::new_v4()),
+            price: 1000,
+            quantity: 5,
+            side: Side::Buy,
+            timestamp: crate::utils::current_time_millis(),
+            time_in_force: TimeInForce::Gtc,
+        };
+        assert!(
+            book.add_order_with_owner(post_only, owner(account, "reuse"))
+                .is_err()
+        );
+        assert_eq!(book.find_order_id(account, "reuse"), None);
+    }
+
+    #[test]
+    fn test_amend_keeps_client_order_id() {
+        let book = OrderBook::new("TEST");
+        let account = Uuid::new_v4();
+        let order = limit_order(1000, 10, Side::Buy);
+        let id = order.id();
+        book.add_order_with_owner(order, owner(account, "amend"))
+            .unwrap();
+
+        let resolved = book.find_order_id(account, "amend").unwrap();
+        book.update_order(OrderUpdate::UpdatePrice {
+            order_id: resolved,
+            new_price: 1005,
+        })
+        .unwrap();
+
+        assert_eq!(book.find_order_id(account, "amend"), Some(id));
+        assert_eq!(book.get_order(id).unwrap().price(), 1005);
+    }
+
+    #[test]
+    fn test_market_order_with_owner() {
+        let book = OrderBook::new("TEST");
+        let account = Uuid::new_v4();
+        book.add_limit_order(
+            OrderId(Uuid::new_v4()),
+            1000,
+            10,
+            Side::Sell,
+            TimeInForce::Gtc,
+        )
+        .unwrap();
+
+        let id = OrderId(Uuid::new_v4());
+        book.submit_market_order_with_owner(id, 4, Side::Buy, owner(account, "mkt"))
+            .unwrap();
+
+        let record = book.order_state(id).unwrap();
+        assert_eq!(record.status, OrderStatus::Filled);
+        assert_eq!(book.find_order_id(account, "mkt"), None);
+    }
+
+    #[test]
+    fn test_cancel_account_orders() {
+        let book = OrderBook::new("TEST");
+        let account = Uuid::new_v4();
+        let other = Uuid::new_v4();
+
+        let bid = limit_order(990, 10, Side::Buy);
+        let ask = limit_order(1010, 10, Side::Sell);
+        let (bid_id, ask_id) = (bid.id(), ask.id());
+        book.add_order_with_owner(bid, owner(account, "bid"))
+            .unwrap();
+        book.add_order_with_owner(ask, owner(account, "ask"))
+            .unwrap();
+        let foreign = limit_order(980, 5, Side::Buy);
+        let foreign_id = foreign.id();
+        book.add_order_with_owner(foreign, owner(other, "bid"))
+            .unwrap();
+
+        // A filled order of the account is not live any more
+        let filled = limit_order(1000, 5, Side::Sell);
+        let filled_id = filled.id();
+        book.add_order_with_owner(filled, owner(account, "filled"))
+            .unwrap();
+        book.submit_market_order(OrderId(Uuid::new_v4()), 5, Side::Buy)
+            .unwrap();
+
+        let sequence = book.sequence();
+        let mut cancelled = book.cancel_account_orders(account).unwrap();
+        cancelled.sort_by_key(|order_id| order_id.0);
+        let mut expected = vec![bid_id, ask_id];
+        expected.sort_by_key(|order_id| order_id.0);
+        assert_eq!(cancelled, expected);
+        assert_eq!(book.sequence(), sequence + 1);
+
+        for order_id in [bid_id, ask_id] {
+            assert!(book.get_order(order_id).is_none());
+            let record = book.order_state(order_id).unwrap();
+            assert_eq!(record.status, OrderStatus::Cancelled);
+        }
+        assert_eq!(book.find_order_id(account, "bid"), None);
+        assert_eq!(
+            book.order_state(filled_id).unwrap().status,
+            OrderStatus::Filled
+        );
+        assert!(book.get_order(foreign_id).is_some());
+        assert_eq!(book.best_bid(), Some(980));
+        assert_eq!(book.best_ask(), None);
+
+        assert!(book.cancel_account_orders(account).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,299 @@
+#[cfg(test)]
+mod tests {
+    use crate::{OrderBook, OrderStatus};
+    use pricelevel::{OrderId, OrderUpdate, Side, TimeInForce};
+    use uuid::Uuid;
+
+    // Helper function to create a unique order ID
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    // Rest three sell orders of 10 at 1000, in order
+    fn setup_level(book: &OrderBook) -> (OrderId, OrderId, OrderId) {
+        let ids = (create_order_id(), create_order_id(), create_order_id());
+        for id in [ids.0, ids.1, ids.2] {
+            book.add_limit_order(id, 1000, 10, Side::Sell, TimeInForce::Gtc)
+                .unwrap();
+        }
+        (ids.0, ids.1, ids.2)
+    }
+
+    // The maker hit first by a small buy market order
+    fn first_maker(book: &OrderBook) -> OrderId {
+        let result = book
+            .submit_market_order(create_order_id(), 1, Side::Buy)
+            .unwrap();
+        result.fills[0].maker_order_id
+    }
+
+    fn queue(book: &OrderBook, price: u64) -> Vec<OrderId> {
+        book.get_orders_at_price(price, Side::Sell)
+            .iter()
+            .map(|order| order.id())
+            .collect()
+    }
+
+    #[test]
+    fn test_initial_queue_positions() {
+        let book = OrderBook::new("TEST");
+        let (a, b, c) = setup_level(&book);
+
+        assert_eq!(book.queue_position(a), Some(0));
+        assert_eq!(book.queue_position(b), Some(1));
+        assert_eq!(book.queue_position(c), Some(2));
+        assert_eq!(book.queue_position(create_order_id()), None);
+        assert_eq!(queue(&book, 1000), vec![a, b, c]);
+    }
+
+    #[test]
+    fn test_quantity_decrease_keeps_position() {
+        let book = OrderBook::new("TEST");
+        let (a, b, c) = setup_level(&book);
+
+        book.update_order(OrderUpdate::UpdateQuantity {
+            order_id: a,
+            new_quantity: 5,
+        })
+        .unwrap();
+
+        assert_eq!(book.get_order(a).unwrap().visible_quantity(), 5);
+        assert_eq!(queue(&book, 1000), vec![a, b, c]);
+        assert_eq!(first_maker(&book), a);
+    }
+
+    #[test]
+    fn test_quantity_update_to_zero_is_rejected() {
+        let book = OrderBook::new("TEST");
+        let (a, b, c) = setup_level(&book);
+
+        for update in [
+            OrderUpdate::UpdateQuantity {
+                order_id: a,
+                new_quantity: 0,
+            },
+            OrderUpdate::UpdatePriceAndQuantity {
+                order_id: a,
+                new_price: 1010,
+                new_quantity: 0,
+            },
+        ] {
+            assert!(book.update_order(update).is_err());
+        }
+
+        // The order is left untouched, in the book and still open
+        assert_eq!(book.get_order(a).unwrap().visible_quantity(), 10);
+        assert_eq!(queue(&book, 1000), vec![a, b, c]);
+        assert_eq!(book.order_state(a).unwrap().status, OrderStatus::New);
+    }
+
+    #[test]
+    fn test_quantity_increase_loses_position() {
+        let book = OrderBook::new("TEST");
+        let (a, b, c) = setup_level(&book);
+        assert_eq!(book.queue_position(a), Some(0));
+
+        book.update_order(OrderUpdate::UpdateQuantity {
+            order_id: a,
+            new_quantity: 15,
+        })
+        .unwrap();
+
+        assert_eq!(book.get_order(a).unwrap().visible_quantity(), 15);
+        assert_eq!(book.queue_position(a), Some(2));
+        assert_eq!(queue(&book, 1000), vec![b, c, a]);
+
+        // Sweep the level: the increased order is matched last
+        let result = book
+            .submit_market_order(create_order_id(), 35, Side::Buy)
+            .unwrap();
+        let makers: Vec<OrderId> = result
+            .fills
+            .iter()
+            .map(|fill| fill.maker_order_id)
+            .collect();
+        assert_eq!(makers, vec![b, c, a]);
+        assert!(book.get_order(a).is_none());
+    }
+
+    #[test]
+    fn test_same_quantity_keeps_position() {
+        let book = OrderBook::new("TEST");
+        let (a, b, c) = setup_level(&book);
+
+        book.update_order(OrderUpdate::UpdateQuantity {
+            order_id: b,
+            new_quantity: 10,
+        })
+        .unwrap();
+
+        assert_eq!(queue(&book, 1000), vec![a, b, c]);
+    }
+
+    #[test]
+    fn test_price_change_loses_position() {
+        let book = OrderBook::new("TEST");
+        let (a, b, c) = setup_level(&book);
+
+        // Move away and back to the original price
+        book.update_order(OrderUpdate::UpdatePrice {
+            order_id: a,
+            new_price: 1001,
+        })
+        .unwrap();
+        assert_eq!(queue(&book, 1001), vec![a]);
+        assert_eq!(book.queue_position(a), Some(0));
+
+        book.update_order(OrderUpdate::UpdatePrice {
+            order_id: a,
+            new_price: 1000,
+        })
+        .unwrap();
+
+        assert_eq!(queue(&book, 1000), vec![b, c, a]);
+        assert!(book.get_orders_at_price(1001, Side::Sell).is_empty());
+        assert_eq!(first_maker(&book), b);
+    }
+
+    #[test]
+    fn test_price_change_joins_back_of_existing_level() {
+        let book = OrderBook::new("TEST");
+        let (a, b, _) = setup_level(&book);
+        let other = create_order_id();
+        book.add_limit_order(other, 1001, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+
+        book.update_order(OrderUpdate::UpdatePrice {
+            order_id: a,
+            new_price: 1001,
+        })
+        .unwrap();
+
+        assert_eq!(queue(&book, 1001), vec![other, a]);
+        assert_eq!(book.queue_position(a), Some(1));
+        assert_eq!(book.queue_position(b), Some(0));
+    }
+
+    #[test]
+    fn test_price_and_quantity_update_at_same_price() {
+        let book = OrderBook::new("TEST");
+        let (a, b, c) = setup_level(&book);
+
+        // A decrease at the same price keeps the position
+        book.update_order(OrderUpdate::UpdatePriceAndQuantity {
+            order_id: a,
+            new_price: 1000,
+            new_quantity: 4,
+        })
+        .unwrap();
+        assert_eq!(queue(&book, 1000), vec![a, b, c]);
+
+        // An increase at the same price loses it
+        book.update_order(OrderUpdate::UpdatePriceAndQuantity {
+            order_id: b,
+            new_price: 1000,
+            new_quantity: 20,
+        })
+        .unwrap();
+        assert_eq!(queue(&book, 1000), vec![a, c, b]);
+        assert_eq!(first_maker(&book), a);
+    }
+
+    #[test]
+    fn test_price_and_quantity_decrease_at_new_price_loses_position() {
+        let book = OrderBook::new("TEST");
+        let (a, b, c) = setup_level(&book);
+
+        book.update_order(OrderUpdate::UpdatePriceAndQuantity {
+            order_id: a,
+            new_price: 1001,
+            new_quantity: 5,
+        })
+        .unwrap();
+        book.update_order(OrderUpdate::UpdatePriceAndQuantity {
+            order_id: a,
+            new_price: 1000,
+            new_quantity: 4,
+        })
+        .unwrap();
+
+        assert_eq!(queue(&book, 1000), vec![b, c, a]);
+        assert_eq!(book.get_order(a).unwrap().visible_quantity(), 4);
+    }
+
+    #[test]
+    fn test_replace_loses_position() {
+        let book = OrderBook::new("TEST");
+        let (a, b, c) = setup_level(&book);
+
+        book.update_order(OrderUpdate::Replace {
+            order_id: a,
+            price: 1000,
+            quantity: 8,
+            side: Side::Sell,
+        })
+        .unwrap();
+
+        assert_eq!(queue(&book, 1000), vec![b, c, a]);
+        assert_eq!(first_maker(&book), b);
+    }
+
+    #[test]
+    fn test_amendment_keeps_fill_history_after_losing_position() {
+        let book = OrderBook::new("TEST");
+        let (a, _, _) = setup_level(&book);
+        assert_eq!(first_maker(&book), a);
+
+        book.update_order(OrderUpdate::UpdateQuantity {
+            order_id: a,
+            new_quantity: 20,
+        })
+        .unwrap();
+
+        let record = book.order_state(a).unwrap();
+        assert_eq!(record.filled_quantity, 1);
+        assert_eq!(record.original_quantity, 21);
+        assert_eq!(book.queue_position(a), Some(2));
+    }
+
+    #[test]
+    fn test_partially_filled_maker_position() {
+        let book = OrderBook::new("TEST");
+        let (a, b, c) = setup_level(&book);
+
+        // The queue reported by the book follows the matching engine, which
+        // sends a partially filled maker to the back of its level
+        assert_eq!(first_maker(&book), a);
+        assert_eq!(queue(&book, 1000), vec![b, c, a]);
+        assert_eq!(first_maker(&book), b);
+    }
+
+    #[test]
+    fn test_fills_after_quantity_decrease_follow_queue_position() {
+        let book = OrderBook::new("TEST");
+        let (a, b, c) = setup_level(&book);
+        book.update_order(OrderUpdate::UpdateQuantity {
+            order_id: a,
+            new_quantity: 5,
+        })
+        .unwrap();
+        let d = create_order_id();
+        book.add_limit_order(d, 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+
+        // The partially filled amended order goes to the back, behind d
+        assert_eq!(first_maker(&book), a);
+        assert_eq!(queue(&book, 1000), vec![b, c, d, a]);
+
+        let result = book
+            .submit_market_order(create_order_id(), 35, Side::Buy)
+            .unwrap();
+        let makers: Vec<OrderId> = result
+            .fills
+            .iter()
+            .map(|fill| fill.maker_order_id)
+            .collect();
+        assert_eq!(makers, vec![b, c, d, a]);
+        assert_eq!(book.queue_position(a), None);
+    }
+}
@@ -0,0 +1,138 @@
+#[cfg(test)]
+mod tests {
+    use crate::{Liquidity, OrderBook};
+    use pricelevel::{OrderId, Side, TimeInForce};
+    use uuid::Uuid;
+
+    // Helper function to create a unique order ID
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    // Bids: 10 @ 990, 20 @ 980. Asks: 10 @ 1010, 20 @ 1020, iceberg 5 + 15 hidden @ 1030
+    fn setup_book() -> OrderBook {
+        let book = OrderBook::new("TEST");
+        book.add_limit_order(create_order_id(), 990, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 980, 20, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 1010, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 1020, 20, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.add_iceberg_order(create_order_id(), 1030, 5, 15, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book
+    }
+
+    #[test]
+    fn test_market_impact_buy() {
+        let book = setup_book();
+
+        let impact = book.market_impact(Side::Buy, 20, Liquidity::Visible);
+
+        assert!(impact.is_complete());
+        assert_eq!(impact.filled_quantity, 20);
+        assert_eq!(impact.total_value, 10 * 1010 + 10 * 1020);
+        assert_eq!(impact.average_price, Some(1015.0));
+        assert_eq!(impact.best_price, Some(1010));
+        assert_eq!(impact.worst_price, Some(1020));
+        assert_eq!(impact.levels_consumed, 2);
+        assert_eq!(impact.mid_price, Some(1000.0));
+        assert_eq!(impact.slippage, Some(15.0));
+        assert_eq!(impact.slippage_bps, Some(150.0));
+    }
+
+    #[test]
+    fn test_market_impact_sell() {
+        let book = setup_book();
+
+        let impact = book.market_impact(Side::Sell, 15, Liquidity::Visible);
+
+        assert_eq!(impact.filled_quantity, 15);
+        assert_eq!(impact.worst_price, Some(980));
+        assert_eq!(impact.levels_consumed, 2);
+        let average = (10.0 * 990.0 + 5.0 * 980.0) / 15.0;
+        assert_eq!(impact.average_price, Some(average));
+        assert_eq!(impact.slippage, Some(1000.0 - average));
+    }
+
+    #[test]
+    fn test_market_impact_hidden_liquidity() {
+        let book = setup_book();
+
+        let visible = book.market_impact(Side::Buy, 100, Liquidity::Visible);
+        assert!(!visible.is_complete());
+        assert_eq!(visible.filled_quantity, 35);
+        assert_eq!(visible.levels_consumed, 3);
+
+        let all = book.market_impact(Side::Buy, 100, Liquidity::VisibleAndHidden);
+        assert_eq!(all.filled_quantity, 50);
+        assert_eq!(all.worst_price, Some(1030));
+    }
+
+    #[test]
+    fn test_market_impact_does_not_change_book() {
+        let book = setup_book();
+        let before = book.get_volume_by_price();
+
+        book.market_impact(Side::Buy, 50, Liquidity::VisibleAndHidden);
+        book.quantity_for_notional(Side::Sell, 1_000_000, Liquidity::VisibleAndHidden);
+
+        assert_eq!(book.get_volume_by_price(), before);
+        assert_eq!(book.get_all_orders().len(), 5);
+        assert_eq!(book.last_trade_price(), None);
+    }
+
+    #[test]
+    fn test_market_impact_empty_side() {
+        let book = OrderBook::new("TEST");
+
+        let impact = book.market_impact(Side::Buy, 10, Liquidity::Visible);
+
+        assert_eq!(impact.filled_quantity, 0);
+        assert_eq!(impact.average_price, None);
+        assert_eq!(impact.best_price, None);
+        assert_eq!(impact.levels_consumed, 0);
+        assert_eq!(impact.slippage, None);
+    }
+
+    #[test]
+    fn test_quantity_for_notional() {
+        let book = setup_book();
+
+        // 10 @ 1010 costs 10_100, the remaining 5_000 buys 4 @ 1020
+        let quote = book.quantity_for_notional(Side::Buy, 15_100, Liquidity::Visible);
+
+        assert_eq!(quote.quantity, 14);
+        assert_eq!(quote.spent, 10_100 + 4 * 1020);
+        assert_eq!(quote.worst_price, Some(1020));
+        assert_eq!(quote.levels_consumed, 2);
+        assert_eq!(quote.average_price, Some((10_100.0 + 4.0 * 1020.0) / 14.0));
+    }
+
+    #[test]
+    fn test_quantity_for_notional_hidden_liquidity() {
+        let book = setup_book();
+        let budget = 1_000_000;
+
+        let visible = book.quantity_for_notional(Side::Buy, budget, Liquidity::Visible);
+        assert_eq!(visible.quantity, 35);
+
+        let all = book.quantity_for_notional(Side::Buy, budget, Liquidity::VisibleAndHidden);
+        assert_eq!(all.quantity, 50);
+        assert_eq!(all.spent, 10 * 1010 + 20 * 1020 + 20 * 1030);
+    }
+
+    #[test]
+    fn test_quantity_for_notional_below_best_price() {
+        let book = setup_book();
+
+        let quote = book.quantity_for_notional(Side::Buy, 1009, Liquidity::Visible);
+
+        assert_eq!(quote.quantity, 0);
+        assert_eq!(quote.spent, 0);
+        assert_eq!(quote.average_price, None);
+        assert_eq!(quote.levels_consumed, 0);
+    }
+}
@@ -0,0 +1,246 @@
+#[cfg(test)]
+mod tests {
+    use crate::{Command, CommandResult, OrderBook, OrderStatus, Sequencer, SequencerConfig};
+    use pricelevel::{OrderId, OrderType, OrderUpdate, Side, TimeInForce};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    // Helper function to create a unique order ID
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    fn limit_order(id: OrderId, price: u64, quantity: u64, side: Side) -> OrderType {
+        OrderType::Standard {
+            id,
+            price,
+            quantity,
+            side,
+            timestamp: crate::current_time_millis(),
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    #[test]
+    fn test_commands_are_applied_in_sequence() {
+        let sequencer = Sequencer::start(OrderBook::new("TEST"));
+        assert_eq!(sequencer.view().version, 0);
+
+        let maker = create_order_id();
+        let first = sequencer
+            .execute(Command::AddOrder {
+                order: limit_order(maker, 1000, 10, Side::Sell),
+                owner: None,
+            })
+            .unwrap();
+        assert_eq!(first.sequence, 1);
+        assert!(matches!(first.result, CommandResult::Execution(Ok(_))));
+
+        let report = sequencer
+            .submit_market_order(create_order_id(), 4, Side::Buy, None)
+            .unwrap();
+        assert_eq!(report.filled_quantity(), 4);
+
+        let updated = sequencer
+            .update_order(OrderUpdate::UpdateQuantity {
+                order_id: maker,
+                new_quantity: 3,
+            })
+            .unwrap();
+        assert!(updated.is_some());
+
+        let view = sequencer.view();
+        assert_eq!(view.version, 3);
+        assert_eq!(view.best_ask, Some(1000));
+        assert_eq!(view.last_trade_price, Some(1000));
+        assert_eq!(view.snapshot.asks[0].visible_quantity, 3);
+
+        assert!(sequencer.cancel_order(maker).unwrap().is_some());
+        assert_eq!(sequencer.view().version, 4);
+        assert_eq!(sequencer.view().best_ask, None);
+        assert_eq!(
+            sequencer.book().order_state(maker).unwrap().status,
+            OrderStatus::Cancelled
+        );
+    }
+
+    #[test]
+    fn test_errors_are_returned_to_the_submitter() {
+        let sequencer = Sequencer::start(OrderBook::new("TEST"));
+
+        let mut order = limit_order(create_order_id(), 1000, 5, Side::Buy);
+        if let OrderType::Standard { time_in_force, .. } = &mut order {
+            *time_in_force = TimeInForce::Fok;
+        }
+        let result = sequencer.add_order(order, None);
+        assert!(result.is_err());
+        // A rejected command still takes its place in the sequence
+        assert_eq!(sequencer.view().version, 1);
+    }
+
+    #[test]
+    fn test_view_is_current_once_a_burst_is_applied() {
+        let sequencer = Sequencer::with_config(
+            OrderBook::new("TEST"),
+            SequencerConfig {
+                view_interval: Duration::from_secs(3600),
+                ..SequencerConfig::default()
+            },
+        );
+
+        let tickets: Vec<_> = (0..50)
+            .map(|i| {
+                sequencer
+                    .submit(Command::AddOrder {
+                        order: limit_order(create_order_id(), 1000 + i, 1, Side::Sell),
+                        owner: None,
+                    })
+                    .unwrap()
+            })
+            .collect();
+        let last = tickets
+            .into_iter()
+            .map(|ticket| ticket.wait().unwrap().sequence)
+            .max();
+
+        let view = sequencer.view();
+        assert_eq!(Some(view.version), last);
+        assert_eq!(view.best_ask, Some(1000));
+        assert_eq!(view.snapshot.asks.len(), 20);
+    }
+
+    #[test]
+    fn test_tickets_can_be_awaited() {
+        let sequencer = Sequencer::start(OrderBook::new("TEST"));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let maker = create_order_id();
+        let report = runtime
+            .block_on(sequencer.add_order_async(limit_order(maker, 1000, 10, Side::Sell), None))
+            .unwrap();
+        assert_eq!(report.status, OrderStatus::New);
+
+        let ticket = sequencer.submit(Command::CancelOrder(maker)).unwrap();
+        let cancelled = runtime.block_on(ticket).unwrap();
+        assert_eq!(cancelled.sequence, 2);
+        assert!(matches!(
+            cancelled.result,
+            CommandResult::Order(Ok(Some(_)))
+        ));
+    }
+
+    #[test]
+    fn test_concurrent_submitters_get_a_total_order() {
+        let sequencer = Arc::new(Sequencer::with_config(
+            OrderBook::new("TEST"),
+            SequencerConfig {
+                ring_capacity: 8,
+                view_depth: 5,
+                ..SequencerConfig::default()
+            },
+        ));
+        let threads: u64 = 8;
+        let orders_per_thread: u64 = 100;
+
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let sequencer = Arc::clone(&sequencer);
+                thread::spawn(move || {
+                    let side = if t % 2 == 0 { Side::Buy } else { Side::Sell };
+                    (0..orders_per_thread)
+                        .map(|i| {
+                            let price = if side == Side::Buy {
+                                990 + i % 10
+                            } else {
+                                1000 + i % 10
+                            };
+                            sequencer
+                                .execute(Command::AddOrder {
+                                    order: limit_order(create_order_id(), price, 1, side),
+                                    owner: None,
+                                })
+                                .unwrap()
+                                .sequence
+                        })
+                        .collect::<Vec<u64>>()
+                })
+            })
+            .collect();
+
+        let mut sequences: Vec<u64> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        sequences.sort_unstable();
+
+        let total = threads * orders_per_thread;
+        assert_eq!(sequences, (1..=total).collect::<Vec<u64>>());
+        assert_eq!(sequencer.view().version, total);
+        assert_eq!(sequencer.book().get_all_orders().len(), total as usize);
+        assert_eq!(sequencer.pending(), 0);
+    }
+
+    #[test]
+    fn test_shutdown_applies_queued_commands_and_rejects_new_ones() {
+        let mut sequencer = Sequencer::start(OrderBook::new("TEST"));
+        let tickets: Vec<_> = (0..10)
+            .map(|i| {
+                sequencer
+                    .submit(Command::AddOrder {
+                        order: limit_order(create_order_id(), 1000 + i, 1, Side::Sell),
+                        owner: None,
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        sequencer.shutdown();
+
+        for ticket in tickets {
+            assert!(ticket.wait().is_ok());
+        }
+        assert_eq!(sequencer.view().version, 10);
+        assert!(
+            sequencer
+                .submit(Command::CancelOrder(create_order_id()))
+                .is_err()
+        );
+        assert!(
+            sequencer
+                .try_submit(Command::CancelOrder(create_order_id()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cancel_account_orders_through_the_sequencer() {
+        let sequencer = Sequencer::start(OrderBook::new("TEST"));
+        let account = Uuid::new_v4();
+        let resting = create_order_id();
+        sequencer
+            .add_order(
+                limit_order(resting, 1000, 10, Side::Sell),
+                Some(crate::OrderOwner::new(account, None)),
+            )
+            .unwrap();
+        sequencer
+            .add_order(limit_order(create_order_id(), 1010, 10, Side::Sell), None)
+            .unwrap();
+
+        let result = sequencer
+            .execute(Command::CancelAccountOrders(account))
+            .unwrap();
+        assert_eq!(result.sequence, 3);
+        assert!(
+            matches!(result.result, CommandResult::Cancelled(Ok(ref ids)) if ids == &vec![resting])
+        );
+
+        let view = sequencer.view();
+        assert_eq!(view.best_ask, Some(1010));
+        assert!(sequencer.cancel_account_orders(account).unwrap().is_empty());
+    }
+}
@@ -7,6 +7,7 @@ mod tests {
     fn create_empty_snapshot() -> OrderBookSnapshot {
         OrderBookSnapshot {
             symbol: "TEST".to_string(),
+            sequence: 0,
             timestamp: 12345678,
             bids: Vec::new(),
             asks: Vec::new(),
@@ -51,6 +52,7 @@ mod tests {
 
         OrderBookSnapshot {
             symbol: "TEST".to_string(),
+            sequence: 0,
             timestamp: 12345678,
             bids: vec![bid1, bid2],
             asks: vec![ask1, ask2],
@@ -291,6 +293,7 @@ mod tests {
 
         let snapshot = OrderBookSnapshot {
             symbol: "TEST".to_string(),
+            sequence: 0,
             timestamp: 12345678,
             bids: vec![bid1, bid2],
             asks: Vec::new(),
@@ -434,6 +437,7 @@ mod tests_bis {
 
         OrderBookSnapshot {
             symbol: "TEST".to_string(),
+            sequence: 0,
             timestamp: 12345678,
             bids: vec![bid1, bid3, bid2], // Deliberately unordered
             asks: vec![ask2, ask1, ask3], // Deliberately unordered
@@ -510,10 +514,12 @@ mod tests_bis {
         let mut snapshot = create_unordered_snapshot();
 
         // Sort the bids by price in descending order
-        snapshot.bids.sort_by(|a, b| b.price.cmp(&a.price));
+        snapshot
+            .bids
+            .sort_by_key(|level| std::cmp::Reverse(level.price));
 
         // Sort the asks by price in ascending order
-        snapshot.asks.sort_by(|a, b| a.price.cmp(&b.price));
+        snapshot.asks.sort_by_key(|level| level.price);
 
         // Now the first element should be the best price
         let best_bid = snapshot
@@ -619,6 +625,7 @@ mod test_orderbook_snapshot {
 
         let snapshot = OrderBookSnapshot {
             symbol: "TEST".to_string(),
+            sequence: 0,
             timestamp: 12345678,
             bids: vec![bid1, bid2],
             asks: vec![ask1, ask2],
@@ -647,6 +654,7 @@ mod test_snapshot_remaining {
     fn test_empty_snapshot_volume_methods() {
         let empty_snapshot = OrderBookSnapshot {
             symbol: "TEST".to_string(),
+            sequence: 0,
             timestamp: 12345678,
             bids: Vec::new(),
             asks: Vec::new(),
@@ -681,6 +689,7 @@ mod test_snapshot_remaining {
 
         let snapshot = OrderBookSnapshot {
             symbol: "TEST".to_string(),
+            sequence: 0,
             timestamp: 12345678,
             bids: vec![bid],
             asks: vec![ask],
@@ -735,6 +744,7 @@ mod test_snapshot_specific {
 
         let snapshot = OrderBookSnapshot {
             symbol: "TEST".to_string(),
+            sequence: 0,
             timestamp: 12345678,
             bids: vec![bid],
             asks: vec![ask],
@@ -754,3 +764,271 @@ mod test_snapshot_specific {
         assert_eq!(best_ask, Some((1010, 15)));
     }
 }
+
+#[cfg(test)]
+mod test_snapshot_aggregation {
+    use crate::{DepthBucket, OrderBook, OrderBookSnapshot};
+    use pricelevel::{OrderId, PriceLevelSnapshot, Side, TimeInForce};
+    use uuid::Uuid;
+
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    fn level(price: u64, visible_quantity: u64, order_count: usize) -> PriceLevelSnapshot {
+        PriceLevelSnapshot {
+            price,
+            visible_quantity,
+            hidden_quantity: 0,
+            order_count,
+            orders: Vec::new(),
+        }
+    }
+
+    fn bucket(
+        price: u64,
+        visible_quantity: u64,
+        order_count: usize,
+        cumulative_quantity: u64,
+    ) -> DepthBucket {
+        DepthBucket {
+            price,
+            visible_quantity,
+            hidden_quantity: 0,
+            order_count,
+            cumulative_quantity,
+        }
+    }
+
+    fn create_snapshot() -> OrderBookSnapshot {
+        OrderBookSnapshot {
+            symbol: "TEST".to_string(),
+            sequence: 0,
+            timestamp: 12345678,
+            bids: vec![
+                level(1005, 10, 1),
+                level(1000, 5, 2),
+                level(999, 7, 1),
+                level(985, 3, 1),
+            ],
+            asks: vec![
+                level(1010, 4, 1),
+                level(1011, 6, 2),
+                level(1020, 8, 1),
+                level(1031, 2, 1),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_aggregate_rounds_bids_down_and_asks_up() {
+        let depth = create_snapshot().aggregate(10);
+
+        assert_eq!(depth.symbol, "TEST");
+        assert_eq!(depth.timestamp, 12345678);
+        assert_eq!(depth.bucket_size, 10);
+        assert_eq!(
+            depth.bids,
+            vec![
+                bucket(1000, 15, 3, 15),
+                bucket(990, 7, 1, 22),
+                bucket(980, 3, 1, 25)
+            ]
+        );
+        assert_eq!(
+            depth.asks,
+            vec![
+                bucket(1010, 4, 1, 4),
+                bucket(1020, 14, 3, 18),
+                bucket(1040, 2, 1, 20)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_by_one_keeps_levels() {
+        let snapshot = create_snapshot();
+        let depth = snapshot.aggregate(1);
+
+        assert_eq!(depth.bids.len(), snapshot.bids.len());
+        assert_eq!(depth.asks.len(), snapshot.asks.len());
+        for (bucket, level) in depth.bids.iter().zip(&snapshot.bids) {
+            assert_eq!(bucket.price, level.price);
+            assert_eq!(bucket.visible_quantity, level.visible_quantity);
+        }
+
+        // A bucket size of zero is treated as one
+        assert_eq!(snapshot.aggregate(0), depth);
+    }
+
+    #[test]
+    fn test_aggregate_sorts_unordered_levels() {
+        let mut snapshot = create_snapshot();
+        snapshot.bids.reverse();
+        snapshot.asks.reverse();
+
+        assert_eq!(snapshot.aggregate(10), create_snapshot().aggregate(10));
+    }
+
+    #[test]
+    fn test_aggregate_book_snapshot_with_hidden_quantity() {
+        let book = OrderBook::new("TEST");
+        book.add_limit_order(create_order_id(), 1001, 10, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_iceberg_order(create_order_id(), 1009, 5, 15, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(create_order_id(), 1101, 4, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+
+        let depth = book.create_snapshot(10).aggregate(100);
+
+        assert_eq!(
+            depth.bids,
+            vec![DepthBucket {
+                price: 1000,
+                visible_quantity: 15,
+                hidden_quantity: 15,
+                order_count: 2,
+                cumulative_quantity: 15,
+            }]
+        );
+        assert_eq!(depth.asks, vec![bucket(1200, 4, 1, 4)]);
+    }
+}
+
+#[cfg(test)]
+mod test_snapshot_consistency {
+    use crate::{OrderBook, Sequencer};
+    use pricelevel::{OrderId, OrderUpdate, Side, TimeInForce};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use uuid::Uuid;
+
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    #[test]
+    fn test_snapshot_carries_sequence() {
+        let book = OrderBook::new("TEST");
+        assert_eq!(book.create_snapshot(10).sequence, 0);
+
+        let id = create_order_id();
+        book.add_limit_order(id, 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.submit_market_order(create_order_id(), 4, Side::Buy)
+            .unwrap();
+        book.update_order(OrderUpdate::UpdatePrice {
+            order_id: id,
+            new_price: 1010,
+        })
+        .unwrap();
+        // Rejected commands take their place in the sequence too
+        assert!(
+            book.add_limit_order(create_order_id(), 1000, 100, Side::Sell, TimeInForce::Fok)
+                .is_err()
+        );
+        book.cancel_order(id).unwrap();
+
+        assert_eq!(book.sequence(), 5);
+        let snapshot = book.create_snapshot(10);
+        assert_eq!(snapshot.sequence, 5);
+        assert_eq!(snapshot.aggregate(10).sequence, 5);
+        // Reads do not move the sequence
+        assert_eq!(book.sequence(), 5);
+    }
+
+    #[test]
+    fn test_snapshots_are_consistent_under_concurrent_matching() {
+        let book = Arc::new(OrderBook::new("TEST"));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let writers: Vec<_> = (0..4)
+            .map(|t| {
+                let book = Arc::clone(&book);
+                thread::spawn(move || {
+                    for i in 0..500u64 {
+                        let offset = (t * 3 + i) % 5;
+                        book.add_limit_order(
+                            create_order_id(),
+                            1000 + offset,
+                            1,
+                            Side::Sell,
+                            TimeInForce::Gtc,
+                        )
+                        .unwrap();
+                        book.add_limit_order(
+                            create_order_id(),
+                            999 - offset,
+                            1,
+                            Side::Buy,
+                            TimeInForce::Gtc,
+                        )
+                        .unwrap();
+                        // Aggressive orders empty the best levels while snapshots are taken
+                        let side = if i % 2 == 0 { Side::Buy } else { Side::Sell };
+                        book.submit_market_order(create_order_id(), 2, side).ok();
+                    }
+                })
+            })
+            .collect();
+
+        let reader = {
+            let book = Arc::clone(&book);
+            let running = Arc::clone(&running);
+            thread::spawn(move || {
+                let mut last_sequence = 0;
+                while running.load(Ordering::Acquire) {
+                    let snapshot = book.create_snapshot(usize::MAX);
+                    assert!(snapshot.sequence >= last_sequence);
+                    last_sequence = snapshot.sequence;
+                    assert!(
+                        snapshot
+                            .bids
+                            .iter()
+                            .chain(snapshot.asks.iter())
+                            .all(|level| level.order_count > 0),
+                        "snapshot holds an empty level"
+                    );
+                    if let (Some((bid, _)), Some((ask, _))) =
+                        (snapshot.best_bid(), snapshot.best_ask())
+                    {
+                        assert!(bid < ask, "snapshot is crossed: {bid} >= {ask}");
+                    }
+                }
+            })
+        };
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        running.store(false, Ordering::Release);
+        reader.join().unwrap();
+
+        assert_eq!(book.create_snapshot(1).sequence, 4 * 500 * 3);
+    }
+
+    #[test]
+    fn test_sequencer_view_matches_book_sequence() {
+        let sequencer = Sequencer::start(OrderBook::new("TEST"));
+        sequencer
+            .add_order(
+                pricelevel::OrderType::Standard {
+                    id: create_order_id(),
+                    price: 1000,
+                    quantity: 10,
+                    side: Side::Buy,
+                    timestamp: crate::current_time_millis(),
+                    time_in_force: TimeInForce::Gtc,
+                },
+                None,
+            )
+            .unwrap();
+        assert!(sequencer.cancel_order(create_order_id()).is_ok());
+
+        let view = sequencer.view();
+        assert_eq!(view.version, 2);
+        assert_eq!(view.snapshot.sequence, view.version);
+    }
+}
@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod tests {
+    use crate::orderbook::trades::TradeRecord;
+    use crate::{OrderBook, RollingTradeStats, TradeTape};
+    use pricelevel::{OrderId, Side, TimeInForce};
+    use uuid::Uuid;
+
+    // Helper function to create a unique order ID
+    fn create_order_id() -> OrderId {
+        OrderId(Uuid::new_v4())
+    }
+
+    fn trade(price: u64, timestamp: u64) -> TradeRecord {
+        TradeRecord {
+            transaction_id: Uuid::new_v4(),
+            taker_order_id: create_order_id(),
+            maker_order_id: create_order_id(),
+            taker_side: Side::Buy,
+            price,
+            quantity: 1,
+            timestamp,
+        }
+    }
+
+    const MINUTE: u64 = 60_000;
+    const HOUR: u64 = 60 * MINUTE;
+
+    #[test]
+    fn test_tape_is_bounded_and_newest_first() {
+        let tape = TradeTape::new(3);
+        assert!(tape.is_empty());
+        for price in 1..=5 {
+            tape.push(trade(price, price));
+        }
+
+        assert_eq!(tape.len(), 3);
+        let prices: Vec<u64> = tape.recent(0, 10).iter().map(|t| t.price).collect();
+        assert_eq!(prices, vec![5, 4, 3]);
+        let prices: Vec<u64> = tape.recent(1, 1).iter().map(|t| t.price).collect();
+        assert_eq!(prices, vec![4]);
+        assert!(tape.recent(3, 10).is_empty());
+    }
+
+    #[test]
+    fn test_book_records_real_trade_ids() {
+        let book = OrderBook::new("TEST");
+        let maker_a = create_order_id();
+        let maker_b = create_order_id();
+        let taker = create_order_id();
+        book.add_limit_order(maker_a, 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.add_limit_order(maker_b, 1010, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+
+        let report = book.submit_market_order(taker, 15, Side::Buy).unwrap();
+
+        let trades = book.trade_tape().recent(0, 10);
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].maker_order_id, maker_b);
+        assert_eq!(trades[1].maker_order_id, maker_a);
+        assert!(trades.iter().all(|t| t.taker_order_id == taker));
+        assert!(trades.iter().all(|t| t.taker_side == Side::Buy));
+        assert_eq!(trades[1].transaction_id, report.fills[0].transaction_id);
+        assert_eq!((trades[0].price, trades[0].quantity), (1010, 5));
+    }
+
+    #[test]
+    fn test_rolling_stats() {
+        let stats = RollingTradeStats::new(24 * HOUR, MINUTE);
+        let start = 100 * 24 * HOUR;
+        stats.record(100, 2, start);
+        stats.record(120, 1, start + HOUR);
+        stats.record(90, 3, start + 2 * HOUR);
+        stats.record(110, 4, start + 3 * HOUR);
+
+        let snapshot = stats.stats(start + 3 * HOUR);
+        assert_eq!(snapshot.volume, 10);
+        assert_eq!(snapshot.quote_volume, 200 + 120 + 270 + 440);
+        assert_eq!(snapshot.trade_count, 4);
+        assert_eq!(snapshot.open, Some(100));
+        assert_eq!(snapshot.high, Some(120));
+        assert_eq!(snapshot.low, Some(90));
+        assert_eq!(snapshot.last, Some(110));
+        assert_eq!(snapshot.vwap, Some(103.0));
+        assert_eq!(snapshot.price_change, Some(10));
+        assert_eq!(snapshot.price_change_percent, Some(10.0));
+    }
+
+    #[test]
+    fn test_rolling_stats_evicts_old_trades() {
+        let stats = RollingTradeStats::new(24 * HOUR, MINUTE);
+        let start = 100 * 24 * HOUR;
+        stats.record(150, 5, start);
+        stats.record(100, 1, start + 2 * HOUR);
+
+        // The first trade is still inside the window
+        assert_eq!(stats.stats(start + 24 * HOUR - MINUTE).high, Some(150));
+
+        // One day later only the second trade is left
+        let snapshot = stats.stats(start + 24 * HOUR + MINUTE);
+        assert_eq!(snapshot.volume, 1);
+        assert_eq!(snapshot.trade_count, 1);
+        assert_eq!(snapshot.high, Some(100));
+        assert_eq!(snapshot.open, Some(100));
+        assert_eq!(snapshot.price_change, Some(0));
+
+        // And then nothing
+        let snapshot = stats.stats(start + 27 * HOUR);
+        assert_eq!(snapshot.volume, 0);
+        assert_eq!(snapshot.quote_volume, 0);
+        assert_eq!(snapshot.vwap, None);
+        assert_eq!(snapshot.last, None);
+    }
+
+    #[test]
+    fn test_book_trade_stats() {
+        let book = OrderBook::new("TEST").with_trade_tape_capacity(1);
+        book.add_limit_order(create_order_id(), 1000, 10, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.submit_market_order(create_order_id(), 4, Side::Buy)
+            .unwrap();
+        book.submit_market_order(create_order_id(), 6, Side::Buy)
+            .unwrap();
+
+        assert_eq!(book.trade_tape().len(), 1);
+        let stats = book.trade_stats();
+        assert_eq!(stats.volume, 10);
+        assert_eq!(stats.trade_count, 2);
+        assert_eq!(stats.vwap, Some(1000.0));
+    }
+}
@@ -0,0 +1,274 @@
+//! Recent trades of a book and rolling trade statistics
+
+use pricelevel::{OrderId, Side, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Default number of trades kept in the trade tape
+pub const DEFAULT_TRADE_TAPE_CAPACITY: usize = 10_000;
+
+/// Default length of the rolling statistics window: 24 hours
+pub const DEFAULT_STATS_WINDOW_MS: u64 = 86_400_000;
+
+/// Default granularity of the rolling statistics window: 1 minute
+pub const DEFAULT_STATS_BUCKET_MS: u64 = 60_000;
+
+/// A trade executed by the book
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeRecord {
+    /// Identifier of the trade
+    pub transaction_id: Uuid,
+    /// The aggressive order
+    pub taker_order_id: OrderId,
+    /// The resting order
+    pub maker_order_id: OrderId,
+    /// Side of the aggressive order
+    pub taker_side: Side,
+    /// Execution price
+    pub price: u64,
+    /// Executed quantity
+    pub quantity: u64,
+    /// Time of the execution (milliseconds since epoch)
+    pub timestamp: u64,
+}
+
+impl From<&Transaction> for TradeRecord {
+    fn from(transaction: &Transaction) -> Self {
+        Self {
+            transaction_id: transaction.transaction_id,
+            taker_order_id: transaction.taker_order_id,
+            maker_order_id: transaction.maker_order_id,
+            taker_side: transaction.taker_side,
+            price: transaction.price,
+            quantity: transaction.quantity,
+            timestamp: transaction.timestamp,
+        }
+    }
+}
+
+/// Bounded ring buffer of the most recent trades of a book
+#[derive(Debug)]
+pub struct TradeTape {
+    capacity: usize,
+    trades: Mutex<VecDeque<TradeRecord>>,
+}
+
+impl Default for TradeTape {
+    fn default() -> Self {
+        Self::new(DEFAULT_TRADE_TAPE_CAPACITY)
+    }
+}
+
+impl TradeTape {
+    /// Create a tape keeping at most `capacity` trades
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            trades: Mutex::new(VecDeque::with_capacity(capacity.min(1_024))),
+        }
+    }
+
+    /// Maximum number of trades kept
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of trades currently kept
+    pub fn len(&self) -> usize {
+        self.trades.lock().unwrap().len()
+    }
+
+    /// Returns true if no trade is kept
+    pub fn is_empty(&self) -> bool {
+        self.trades.lock().unwrap().is_empty()
+    }
+
+    /// Add a trade, dropping the oldest one if the tape is full
+    pub fn push(&self, trade: TradeRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut trades = self.trades.lock().unwrap();
+        while trades.len() >= self.capacity {
+            trades.pop_front();
+        }
+        trades.push_back(trade);
+    }
+
+    /// Up to `limit` trades, newest first, skipping the `offset` most recent ones
+    pub fn recent(&self, offset: usize, limit: usize) -> Vec<TradeRecord> {
+        let trades = self.trades.lock().unwrap();
+        trades
+            .iter()
+            .rev()
+            .skip(offset)
+            .take(limit)
+            .copied()
+            .collect()
+    }
+}
+
+/// Trade statistics over a rolling time window
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TradeStats {
+    /// Length of the window in milliseconds
+    pub window_ms: u64,
+    /// Traded quantity
+    pub volume: u64,
+    /// Traded value (sum of price * quantity)
+    pub quote_volume: u128,
+    /// Number of trades
+    pub trade_count: u64,
+    /// Price of the first trade of the window
+    pub open: Option<u64>,
+    /// Highest trade price
+    pub high: Option<u64>,
+    /// Lowest trade price
+    pub low: Option<u64>,
+    /// Price of the last trade
+    pub last: Option<u64>,
+    /// Volume-weighted average price
+    pub vwap: Option<f64>,
+    /// Last price minus open price
+    pub price_change: Option<i64>,
+    /// Price change in percent of the open price
+    pub price_change_percent: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StatsBucket {
+    start: u64,
+    open: u64,
+    high: u64,
+    low: u64,
+    close: u64,
+    volume: u64,
+    quote_volume: u128,
+    trade_count: u64,
+}
+
+#[derive(Debug, Default)]
+struct RollingState {
+    buckets: VecDeque<StatsBucket>,
+    volume: u64,
+    quote_volume: u128,
+    trade_count: u64,
+}
+
+/// Trade statistics over a rolling window, maintained as trades are executed.
+///
+/// Trades are accumulated in fixed-size time buckets and whole buckets leave the window,
+/// so the window is accurate to one bucket (one minute by default).
+#[derive(Debug)]
+pub struct RollingTradeStats {
+    window_ms: u64,
+    bucket_ms: u64,
+    state: Mutex<RollingState>,
+}
+
+impl Default for RollingTradeStats {
+    fn default() -> Self {
+        Self::new(DEFAULT_STATS_WINDOW_MS, DEFAULT_STATS_BUCKET_MS)
+    }
+}
+
+impl RollingTradeStats {
+    /// Create statistics over `window_ms` accumulated in buckets of `bucket_ms`
+    pub fn new(window_ms: u64, bucket_ms: u64) -> Self {
+        Self {
+            window_ms,
+            bucket_ms: bucket_ms.max(1),
+            state: Mutex::new(RollingState::default()),
+        }
+    }
+
+    /// Length of the window in milliseconds
+    pub fn window_ms(&self) -> u64 {
+        self.window_ms
+    }
+
+    /// Add a trade to the statistics
+    pub fn record(&self, price: u64, quantity: u64, timestamp: u64) {
+        let start = timestamp - timestamp % self.bucket_ms;
+        let mut state = self.state.lock().unwrap();
+        self.evict(&mut state, timestamp);
+
+        match state.buckets.back_mut() {
+            // Trades stamped slightly out of order go into the latest bucket
+            Some(bucket) if bucket.start >= start => {
+                bucket.high = bucket.high.max(price);
+                bucket.low = bucket.low.min(price);
+                bucket.close = price;
+                bucket.volume += quantity;
+                bucket.quote_volume += price as u128 * quantity as u128;
+                bucket.trade_count += 1;
+            }
+            _ => state.buckets.push_back(StatsBucket {
+                start,
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+                volume: quantity,
+                quote_volume: price as u128 * quantity as u128,
+                trade_count: 1,
+            }),
+        }
+        state.volume += quantity;
+        state.quote_volume += price as u128 * quantity as u128;
+        state.trade_count += 1;
+    }
+
+    /// Statistics of the window ending at `now` (milliseconds since epoch)
+    pub fn stats(&self, now: u64) -> TradeStats {
+        let mut state = self.state.lock().unwrap();
+        self.evict(&mut state, now);
+
+        let open = state.buckets.front().map(|bucket| bucket.open);
+        let last = state.buckets.back().map(|bucket| bucket.close);
+        let high = state.buckets.iter().map(|bucket| bucket.high).max();
+        let low = state.buckets.iter().map(|bucket| bucket.low).min();
+        let vwap = match state.volume {
+            0 => None,
+            volume => Some(state.quote_volume as f64 / volume as f64),
+        };
+        let price_change = match (open, last) {
+            (Some(open), Some(last)) => Some(last as i64 - open as i64),
+            _ => None,
+        };
+        let price_change_percent = match (price_change, open) {
+            (Some(change), Some(open)) if open > 0 => Some(change as f64 / open as f64 * 100.0),
+            _ => None,
+        };
+
+        TradeStats {
+            window_ms: self.window_ms,
+            volume: state.volume,
+            quote_volume: state.quote_volume,
+            trade_count: state.trade_count,
+            open,
+            high,
+            low,
+            last,
+            vwap,
+            price_change,
+            price_change_percent,
+        }
+    }
+
+    /// Drop the buckets that ended before the window ending at `now` started
+    fn evict(&self, state: &mut RollingState, now: u64) {
+        let window_start = now.saturating_sub(self.window_ms);
+        while let Some(bucket) = state.buckets.front().copied() {
+            if bucket.start + self.bucket_ms > window_start {
+                break;
+            }
+            state.buckets.pop_front();
+            state.volume -= bucket.volume;
+            state.quote_volume -= bucket.quote_volume;
+            state.trade_count -= bucket.trade_count;
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Sources of time for the order book
+
+use super::time::current_time_millis;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// A source of the current time, in milliseconds since UNIX epoch.
+///
+/// The order book reads every timestamp it assigns (order entry, fills, snapshots, expiry
+/// checks) from its clock, so tests and simulations can control time.
+pub trait Clock: Debug + Send + Sync {
+    /// The current time in milliseconds since UNIX epoch
+    fn now_millis(&self) -> u64;
+}
+
+/// The wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        current_time_millis()
+    }
+}
+
+/// A clock that only moves when told to, for deterministic tests
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    /// Create a clock stopped at `now` (milliseconds since epoch)
+    pub fn new(now: u64) -> Self {
+        Self {
+            now: AtomicU64::new(now),
+        }
+    }
+
+    /// Move the clock to `now`, which may be in the past
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    /// Move the clock forward by `duration` and return the new time
+    pub fn advance(&self, duration: Duration) -> u64 {
+        let step = duration.as_millis() as u64;
+        self.now.fetch_add(step, Ordering::SeqCst) + step
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+/// A clock for replaying recorded activity.
+///
+/// It starts at a recorded time and runs `speed` times faster than the wall clock, so a
+/// simulation can go through a session faster than real time. Jumping to the timestamp of
+/// each replayed event keeps it in step with the recording; it never goes backwards.
+#[derive(Debug)]
+pub struct ReplayClock {
+    start: u64,
+    started: Instant,
+    speed: f64,
+    skipped: AtomicU64,
+}
+
+impl ReplayClock {
+    /// Create a clock starting at `start` (milliseconds since epoch) that runs `speed`
+    /// times as fast as the wall clock. A speed of 0 only moves with `jump_to`.
+    pub fn new(start: u64, speed: f64) -> Self {
+        Self {
+            start,
+            started: Instant::now(),
+            speed: speed.max(0.0),
+            skipped: AtomicU64::new(0),
+        }
+    }
+
+    /// Move the clock forward to `timestamp` if it is behind it and return the new time
+    pub fn jump_to(&self, timestamp: u64) -> u64 {
+        let now = self.now_millis();
+        if timestamp > now {
+            self.skipped.fetch_add(timestamp - now, Ordering::SeqCst);
+        }
+        self.now_millis()
+    }
+
+    /// Replay speed relative to the wall clock
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+}
+
+impl Clock for ReplayClock {
+    fn now_millis(&self) -> u64 {
+        let elapsed = self.started.elapsed().as_millis() as f64 * self.speed;
+        self.start + elapsed as u64 + self.skipped.load(Ordering::SeqCst)
+    }
+}
@@ -1,5 +1,7 @@
+mod clock;
 mod time;
 
 mod tests;
 
+pub use clock::{Clock, ManualClock, ReplayClock, SystemClock};
 pub use time::current_time_millis;
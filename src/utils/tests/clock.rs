@@ -0,0 +1,44 @@
+#[cfg(test)]
+mod tests {
+    use crate::{Clock, ManualClock, ReplayClock, SystemClock, current_time_millis};
+    use std::time::Duration;
+
+    #[test]
+    fn test_system_clock_follows_wall_clock() {
+        let difference = SystemClock.now_millis().abs_diff(current_time_millis());
+        assert!(difference <= 10, "Got a {difference}ms difference");
+    }
+
+    #[test]
+    fn test_manual_clock_only_moves_when_told() {
+        let clock = ManualClock::new(1_000);
+        assert_eq!(clock.now_millis(), 1_000);
+        assert_eq!(clock.now_millis(), 1_000);
+
+        assert_eq!(clock.advance(Duration::from_secs(2)), 3_000);
+        assert_eq!(clock.now_millis(), 3_000);
+
+        clock.set(500);
+        assert_eq!(clock.now_millis(), 500);
+    }
+
+    #[test]
+    fn test_replay_clock_jumps_forward_only() {
+        let clock = ReplayClock::new(10_000, 0.0);
+        assert_eq!(clock.now_millis(), 10_000);
+
+        assert_eq!(clock.jump_to(15_000), 15_000);
+        // Recorded events stamped in the past do not move it back
+        assert_eq!(clock.jump_to(12_000), 15_000);
+        assert_eq!(clock.now_millis(), 15_000);
+    }
+
+    #[test]
+    fn test_replay_clock_runs_faster_than_real_time() {
+        let clock = ReplayClock::new(0, 1_000.0);
+        std::thread::sleep(Duration::from_millis(5));
+        // Five real milliseconds are at least five replayed seconds
+        assert!(clock.now_millis() >= 5_000);
+        assert_eq!(clock.speed(), 1_000.0);
+    }
+}
@@ -0,0 +1,389 @@
+use actix_codec::{Decoder, Encoder};
+use bytes::BytesMut;
+use dashmap::DashMap;
+use orderbook_rs::api::binary::protocol::body_length;
+use orderbook_rs::api::binary::{
+    BinaryClient, BinaryCodec, BinaryGateway, BinaryGatewayConfig, ClientMessage, EnterOrder,
+    ExecutionReport, OrderKind, ReasonCode, ReplaceOrder, ServerMessage, run_binary_acceptor,
+};
+use orderbook_rs::api::persistence::Outbox;
+use orderbook_rs::api::stream::{
+    LiquidityRole, OrderEvent, UserStreamConfig, UserStreamHub, UserStreamSink, run_user_streams,
+};
+use orderbook_rs::{OrderBook, Sequencer};
+use pricelevel::{OrderId, Side, TimeInForce};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Start a gateway for the users alice and bob on two books, with heartbeats every 50 ms
+/// and an idle timeout of 300 ms
+async fn start_gateway() -> SocketAddr {
+    let hub = Arc::new(UserStreamHub::new(UserStreamConfig::default(), None, 1));
+    let (sink, receiver) = UserStreamSink::channel();
+    let sink = Arc::new(sink);
+    let sequencers = Arc::new(DashMap::new());
+    for symbol in ["BTC/USD", "ETH/USD"] {
+        let book = OrderBook::new(symbol);
+        book.subscribe(sink.clone());
+        sequencers.insert(symbol.to_string(), Arc::new(Sequencer::start(book)));
+    }
+    actix_rt::spawn(run_user_streams(hub.clone(), receiver));
+
+    let dir = std::env::temp_dir().join(format!("binary-order-entry-{}", Uuid::new_v4()));
+    let outbox = Arc::new(Outbox::open(dir).unwrap());
+    let config = BinaryGatewayConfig {
+        heartbeat_interval: Duration::from_millis(50),
+        idle_timeout: Duration::from_millis(300),
+        ..BinaryGatewayConfig::default()
+    }
+    .with_session("alice", Uuid::new_v4())
+    .with_session("bob", Uuid::new_v4());
+    let gateway = Arc::new(BinaryGateway::new(config, sequencers, hub, outbox));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    actix_rt::spawn(run_binary_acceptor(listener, gateway));
+    addr
+}
+
+async fn login(addr: SocketAddr, username: &str) -> BinaryClient {
+    let mut client = BinaryClient::connect(addr).await.unwrap();
+    client.login(username, 0).await.unwrap();
+    client
+}
+
+/// Next message other than a heartbeat
+async fn next(client: &mut BinaryClient) -> ServerMessage {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), client.next_message())
+            .await
+            .expect("no message received in time")
+            .unwrap()
+            .expect("connection closed");
+        if message != ServerMessage::Heartbeat {
+            return message;
+        }
+    }
+}
+
+async fn next_report(client: &mut BinaryClient) -> ExecutionReport {
+    match next(client).await {
+        ServerMessage::ExecutionReport(report) => report,
+        message => panic!("expected an execution report, got {message:?}"),
+    }
+}
+
+fn limit_order(
+    client_order_id: u64,
+    symbol: &str,
+    side: Side,
+    quantity: u64,
+    price: u64,
+) -> EnterOrder {
+    EnterOrder {
+        client_order_id,
+        symbol: symbol.to_string(),
+        side,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        price,
+        quantity,
+    }
+}
+
+fn encode<M: orderbook_rs::api::binary::WireMessage>(message: M) -> BytesMut {
+    let mut buffer = BytesMut::new();
+    BinaryCodec::<ClientMessage>::new()
+        .encode(message, &mut buffer)
+        .unwrap();
+    buffer
+}
+
+#[test]
+fn test_messages_have_fixed_little_endian_layouts() {
+    let order = ClientMessage::EnterOrder(limit_order(0x0102, "BTC/USD", Side::Sell, 5, 700));
+    let frame = encode(order.clone());
+    assert_eq!(frame.len(), 2 + 1 + body_length(0x10).unwrap());
+    assert_eq!(&frame[..3], &[frame.len() as u8 - 2, 0, 0x10]);
+    assert_eq!(&frame[3..11], &0x0102u64.to_le_bytes());
+    assert_eq!(&frame[11..27], b"BTC/USD\0\0\0\0\0\0\0\0\0");
+    assert_eq!(&frame[27..30], &[2, 0, 0]);
+    assert_eq!(&frame[30..38], &700u64.to_le_bytes());
+
+    let client_messages = [
+        ClientMessage::Login {
+            username: "alice".to_string(),
+            from_sequence: 42,
+        },
+        ClientMessage::Heartbeat,
+        order,
+        ClientMessage::CancelOrder {
+            client_order_id: 9,
+            symbol: "ETH/USD".to_string(),
+        },
+        ClientMessage::ReplaceOrder(ReplaceOrder {
+            client_order_id: 9,
+            symbol: "ETH/USD".to_string(),
+            price: 0,
+            quantity: 12,
+        }),
+        ClientMessage::MassCancel {
+            request_id: 3,
+            symbol: None,
+        },
+        ClientMessage::Logout,
+    ];
+    let report = ExecutionReport {
+        sequence: 7,
+        client_order_id: 9,
+        order_id: OrderId(Uuid::new_v4()),
+        symbol: "ETH/USD".to_string(),
+        event: OrderEvent::PartiallyFilled,
+        side: Side::Buy,
+        role: Some(LiquidityRole::Maker),
+        reason: ReasonCode::None,
+        price: 100,
+        quantity: 10,
+        last_price: 100,
+        last_quantity: 4,
+        cumulative_quantity: 4,
+        remaining_quantity: 6,
+        trade_id: Uuid::new_v4(),
+        timestamp: 1_700_000_000_000,
+    };
+    let server_messages = [
+        ServerMessage::LoginAccepted {
+            epoch: 1,
+            next_sequence: 8,
+        },
+        ServerMessage::LoginRejected {
+            reason: ReasonCode::UnknownUser,
+        },
+        ServerMessage::ExecutionReport(report),
+        ServerMessage::CancelRejected {
+            client_order_id: 9,
+            reason: ReasonCode::UnknownOrder,
+        },
+        ServerMessage::MassCancelAck {
+            request_id: 3,
+            cancelled: 2,
+        },
+        ServerMessage::Logout {
+            reason: ReasonCode::HeartbeatTimeout,
+        },
+    ];
+
+    // A stream of frames decodes back, waiting for the end of a split one
+    let mut buffer = BytesMut::new();
+    for message in &client_messages {
+        buffer.extend_from_slice(&encode(message.clone()));
+    }
+    let split = buffer.split_off(buffer.len() - 1);
+    let mut decoder = BinaryCodec::<ClientMessage>::new();
+    for message in &client_messages[..client_messages.len() - 1] {
+        assert_eq!(decoder.decode(&mut buffer).unwrap().as_ref(), Some(message));
+    }
+    assert_eq!(decoder.decode(&mut buffer).unwrap(), None);
+    buffer.unsplit(split);
+    assert_eq!(
+        decoder.decode(&mut buffer).unwrap(),
+        Some(ClientMessage::Logout)
+    );
+
+    let mut decoder = BinaryCodec::<ServerMessage>::new();
+    for message in server_messages {
+        let mut frame = encode(message.clone());
+        assert_eq!(decoder.decode(&mut frame).unwrap(), Some(message));
+        assert!(frame.is_empty());
+    }
+
+    // Unknown types and wrong lengths end the connection, fields too long are not sent
+    assert!(
+        decoder
+            .decode(&mut BytesMut::from(&[1u8, 0, 0x7f][..]))
+            .is_err()
+    );
+    assert!(
+        decoder
+            .decode(&mut BytesMut::from(&[9u8, 0, 0x92][..]))
+            .is_err()
+    );
+    let mut buffer = BytesMut::new();
+    let too_long = ClientMessage::CancelOrder {
+        client_order_id: 1,
+        symbol: "A".repeat(17),
+    };
+    assert!(
+        BinaryCodec::<ServerMessage>::new()
+            .encode(too_long, &mut buffer)
+            .is_err()
+    );
+    assert!(buffer.is_empty());
+}
+
+#[actix_rt::test]
+async fn test_orders_are_entered_amended_and_cancelled() {
+    let addr = start_gateway().await;
+    let mut alice = login(addr, "alice").await;
+    let mut bob = login(addr, "bob").await;
+
+    alice
+        .enter_order(limit_order(1, "BTC/USD", Side::Buy, 10, 100))
+        .await
+        .unwrap();
+    let accepted = next_report(&mut alice).await;
+    assert_eq!(accepted.event, OrderEvent::Accepted);
+    assert_eq!(accepted.client_order_id, 1);
+    assert_eq!(accepted.remaining_quantity, 10);
+    assert!(accepted.sequence > 0);
+
+    // Bob takes 4: his order is acknowledged, then each side gets its fill
+    bob.enter_order(limit_order(7, "BTC/USD", Side::Sell, 4, 100))
+        .await
+        .unwrap();
+    let bob_accepted = next_report(&mut bob).await;
+    assert_eq!(bob_accepted.event, OrderEvent::Accepted);
+    assert_eq!(bob_accepted.cumulative_quantity, 0);
+    let bob_fill = next_report(&mut bob).await;
+    assert_eq!(bob_fill.event, OrderEvent::Filled);
+    assert_eq!(bob_fill.role, Some(LiquidityRole::Taker));
+    assert_eq!((bob_fill.last_quantity, bob_fill.last_price), (4, 100));
+    let alice_fill = next_report(&mut alice).await;
+    assert_eq!(alice_fill.event, OrderEvent::PartiallyFilled);
+    assert_eq!(alice_fill.role, Some(LiquidityRole::Maker));
+    assert_eq!(alice_fill.trade_id, bob_fill.trade_id);
+    assert_eq!(
+        (
+            alice_fill.cumulative_quantity,
+            alice_fill.remaining_quantity
+        ),
+        (4, 6)
+    );
+
+    // The quantity of a replace is the new total
+    let replace = ReplaceOrder {
+        client_order_id: 1,
+        symbol: "BTC/USD".to_string(),
+        price: 99,
+        quantity: 20,
+    };
+    alice.replace_order(replace).await.unwrap();
+    let replaced = next_report(&mut alice).await;
+    assert_eq!(replaced.event, OrderEvent::Replaced);
+    assert_eq!((replaced.price, replaced.remaining_quantity), (99, 16));
+
+    alice.cancel_order(1, "BTC/USD").await.unwrap();
+    let cancelled = next_report(&mut alice).await;
+    assert_eq!(cancelled.event, OrderEvent::Cancelled);
+    assert_eq!(cancelled.reason, ReasonCode::Cancelled);
+    alice.cancel_order(1, "BTC/USD").await.unwrap();
+    assert_eq!(
+        next(&mut alice).await,
+        ServerMessage::CancelRejected {
+            client_order_id: 1,
+            reason: ReasonCode::UnknownOrder
+        }
+    );
+
+    alice
+        .enter_order(limit_order(2, "DOGE/USD", Side::Buy, 1, 1))
+        .await
+        .unwrap();
+    let rejected = next_report(&mut alice).await;
+    assert_eq!(rejected.event, OrderEvent::Rejected);
+    assert_eq!(
+        (rejected.sequence, rejected.reason),
+        (0, ReasonCode::UnknownSymbol)
+    );
+
+    // A post-only order that would cross is rejected by the book
+    let post_only = EnterOrder {
+        kind: OrderKind::PostOnly,
+        ..limit_order(3, "BTC/USD", Side::Buy, 1, 100)
+    };
+    bob.enter_order(limit_order(8, "BTC/USD", Side::Sell, 1, 100))
+        .await
+        .unwrap();
+    next_report(&mut bob).await;
+    alice.enter_order(post_only).await.unwrap();
+    let rejected = next_report(&mut alice).await;
+    assert_eq!(rejected.event, OrderEvent::Rejected);
+    assert_eq!(rejected.reason, ReasonCode::WouldCross);
+    assert!(rejected.sequence > 0);
+
+    // A mass cancel is acknowledged with the number of orders, then each one is reported
+    alice
+        .enter_order(limit_order(4, "BTC/USD", Side::Buy, 1, 90))
+        .await
+        .unwrap();
+    alice
+        .enter_order(limit_order(5, "ETH/USD", Side::Buy, 1, 90))
+        .await
+        .unwrap();
+    next_report(&mut alice).await;
+    next_report(&mut alice).await;
+    alice.mass_cancel(11, None).await.unwrap();
+    assert_eq!(
+        next(&mut alice).await,
+        ServerMessage::MassCancelAck {
+            request_id: 11,
+            cancelled: 2
+        }
+    );
+    let mut cancelled: Vec<u64> = Vec::new();
+    for _ in 0..2 {
+        let report = next_report(&mut alice).await;
+        assert_eq!(report.event, OrderEvent::Cancelled);
+        cancelled.push(report.client_order_id);
+    }
+    cancelled.sort();
+    assert_eq!(cancelled, [4, 5]);
+
+    assert!(
+        alice
+            .logout()
+            .await
+            .unwrap()
+            .iter()
+            .all(|message| *message == ServerMessage::Heartbeat)
+    );
+}
+
+#[actix_rt::test]
+async fn test_sessions_replay_reports_and_time_out() {
+    let addr = start_gateway().await;
+    let mut mallory = BinaryClient::connect(addr).await.unwrap();
+    let error = mallory.login("mallory", 0).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+
+    let mut alice = login(addr, "alice").await;
+    let mut second = BinaryClient::connect(addr).await.unwrap();
+    assert!(second.login("alice", 0).await.is_err());
+
+    alice
+        .enter_order(limit_order(1, "BTC/USD", Side::Buy, 10, 100))
+        .await
+        .unwrap();
+    let accepted = next_report(&mut alice).await;
+    alice.logout().await.unwrap();
+
+    // Logging in again from the sequence of the report sends it again
+    let mut alice = BinaryClient::connect(addr).await.unwrap();
+    let (epoch, next_sequence) = alice.login("alice", accepted.sequence).await.unwrap();
+    assert_eq!((epoch, next_sequence), (1, accepted.sequence));
+    assert_eq!(next_report(&mut alice).await, accepted);
+
+    // A client sending nothing is logged out
+    assert_eq!(
+        next(&mut alice).await,
+        ServerMessage::Logout {
+            reason: ReasonCode::HeartbeatTimeout
+        }
+    );
+    let closed = tokio::time::timeout(Duration::from_secs(5), alice.next_message())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(closed.is_none());
+}
@@ -0,0 +1,241 @@
+use actix_web::{App, HttpServer, web};
+use dashmap::DashMap;
+use orderbook_rs::api::auth::issue_token;
+use orderbook_rs::api::stream::{
+    CancelOnDisconnectConfig, DisconnectCanceller, StreamClient, UserStreamConfig, UserStreamHub,
+    UserStreamMessage, user_stream_ws,
+};
+use orderbook_rs::{OrderBook, OrderOwner, OrderStatus, Sequencer};
+use pricelevel::{OrderId, OrderType, Side, TimeInForce};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+const SECRET: &[u8] = b"test-secret";
+
+fn limit_order(price: u64, quantity: u64, side: Side) -> OrderType {
+    OrderType::Standard {
+        id: OrderId(Uuid::new_v4()),
+        price,
+        quantity,
+        side,
+        timestamp: orderbook_rs::current_time_millis(),
+        time_in_force: TimeInForce::Gtc,
+    }
+}
+
+struct Exchange {
+    sequencers: Arc<DashMap<String, Arc<Sequencer>>>,
+    addr: SocketAddr,
+}
+
+impl Exchange {
+    /// Serve the user stream of two books, disconnecting clients silent for 200 ms
+    fn start() -> Self {
+        let sequencers = Arc::new(DashMap::new());
+        for symbol in ["BTC/USD", "ETH/USD"] {
+            sequencers.insert(
+                symbol.to_string(),
+                Arc::new(Sequencer::start(OrderBook::new(symbol))),
+            );
+        }
+        let canceller = Arc::new(DisconnectCanceller::new(
+            sequencers.clone(),
+            CancelOnDisconnectConfig::default(),
+        ));
+        let config = UserStreamConfig {
+            heartbeat_interval: Duration::from_millis(50),
+            client_timeout: Duration::from_millis(200),
+            ..UserStreamConfig::default()
+        };
+        let hub = Arc::new(
+            UserStreamHub::new(config, Some(SECRET.to_vec()), 1)
+                .with_cancel_on_disconnect(canceller),
+        );
+
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(hub.clone()))
+                .route("/ws/user", web::get().to(user_stream_ws))
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_rt::spawn(server.run());
+        Self { sequencers, addr }
+    }
+
+    fn sequencer(&self, symbol: &str) -> Arc<Sequencer> {
+        self.sequencers.get(symbol).unwrap().value().clone()
+    }
+
+    fn rest_order(&self, symbol: &str, account: Uuid, client_order_id: &str) -> OrderId {
+        let order = limit_order(1000, 10, Side::Buy);
+        let order_id = order.id();
+        let owner = OrderOwner::new(account, Some(client_order_id.to_string()));
+        self.sequencer(symbol)
+            .add_order(order, Some(owner))
+            .unwrap();
+        order_id
+    }
+
+    fn is_resting(&self, symbol: &str, order_id: OrderId) -> bool {
+        self.sequencer(symbol)
+            .shared_book()
+            .get_order(order_id)
+            .is_some()
+    }
+
+    async fn connect(&self, account: Uuid, options: &str) -> std::io::Result<StreamClient> {
+        let token = issue_token(SECRET, account, u64::MAX);
+        StreamClient::connect(self.addr, &format!("/ws/user?token={token}{options}")).await
+    }
+
+    /// Wait until the order left the book, failing after five seconds
+    async fn wait_cancelled(&self, symbol: &str, order_id: OrderId) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while self.is_resting(symbol, order_id) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("order was not cancelled");
+    }
+}
+
+async fn next_message(client: &mut StreamClient) -> UserStreamMessage {
+    tokio::time::timeout(Duration::from_secs(5), client.next_message())
+        .await
+        .expect("no message received in time")
+        .unwrap()
+        .expect("stream closed")
+}
+
+#[actix_rt::test]
+async fn test_orders_are_cancelled_when_the_session_drops() {
+    let exchange = Exchange::start();
+    let account = Uuid::new_v4();
+    let btc = exchange.rest_order("BTC/USD", account, "btc-1");
+    let eth = exchange.rest_order("ETH/USD", account, "eth-1");
+    let other = exchange.rest_order("BTC/USD", Uuid::new_v4(), "btc-1");
+
+    let mut client = exchange
+        .connect(
+            account,
+            "&cancel_on_disconnect=BTC%2FUSD&grace_period_ms=100",
+        )
+        .await
+        .unwrap();
+    assert!(matches!(
+        next_message(&mut client).await,
+        UserStreamMessage::Welcome { .. }
+    ));
+    client.close().await.unwrap();
+
+    // Only the orders of the account in the chosen symbols are cancelled
+    exchange.wait_cancelled("BTC/USD", btc).await;
+    let record = exchange
+        .sequencer("BTC/USD")
+        .shared_book()
+        .order_state(btc)
+        .unwrap();
+    assert_eq!(record.status, OrderStatus::Cancelled);
+    assert!(exchange.is_resting("ETH/USD", eth));
+    assert!(exchange.is_resting("BTC/USD", other));
+
+    // The next session is told which orders were cancelled, once
+    let mut client = exchange.connect(account, "").await.unwrap();
+    assert!(matches!(
+        next_message(&mut client).await,
+        UserStreamMessage::Welcome { .. }
+    ));
+    let UserStreamMessage::CancelledOnDisconnect(report) = next_message(&mut client).await else {
+        panic!("expected a disconnect report");
+    };
+    assert_eq!(report.orders.len(), 1);
+    assert_eq!(report.orders[0].symbol, "BTC/USD");
+    assert_eq!(report.orders[0].order_id, btc);
+    assert_eq!(report.orders[0].client_order_id.as_deref(), Some("btc-1"));
+    assert!(report.cancelled_at >= report.disconnected_at);
+    client.close().await.unwrap();
+
+    let mut client = exchange.connect(account, "").await.unwrap();
+    assert!(matches!(
+        next_message(&mut client).await,
+        UserStreamMessage::Welcome { .. }
+    ));
+    let pending = tokio::time::timeout(
+        Duration::from_millis(100),
+        client.next_message::<UserStreamMessage>(),
+    )
+    .await;
+    assert!(pending.is_err(), "unexpected message: {pending:?}");
+}
+
+#[actix_rt::test]
+async fn test_orders_are_cancelled_when_heartbeats_stop() {
+    let exchange = Exchange::start();
+    let account = Uuid::new_v4();
+    let btc = exchange.rest_order("BTC/USD", account, "btc-1");
+    let eth = exchange.rest_order("ETH/USD", account, "eth-1");
+
+    let mut client = exchange
+        .connect(account, "&cancel_on_disconnect=all&grace_period_ms=0")
+        .await
+        .unwrap();
+    assert!(matches!(
+        next_message(&mut client).await,
+        UserStreamMessage::Welcome { .. }
+    ));
+
+    // The client stops reading, so it no longer answers the pings of the server
+    exchange.wait_cancelled("BTC/USD", btc).await;
+    exchange.wait_cancelled("ETH/USD", eth).await;
+    drop(client);
+}
+
+#[actix_rt::test]
+async fn test_reconnecting_within_the_grace_period_keeps_the_orders() {
+    let exchange = Exchange::start();
+    let account = Uuid::new_v4();
+    let btc = exchange.rest_order("BTC/USD", account, "btc-1");
+
+    let client = exchange
+        .connect(account, "&cancel_on_disconnect=all&grace_period_ms=300")
+        .await
+        .unwrap();
+    client.close().await.unwrap();
+    let mut client = exchange.connect(account, "").await.unwrap();
+    assert!(matches!(
+        next_message(&mut client).await,
+        UserStreamMessage::Welcome { .. }
+    ));
+
+    // Keep the new session alive past the grace period of the first one
+    let idle = tokio::time::timeout(
+        Duration::from_millis(500),
+        client.next_message::<UserStreamMessage>(),
+    )
+    .await;
+    assert!(idle.is_err(), "unexpected message: {idle:?}");
+    assert!(exchange.is_resting("BTC/USD", btc));
+    client.close().await.unwrap();
+}
+
+#[actix_rt::test]
+async fn test_invalid_cancel_on_disconnect_requests_are_refused() {
+    let exchange = Exchange::start();
+    let account = Uuid::new_v4();
+    for options in [
+        "&cancel_on_disconnect=XRP%2FUSD",
+        "&cancel_on_disconnect=",
+        "&cancel_on_disconnect=all&grace_period_ms=600000",
+    ] {
+        assert!(
+            exchange.connect(account, options).await.is_err(),
+            "{options} was accepted"
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use actix_codec::Decoder;
+use bytes::BytesMut;
+use dashmap::DashMap;
+use orderbook_rs::api::fix::{
+    FixClient, FixCodec, FixGateway, FixGatewayConfig, FixMessage, msg_type, run_fix_acceptor, tags,
+};
+use orderbook_rs::api::persistence::Outbox;
+use orderbook_rs::api::stream::{
+    UserStreamConfig, UserStreamHub, UserStreamSink, run_user_streams,
+};
+use orderbook_rs::{OrderBook, Sequencer};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Start a gateway for the sessions ALICE and BOB on two books, keeping its files in `dir`.
+/// `epoch` is the epoch of the user stream, a restarted server has a new one.
+async fn start_gateway(dir: &Path, epoch: u64) -> SocketAddr {
+    let hub = Arc::new(UserStreamHub::new(UserStreamConfig::default(), None, epoch));
+    let (sink, receiver) = UserStreamSink::channel();
+    let sink = Arc::new(sink);
+    let sequencers = Arc::new(DashMap::new());
+    for symbol in ["BTC/USD", "ETH/USD"] {
+        let book = OrderBook::new(symbol);
+        book.subscribe(sink.clone());
+        sequencers.insert(symbol.to_string(), Arc::new(Sequencer::start(book)));
+    }
+    actix_rt::spawn(run_user_streams(hub.clone(), receiver));
+
+    let outbox = Arc::new(Outbox::open(dir.join("outbox")).unwrap());
+    let config = FixGatewayConfig::new("ORDERBOOK", dir.join("fix"))
+        .with_session("ALICE", Uuid::new_v4())
+        .with_session("BOB", Uuid::new_v4());
+    let gateway = Arc::new(FixGateway::new(config, sequencers, hub, outbox));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    actix_rt::spawn(run_fix_acceptor(listener, gateway));
+    addr
+}
+
+fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("fix-gateway-{}", Uuid::new_v4()))
+}
+
+async fn logon(addr: SocketAddr, sender: &str) -> FixClient {
+    let mut client = FixClient::connect(addr, sender, "ORDERBOOK").await.unwrap();
+    let logon = client.logon(30, true).await.unwrap();
+    assert_eq!(logon.msg_type(), msg_type::LOGON, "{logon}");
+    client
+}
+
+async fn next(client: &mut FixClient) -> FixMessage {
+    tokio::time::timeout(Duration::from_secs(5), client.next_message())
+        .await
+        .expect("no message received in time")
+        .unwrap()
+        .expect("connection closed")
+}
+
+/// Next message, which must be of type `expected`
+async fn expect(client: &mut FixClient, expected: &str) -> FixMessage {
+    let message = next(client).await;
+    assert_eq!(message.msg_type(), expected, "{message}");
+    message
+}
+
+fn limit_order(symbol: &str, cl_ord_id: &str, side: &str, quantity: u64, price: u64) -> FixMessage {
+    format!("35=D|55={symbol}|11={cl_ord_id}|54={side}|38={quantity}|40=2|44={price}|59=1")
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn test_fix_messages_are_framed_and_checked() {
+    let message: FixMessage = "49=ALICE|56=ORDERBOOK|34=7|35=D|11=order-1|55=BTC/USD"
+        .parse()
+        .unwrap();
+    assert_eq!(message.msg_type(), msg_type::NEW_ORDER_SINGLE);
+    let wire = message.to_bytes();
+    let text = String::from_utf8(wire.to_vec())
+        .unwrap()
+        .replace('\u{1}', "|");
+    assert!(text.starts_with("8=FIX.4.4|9="), "{text}");
+    assert!(
+        text.contains("|35=D|49=ALICE|56=ORDERBOOK|34=7|11=order-1|55=BTC/USD|"),
+        "{text}"
+    );
+
+    // BodyLength counts from MsgType to the CheckSum, which is the byte sum modulo 256
+    let trailer = wire.len() - 7;
+    let body_start = text.find("35=").unwrap();
+    let length: usize = text[..body_start]
+        .trim_end_matches('|')
+        .rsplit_once("9=")
+        .unwrap()
+        .1
+        .parse()
+        .unwrap();
+    assert_eq!(length, trailer - body_start);
+    let checksum = wire[..trailer].iter().map(|byte| *byte as u32).sum::<u32>() % 256;
+    assert_eq!(&text[trailer..], format!("10={checksum:03}|"));
+
+    // Noise and a corrupted copy are skipped, a partial message waits for its end
+    let mut corrupted = wire.to_vec();
+    corrupted[trailer - 2] = b'X';
+    let mut buffer = BytesMut::from(&b"noise"[..]);
+    buffer.extend_from_slice(&corrupted);
+    buffer.extend_from_slice(&wire);
+    buffer.extend_from_slice(&wire[..20]);
+    let decoded = FixCodec.decode(&mut buffer).unwrap().unwrap();
+    assert_eq!(decoded.get(tags::CL_ORD_ID), Some("order-1"));
+    assert_eq!(decoded.get(tags::MSG_SEQ_NUM), Some("7"));
+    assert_eq!(FixCodec.decode(&mut buffer).unwrap(), None);
+    buffer.extend_from_slice(&wire[20..]);
+    assert_eq!(FixCodec.decode(&mut buffer).unwrap(), Some(decoded));
+}
+
+#[actix_rt::test]
+async fn test_orders_are_entered_amended_and_cancelled_over_fix() {
+    let dir = temp_dir();
+    let addr = start_gateway(&dir, 1).await;
+    let mut alice = logon(addr, "ALICE").await;
+    let mut bob = logon(addr, "BOB").await;
+
+    alice
+        .send(limit_order("BTC/USD", "a-1", "1", 10, 100))
+        .await
+        .unwrap();
+    let new = expect(&mut alice, msg_type::EXECUTION_REPORT).await;
+    assert_eq!(new.get(tags::EXEC_TYPE), Some("0"));
+    assert_eq!(new.get(tags::CL_ORD_ID), Some("a-1"));
+    assert_eq!(new.get(tags::LEAVES_QTY), Some("10"));
+
+    // Bob takes 4, each side gets a trade report
+    bob.send(limit_order("BTC/USD", "b-1", "2", 4, 100))
+        .await
+        .unwrap();
+    let bob_new = expect(&mut bob, msg_type::EXECUTION_REPORT).await;
+    assert_eq!(bob_new.get(tags::EXEC_TYPE), Some("0"));
+    let bob_fill = expect(&mut bob, msg_type::EXECUTION_REPORT).await;
+    assert_eq!(bob_fill.get(tags::EXEC_TYPE), Some("F"));
+    assert_eq!(bob_fill.get(tags::ORD_STATUS), Some("2"));
+    assert_eq!(bob_fill.get(tags::LAST_QTY), Some("4"));
+    let alice_fill = expect(&mut alice, msg_type::EXECUTION_REPORT).await;
+    assert_eq!(alice_fill.get(tags::EXEC_TYPE), Some("F"));
+    assert_eq!(alice_fill.get(tags::ORD_STATUS), Some("1"));
+    assert_eq!(alice_fill.get(tags::LAST_PX), Some("100"));
+    assert_eq!(alice_fill.get(tags::CUM_QTY), Some("4"));
+    assert_eq!(alice_fill.get(tags::LEAVES_QTY), Some("6"));
+
+    // OrderQty of a replace is the new total
+    let replace = "35=G|55=BTC/USD|41=a-1|11=a-2|54=1|38=20|40=2|44=99"
+        .parse()
+        .unwrap();
+    alice.send(replace).await.unwrap();
+    let replaced = expect(&mut alice, msg_type::EXECUTION_REPORT).await;
+    assert_eq!(replaced.get(tags::EXEC_TYPE), Some("5"));
+    assert_eq!(replaced.get(tags::CL_ORD_ID), Some("a-2"));
+    assert_eq!(replaced.get(tags::ORIG_CL_ORD_ID), Some("a-1"));
+    assert_eq!(replaced.get(tags::PRICE), Some("99"));
+    assert_eq!(replaced.get(tags::LEAVES_QTY), Some("16"));
+
+    let cancel = "35=F|55=BTC/USD|41=a-2|11=a-3|54=1".parse().unwrap();
+    alice.send(cancel).await.unwrap();
+    let cancelled = expect(&mut alice, msg_type::EXECUTION_REPORT).await;
+    assert_eq!(cancelled.get(tags::EXEC_TYPE), Some("4"));
+    assert_eq!(cancelled.get(tags::CL_ORD_ID), Some("a-3"));
+    assert_eq!(cancelled.get(tags::ORIG_CL_ORD_ID), Some("a-2"));
+
+    let unknown = "35=F|55=BTC/USD|41=a-2|11=a-4|54=1".parse().unwrap();
+    alice.send(unknown).await.unwrap();
+    let reject = expect(&mut alice, msg_type::ORDER_CANCEL_REJECT).await;
+    assert_eq!(reject.get(tags::CXL_REJ_REASON), Some("1"));
+
+    // Invalid orders are refused at the session level or by an execution report
+    alice
+        .send("35=D|55=BTC/USD|11=a-5|54=1|40=2|44=100".parse().unwrap())
+        .await
+        .unwrap();
+    let reject = expect(&mut alice, msg_type::REJECT).await;
+    assert_eq!(reject.get(tags::REF_TAG_ID), Some("38"));
+    alice
+        .send(limit_order("DOGE/USD", "a-6", "1", 1, 1))
+        .await
+        .unwrap();
+    let rejected = expect(&mut alice, msg_type::EXECUTION_REPORT).await;
+    assert_eq!(rejected.get(tags::EXEC_TYPE), Some("8"));
+
+    // Mass cancel reports the number of orders, then each cancellation
+    alice
+        .send(limit_order("BTC/USD", "a-7", "1", 1, 90))
+        .await
+        .unwrap();
+    alice
+        .send(limit_order("ETH/USD", "a-8", "1", 1, 90))
+        .await
+        .unwrap();
+    expect(&mut alice, msg_type::EXECUTION_REPORT).await;
+    expect(&mut alice, msg_type::EXECUTION_REPORT).await;
+    alice
+        .send("35=q|11=mass-1|530=7".parse().unwrap())
+        .await
+        .unwrap();
+    let report = expect(&mut alice, msg_type::ORDER_MASS_CANCEL_REPORT).await;
+    assert_eq!(report.get(tags::MASS_CANCEL_RESPONSE), Some("7"));
+    assert_eq!(report.get(tags::TOTAL_AFFECTED_ORDERS), Some("2"));
+    for _ in 0..2 {
+        let cancelled = expect(&mut alice, msg_type::EXECUTION_REPORT).await;
+        assert_eq!(cancelled.get(tags::EXEC_TYPE), Some("4"));
+    }
+
+    assert!(alice.logout().await.unwrap().is_empty());
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[actix_rt::test]
+async fn test_sequence_numbers_survive_a_restart_and_messages_are_resent() {
+    let dir = temp_dir();
+    let addr = start_gateway(&dir, 1).await;
+    let mut alice = logon(addr, "ALICE").await;
+    alice
+        .send(limit_order("BTC/USD", "a-1", "1", 10, 100))
+        .await
+        .unwrap();
+    let new = expect(&mut alice, msg_type::EXECUTION_REPORT).await;
+    assert_eq!(new.get(tags::MSG_SEQ_NUM), Some("2"));
+    assert!(alice.logout().await.unwrap().is_empty());
+    let next_sequence = alice.next_sequence();
+
+    // The restarted gateway continues both sequences from its store
+    let addr = start_gateway(&dir, 2).await;
+    let mut alice = FixClient::connect(addr, "ALICE", "ORDERBOOK")
+        .await
+        .unwrap();
+    alice.set_next_sequence(next_sequence);
+    let logon = alice.logon(30, false).await.unwrap();
+    assert_eq!(logon.msg_type(), msg_type::LOGON, "{logon}");
+    assert_eq!(logon.get(tags::MSG_SEQ_NUM), Some("4"));
+
+    alice.send("35=1|112=ping".parse().unwrap()).await.unwrap();
+    let heartbeat = expect(&mut alice, msg_type::HEARTBEAT).await;
+    assert_eq!(heartbeat.get(tags::TEST_REQ_ID), Some("ping"));
+
+    // Session messages are skipped by gap fills, the execution report is sent again
+    alice.send("35=2|7=1|16=0".parse().unwrap()).await.unwrap();
+    let gap_fill = expect(&mut alice, msg_type::SEQUENCE_RESET).await;
+    assert_eq!(gap_fill.get(tags::MSG_SEQ_NUM), Some("1"));
+    assert_eq!(gap_fill.get(tags::NEW_SEQ_NO), Some("2"));
+    assert!(gap_fill.flag(tags::GAP_FILL_FLAG));
+    let resent = expect(&mut alice, msg_type::EXECUTION_REPORT).await;
+    assert_eq!(resent.get(tags::MSG_SEQ_NUM), Some("2"));
+    assert_eq!(resent.get(tags::CL_ORD_ID), Some("a-1"));
+    assert!(resent.flag(tags::POSS_DUP_FLAG));
+    assert_eq!(
+        resent.get(tags::ORIG_SENDING_TIME),
+        new.get(tags::SENDING_TIME)
+    );
+    let gap_fill = expect(&mut alice, msg_type::SEQUENCE_RESET).await;
+    assert_eq!(gap_fill.get(tags::MSG_SEQ_NUM), Some("3"));
+    assert_eq!(gap_fill.get(tags::NEW_SEQ_NO), Some("6"));
+
+    assert!(alice.logout().await.unwrap().is_empty());
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[actix_rt::test]
+async fn test_sequence_gaps_are_recovered_and_low_sequence_numbers_end_the_session() {
+    let dir = temp_dir();
+    let addr = start_gateway(&dir, 1).await;
+
+    let mut mallory = FixClient::connect(addr, "MALLORY", "ORDERBOOK")
+        .await
+        .unwrap();
+    assert!(mallory.logon(30, true).await.is_err());
+
+    let mut alice = logon(addr, "ALICE").await;
+    alice
+        .send_with_sequence(FixMessage::new(msg_type::HEARTBEAT), 5)
+        .await
+        .unwrap();
+    let resend = expect(&mut alice, msg_type::RESEND_REQUEST).await;
+    assert_eq!(resend.get(tags::BEGIN_SEQ_NO), Some("2"));
+    assert_eq!(resend.get(tags::END_SEQ_NO), Some("0"));
+
+    // Nothing worth resending: fill the gap up to the message after the one received
+    let gap_fill = "35=4|43=Y|123=Y|36=6".parse().unwrap();
+    alice.send_with_sequence(gap_fill, 2).await.unwrap();
+    alice.set_next_sequence(6);
+    alice
+        .send("35=1|112=after-gap".parse().unwrap())
+        .await
+        .unwrap();
+    let heartbeat = expect(&mut alice, msg_type::HEARTBEAT).await;
+    assert_eq!(heartbeat.get(tags::TEST_REQ_ID), Some("after-gap"));
+
+    // A possible duplicate is ignored, a new message with a used number ends the session
+    let duplicate = "35=1|43=Y|112=duplicate".parse().unwrap();
+    alice.send_with_sequence(duplicate, 3).await.unwrap();
+    alice
+        .send_with_sequence(FixMessage::new(msg_type::HEARTBEAT), 4)
+        .await
+        .unwrap();
+    let logout = expect(&mut alice, msg_type::LOGOUT).await;
+    assert!(
+        logout
+            .get(tags::TEXT)
+            .unwrap()
+            .contains("MsgSeqNum too low"),
+        "{logout}"
+    );
+    let closed = tokio::time::timeout(Duration::from_secs(5), alice.next_message())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(closed.is_none());
+    let _ = std::fs::remove_dir_all(dir);
+}
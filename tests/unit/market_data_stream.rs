@@ -0,0 +1,236 @@
+use actix_web::{App, HttpServer, web};
+use dashmap::DashMap;
+use orderbook_rs::OrderBook;
+use orderbook_rs::api::stream::{
+    ClientRequest, DepthLevel, MarketDataHub, MarketDataSink, StreamClient, StreamConfig,
+    StreamMessage, market_data_ws, run_market_data_hub,
+};
+use pricelevel::{OrderId, Side, TimeInForce};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+fn create_order_id() -> OrderId {
+    OrderId(Uuid::new_v4())
+}
+
+fn start_hub(config: StreamConfig) -> (Arc<OrderBook>, Arc<MarketDataHub>) {
+    let book = Arc::new(OrderBook::new("TEST"));
+    let (sink, receiver) = MarketDataSink::channel();
+    book.subscribe(Arc::new(sink));
+    let orderbooks = Arc::new(DashMap::new());
+    orderbooks.insert("TEST".to_string(), book.clone());
+    let hub = Arc::new(MarketDataHub::new(orderbooks, config));
+    actix_rt::spawn(run_market_data_hub(hub.clone(), receiver));
+    (book, hub)
+}
+
+fn fast_config() -> StreamConfig {
+    StreamConfig {
+        throttle: Duration::from_millis(5),
+        ..StreamConfig::default()
+    }
+}
+
+/// Depth rebuilt by a client from a snapshot and the updates that follow it
+#[derive(Debug, Default)]
+struct LocalDepth {
+    sequence: u64,
+    bids: BTreeMap<u64, DepthLevel>,
+    asks: BTreeMap<u64, DepthLevel>,
+}
+
+impl LocalDepth {
+    fn apply(&mut self, message: &StreamMessage) {
+        match message {
+            StreamMessage::DepthSnapshot {
+                sequence,
+                bids,
+                asks,
+                ..
+            } => {
+                *self = LocalDepth::default();
+                self.sequence = *sequence;
+                self.update(bids, asks);
+            }
+            StreamMessage::DepthUpdate {
+                prev_sequence,
+                sequence,
+                bids,
+                asks,
+                ..
+            } => {
+                assert_eq!(*prev_sequence, self.sequence, "gap in the depth stream");
+                self.sequence = *sequence;
+                self.update(bids, asks);
+            }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self, bids: &[DepthLevel], asks: &[DepthLevel]) {
+        for (levels, changes) in [(&mut self.bids, bids), (&mut self.asks, asks)] {
+            for level in changes {
+                if level.quantity == 0 {
+                    levels.remove(&level.price);
+                } else {
+                    levels.insert(level.price, *level);
+                }
+            }
+        }
+    }
+
+    fn matches(&self, book: &OrderBook) -> bool {
+        let snapshot = book.create_snapshot(usize::MAX);
+        let levels = |levels: &BTreeMap<u64, DepthLevel>| {
+            levels
+                .values()
+                .map(|level| (level.price, level.quantity))
+                .collect::<Vec<_>>()
+        };
+        let mut bids: Vec<_> = snapshot
+            .bids
+            .iter()
+            .map(|level| (level.price, level.visible_quantity))
+            .collect();
+        bids.sort();
+        let mut asks: Vec<_> = snapshot
+            .asks
+            .iter()
+            .map(|level| (level.price, level.visible_quantity))
+            .collect();
+        asks.sort();
+        self.sequence == snapshot.sequence
+            && levels(&self.bids) == bids
+            && levels(&self.asks) == asks
+    }
+}
+
+async fn next_message(client: &mut StreamClient) -> StreamMessage {
+    tokio::time::timeout(Duration::from_secs(5), client.next_message())
+        .await
+        .expect("no message received in time")
+        .unwrap()
+        .expect("stream closed")
+}
+
+#[actix_rt::test]
+async fn test_websocket_streams_depth_trades_and_bbo() {
+    let (book, hub) = start_hub(fast_config());
+    book.add_limit_order(create_order_id(), 1000, 10, Side::Sell, TimeInForce::Gtc)
+        .unwrap();
+    book.add_limit_order(create_order_id(), 990, 5, Side::Buy, TimeInForce::Gtc)
+        .unwrap();
+
+    let server_hub = hub.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(server_hub.clone()))
+            .route("/ws/market-data", web::get().to(market_data_ws))
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+    let addr = server.addrs()[0];
+    actix_rt::spawn(server.run());
+
+    let mut client = StreamClient::connect(addr, "/ws/market-data")
+        .await
+        .unwrap();
+    client
+        .subscribe(&["depth:TEST", "trades:TEST", "bbo:TEST", "depth:OTHER"])
+        .await
+        .unwrap();
+
+    let mut depth = LocalDepth::default();
+    let mut subscribed = Vec::new();
+    let mut errors = 0;
+    while depth.sequence == 0 {
+        let message = next_message(&mut client).await;
+        match &message {
+            StreamMessage::Subscribed { channel } => subscribed.push(channel.clone()),
+            StreamMessage::Error { .. } => errors += 1,
+            _ => depth.apply(&message),
+        }
+    }
+    assert_eq!(subscribed, vec!["depth:TEST", "trades:TEST", "bbo:TEST"]);
+    assert_eq!(errors, 1);
+    assert!(depth.matches(&book));
+
+    let taker = create_order_id();
+    book.submit_market_order(taker, 4, Side::Buy).unwrap();
+    book.add_limit_order(create_order_id(), 995, 3, Side::Buy, TimeInForce::Gtc)
+        .unwrap();
+
+    let mut trade = None;
+    let mut best_bid = None;
+    while trade.is_none() || best_bid != Some(995) || !depth.matches(&book) {
+        let message = next_message(&mut client).await;
+        match &message {
+            StreamMessage::Trade { trade: t, .. } => trade = Some(*t),
+            StreamMessage::Bbo { bid, .. } => best_bid = bid.map(|level| level.price),
+            _ => depth.apply(&message),
+        }
+    }
+    let trade = trade.unwrap();
+    assert_eq!(trade.taker_order_id, taker);
+    assert_eq!((trade.price, trade.quantity), (1000, 4));
+
+    client.unsubscribe(&["trades:TEST"]).await.unwrap();
+    loop {
+        if let StreamMessage::Unsubscribed { channel } = next_message(&mut client).await {
+            assert_eq!(channel, "trades:TEST");
+            break;
+        }
+    }
+    client.close().await.unwrap();
+}
+
+#[actix_rt::test]
+async fn test_slow_sessions_are_conflated() {
+    let (book, hub) = start_hub(StreamConfig {
+        max_queued_trades: 1,
+        ..fast_config()
+    });
+    book.add_limit_order(create_order_id(), 1000, 10, Side::Sell, TimeInForce::Gtc)
+        .unwrap();
+
+    let session = hub.open_session();
+    session.handle(ClientRequest::Subscribe {
+        channels: vec!["depth:TEST".to_string(), "trades:TEST".to_string()],
+    });
+    let mut depth = LocalDepth::default();
+    for message in session.next_batch().await {
+        depth.apply(&message);
+    }
+    assert!(depth.matches(&book));
+
+    // Several publications happen while the session sends nothing
+    for price in [1010, 1020, 1030] {
+        book.add_limit_order(create_order_id(), price, 1, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+        book.submit_market_order(create_order_id(), 1, Side::Buy)
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+    }
+
+    let batch = session.try_batch();
+    let updates: Vec<_> = batch
+        .iter()
+        .filter(|message| matches!(message, StreamMessage::DepthUpdate { .. }))
+        .collect();
+    assert_eq!(updates.len(), 1);
+    updates.iter().for_each(|message| depth.apply(message));
+    assert!(depth.matches(&book));
+
+    let trades = batch
+        .iter()
+        .filter(|message| matches!(message, StreamMessage::Trade { .. }))
+        .count();
+    assert_eq!(trades, 1);
+    assert!(batch.contains(&StreamMessage::TradesDropped {
+        channel: "trades:TEST".to_string(),
+        count: 2
+    }));
+}
@@ -0,0 +1,252 @@
+use dashmap::DashMap;
+use orderbook_rs::api::feed::{
+    FeedDecoder, MarketFeed, MarketFeedConfig, MarketFeedSink, Packet, RetransmissionClient,
+    SequenceGap, run_market_feed, run_retransmission_server,
+};
+use orderbook_rs::{OrderBook, OrderBookSnapshot};
+use pricelevel::{OrderId, OrderUpdate, PriceLevelSnapshot, Side, TimeInForce};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use uuid::Uuid;
+
+fn create_order_id() -> OrderId {
+    OrderId(Uuid::new_v4())
+}
+
+struct TestFeed {
+    book: Arc<OrderBook>,
+    feed: Arc<MarketFeed>,
+    receiver: UdpSocket,
+    retransmissions: SocketAddr,
+}
+
+/// Start a feed of the book TEST sending small datagrams to a local socket, after `setup`
+/// placed the orders the first snapshot shows
+async fn start_feed(retained_messages: usize, setup: impl FnOnce(&OrderBook)) -> TestFeed {
+    let book = Arc::new(OrderBook::new("TEST"));
+    setup(&book);
+    let (sink, receiver) = MarketFeedSink::channel();
+    book.subscribe(Arc::new(sink));
+    let orderbooks = Arc::new(DashMap::new());
+    orderbooks.insert("TEST".to_string(), book.clone());
+
+    let udp_receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let config = MarketFeedConfig {
+        session: "TEST".to_string(),
+        bind: "127.0.0.1:0".parse().unwrap(),
+        destinations: vec![udp_receiver.local_addr().unwrap()],
+        max_packet_bytes: 200,
+        heartbeat_interval: Duration::from_millis(50),
+        snapshot_interval: Duration::from_secs(3600),
+        retained_messages,
+        ..MarketFeedConfig::default()
+    };
+    let feed = Arc::new(MarketFeed::bind(config, orderbooks).await.unwrap());
+    actix_rt::spawn(run_market_feed(feed.clone(), receiver));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let retransmissions = listener.local_addr().unwrap();
+    actix_rt::spawn(run_retransmission_server(listener, feed.clone()));
+    TestFeed {
+        book,
+        feed,
+        receiver: udp_receiver,
+        retransmissions,
+    }
+}
+
+async fn next_packet(socket: &UdpSocket) -> Packet {
+    let mut buffer = [0; 2048];
+    let length = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut buffer))
+        .await
+        .expect("no datagram received in time")
+        .unwrap();
+    assert!(length <= 200, "datagram of {length} bytes");
+    Packet::decode(&buffer[..length]).unwrap()
+}
+
+/// Apply the datagrams of the feed until the decoded book reaches the sequence of `book`
+async fn decode_until_current(socket: &UdpSocket, decoder: &mut FeedDecoder, book: &OrderBook) {
+    loop {
+        decoder.apply(&next_packet(socket).await).unwrap();
+        if decoder
+            .snapshot("TEST")
+            .is_some_and(|snapshot| snapshot.sequence == book.sequence())
+        {
+            return;
+        }
+    }
+}
+
+type Level = (u64, u64, usize, Vec<(OrderId, u64)>);
+
+/// What the feed shows of the levels: displayed quantities and the orders of each level
+fn displayed(levels: &[PriceLevelSnapshot]) -> Vec<Level> {
+    levels
+        .iter()
+        .filter(|level| level.visible_quantity > 0)
+        .map(|level| {
+            let mut orders: Vec<_> = level
+                .orders
+                .iter()
+                .map(|order| (order.id(), order.visible_quantity()))
+                .collect();
+            orders.sort_by_key(|(id, _)| id.0);
+            (
+                level.price,
+                level.visible_quantity,
+                level.order_count,
+                orders,
+            )
+        })
+        .collect()
+}
+
+fn assert_same_book(decoded: &OrderBookSnapshot, book: &OrderBook) {
+    let expected = book.create_snapshot(usize::MAX);
+    assert_eq!(decoded.symbol, expected.symbol);
+    assert_eq!(decoded.sequence, expected.sequence);
+    assert_eq!(displayed(&decoded.bids), displayed(&expected.bids));
+    assert_eq!(displayed(&decoded.asks), displayed(&expected.asks));
+}
+
+/// Orders and executions of every kind the feed publishes
+fn trade_a_while(book: &OrderBook) {
+    let iceberg = create_order_id();
+    book.add_iceberg_order(iceberg, 1010, 5, 20, Side::Sell, TimeInForce::Gtc)
+        .unwrap();
+    book.add_limit_order(create_order_id(), 1010, 4, Side::Sell, TimeInForce::Gtc)
+        .unwrap();
+    let amended = create_order_id();
+    book.add_limit_order(amended, 990, 10, Side::Buy, TimeInForce::Gtc)
+        .unwrap();
+    let cancelled = create_order_id();
+    book.add_limit_order(cancelled, 980, 3, Side::Buy, TimeInForce::Gtc)
+        .unwrap();
+
+    // Executes the displayed part of the iceberg, which is refreshed from its reserve
+    book.submit_market_order(create_order_id(), 12, Side::Buy)
+        .unwrap();
+    book.submit_market_order(create_order_id(), 2, Side::Sell)
+        .unwrap();
+    book.cancel_order(cancelled).unwrap();
+    book.update_order(OrderUpdate::UpdateQuantity {
+        order_id: amended,
+        new_quantity: 6,
+    })
+    .unwrap();
+    book.update_order(OrderUpdate::UpdatePrice {
+        order_id: amended,
+        new_price: 995,
+    })
+    .unwrap();
+
+    book.add_limit_order(create_order_id(), 1020, 7, Side::Sell, TimeInForce::Gtc)
+        .unwrap();
+}
+
+#[actix_rt::test]
+async fn test_decoded_feed_matches_the_book() {
+    let resting = create_order_id();
+    let test = start_feed(1000, |book| {
+        book.add_limit_order(resting, 1000, 8, Side::Buy, TimeInForce::Gtc)
+            .unwrap();
+    })
+    .await;
+
+    // The first snapshot gives the order placed before the feed started
+    let mut decoder = FeedDecoder::new();
+    decode_until_current(&test.receiver, &mut decoder, &test.book).await;
+    let snapshot = decoder.snapshot("TEST").unwrap();
+    assert_eq!(
+        displayed(&snapshot.bids),
+        vec![(1000, 8, 1, vec![(resting, 8)])]
+    );
+    assert!(snapshot.asks.is_empty());
+
+    trade_a_while(&test.book);
+    decode_until_current(&test.receiver, &mut decoder, &test.book).await;
+    assert_same_book(&decoder.snapshot("TEST").unwrap(), &test.book);
+
+    // Quiet feeds send heartbeats giving the next sequence
+    let heartbeat = next_packet(&test.receiver).await;
+    assert!(heartbeat.messages.is_empty());
+    assert_eq!(Some(heartbeat.sequence), decoder.next_sequence());
+    assert_eq!(heartbeat.sequence, test.feed.next_sequence());
+}
+
+#[actix_rt::test]
+async fn test_retransmissions_fill_the_gaps() {
+    let test = start_feed(1000, |_| {}).await;
+    let mut live = FeedDecoder::new();
+    decode_until_current(&test.receiver, &mut live, &test.book).await;
+    trade_a_while(&test.book);
+    decode_until_current(&test.receiver, &mut live, &test.book).await;
+    let next_sequence = live.next_sequence().unwrap();
+
+    // A decoder that lost a datagram refuses the next one until the lost one is applied
+    let mut client = RetransmissionClient::connect(test.retransmissions, "TEST")
+        .await
+        .unwrap();
+    let packets = client.request_range(1, next_sequence).await.unwrap();
+    assert!(packets.len() > 2);
+    assert_eq!(packets.last().unwrap().next_sequence(), next_sequence);
+    let mut late = FeedDecoder::new();
+    late.apply(&packets[0]).unwrap();
+    assert_eq!(
+        late.apply(&packets[2]),
+        Err(SequenceGap {
+            expected: packets[1].sequence,
+            received: packets[2].sequence
+        })
+    );
+    for packet in &packets[1..] {
+        late.apply(packet).unwrap();
+    }
+    // Messages applied already are skipped
+    late.apply(&packets[1]).unwrap();
+    assert_same_book(&late.snapshot("TEST").unwrap(), &test.book);
+
+    // Nothing to send yet
+    let pending = client.request(next_sequence, 10).await.unwrap();
+    assert!(pending.messages.is_empty());
+    assert_eq!(pending.sequence, next_sequence);
+}
+
+#[actix_rt::test]
+async fn test_messages_no_longer_retained_are_not_retransmitted() {
+    let test = start_feed(5, |_| {}).await;
+    let mut decoder = FeedDecoder::new();
+    decode_until_current(&test.receiver, &mut decoder, &test.book).await;
+    trade_a_while(&test.book);
+    decode_until_current(&test.receiver, &mut decoder, &test.book).await;
+    let next_sequence = decoder.next_sequence().unwrap();
+
+    let mut client = RetransmissionClient::connect(test.retransmissions, "TEST")
+        .await
+        .unwrap();
+    let expired = client.request(1, 10).await.unwrap();
+    assert!(expired.messages.is_empty());
+    assert_eq!(expired.sequence, next_sequence - 5);
+
+    // The retained messages come in as many datagrams as they need
+    let retained = client
+        .request_range(next_sequence - 5, next_sequence)
+        .await
+        .unwrap();
+    assert_eq!(retained[0].sequence, next_sequence - 5);
+    assert_eq!(
+        retained
+            .iter()
+            .map(|packet| packet.messages.len())
+            .sum::<usize>(),
+        5
+    );
+
+    // Requests of another session end the connection
+    let mut stranger = RetransmissionClient::connect(test.retransmissions, "OTHER")
+        .await
+        .unwrap();
+    assert!(stranger.request(1, 10).await.is_err());
+}
@@ -1 +1,8 @@
-
+mod binary_order_entry;
+mod cancel_on_disconnect;
+mod fix_gateway;
+mod market_data_stream;
+mod market_feed;
+mod outbox;
+mod redis_publisher;
+mod user_stream;
@@ -0,0 +1,260 @@
+use orderbook_rs::api::persistence::{DeadLetter, Outbox, OutboxRecord};
+use orderbook_rs::{CheckpointConfig, CheckpointManager, OrderBook, Sequencer, SequencerConfig};
+use pricelevel::{OrderId, OrderType, Side, TimeInForce};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use uuid::Uuid;
+
+fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("outbox-{}", Uuid::new_v4()))
+}
+
+fn rejected(order_id: Uuid) -> OutboxRecord {
+    OutboxRecord::OrderRejected { order_id }
+}
+
+fn rejected_ids(records: &[OutboxRecord]) -> Vec<Uuid> {
+    records
+        .iter()
+        .map(|record| match record {
+            OutboxRecord::OrderRejected { order_id } => *order_id,
+            other => panic!("unexpected record {other:?}"),
+        })
+        .collect()
+}
+
+/// Symbol and sequence number of the book events of `records`
+fn event_sequences(records: &[OutboxRecord]) -> Vec<(String, u64)> {
+    records
+        .iter()
+        .filter_map(|record| match record {
+            OutboxRecord::Book(event) => Some((event.symbol().to_string(), event.sequence())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn limit_order(price: u64, quantity: u64, side: Side) -> OrderType {
+    OrderType::Standard {
+        id: OrderId(Uuid::new_v4()),
+        price,
+        quantity,
+        side,
+        timestamp: orderbook_rs::current_time_millis(),
+        time_in_force: TimeInForce::Gtc,
+    }
+}
+
+/// Run a few journaled commands on a book feeding the outbox in `dir`, returning the
+/// length of the outbox after the first one
+fn run_commands(dir: &Path) -> u64 {
+    let manager = CheckpointManager::open(dir.join("data"), CheckpointConfig::default()).unwrap();
+    let outbox = Arc::new(Outbox::open(dir.join("outbox")).unwrap());
+    let book = OrderBook::new("TEST");
+    book.subscribe(outbox.clone());
+    let sequencer = Sequencer::with_journal(book, SequencerConfig::default(), manager.journal());
+    sequencer
+        .add_order(limit_order(1000, 10, Side::Sell), None)
+        .unwrap();
+    let first = outbox.pending_bytes();
+    sequencer
+        .add_order(limit_order(1010, 5, Side::Sell), None)
+        .unwrap();
+    sequencer
+        .add_order(limit_order(1010, 12, Side::Buy), None)
+        .unwrap();
+    first
+}
+
+/// Recover a book feeding the outbox in `dir` from the journal
+fn recover(dir: &Path, outbox: Arc<Outbox>) -> OrderBook {
+    let manager = CheckpointManager::open(dir.join("data"), CheckpointConfig::default()).unwrap();
+    let book = OrderBook::new("TEST");
+    book.subscribe(outbox);
+    manager.recover([&book]).unwrap();
+    book
+}
+
+#[test]
+fn test_partial_line_is_not_read_and_dropped_on_reopen() {
+    let dir = temp_dir();
+    let ids = [Uuid::new_v4(), Uuid::new_v4()];
+    {
+        let outbox = Outbox::open(&dir).unwrap();
+        for id in ids {
+            outbox.append(&rejected(id)).unwrap();
+        }
+    }
+    // A crash in the middle of an append leaves the start of a line behind
+    let complete = fs::metadata(dir.join("outbox.jsonl")).unwrap().len();
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(dir.join("outbox.jsonl"))
+        .unwrap();
+    file.write_all(b"{\"OrderRejected\":{\"order_id\"").unwrap();
+
+    let outbox = Outbox::open(&dir).unwrap();
+    let (records, offset) = outbox.read_batch(10).unwrap();
+    assert_eq!(rejected_ids(&records), ids);
+    assert_eq!(offset, complete);
+
+    // The partial line is dropped, so the next record starts on a line of its own
+    let next = Uuid::new_v4();
+    outbox.append(&rejected(next)).unwrap();
+    let (records, _) = outbox.read_batch(10).unwrap();
+    assert_eq!(rejected_ids(&records), [ids[0], ids[1], next]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_reopened_outbox_resumes_after_the_committed_offset() {
+    let dir = temp_dir();
+    let ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+    {
+        let outbox = Outbox::open(&dir).unwrap();
+        for id in ids {
+            outbox.append(&rejected(id)).unwrap();
+        }
+        let (records, offset) = outbox.read_batch(2).unwrap();
+        assert_eq!(rejected_ids(&records), ids[..2]);
+        outbox.commit(offset).unwrap();
+    }
+
+    let outbox = Outbox::open(&dir).unwrap();
+    assert!(outbox.pending_bytes() > 0);
+    let (records, offset) = outbox.read_batch(10).unwrap();
+    assert_eq!(rejected_ids(&records), ids[2..]);
+    outbox.commit(offset).unwrap();
+    assert_eq!(outbox.pending_bytes(), 0);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_fully_persisted_outbox_is_truncated() {
+    let dir = temp_dir();
+    let outbox = Outbox::open(&dir).unwrap().with_compact_bytes(1);
+    outbox.append(&rejected(Uuid::new_v4())).unwrap();
+    outbox.append(&rejected(Uuid::new_v4())).unwrap();
+
+    // Nothing is truncated while records are still pending
+    let (_, offset) = outbox.read_batch(1).unwrap();
+    outbox.commit(offset).unwrap();
+    assert!(fs::metadata(dir.join("outbox.jsonl")).unwrap().len() > offset);
+
+    let (_, offset) = outbox.read_batch(10).unwrap();
+    outbox.commit(offset).unwrap();
+    assert_eq!(fs::metadata(dir.join("outbox.jsonl")).unwrap().len(), 0);
+    assert_eq!(fs::read_to_string(dir.join("outbox.offset")).unwrap(), "0");
+
+    // Appends go on at the start of the truncated file
+    let id = Uuid::new_v4();
+    outbox.append(&rejected(id)).unwrap();
+    drop(outbox);
+    let outbox = Outbox::open(&dir).unwrap();
+    let (records, _) = outbox.read_batch(10).unwrap();
+    assert_eq!(rejected_ids(&records), [id]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_dead_letters_keep_the_record_and_the_error() {
+    let dir = temp_dir();
+    let outbox = Outbox::open(&dir).unwrap();
+    let id = Uuid::new_v4();
+    outbox
+        .dead_letter(&rejected(id), "violates foreign key constraint")
+        .unwrap();
+
+    let content = fs::read_to_string(outbox.dead_letter_path()).unwrap();
+    let letters: Vec<DeadLetter> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].error, "violates foreign key constraint");
+    assert_eq!(rejected_ids(&[letters[0].record.clone()]), [id]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_replayed_events_already_in_the_outbox_are_skipped() {
+    let dir = temp_dir();
+    run_commands(&dir);
+    let outbox = Outbox::open(dir.join("outbox"))
+        .unwrap()
+        .with_compact_bytes(1);
+    let (records, offset) = outbox.read_batch(100).unwrap();
+    let sequences = event_sequences(&records);
+    assert_eq!(sequences.last(), Some(&("TEST".to_string(), 3)));
+    // Truncated once persisted, the outbox still knows the events it held
+    outbox.commit(offset).unwrap();
+    assert_eq!(outbox.pending_bytes(), 0);
+    drop(outbox);
+
+    let outbox = Arc::new(Outbox::open(dir.join("outbox")).unwrap());
+    let book = recover(&dir, outbox.clone());
+    assert_eq!(book.sequence(), 3);
+    assert_eq!(outbox.pending_bytes(), 0);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_replay_appends_the_events_the_outbox_lost() {
+    let dir = temp_dir();
+    let first = run_commands(&dir);
+    let path = dir.join("outbox").join("outbox.jsonl");
+    let (records, _) = Outbox::open(dir.join("outbox"))
+        .unwrap()
+        .read_batch(100)
+        .unwrap();
+    let expected = event_sequences(&records);
+
+    // A crash of the machine loses what was not synced yet
+    OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_len(first)
+        .unwrap();
+
+    let outbox = Arc::new(Outbox::open(dir.join("outbox")).unwrap());
+    recover(&dir, outbox.clone());
+    let (records, _) = outbox.read_batch(100).unwrap();
+    assert_eq!(event_sequences(&records), expected);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_sequences_restart_only_once_everything_is_persisted() {
+    let dir = temp_dir();
+    run_commands(&dir);
+    let outbox = Arc::new(Outbox::open(dir.join("outbox")).unwrap());
+    assert!(outbox.restart_sequences().is_err());
+
+    let (_, offset) = outbox.read_batch(100).unwrap();
+    outbox.commit(offset).unwrap();
+    outbox.restart_sequences().unwrap();
+    assert_eq!(
+        fs::metadata(dir.join("outbox").join("outbox.jsonl"))
+            .unwrap()
+            .len(),
+        0
+    );
+
+    // A book numbering its mutations from 0 again has all of its events appended
+    let book = OrderBook::new("TEST");
+    book.subscribe(outbox.clone());
+    book.add_limit_order(
+        OrderId(Uuid::new_v4()),
+        1000,
+        1,
+        Side::Sell,
+        TimeInForce::Gtc,
+    )
+    .unwrap();
+    let (records, _) = outbox.read_batch(100).unwrap();
+    assert_eq!(event_sequences(&records), [("TEST".to_string(), 1)]);
+    fs::remove_dir_all(dir).unwrap();
+}
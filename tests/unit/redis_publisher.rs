@@ -0,0 +1,129 @@
+use orderbook_rs::OrderBook;
+use orderbook_rs::api::redis::{RedisEventSink, orderbook_cache};
+use pricelevel::{OrderId, Side, TimeInForce};
+use std::collections::HashSet;
+use std::sync::Arc;
+use uuid::Uuid;
+
+fn create_order_id() -> OrderId {
+    OrderId(Uuid::new_v4())
+}
+
+#[actix_rt::test]
+async fn test_trades_are_queued_and_book_changes_marked() {
+    let (sink, mut receiver) = RedisEventSink::channel(100);
+    let book = OrderBook::new("BTC/USD");
+    book.subscribe(Arc::new(sink));
+
+    book.add_limit_order(create_order_id(), 1000, 5, Side::Sell, TimeInForce::Gtc)
+        .unwrap();
+    book.add_limit_order(create_order_id(), 1010, 5, Side::Sell, TimeInForce::Gtc)
+        .unwrap();
+    assert_eq!(
+        receiver.take_changed(),
+        HashSet::from(["BTC/USD".to_string()])
+    );
+
+    book.submit_market_order(create_order_id(), 8, Side::Buy)
+        .unwrap();
+    let trades = receiver.recv_trades(100).await.unwrap();
+    let executions: Vec<(&str, u64, u64)> = trades
+        .iter()
+        .map(|(symbol, trade)| (symbol.as_str(), trade.price, trade.quantity))
+        .collect();
+    assert_eq!(executions, [("BTC/USD", 1000, 5), ("BTC/USD", 1010, 3)]);
+    assert_eq!(
+        receiver.take_changed(),
+        HashSet::from(["BTC/USD".to_string()])
+    );
+    assert!(!receiver.take_lagged());
+}
+
+#[test]
+fn test_book_changes_are_conflated_until_taken() {
+    let (sink, receiver) = RedisEventSink::channel(100);
+    let sink = Arc::new(sink);
+    let btc = OrderBook::new("BTC/USD");
+    let eth = OrderBook::new("ETH/USD");
+    btc.subscribe(sink.clone());
+    eth.subscribe(sink);
+
+    for i in 0..100 {
+        btc.add_limit_order(create_order_id(), 1000 + i, 1, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+    }
+    eth.add_limit_order(create_order_id(), 900, 1, Side::Buy, TimeInForce::Gtc)
+        .unwrap();
+
+    // One publish per throttle interval covers every change of a book
+    assert_eq!(
+        receiver.take_changed(),
+        HashSet::from(["BTC/USD".to_string(), "ETH/USD".to_string()])
+    );
+    assert!(receiver.take_changed().is_empty());
+
+    btc.add_limit_order(create_order_id(), 990, 1, Side::Buy, TimeInForce::Gtc)
+        .unwrap();
+    assert_eq!(
+        receiver.take_changed(),
+        HashSet::from(["BTC/USD".to_string()])
+    );
+}
+
+#[actix_rt::test]
+async fn test_trades_beyond_the_capacity_mark_the_receiver_lagged() {
+    let (sink, mut receiver) = RedisEventSink::channel(2);
+    let book = OrderBook::new("BTC/USD");
+    book.subscribe(Arc::new(sink));
+    for price in [1000, 1010, 1020] {
+        book.add_limit_order(create_order_id(), price, 1, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+    }
+
+    book.submit_market_order(create_order_id(), 3, Side::Buy)
+        .unwrap();
+    assert_eq!(receiver.recv_trades(100).await.unwrap().len(), 2);
+    assert!(receiver.take_lagged());
+    assert!(!receiver.take_lagged());
+}
+
+#[actix_rt::test]
+async fn test_trades_are_taken_in_batches() {
+    let (sink, mut receiver) = RedisEventSink::channel(100);
+    let book = OrderBook::new("BTC/USD");
+    book.subscribe(Arc::new(sink));
+    for price in 1000..1005 {
+        book.add_limit_order(create_order_id(), price, 1, Side::Sell, TimeInForce::Gtc)
+            .unwrap();
+    }
+
+    book.submit_market_order(create_order_id(), 5, Side::Buy)
+        .unwrap();
+    assert_eq!(receiver.recv_trades(3).await.unwrap().len(), 3);
+    assert_eq!(receiver.recv_trades(3).await.unwrap().len(), 2);
+}
+
+#[test]
+fn test_orderbook_cache_is_built_from_one_snapshot() {
+    let book = OrderBook::new("BTC/USD");
+    book.add_limit_order(create_order_id(), 990, 5, Side::Buy, TimeInForce::Gtc)
+        .unwrap();
+    book.add_limit_order(create_order_id(), 990, 3, Side::Buy, TimeInForce::Gtc)
+        .unwrap();
+    book.add_iceberg_order(create_order_id(), 1010, 2, 8, Side::Sell, TimeInForce::Gtc)
+        .unwrap();
+    book.add_limit_order(create_order_id(), 1020, 4, Side::Sell, TimeInForce::Gtc)
+        .unwrap();
+
+    let cache = orderbook_cache(&book.create_snapshot(usize::MAX), Some(1000));
+    assert_eq!(cache.symbol, "BTC/USD");
+    assert_eq!(cache.best_bid, Some(990));
+    assert_eq!(cache.best_ask, Some(1010));
+    assert_eq!(cache.spread, Some(20));
+    assert_eq!(cache.mid_price, Some(1000.0));
+    assert_eq!(cache.last_trade_price, Some(1000));
+    assert_eq!(cache.total_orders, 4);
+    assert_eq!((cache.bid_levels, cache.ask_levels), (1, 2));
+    assert_eq!(cache.total_bid_quantity, 8);
+    assert_eq!(cache.total_ask_quantity, 14);
+}
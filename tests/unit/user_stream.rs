@@ -0,0 +1,222 @@
+use actix_web::{App, HttpServer, web};
+use orderbook_rs::api::auth::{TokenError, issue_token, verify_token};
+use orderbook_rs::api::stream::{
+    LiquidityRole, OrderEvent, OrderNotification, StreamClient, UserStreamConfig, UserStreamHub,
+    UserStreamMessage, UserStreamSink, run_user_streams, user_stream_ws,
+};
+use orderbook_rs::{OrderBook, OrderOwner};
+use pricelevel::{OrderId, OrderType, Side, TimeInForce};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+const SECRET: &[u8] = b"test-secret";
+
+fn create_order_id() -> OrderId {
+    OrderId(Uuid::new_v4())
+}
+
+fn limit_order(id: OrderId, price: u64, quantity: u64, side: Side) -> OrderType {
+    OrderType::Standard {
+        id,
+        price,
+        quantity,
+        side,
+        timestamp: orderbook_rs::current_time_millis(),
+        time_in_force: TimeInForce::Gtc,
+    }
+}
+
+fn start_hub(config: UserStreamConfig) -> (Arc<OrderBook>, Arc<UserStreamHub>) {
+    let book = Arc::new(OrderBook::new("TEST"));
+    let hub = Arc::new(UserStreamHub::new(config, Some(SECRET.to_vec()), 1));
+    let (sink, receiver) = UserStreamSink::channel();
+    book.subscribe(Arc::new(sink));
+    actix_rt::spawn(run_user_streams(hub.clone(), receiver));
+    (book, hub)
+}
+
+fn start_server(hub: Arc<UserStreamHub>) -> SocketAddr {
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(hub.clone()))
+            .route("/ws/user", web::get().to(user_stream_ws))
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+    let addr = server.addrs()[0];
+    actix_rt::spawn(server.run());
+    addr
+}
+
+async fn next_message(client: &mut StreamClient) -> UserStreamMessage {
+    tokio::time::timeout(Duration::from_secs(5), client.next_message())
+        .await
+        .expect("no message received in time")
+        .unwrap()
+        .expect("stream closed")
+}
+
+async fn next_order(client: &mut StreamClient) -> OrderNotification {
+    match next_message(client).await {
+        UserStreamMessage::Order(notification) => notification,
+        message => panic!("expected an order notification, got {message:?}"),
+    }
+}
+
+#[test]
+fn test_tokens() {
+    let account = Uuid::new_v4();
+    let token = issue_token(SECRET, account, 2_000);
+    assert_eq!(verify_token(SECRET, &token, 1_000), Ok(account));
+    assert_eq!(
+        verify_token(SECRET, &token, 2_000),
+        Err(TokenError::Expired)
+    );
+    assert_eq!(
+        verify_token(b"other-secret", &token, 1_000),
+        Err(TokenError::BadSignature)
+    );
+
+    let other = issue_token(SECRET, Uuid::new_v4(), 2_000);
+    let forged = format!(
+        "{}.{}",
+        token.rsplit_once('.').unwrap().0,
+        other.rsplit_once('.').unwrap().1
+    );
+    assert_eq!(
+        verify_token(SECRET, &forged, 1_000),
+        Err(TokenError::BadSignature)
+    );
+    assert_eq!(
+        verify_token(SECRET, "not-a-token", 1_000),
+        Err(TokenError::Malformed)
+    );
+}
+
+#[actix_rt::test]
+async fn test_user_stream_notifies_orders_and_replays_after_reconnect() {
+    let (book, hub) = start_hub(UserStreamConfig::default());
+    let addr = start_server(hub.clone());
+    let account = Uuid::new_v4();
+    let token = issue_token(SECRET, account, u64::MAX);
+
+    assert!(
+        StreamClient::connect(addr, "/ws/user?token=invalid")
+            .await
+            .is_err()
+    );
+
+    let path = format!("/ws/user?token={token}");
+    let mut client = StreamClient::connect(addr, &path).await.unwrap();
+    assert_eq!(
+        next_message(&mut client).await,
+        UserStreamMessage::Welcome {
+            account,
+            epoch: 1,
+            next_sequence: 1
+        }
+    );
+
+    let maker = create_order_id();
+    let owner = OrderOwner::new(account, Some("maker-1".to_string()));
+    book.add_order_with_owner(limit_order(maker, 1000, 10, Side::Sell), owner)
+        .unwrap();
+    let accepted = next_order(&mut client).await;
+    assert_eq!(accepted.sequence, 1);
+    assert_eq!(accepted.event, OrderEvent::Accepted);
+    assert_eq!(accepted.order_id, maker);
+    assert_eq!(accepted.client_order_id.as_deref(), Some("maker-1"));
+    assert_eq!(accepted.remaining_quantity, 10);
+
+    // Orders of other accounts are not sent
+    let other = OrderOwner::new(Uuid::new_v4(), None);
+    book.add_order_with_owner(limit_order(create_order_id(), 900, 1, Side::Buy), other)
+        .unwrap();
+    book.submit_market_order(create_order_id(), 4, Side::Buy)
+        .unwrap();
+    let partial = next_order(&mut client).await;
+    assert_eq!(partial.sequence, 2);
+    assert_eq!(partial.event, OrderEvent::PartiallyFilled);
+    assert_eq!(partial.cumulative_quantity, 4);
+    assert_eq!(partial.remaining_quantity, 6);
+    assert_eq!(partial.fills.len(), 1);
+    assert_eq!(partial.fills[0].role, LiquidityRole::Maker);
+    assert_eq!(
+        (partial.fills[0].price, partial.fills[0].quantity),
+        (1000, 4)
+    );
+    client.close().await.unwrap();
+
+    // Notifications published while disconnected are replayed from the requested sequence
+    book.submit_market_order(create_order_id(), 6, Side::Buy)
+        .unwrap();
+    let resting = create_order_id();
+    let owner = OrderOwner::new(account, None);
+    book.add_order_with_owner(limit_order(resting, 1100, 2, Side::Sell), owner)
+        .unwrap();
+    book.cancel_order(resting).unwrap();
+
+    let mut client = StreamClient::connect(addr, &format!("{path}&from_sequence=3"))
+        .await
+        .unwrap();
+    assert!(matches!(
+        next_message(&mut client).await,
+        UserStreamMessage::Welcome {
+            next_sequence: 3,
+            ..
+        }
+    ));
+    let filled = next_order(&mut client).await;
+    assert_eq!((filled.sequence, filled.event), (3, OrderEvent::Filled));
+    assert_eq!(filled.cumulative_quantity, 10);
+    assert_eq!(filled.average_price, Some(1000.0));
+    let events: Vec<_> = [next_order(&mut client).await, next_order(&mut client).await]
+        .iter()
+        .map(|notification| (notification.sequence, notification.event))
+        .collect();
+    assert_eq!(
+        events,
+        vec![(4, OrderEvent::Accepted), (5, OrderEvent::Cancelled)]
+    );
+    client.close().await.unwrap();
+}
+
+#[actix_rt::test]
+async fn test_replay_past_the_retained_history_is_refused() {
+    let (book, hub) = start_hub(UserStreamConfig {
+        retained_notifications: 2,
+        ..UserStreamConfig::default()
+    });
+    let account = Uuid::new_v4();
+    for price in [1000, 1010, 1020] {
+        let owner = OrderOwner::new(account, None);
+        book.add_order_with_owner(limit_order(create_order_id(), price, 1, Side::Sell), owner)
+            .unwrap();
+    }
+
+    let mut live = hub.open_session(account, None);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while live.next_sequence() < 4 {
+            live = hub.open_session(account, None);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let mut session = hub.open_session(account, Some(1));
+    let gap = session.try_batch().unwrap_err();
+    assert_eq!((gap.from_sequence, gap.first_available), (1, 2));
+
+    let mut session = hub.open_session(account, Some(2));
+    let sequences: Vec<u64> = session
+        .try_batch()
+        .unwrap()
+        .iter()
+        .map(|notification| notification.sequence)
+        .collect();
+    assert_eq!(sequences, vec![2, 3]);
+}